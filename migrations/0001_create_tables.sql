@@ -0,0 +1,75 @@
+-- Documents are stored as JSON in `data`, the other columns hold the fields they are queried by.
+
+CREATE TABLE expenses (
+    id TEXT PRIMARY KEY,
+    group_id TEXT,
+    series_id TEXT,
+    date TEXT,
+    repeats INTEGER NOT NULL DEFAULT 0,
+    deleted INTEGER NOT NULL DEFAULT 0,
+    data TEXT NOT NULL
+);
+
+CREATE INDEX expenses_group_id ON expenses (group_id);
+
+-- at most one occurrence of a repeating expense per date
+CREATE UNIQUE INDEX expenses_series_date ON expenses (series_id, date);
+
+CREATE TABLE expense_history (
+    expense_id TEXT NOT NULL,
+    version BIGINT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (expense_id, version)
+);
+
+CREATE TABLE groups (
+    id TEXT PRIMARY KEY,
+    data TEXT NOT NULL
+);
+
+CREATE TABLE group_members (
+    group_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    PRIMARY KEY (group_id, user_id)
+);
+
+CREATE INDEX group_members_user_id ON group_members (user_id);
+
+CREATE TABLE users (
+    id TEXT PRIMARY KEY,
+    data TEXT NOT NULL
+);
+
+CREATE TABLE balances (
+    user_id TEXT PRIMARY KEY,
+    balance BIGINT NOT NULL
+);
+
+CREATE TABLE comments (
+    id TEXT PRIMARY KEY,
+    expense_id TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+
+CREATE INDEX comments_expense_id ON comments (expense_id);
+
+CREATE TABLE activity (
+    id TEXT PRIMARY KEY,
+    group_id TEXT,
+    data TEXT NOT NULL
+);
+
+CREATE INDEX activity_group_id ON activity (group_id);
+
+CREATE TABLE activity_users (
+    activity_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    PRIMARY KEY (activity_id, user_id)
+);
+
+CREATE INDEX activity_users_user_id ON activity_users (user_id);
+
+CREATE TABLE activity_reads (
+    user_id TEXT PRIMARY KEY,
+    last_read_id TEXT NOT NULL
+);
@@ -0,0 +1,10 @@
+-- `Idempotency-Key`s of create requests, `status` and `body` are set once the request completed.
+
+CREATE TABLE idempotency_keys (
+    idempotency_key TEXT PRIMARY KEY,
+    request_hash TEXT NOT NULL,
+    status INTEGER,
+    body TEXT,
+    -- milliseconds since the epoch, compared against the current time
+    expires_at BIGINT NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Version of every expense and group, compared on conditional updates. Rows stored before are
+-- at version 0, like their documents without a `version`.
+
+ALTER TABLE expenses ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE groups ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- Email of every user in lower case, users are looked up by email when importing groups. Rows
+-- stored before have none, their documents had no email either.
+
+ALTER TABLE users ADD COLUMN email TEXT;
+
+CREATE INDEX users_email ON users (email);
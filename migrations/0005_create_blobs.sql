@@ -0,0 +1,8 @@
+-- Content of receipts, referred to by key from the documents. `BYTEA` is PostgreSQL's binary
+-- type, SQLite stores the bytes as they are whatever the declared type.
+
+CREATE TABLE blobs (
+    key TEXT PRIMARY KEY,
+    content_type TEXT NOT NULL,
+    data BYTEA NOT NULL
+);
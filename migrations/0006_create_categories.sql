@@ -0,0 +1,9 @@
+-- Custom categories of groups, the default categories are defined in the code.
+
+CREATE TABLE categories (
+    id TEXT PRIMARY KEY,
+    group_id TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+
+CREATE INDEX categories_group_id ON categories (group_id);
@@ -0,0 +1,8 @@
+-- Parent and name in lower case of every custom category, names are unique among the categories
+-- of a group with the same parent. Top level categories have an empty parent id. Rows stored
+-- before have no name and are not checked.
+
+ALTER TABLE categories ADD COLUMN parent_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE categories ADD COLUMN name_key TEXT;
+
+CREATE UNIQUE INDEX categories_name ON categories (group_id, parent_id, name_key);
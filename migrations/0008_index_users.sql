@@ -0,0 +1,22 @@
+-- Users every expense involves or has involved, as share holders or as the users who created,
+-- updated or deleted it, so that the expenses and history of a user are found without reading
+-- every row. Users are never removed, the history of the expense keeps mentioning them.
+
+CREATE TABLE expense_users (
+    expense_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    PRIMARY KEY (expense_id, user_id)
+);
+
+CREATE INDEX expense_users_user_id ON expense_users (user_id);
+
+-- Rows stored before are at 0 until their users are indexed on startup.
+ALTER TABLE expenses ADD COLUMN users_indexed INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX expenses_users_indexed ON expenses (users_indexed);
+
+-- Author of every comment, empty for system comments. Rows stored before have none until it is
+-- set on startup.
+ALTER TABLE comments ADD COLUMN user_id TEXT;
+
+CREATE INDEX comments_user_id ON comments (user_id);
@@ -0,0 +1,419 @@
+use crate::service::currency::ExchangeRates;
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::{bail, Context, Error};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+
+/// File read when `CONFIG_FILE` is not set. It's optional, env vars alone are enough.
+const DEFAULT_CONFIG_FILE: &str = "swc.toml";
+
+/// Configuration of the server, read from an optional TOML file and overridden by env vars:
+///
+/// ```toml
+/// [server]
+/// host = "0.0.0.0"            # HOST
+/// port = 8080                 # PORT
+/// body_limit = 16384          # BODY_LIMIT, maximum size of a request body in bytes
+/// import_limit = 1048576      # IMPORT_LIMIT, maximum size of an imported CSV in bytes
+/// receipt_limit = 5242880     # RECEIPT_LIMIT, maximum size of an uploaded receipt in bytes
+/// cors_origins = ["https://swc.example.com"]  # CORS_ORIGINS, comma separated
+/// shutdown_timeout = 30       # SHUTDOWN_TIMEOUT, seconds to drain requests on shutdown
+/// swagger_ui = true           # SWAGGER_UI, serves Swagger UI at `/docs`
+/// idempotency_ttl = 86400     # IDEMPOTENCY_TTL, seconds responses are kept for retries
+/// exchange_rates = { EUR = 1.0, USD = 0.92 }  # EXCHANGE_RATES, like `EUR=1,USD=0.92`
+///
+/// [database]
+/// url = "mongodb://localhost:27017"  # DATABASE_URL or MONGO_URL
+/// name = "swc"                # DATABASE_NAME
+/// storage = "memory"          # STORAGE, keeps everything in memory instead
+/// receipts_dir = "receipts"   # RECEIPTS_DIR, stores receipts as files instead of in the database
+///
+/// [log]
+/// format = "json"             # LOG_FORMAT, `text` or `json`
+/// ```
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+
+    pub database: DatabaseConfig,
+
+    pub log: LogConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+
+    pub port: u16,
+
+    /// Maximum size of a request body in bytes.
+    pub body_limit: u64,
+
+    /// Maximum size of a CSV imported into a group in bytes.
+    pub import_limit: u64,
+
+    /// Maximum size of a receipt uploaded for an expense in bytes.
+    pub receipt_limit: u64,
+
+    /// Origins allowed to call the API from a browser. Any origin is allowed if empty.
+    pub cors_origins: Vec<String>,
+
+    /// Seconds in-flight requests may take to finish after a shutdown signal.
+    pub shutdown_timeout: u64,
+
+    /// Whether to serve Swagger UI for `/openapi.json` at `/docs`.
+    pub swagger_ui: bool,
+
+    /// Seconds the response to a request with an `Idempotency-Key` is returned to its retries.
+    pub idempotency_ttl: u64,
+
+    /// Rates user summaries are converted to the default currency of the user with.
+    pub exchange_rates: ExchangeRates,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            body_limit: 1024 * 16,
+            import_limit: 1024 * 1024,
+            receipt_limit: 5 * 1024 * 1024,
+            cors_origins: Vec::new(),
+            shutdown_timeout: 30,
+            swagger_ui: false,
+            idempotency_ttl: 24 * 60 * 60,
+            exchange_rates: ExchangeRates::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn address(&self) -> Result<SocketAddr, Error> {
+        format!("{}:{}", self.host, self.port)
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid server address {}:{}", self.host, self.port))?
+            .next()
+            .with_context(|| format!("Server address {} does not resolve", self.host))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// `mongodb://`, or with the `sql` feature `sqlite:` and `postgres://` URL.
+    pub url: Option<String>,
+
+    /// Name of the MongoDB database.
+    pub name: String,
+
+    pub storage: StorageKind,
+
+    /// Directory receipts are stored in as files, they are stored in the database if unset.
+    pub receipts_dir: Option<String>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            name: DEFAULT_DATABASE_NAME.to_string(),
+            storage: StorageKind::default(),
+            receipts_dir: None,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    /// Storage selected by the scheme of the database URL.
+    #[default]
+    Database,
+    /// Everything is kept in memory and lost on shutdown.
+    Memory,
+}
+
+#[derive(Default, Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl Config {
+    /// Reads the file named by `CONFIG_FILE`, or `swc.toml` if it exists, and applies the env
+    /// vars on top of it.
+    pub fn load() -> Result<Self, Error> {
+        let config = match env::var("CONFIG_FILE") {
+            Ok(path) => Self::from_file(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            Err(_) => Self::default(),
+        };
+        let config = config.with_env(|key| env::var(key).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::from_toml(&content).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, Error> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Overrides the settings with the env vars returned by `var`.
+    pub fn with_env(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        if let Some(host) = var("HOST") {
+            self.server.host = host;
+        }
+        if let Some(port) = var("PORT") {
+            self.server.port = port
+                .parse()
+                .with_context(|| format!("PORT must be a number up to 65535, got `{}`", port))?;
+        }
+        if let Some(body_limit) = var("BODY_LIMIT") {
+            self.server.body_limit = body_limit.parse().with_context(|| {
+                format!("BODY_LIMIT must be a number of bytes, got `{}`", body_limit)
+            })?;
+        }
+        if let Some(import_limit) = var("IMPORT_LIMIT") {
+            self.server.import_limit = import_limit.parse().with_context(|| {
+                format!(
+                    "IMPORT_LIMIT must be a number of bytes, got `{}`",
+                    import_limit
+                )
+            })?;
+        }
+        if let Some(receipt_limit) = var("RECEIPT_LIMIT") {
+            self.server.receipt_limit = receipt_limit.parse().with_context(|| {
+                format!(
+                    "RECEIPT_LIMIT must be a number of bytes, got `{}`",
+                    receipt_limit
+                )
+            })?;
+        }
+        if let Some(timeout) = var("SHUTDOWN_TIMEOUT") {
+            self.server.shutdown_timeout = timeout.parse().with_context(|| {
+                format!(
+                    "SHUTDOWN_TIMEOUT must be a number of seconds, got `{}`",
+                    timeout
+                )
+            })?;
+        }
+        if let Some(ttl) = var("IDEMPOTENCY_TTL") {
+            self.server.idempotency_ttl = ttl.parse().with_context(|| {
+                format!("IDEMPOTENCY_TTL must be a number of seconds, got `{}`", ttl)
+            })?;
+        }
+        if let Some(swagger_ui) = var("SWAGGER_UI") {
+            self.server.swagger_ui = swagger_ui.parse().with_context(|| {
+                format!("SWAGGER_UI must be `true` or `false`, got `{}`", swagger_ui)
+            })?;
+        }
+        if let Some(rates) = var("EXCHANGE_RATES") {
+            self.server.exchange_rates = ExchangeRates::parse(&rates)
+                .with_context(|| format!("Invalid EXCHANGE_RATES `{}`", rates))?;
+        }
+        if let Some(origins) = var("CORS_ORIGINS") {
+            self.server.cors_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(url) = var("DATABASE_URL").or_else(|| var("MONGO_URL")) {
+            self.database.url = Some(url);
+        }
+        if let Some(name) = var("DATABASE_NAME") {
+            self.database.name = name;
+        }
+        if let Some(dir) = var("RECEIPTS_DIR") {
+            self.database.receipts_dir = Some(dir);
+        }
+        if let Some(storage) = var("STORAGE") {
+            self.database.storage = match storage.as_str() {
+                "memory" => StorageKind::Memory,
+                "database" => StorageKind::Database,
+                _ => bail!("STORAGE must be `memory` or `database`, got `{}`", storage),
+            };
+        }
+        if let Some(format) = var("LOG_FORMAT") {
+            self.log.format = match format.as_str() {
+                "text" => LogFormat::Text,
+                "json" => LogFormat::Json,
+                _ => bail!("LOG_FORMAT must be `text` or `json`, got `{}`", format),
+            };
+        }
+        Ok(self)
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.server.body_limit == 0 {
+            bail!("Body limit must be greater than 0");
+        }
+        if self.server.import_limit == 0 {
+            bail!("Import limit must be greater than 0");
+        }
+        if self.server.receipt_limit == 0 {
+            bail!("Receipt limit must be greater than 0");
+        }
+        if self.server.idempotency_ttl == 0 {
+            bail!("Idempotency TTL must be greater than 0");
+        }
+        self.server.exchange_rates.validate()?;
+        for origin in &self.server.cors_origins {
+            let host = origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"));
+            if !matches!(host, Some(host) if !host.is_empty() && !host.contains('/')) {
+                bail!(
+                    "Invalid CORS origin `{}`, expected scheme and host like `https://example.com`",
+                    origin
+                );
+            }
+        }
+        if self.database.storage == StorageKind::Database {
+            match &self.database.url {
+                None => bail!("Missing database URL, set DATABASE_URL or STORAGE=memory"),
+                Some(url) if !url.contains(':') => {
+                    bail!("Invalid database URL `{}`, expected a scheme", url)
+                }
+                Some(_) => {}
+            }
+        }
+        if self.database.name.is_empty() {
+            bail!("Database name must not be empty");
+        }
+        if self.database.receipts_dir.as_deref() == Some("") {
+            bail!("Receipts directory must not be empty");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Config, LogFormat, StorageKind};
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let config = Config::from_toml(
+            r#"
+            [server]
+            port = 9000
+            body_limit = 1024
+
+            [database]
+            url = "mongodb://localhost:27017"
+            name = "expenses"
+            "#,
+        )
+        .unwrap()
+        .with_env(env(&[
+            ("PORT", "8081"),
+            (
+                "CORS_ORIGINS",
+                "https://a.example.com, http://localhost:3000",
+            ),
+            ("LOG_FORMAT", "json"),
+            ("SWAGGER_UI", "true"),
+            ("IDEMPOTENCY_TTL", "3600"),
+            ("IMPORT_LIMIT", "65536"),
+            ("RECEIPT_LIMIT", "1048576"),
+            ("RECEIPTS_DIR", "/var/lib/swc/receipts"),
+            ("EXCHANGE_RATES", "EUR=1,USD=0.92"),
+        ]))
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.server.port, 8081);
+        assert_eq!(config.server.body_limit, 1024);
+        assert_eq!(
+            config.server.cors_origins,
+            vec!["https://a.example.com", "http://localhost:3000"]
+        );
+        assert_eq!(config.database.name, "expenses");
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert!(config.server.swagger_ui);
+        assert_eq!(config.server.idempotency_ttl, 3600);
+        assert_eq!(config.server.import_limit, 65536);
+        assert_eq!(config.server.receipt_limit, 1048576);
+        assert_eq!(
+            config.database.receipts_dir.as_deref(),
+            Some("/var/lib/swc/receipts")
+        );
+        assert_eq!(
+            config.server.exchange_rates.convert(10000, "USD", "EUR"),
+            Some(9200)
+        );
+    }
+
+    #[test]
+    fn reject_invalid_port() {
+        let error = Config::default()
+            .with_env(env(&[("PORT", "http")]))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "PORT must be a number up to 65535, got `http`"
+        );
+    }
+
+    #[test]
+    fn require_database_url() {
+        let error = Config::default().validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Missing database URL, set DATABASE_URL or STORAGE=memory"
+        );
+        let config = Config::default()
+            .with_env(env(&[("STORAGE", "memory")]))
+            .unwrap();
+        assert_eq!(config.database.storage, StorageKind::Memory);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn reject_unknown_setting() {
+        assert!(Config::from_toml("[server]\nbody_size = 10").is_err());
+    }
+
+    #[test]
+    fn reject_cors_origin_with_path() {
+        let config = Config::default()
+            .with_env(env(&[
+                ("STORAGE", "memory"),
+                ("CORS_ORIGINS", "https://example.com/app"),
+            ]))
+            .unwrap();
+        assert!(config.validate().is_err());
+    }
+}
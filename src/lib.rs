@@ -1,4 +1,3 @@
-#![feature(iterator_try_collect)]
 #![warn(
     anonymous_parameters,
     missing_copy_implementations,
@@ -16,5 +15,7 @@
     variant_size_differences
 )]
 
+pub mod config;
+pub mod metrics;
 pub mod route;
 pub mod service;
@@ -1,11 +1,19 @@
-extern crate pretty_env_logger;
-
 use dotenv::dotenv;
 
 use std::env;
-use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::time::Duration;
+use swc::config::{Config, DatabaseConfig, LogFormat, StorageKind};
 use swc::route::routes;
-use warp::Filter;
+use swc::service::blob::FileBlobStore;
+use swc::service::events::EventBus;
+use swc::service::migration::MongoMigrator;
+use swc::service::recurring::run_scheduler;
+use swc::service::storage::Storage;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
@@ -14,26 +22,123 @@ async fn main() -> Result<(), anyhow::Error> {
             dotenv().ok();
         }
     }
-    if env::var_os("RUST_LOG").is_none() {
-        env::set_var("RUST_LOG", "info");
+    let config = Config::load()?;
+    init_tracing(config.log.format);
+    // `swc migrate` only brings the database schema up to date
+    let migrate_only = matches!(env::args().nth(1).as_deref(), Some("migrate"));
+    let storage = storage(&config.database, EventBus::default()).await?;
+    if migrate_only {
+        return Ok(());
+    }
+
+    tracing::info!("Starting server");
+    let address = config.server.address()?;
+    let (shutdown, _) = watch::channel(false);
+
+    let scheduler = tokio::spawn(run_scheduler(
+        storage.recurring.clone(),
+        Duration::from_secs(60),
+        shutdown.subscribe(),
+    ));
+
+    let routes = routes(storage, &config.server);
+    let mut server_shutdown = shutdown.subscribe();
+    let (_address, server) = warp::serve(routes).bind_with_graceful_shutdown(address, async move {
+        let _changed = server_shutdown.changed().await;
+    });
+    let server = tokio::spawn(server);
+
+    shutdown_signal().await;
+    tracing::info!("Shutting down, waiting for in-flight requests");
+    let _ = shutdown.send(true);
+    let timeout = Duration::from_secs(config.server.shutdown_timeout);
+    let stopped = tokio::time::timeout(timeout, async {
+        let _server = server.await;
+        let _scheduler = scheduler.await;
+    })
+    .await;
+    if stopped.is_err() {
+        // e.g. clients still subscribed to the event stream
+        tracing::warn!(
+            "Requests still running after {:?}, stopping anyway",
+            timeout
+        );
     }
-    pretty_env_logger::init();
-    log::info!("Starting server");
-    let host = env::var("HOST").expect("Missing HOST env var");
-    let port = env::var("PORT").expect("Missing PORT env var");
-    let server_details = format!("{}:{}", host, port);
-    let server = server_details
-        .clone()
-        .to_socket_addrs()
-        .expect("Unable to parse socket address")
-        .next()
-        .expect("Unable to parse socket address");
-    let mongo_url = env::var("MONGO_URL").expect("Missing MONGO_URL env var");
-    let client = mongodb::Client::with_uri_str(&mongo_url).await?;
-
-    let api = routes(client);
-
-    let routes = api.with(warp::log("groups"));
-    warp::serve(routes).run(server).await;
     Ok(())
 }
+
+/// Resolves on SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for SIGINT");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        let _signal = signal(SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Logs to stdout, filtered by `RUST_LOG` which defaults to `info`. Events carry the fields of
+/// the spans they happen in, such as the request id.
+fn init_tracing(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).init(),
+    }
+}
+
+async fn storage(config: &DatabaseConfig, events: EventBus) -> Result<Storage, anyhow::Error> {
+    let storage = database_storage(config, events).await?;
+    Ok(match &config.receipts_dir {
+        Some(dir) => storage.with_blobs(Arc::new(FileBlobStore::new(dir))),
+        None => storage,
+    })
+}
+
+async fn database_storage(
+    config: &DatabaseConfig,
+    events: EventBus,
+) -> Result<Storage, anyhow::Error> {
+    if config.storage == StorageKind::Memory {
+        tracing::warn!("Using in-memory storage, data is lost on shutdown");
+        return Ok(Storage::in_memory(events));
+    }
+    // validated to be present unless the storage is in memory
+    let database_url = config.url.as_deref().unwrap_or_default();
+    if database_url.starts_with("mongodb") {
+        let client = mongodb::Client::with_uri_str(database_url).await?;
+        let db = client.database(&config.name);
+        let applied = MongoMigrator::new(db.clone()).run().await?;
+        tracing::info!("Applied {} migrations", applied.len());
+        Ok(Storage::mongo(db, events))
+    } else {
+        sql_storage(database_url, events).await
+    }
+}
+
+#[cfg(feature = "sql")]
+async fn sql_storage(database_url: &str, events: EventBus) -> Result<Storage, anyhow::Error> {
+    let pool = swc::service::sql::connect(database_url).await?;
+    Ok(Storage::sql(pool, events))
+}
+
+#[cfg(not(feature = "sql"))]
+async fn sql_storage(database_url: &str, _events: EventBus) -> Result<Storage, anyhow::Error> {
+    anyhow::bail!(
+        "Unsupported DATABASE_URL {}, build with the `sql` feature for SQLite and PostgreSQL",
+        database_url
+    )
+}
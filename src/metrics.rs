@@ -0,0 +1,168 @@
+use anyhow::Error;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+static METRICS: LazyLock<Metrics> =
+    LazyLock::new(|| Metrics::new().expect("Failed to register metrics"));
+
+/// Metrics of the process, exposed on `/metrics` in the Prometheus text format.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+
+    /// Handled requests by `method`, `route` and `status`.
+    pub http_requests: IntCounterVec,
+
+    /// Seconds spent handling a request by `method`, `route` and `status`.
+    pub http_request_duration: HistogramVec,
+
+    /// Seconds a storage call took by `service` and `operation`, e.g. `expenses` and
+    /// `create_expense`.
+    pub storage_operation_duration: HistogramVec,
+
+    /// Storage calls which failed by `service` and `operation`.
+    pub storage_errors: IntCounterVec,
+
+    pub expenses_created: IntCounter,
+
+    pub payments_recorded: IntCounter,
+
+    /// Occurrences of repeating expenses generated by the scheduler.
+    pub recurring_expenses_generated: IntCounter,
+
+    pub comments_created: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("swc".to_string()), None)?;
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Handled HTTP requests"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent handling HTTP requests",
+            ),
+            &["method", "route", "status"],
+        )?;
+        let storage_operation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "storage_operation_duration_seconds",
+                "Time spent in calls to the storage",
+            ),
+            &["service", "operation"],
+        )?;
+        let storage_errors = IntCounterVec::new(
+            Opts::new("storage_errors_total", "Failed calls to the storage"),
+            &["service", "operation"],
+        )?;
+        let expenses_created = IntCounter::new("expenses_created_total", "Created expenses")?;
+        let payments_recorded = IntCounter::new("payments_recorded_total", "Recorded payments")?;
+        let recurring_expenses_generated = IntCounter::new(
+            "recurring_expenses_generated_total",
+            "Generated occurrences of repeating expenses",
+        )?;
+        let comments_created = IntCounter::new("comments_created_total", "Created comments")?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(storage_operation_duration.clone()))?;
+        registry.register(Box::new(storage_errors.clone()))?;
+        registry.register(Box::new(expenses_created.clone()))?;
+        registry.register(Box::new(payments_recorded.clone()))?;
+        registry.register(Box::new(recurring_expenses_generated.clone()))?;
+        registry.register(Box::new(comments_created.clone()))?;
+        Ok(Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            storage_operation_duration,
+            storage_errors,
+            expenses_created,
+            payments_recorded,
+            recurring_expenses_generated,
+            comments_created,
+        })
+    }
+
+    pub fn record_request(&self, method: &str, path: &str, status: u16, elapsed: Duration) {
+        let route = route_label(path);
+        let status = status.to_string();
+        let labels = [method, route.as_str(), status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_request_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record_storage_call(
+        &self,
+        service: &str,
+        operation: &str,
+        elapsed: Duration,
+        failed: bool,
+    ) {
+        let labels = [service, operation];
+        self.storage_operation_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+        if failed {
+            self.storage_errors.with_label_values(&labels).inc();
+        }
+    }
+
+    /// All metrics in the Prometheus text format.
+    pub fn encode(&self) -> Result<String, Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Path of the request with ids replaced by `{id}`, so that every route gets one label value.
+pub fn route_label(path: &str) -> String {
+    let is_id = |segment: &str| {
+        let object_id = segment.len() == 24 && segment.chars().all(|c| c.is_ascii_hexdigit());
+        let number = !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit());
+        object_id || number
+    };
+    path.split('/')
+        .map(|segment| if is_id(segment) { "{id}" } else { segment })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{metrics, route_label};
+    use std::time::Duration;
+
+    #[test]
+    fn replace_ids_in_route() {
+        assert_eq!(
+            route_label("/expenses/635d2a5f0b6a4c3e9c8f1a2b/comments"),
+            "/expenses/{id}/comments"
+        );
+        assert_eq!(route_label("/users/1234/activity"), "/users/{id}/activity");
+        assert_eq!(route_label("/health/ready"), "/health/ready");
+    }
+
+    #[test]
+    fn encode_request_metrics() {
+        metrics().record_request("GET", "/groups/1/activity", 200, Duration::from_millis(5));
+        let encoded = metrics().encode().unwrap();
+        assert!(encoded.contains(
+            r#"swc_http_requests_total{method="GET",route="/groups/{id}/activity",status="200"}"#
+        ));
+        assert!(encoded.contains("swc_http_request_duration_seconds_bucket"));
+    }
+}
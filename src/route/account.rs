@@ -0,0 +1,88 @@
+use crate::route::with_storage;
+use crate::service::storage::Storage;
+use warp::Filter;
+
+pub fn accounts(
+    storage: Storage,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let export = warp::path!("users" / String / "export")
+        .and(warp::get())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::export_account);
+    let delete = warp::path!("users" / String)
+        .and(warp::delete())
+        .and(with_storage(storage))
+        .and_then(handlers::delete_account);
+    export.or(delete)
+}
+
+pub(super) mod handlers {
+    use crate::route::request::{service_error, NotFound};
+    use crate::service::account::{delete, export, AccountDeletion, AccountExport};
+    use crate::service::storage::Storage;
+    use anyhow::Context;
+    use warp::http::header::CONTENT_DISPOSITION;
+
+    #[utoipa::path(
+        get,
+        path = "/users/{user_id}/export",
+        tag = "users",
+        params(("user_id" = String, Path, description = "Id of the user")),
+        responses(
+            (status = 200, description = "Profile, groups, expenses, comments and activity of the user", body = AccountExport),
+            (status = 404, description = "Nothing is stored about the user"),
+        )
+    )]
+    pub async fn export_account(
+        user_id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let account = export(&storage, user_id.clone())
+            .await
+            .context("Failed to export account")
+            .map_err(service_error)?;
+        if account.profile.is_none() && account.groups.is_empty() && account.expenses.is_empty() {
+            return Err(unknown_user(&user_id));
+        }
+        let disposition = format!(
+            "attachment; filename=\"user-{}.json\"",
+            user_id.replace('"', "")
+        );
+        Ok(warp::reply::with_header(
+            warp::reply::json(&account),
+            CONTENT_DISPOSITION,
+            disposition,
+        ))
+    }
+
+    #[utoipa::path(
+        delete,
+        path = "/users/{user_id}",
+        tag = "users",
+        params(("user_id" = String, Path, description = "Id of the user")),
+        responses(
+            (status = 200, description = "Profile deleted and the user anonymised in their groups and expenses, the balances of the other members are unchanged", body = AccountDeletion),
+            (status = 404, description = "Nothing is stored about the user"),
+        )
+    )]
+    pub async fn delete_account(
+        user_id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let deletion = delete(&storage, user_id.clone())
+            .await
+            .context("Failed to delete account")
+            .map_err(service_error)?;
+        if !deletion.profile_deleted && deletion.groups == 0 && deletion.expenses == 0 {
+            return Err(unknown_user(&user_id));
+        }
+        Ok(warp::reply::json(&deletion))
+    }
+
+    fn unknown_user(user_id: &str) -> warp::Rejection {
+        warp::reject::custom(NotFound(format!(
+            "Nothing is stored about user {}",
+            user_id
+        )))
+    }
+}
@@ -0,0 +1,149 @@
+use crate::route::with_storage;
+use crate::service::activity::{ActivityRequest, MAX_PAGE_SIZE};
+use crate::service::storage::Storage;
+use mongodb::bson::oid::ObjectId;
+use std::fmt;
+use warp::reject::Reject;
+use warp::Filter;
+
+/// The requested page of activity is empty or larger than [`MAX_PAGE_SIZE`].
+#[derive(Debug)]
+pub struct InvalidLimit(i64);
+
+impl Reject for InvalidLimit {}
+
+impl fmt::Display for InvalidLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid limit {}, expected 1 to {}",
+            self.0, MAX_PAGE_SIZE
+        )
+    }
+}
+
+/// The cursor of the requested page is not the id of an activity.
+#[derive(Debug)]
+pub struct InvalidCursor(String);
+
+impl Reject for InvalidCursor {}
+
+impl fmt::Display for InvalidCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid cursor {:?}", self.0)
+    }
+}
+
+pub fn activity(
+    storage: Storage,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let group_activity = warp::path!("groups" / String / "activity")
+        .and(warp::get())
+        .and(activity_request())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_group_activity);
+    let user_activity = warp::path!("users" / String / "activity")
+        .and(warp::get())
+        .and(activity_request())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_user_activity);
+    let mark_read = warp::path!("users" / String / "activity" / "read")
+        .and(warp::put())
+        .and(with_storage(storage))
+        .and_then(handlers::mark_read);
+    group_activity.or(user_activity).or(mark_read)
+}
+
+/// Query of an activity page, rejected when its limit is out of range or its cursor is not an
+/// activity id.
+fn activity_request() -> impl Filter<Extract = (ActivityRequest,), Error = warp::Rejection> + Clone
+{
+    warp::query::<ActivityRequest>().and_then(|request: ActivityRequest| async move {
+        if let Some(limit) = request.limit {
+            if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+                return Err(warp::reject::custom(InvalidLimit(limit)));
+            }
+        }
+        if let Some(cursor) = &request.cursor {
+            if ObjectId::parse_str(cursor).is_err() {
+                return Err(warp::reject::custom(InvalidCursor(cursor.clone())));
+            }
+        }
+        Ok(request)
+    })
+}
+
+pub(super) mod handlers {
+    use crate::route::request::service_error;
+    use crate::service::activity::{ActivityPage, ActivityRequest};
+    use crate::service::storage::Storage;
+    use anyhow::Context;
+
+    #[utoipa::path(
+        get,
+        path = "/groups/{group_id}/activity",
+        tag = "activity",
+        params(("group_id" = String, Path, description = "Id of the group"), ActivityRequest),
+        responses(
+            (status = 200, description = "Activity of the group, newest first", body = ActivityPage),
+            (status = 400, description = "Limit out of range or invalid cursor"),
+        )
+    )]
+    pub async fn get_group_activity(
+        group_id: String,
+        request: ActivityRequest,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let page = storage
+            .activity
+            .get_group_activity(group_id, request)
+            .await
+            .context("Failed to get group activity")
+            .map_err(service_error)?;
+        Ok(warp::reply::json(&page))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/users/{user_id}/activity",
+        tag = "activity",
+        params(("user_id" = String, Path, description = "Id of the user"), ActivityRequest),
+        responses(
+            (status = 200, description = "Activity involving the user, newest first", body = ActivityPage),
+            (status = 400, description = "Limit out of range or invalid cursor"),
+        )
+    )]
+    pub async fn get_user_activity(
+        user_id: String,
+        request: ActivityRequest,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let page = storage
+            .activity
+            .get_user_activity(user_id, request)
+            .await
+            .context("Failed to get user activity")
+            .map_err(service_error)?;
+        Ok(warp::reply::json(&page))
+    }
+
+    #[utoipa::path(
+        put,
+        path = "/users/{user_id}/activity/read",
+        tag = "activity",
+        params(("user_id" = String, Path, description = "Id of the user")),
+        responses((status = 204, description = "Everything up to now marked as read"))
+    )]
+    pub async fn mark_read(
+        user_id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        storage
+            .activity
+            .mark_read(user_id)
+            .await
+            .context("Failed to mark activity as read")
+            .map_err(service_error)?;
+        Ok(warp::http::StatusCode::NO_CONTENT)
+    }
+}
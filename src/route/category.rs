@@ -0,0 +1,110 @@
+use crate::route::with_storage;
+use crate::service::category::{CategoriesRequest, CreateCategorySpec};
+use crate::service::storage::Storage;
+use std::fmt;
+use warp::reject::Reject;
+use warp::Filter;
+
+/// The category of an expense is unknown to its group, or a custom category can't be created.
+#[derive(Debug)]
+pub struct InvalidCategory(pub(super) String);
+
+impl Reject for InvalidCategory {}
+
+impl fmt::Display for InvalidCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid category: {}", self.0)
+    }
+}
+
+pub fn categories(
+    storage: Storage,
+    body_limit: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let list = warp::path!("categories")
+        .and(warp::get())
+        .and(warp::query::<CategoriesRequest>())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_categories);
+    let create = warp::path!("groups" / String / "categories")
+        .and(warp::post())
+        .and(json_body(body_limit))
+        .and(with_storage(storage))
+        .and_then(handlers::create_category);
+    list.or(create)
+}
+
+fn json_body(
+    body_limit: u64,
+) -> impl Filter<Extract = (CreateCategorySpec,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(body_limit).and(warp::body::json())
+}
+
+pub(super) mod handlers {
+    use super::InvalidCategory;
+    use crate::route::request::service_error;
+    use crate::service::category::{
+        categories, CategoriesRequest, Category, CategoryTaken, CreateCategorySpec,
+    };
+    use crate::service::storage::Storage;
+    use anyhow::Context;
+
+    #[utoipa::path(
+        get,
+        path = "/categories",
+        tag = "categories",
+        params(CategoriesRequest),
+        responses((status = 200, description = "Default categories followed by the custom categories of the group, subcategories refer to their parent", body = Vec<Category>))
+    )]
+    pub async fn get_categories(
+        request: CategoriesRequest,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let categories = categories(&storage, request.group_id)
+            .await
+            .context("Failed to get categories")
+            .map_err(service_error)?;
+        Ok(warp::reply::json(&categories))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/groups/{group_id}/categories",
+        tag = "categories",
+        params(("group_id" = String, Path, description = "Id of the group")),
+        request_body = CreateCategorySpec,
+        responses(
+            (status = 200, description = "Custom category of the group", body = Category),
+            (status = 400, description = "The name is empty or taken, or the parent is unknown or a subcategory"),
+            (status = 404, description = "No such group"),
+        )
+    )]
+    pub async fn create_category(
+        group_id: String,
+        spec: CreateCategorySpec,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let _group = storage
+            .groups
+            .get_group(group_id.clone())
+            .await
+            .context("Failed to get group")
+            .map_err(service_error)?;
+        let existing = categories(&storage, Some(group_id.clone()))
+            .await
+            .context("Failed to get categories")
+            .map_err(service_error)?;
+        spec.validate(&existing)
+            .map_err(|error| warp::reject::custom(InvalidCategory(error.to_string())))?;
+        // a category of the same name may have been created since the validation
+        let category = storage
+            .categories
+            .create_category(group_id, spec)
+            .await
+            .map_err(|error| match error.downcast::<CategoryTaken>() {
+                Ok(taken) => warp::reject::custom(InvalidCategory(taken.to_string())),
+                Err(error) => service_error(error.context("Failed to create category")),
+            })?;
+        Ok(warp::reply::json(&category))
+    }
+}
@@ -0,0 +1,106 @@
+use crate::route::with_storage;
+use crate::service::comment::CreateCommentSpec;
+use crate::service::storage::Storage;
+use warp::Filter;
+
+pub fn comments(
+    storage: Storage,
+    body_limit: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let list = warp::path!("expenses" / String / "comments")
+        .and(warp::get())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_comments);
+    let create = warp::path!("expenses" / String / "comments")
+        .and(warp::post())
+        .and(json_body(body_limit))
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::create_comment);
+    let delete = warp::path!("comments" / String)
+        .and(warp::delete())
+        .and(with_storage(storage))
+        .and_then(handlers::delete_comment);
+    list.or(create).or(delete)
+}
+
+fn json_body(
+    body_limit: u64,
+) -> impl Filter<Extract = (CreateCommentSpec,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(body_limit).and(warp::body::json())
+}
+
+pub(super) mod handlers {
+    use crate::route::request::{service_error, NotFound};
+    use crate::service::comment::{Comment, CreateCommentSpec};
+    use crate::service::storage::Storage;
+    use anyhow::Context;
+
+    #[utoipa::path(
+        get,
+        path = "/expenses/{expense_id}/comments",
+        tag = "comments",
+        params(("expense_id" = String, Path, description = "Id of the expense")),
+        responses((status = 200, description = "Comments on the expense", body = Vec<Comment>))
+    )]
+    pub async fn get_comments(
+        expense_id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let comments = storage
+            .comments
+            .get_comments(expense_id)
+            .await
+            .context("Failed to get comments")
+            .map_err(service_error)?;
+        Ok(warp::reply::json(&comments))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/expenses/{expense_id}/comments",
+        tag = "comments",
+        params(("expense_id" = String, Path, description = "Id of the expense")),
+        request_body = CreateCommentSpec,
+        responses(
+            (status = 200, description = "Created comment", body = Comment),
+            (status = 404, description = "No such expense"),
+        )
+    )]
+    pub async fn create_comment(
+        expense_id: String,
+        create_comment_spec: CreateCommentSpec,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let comment = storage
+            .comments
+            .create_comment(expense_id, create_comment_spec)
+            .await
+            .context("Failed to create comment")
+            .map_err(service_error)?;
+        Ok(warp::reply::json(&comment))
+    }
+
+    #[utoipa::path(
+        delete,
+        path = "/comments/{id}",
+        tag = "comments",
+        params(("id" = String, Path, description = "Id of the comment")),
+        responses(
+            (status = 200, description = "Deleted comment", body = Comment),
+            (status = 404, description = "No comment with such id"),
+        )
+    )]
+    pub async fn delete_comment(
+        id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let comment = storage
+            .comments
+            .delete_comment(id.clone())
+            .await
+            .context("Failed to delete comment")
+            .map_err(service_error)?
+            .ok_or_else(|| warp::reject::custom(NotFound(format!("Comment {} not found", id))))?;
+        Ok(warp::reply::json(&comment))
+    }
+}
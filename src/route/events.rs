@@ -0,0 +1,48 @@
+use crate::route::with_storage;
+use crate::service::storage::Storage;
+use warp::Filter;
+
+pub fn events(
+    storage: Storage,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("groups" / String / "events")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .map(handlers::group_events)
+}
+
+pub(super) mod handlers {
+    use crate::service::events::GroupEvent;
+    use crate::service::storage::Storage;
+    use std::convert::Infallible;
+    use tokio_stream::wrappers::BroadcastStream;
+    use tokio_stream::StreamExt;
+    use warp::sse::Event;
+
+    #[utoipa::path(
+        get,
+        path = "/groups/{group_id}/events",
+        tag = "groups",
+        params(("group_id" = String, Path, description = "Id of the group")),
+        responses((
+            status = 200,
+            description = "Server-sent events named after the `eventType` of each `GroupEvent`",
+            body = GroupEvent,
+            content_type = "text/event-stream"
+        ))
+    )]
+    pub fn group_events(group_id: String, storage: Storage) -> impl warp::Reply {
+        // subscribers which fall too far behind skip the missed events instead of disconnecting
+        let stream = BroadcastStream::new(storage.events.subscribe())
+            .filter_map(|event| event.ok())
+            .filter(move |event| event.group_id == group_id)
+            .filter_map(|event| {
+                Event::default()
+                    .event(event.event_type.name())
+                    .json_data(&event)
+                    .ok()
+            })
+            .map(Ok::<_, Infallible>);
+        warp::sse::reply(warp::sse::keep_alive().stream(stream))
+    }
+}
@@ -1,35 +1,346 @@
-use crate::route::with_client;
-use crate::service::expense::CreateExpenseSpec;
-use mongodb::Client;
+use crate::route::idempotency::idempotency;
+use crate::route::precondition::if_match;
+use crate::route::with_storage;
+use crate::service::expense::{CreateExpenseSpec, UpdateExpenseSpec, User};
+use crate::service::storage::Storage;
+use std::fmt;
+use std::time::Duration;
+use warp::reject::Reject;
 use warp::Filter;
 
+/// Only deleted expenses can be purged.
+#[derive(Debug)]
+pub struct ExpenseNotDeleted(String);
+
+impl Reject for ExpenseNotDeleted {}
+
+impl fmt::Display for ExpenseNotDeleted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expense {} must be deleted before it is purged", self.0)
+    }
+}
+
+/// The line items of an itemized expense are invalid or don't add up to its cost.
+#[derive(Debug)]
+pub struct InvalidSplit(String);
+
+impl Reject for InvalidSplit {}
+
+impl fmt::Display for InvalidSplit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid split: {}", self.0)
+    }
+}
+
+/// The cost of the expense is not a valid amount or its payer has no id.
+#[derive(Debug)]
+pub struct InvalidExpense(String);
+
+impl Reject for InvalidExpense {}
+
+impl fmt::Display for InvalidExpense {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid expense: {}", self.0)
+    }
+}
+
 pub fn expenses(
-    client: Client,
+    storage: Storage,
+    body_limit: u64,
+    idempotency_ttl: Duration,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("expenses")
+    let create = warp::path!("expenses")
+        .and(warp::post())
+        .and(idempotency(storage.clone(), idempotency_ttl))
+        .and(json_body(body_limit))
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::create_expense);
+    let get = warp::path!("expenses" / String)
+        .and(warp::get())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_expense);
+    let update = warp::path!("expenses" / String)
+        .and(warp::patch())
+        .and(if_match())
+        .and(update_json_body(body_limit))
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::update_expense);
+    let delete = warp::path!("expenses" / String)
+        .and(warp::delete())
+        .and(if_match())
+        .and(user_json_body(body_limit))
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::delete_expense);
+    let history = warp::path!("expenses" / String / "history")
+        .and(warp::get())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_expense_history);
+    let purge = warp::path!("expenses" / String / "purge")
         .and(warp::post())
-        .and(json_body())
-        .and(with_client(client))
-        .and_then(handlers::create_expense)
+        .and(with_storage(storage))
+        .and_then(handlers::purge_expense);
+    create.or(get).or(update).or(delete).or(history).or(purge)
 }
 
-fn json_body() -> impl Filter<Extract = (CreateExpenseSpec,), Error = warp::Rejection> + Clone {
-    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+fn json_body(
+    body_limit: u64,
+) -> impl Filter<Extract = (CreateExpenseSpec,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(body_limit).and(warp::body::json())
 }
 
-mod handlers {
+fn update_json_body(
+    body_limit: u64,
+) -> impl Filter<Extract = (UpdateExpenseSpec,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(body_limit).and(warp::body::json())
+}
+
+fn user_json_body(
+    body_limit: u64,
+) -> impl Filter<Extract = (User,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(body_limit).and(warp::body::json())
+}
 
-    use crate::service::expense::{CreateExpenseSpec, ExpenseApiMongoAdapter, ExpensesApi};
-    use mongodb::Client;
+pub(super) mod handlers {
+    use super::{ExpenseNotDeleted, InvalidExpense, InvalidSplit};
+    use crate::route::category::InvalidCategory;
+    use crate::route::idempotency::Idempotency;
+    use crate::route::precondition::{conditional_json, versioned_json};
+    use crate::route::request::service_error;
+    use crate::service::category::{categories, validate_category};
+    use crate::service::expense::{
+        validate_cost, CreateExpenseSpec, Expense, ExpenseEntity, UpdateExpenseSpec, User,
+    };
+    use crate::service::history::ExpenseRevision;
+    use crate::service::receipt::purge;
+    use crate::service::storage::Storage;
+    use crate::service::version::Conditional;
+    use anyhow::Context;
+    use warp::http::StatusCode;
+    use warp::Reply;
 
+    #[utoipa::path(
+        post,
+        path = "/expenses",
+        tag = "expenses",
+        params(("Idempotency-Key" = Option<String>, Header, description = "Key under which retries get the response of the first request")),
+        request_body = CreateExpenseSpec,
+        responses(
+            (status = 200, description = "Created expense", body = ExpenseEntity),
+            (status = 400, description = "The cost is invalid, the line items are invalid or don't add up to the cost, or the category is unknown to the group"),
+            (status = 409, description = "Idempotency-Key used for a different or unfinished request"),
+        )
+    )]
     pub async fn create_expense(
+        idempotency: Idempotency,
         create_expense_spec: CreateExpenseSpec,
-        client: Client,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        create_expense_spec
+            .validate()
+            .map_err(|error| warp::reject::custom(InvalidExpense(error.to_string())))?;
+        if let Some(itemized) = &create_expense_spec.itemized {
+            itemized
+                .validate(Some(&create_expense_spec.cost))
+                .map_err(|error| warp::reject::custom(InvalidSplit(error.to_string())))?;
+        }
+        if let Some(category) = &create_expense_spec.category {
+            let group_id = create_expense_spec.group_id.clone();
+            check_category(&storage, Some(group_id), category).await?;
+        }
+        idempotency
+            .run("POST /expenses", create_expense_spec, |spec| async move {
+                let expense = storage
+                    .expenses
+                    .create_expense(spec)
+                    .await
+                    .context("Failed to create expense")
+                    .map_err(service_error)?;
+                Ok::<_, warp::Rejection>(warp::reply::json(&expense))
+            })
+            .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/expenses/{id}",
+        tag = "expenses",
+        params(("id" = String, Path, description = "Id of the expense")),
+        responses(
+            (status = 200, description = "Expense, with its version as ETag", body = Expense),
+            (status = 404, description = "No such expense"),
+        )
+    )]
+    pub async fn get_expense(
+        id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let expense = storage
+            .expenses
+            .get_expense(id)
+            .await
+            .context("Failed to get expense")
+            .map_err(service_error)?;
+        Ok(versioned_json(&expense, expense.version))
+    }
+
+    #[utoipa::path(
+        patch,
+        path = "/expenses/{id}",
+        tag = "expenses",
+        params(
+            ("id" = String, Path, description = "Id of the expense"),
+            ("If-Match" = String, Header, description = "ETag of the expense the update is based on, or `*`"),
+        ),
+        request_body = UpdateExpenseSpec,
+        responses(
+            (status = 200, description = "Updated expense", body = Expense),
+            (status = 400, description = "The cost is invalid, the line items are invalid, don't add up to the cost or the expense is paid by several users, or the category is unknown to the group"),
+            (status = 404, description = "No such expense"),
+            (status = 412, description = "Expense was changed since the ETag, with its current state", body = Expense),
+            (status = 428, description = "If-Match is missing"),
+        )
+    )]
+    pub async fn update_expense(
+        id: String,
+        expected_version: Option<i64>,
+        update_expense_spec: UpdateExpenseSpec,
+        storage: Storage,
     ) -> Result<impl warp::Reply, warp::Rejection> {
-        let expense = ExpenseApiMongoAdapter::new_with(client)
-            .create_expense(create_expense_spec)
+        if let Some(cost) = &update_expense_spec.cost {
+            validate_cost(cost)
+                .map_err(|error| warp::reject::custom(InvalidExpense(error.to_string())))?;
+        }
+        if let Some(itemized) = &update_expense_spec.itemized {
+            itemized
+                .validate(update_expense_spec.cost.as_deref())
+                .map_err(|error| warp::reject::custom(InvalidSplit(error.to_string())))?;
+            // the line items are split with the payer of the expense
+            let payer_id = storage
+                .expenses
+                .get_expense(id.clone())
+                .await
+                .context("Failed to get expense")
+                .map_err(service_error)?
+                .payer_id()
+                .context("Failed to find payer")
+                .map_err(service_error)?;
+            if payer_id.is_none() {
+                return Err(warp::reject::custom(InvalidSplit(
+                    "Itemized expenses must be paid by a single user".to_string(),
+                )));
+            }
+        }
+        if let Some(category) = &update_expense_spec.category {
+            let group_id = match &update_expense_spec.group_id {
+                Some(group_id) => Some(group_id.clone()),
+                None => {
+                    storage
+                        .expenses
+                        .get_expense(id.clone())
+                        .await
+                        .context("Failed to get expense")
+                        .map_err(service_error)?
+                        .group_id
+                }
+            };
+            check_category(&storage, group_id, category).await?;
+        }
+        let result = storage
+            .expenses
+            .update_expense(id, update_expense_spec, expected_version)
+            .await
+            .context("Failed to update expense")
+            .map_err(service_error)?;
+        Ok(conditional_json(result, |expense| expense.version))
+    }
+
+    #[utoipa::path(
+        delete,
+        path = "/expenses/{id}",
+        tag = "expenses",
+        params(
+            ("id" = String, Path, description = "Id of the expense"),
+            ("If-Match" = String, Header, description = "ETag of the expense the deletion is based on, or `*`"),
+        ),
+        request_body(content = User, description = "User deleting the expense"),
+        responses(
+            (status = 204, description = "Expense deleted"),
+            (status = 404, description = "No such expense"),
+            (status = 412, description = "Expense was changed since the ETag, with its current state", body = Expense),
+            (status = 428, description = "If-Match is missing"),
+        )
+    )]
+    pub async fn delete_expense(
+        id: String,
+        expected_version: Option<i64>,
+        user: User,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let result = storage
+            .expenses
+            .delete_expense(id, user, expected_version)
+            .await
+            .context("Failed to delete expense")
+            .map_err(service_error)?;
+        Ok(match result {
+            Conditional::Applied(_deleted) => StatusCode::NO_CONTENT.into_response(),
+            stale => conditional_json(stale, |expense| expense.version),
+        })
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/expenses/{id}/history",
+        tag = "expenses",
+        params(("id" = String, Path, description = "Id of the expense")),
+        responses((status = 200, description = "Revisions, oldest first", body = Vec<ExpenseRevision>))
+    )]
+    pub async fn get_expense_history(
+        id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let history = storage
+            .history
+            .get_history(id)
+            .await
+            .context("Failed to get expense history")
+            .map_err(service_error)?;
+        Ok(warp::reply::json(&history))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/expenses/{id}/purge",
+        tag = "expenses",
+        params(("id" = String, Path, description = "Id of the expense")),
+        responses(
+            (status = 204, description = "Expense removed for good with its comments, history and receipt"),
+            (status = 409, description = "The expense is not deleted"),
+        )
+    )]
+    pub async fn purge_expense(
+        id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let _purged = purge(&storage, id.clone())
+            .await
+            .context("Failed to purge expense")
+            .map_err(service_error)?
+            .ok_or_else(|| warp::reject::custom(ExpenseNotDeleted(id)))?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    /// Rejects categories which are neither default ones nor custom ones of the group.
+    async fn check_category(
+        storage: &Storage,
+        group_id: Option<String>,
+        category: &str,
+    ) -> Result<(), warp::Rejection> {
+        let categories = categories(storage, group_id)
             .await
-            .expect("Failed to create expense");
-        Ok(warp::reply::json(&expense))
+            .context("Failed to get categories")
+            .map_err(service_error)?;
+        validate_category(&categories, category)
+            .map_err(|error| warp::reject::custom(InvalidCategory(error.to_string())))
     }
 }
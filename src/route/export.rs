@@ -0,0 +1,219 @@
+use crate::route::with_storage;
+use crate::service::export::ExportRequest;
+use crate::service::storage::Storage;
+use warp::Filter;
+
+pub fn exports(
+    storage: Storage,
+    body_limit: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let group_export = warp::path!("groups" / String / "export.csv")
+        .and(warp::get())
+        .and(warp::query::<ExportRequest>())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::export_group);
+    let user_export = warp::path!("users" / String / "export.csv")
+        .and(warp::get())
+        .and(warp::query::<ExportRequest>())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::export_user);
+    let ledger_export = warp::path!("users" / String / "export.ledger")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(body_limit))
+        .and(warp::body::json())
+        .and(with_storage(storage))
+        .and_then(handlers::export_ledger);
+    group_export.or(user_export).or(ledger_export)
+}
+
+pub(super) mod handlers {
+    use crate::route::request::service_error;
+    use crate::service::export::{group_csv, user_csv, ExportRequest};
+    use crate::service::ledger::{user_ledger, LedgerRequest};
+    use crate::service::storage::Storage;
+    use anyhow::{Context, Error};
+    use std::collections::BTreeSet;
+    use std::io::{self, Write};
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::ReceiverStream;
+    use tracing::Span;
+    use warp::http::header::{HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE};
+    use warp::hyper::body::{Body, Bytes};
+    use warp::reply::Response;
+
+    /// CSV exports are sent in chunks of this size as they are written.
+    const CHUNK_SIZE: usize = 8 * 1024;
+
+    /// Chunks written but not sent yet, the writer waits for the client beyond this.
+    const PENDING_CHUNKS: usize = 4;
+
+    #[utoipa::path(
+        get,
+        path = "/groups/{group_id}/export.csv",
+        tag = "export",
+        params(("group_id" = String, Path, description = "Id of the group"), ExportRequest),
+        responses(
+            (status = 200, description = "Expenses of the group with every member's share, then the final balances", body = String, content_type = "text/csv"),
+            (status = 404, description = "No such group"),
+        )
+    )]
+    pub async fn export_group(
+        group_id: String,
+        request: ExportRequest,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let group = storage
+            .groups
+            .get_group(group_id.clone())
+            .await
+            .context("Failed to get group")
+            .map_err(service_error)?;
+        let expenses = storage
+            .expenses
+            .list_expenses(request.for_group(group_id.clone()))
+            .await
+            .context("Failed to list expenses")
+            .map_err(service_error)?;
+        let csv = streamed(move |csv| group_csv(csv, &group, &expenses.expenses));
+        Ok(attachment(
+            csv,
+            "text/csv",
+            &format!("group-{}.csv", group_id),
+        ))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/users/{user_id}/export.csv",
+        tag = "export",
+        params(("user_id" = String, Path, description = "Id of the user"), ExportRequest),
+        responses((status = 200, description = "Expenses the user has a share in, then their final balances", body = String, content_type = "text/csv"))
+    )]
+    pub async fn export_user(
+        user_id: String,
+        request: ExportRequest,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let expenses = storage
+            .expenses
+            .list_expenses(request.for_user(user_id.clone()))
+            .await
+            .context("Failed to list expenses")
+            .map_err(service_error)?;
+        let filename = format!("user-{}.csv", user_id);
+        let csv = streamed(move |csv| user_csv(csv, &user_id, &expenses.expenses));
+        Ok(attachment(csv, "text/csv", &filename))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/users/{user_id}/export.ledger",
+        tag = "export",
+        params(("user_id" = String, Path, description = "Id of the user")),
+        request_body = LedgerRequest,
+        responses((status = 200, description = "Ledger or beancount journal of the expenses and payments the user has a share in", body = String, content_type = "text/plain"))
+    )]
+    pub async fn export_ledger(
+        user_id: String,
+        request: LedgerRequest,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let range = ExportRequest {
+            dated_after: request.dated_after,
+            dated_before: request.dated_before,
+        };
+        let expenses = storage
+            .expenses
+            .list_expenses(range.for_user(user_id.clone()))
+            .await
+            .context("Failed to list expenses")
+            .map_err(service_error)?
+            .expenses;
+        let group_ids = expenses
+            .iter()
+            .filter_map(|expense| expense.group_id.clone())
+            .collect::<BTreeSet<_>>();
+        let mut groups = Vec::with_capacity(group_ids.len());
+        for group_id in group_ids {
+            // only used to name counterparties, expenses may refer to groups which are gone
+            if let Ok(group) = storage.groups.get_group(group_id).await {
+                groups.push(group);
+            }
+        }
+        let journal = user_ledger(&user_id, &expenses, &groups, &request)
+            .context("Failed to export expenses")
+            .map_err(service_error)?;
+        let filename = format!("user-{}.{}", user_id, request.format.extension());
+        Ok(attachment(journal.into(), "text/plain", &filename))
+    }
+
+    /// File to be saved as `filename` rather than shown by browsers.
+    fn attachment(body: Body, content_type: &str, filename: &str) -> Response {
+        let mut response = Response::new(body);
+        let disposition = format!("attachment; filename=\"{}\"", filename.replace('"', ""));
+        let headers = response.headers_mut();
+        for (name, value) in [
+            (CONTENT_TYPE, format!("{}; charset=utf-8", content_type)),
+            (CONTENT_DISPOSITION, disposition),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                let _previous = headers.insert(name, value);
+            }
+        }
+        response
+    }
+
+    /// Body streamed while `write` writes it on a blocking thread. The status is sent before, so
+    /// a failure is logged and aborts the body, which the client sees as a truncated response.
+    fn streamed<F>(write: F) -> Body
+    where
+        F: FnOnce(&mut Chunks) -> Result<(), Error> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(PENDING_CHUNKS);
+        let span = Span::current();
+        let _writer = tokio::task::spawn_blocking(move || {
+            let _entered = span.enter();
+            let mut chunks = Chunks {
+                buffer: Vec::with_capacity(CHUNK_SIZE),
+                sender,
+            };
+            let written = write(&mut chunks).and_then(|()| Ok(chunks.flush()?));
+            // a client which went away is no failure of the export
+            if let Err(error) = written {
+                if chunks.sender.is_closed() {
+                    return;
+                }
+                tracing::error!("{:#}", error.context("Failed to export expenses"));
+                let aborted = io::Error::other("Failed to export expenses");
+                let _closed = chunks.sender.blocking_send(Err(aborted));
+            }
+        });
+        Body::wrap_stream(ReceiverStream::new(receiver))
+    }
+
+    /// Sends what is written to the body in chunks of [`CHUNK_SIZE`].
+    struct Chunks {
+        buffer: Vec<u8>,
+        sender: mpsc::Sender<Result<Bytes, io::Error>>,
+    }
+
+    impl Write for Chunks {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.buffer.extend_from_slice(bytes);
+            if self.buffer.len() >= CHUNK_SIZE {
+                self.flush()?;
+            }
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            if self.buffer.is_empty() {
+                return Ok(());
+            }
+            let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(CHUNK_SIZE));
+            self.sender
+                .blocking_send(Ok(chunk.into()))
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Client went away"))
+        }
+    }
+}
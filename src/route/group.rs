@@ -1,34 +1,134 @@
-use crate::route::with_client;
-use crate::service::group::CreateGroupSpec;
-use mongodb::Client;
+use crate::route::idempotency::idempotency;
+use crate::route::precondition::optional_if_match;
+use crate::route::with_storage;
+use crate::service::group::{CreateGroupSpec, GroupUser};
+use crate::service::storage::Storage;
+use std::time::Duration;
 use warp::Filter;
 
 pub fn groups(
-    client: Client,
+    storage: Storage,
+    body_limit: u64,
+    idempotency_ttl: Duration,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("groups")
+    let create = warp::path!("groups")
         .and(warp::post())
-        .and(json_body())
-        .and(with_client(client))
-        .and_then(handlers::create_group)
+        .and(idempotency(storage.clone(), idempotency_ttl))
+        .and(json_body(body_limit))
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::create_group);
+    let get = warp::path!("groups" / String)
+        .and(warp::get())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_group);
+    let add_member = warp::path!("groups" / String / "members")
+        .and(warp::post())
+        .and(optional_if_match())
+        .and(member_json_body(body_limit))
+        .and(with_storage(storage))
+        .and_then(handlers::add_member);
+    create.or(get).or(add_member)
+}
+
+fn json_body(
+    body_limit: u64,
+) -> impl Filter<Extract = (CreateGroupSpec,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(body_limit).and(warp::body::json())
 }
 
-fn json_body() -> impl Filter<Extract = (CreateGroupSpec,), Error = warp::Rejection> + Clone {
-    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+fn member_json_body(
+    body_limit: u64,
+) -> impl Filter<Extract = (GroupUser,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(body_limit).and(warp::body::json())
 }
 
-mod handlers {
-    use crate::service::group::{CreateGroupSpec, GroupApi, GroupApiMongoAdapter};
-    use mongodb::Client;
+pub(super) mod handlers {
+    use crate::route::idempotency::Idempotency;
+    use crate::route::precondition::{conditional_json, versioned_json};
+    use crate::route::request::service_error;
+    use crate::service::group::{CreateGroupSpec, Group, GroupUser};
+    use crate::service::storage::Storage;
+    use anyhow::Context;
 
+    #[utoipa::path(
+        post,
+        path = "/groups",
+        tag = "groups",
+        params(("Idempotency-Key" = Option<String>, Header, description = "Key under which retries get the response of the first request")),
+        request_body = CreateGroupSpec,
+        responses(
+            (status = 200, description = "Created group", body = Group),
+            (status = 409, description = "Idempotency-Key used for a different or unfinished request"),
+        )
+    )]
     pub async fn create_group(
+        idempotency: Idempotency,
         create_group_spec: CreateGroupSpec,
-        client: Client,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        idempotency
+            .run("POST /groups", create_group_spec, |spec| async move {
+                let group = storage
+                    .groups
+                    .create_group(spec)
+                    .await
+                    .context("Failed to create group")
+                    .map_err(service_error)?;
+                Ok::<_, warp::Rejection>(warp::reply::json(&group))
+            })
+            .await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/groups/{id}",
+        tag = "groups",
+        params(("id" = String, Path, description = "Id of the group")),
+        responses(
+            (status = 200, description = "Group, with its version as ETag", body = Group),
+            (status = 404, description = "No such group"),
+        )
+    )]
+    pub async fn get_group(
+        id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let group = storage
+            .groups
+            .get_group(id)
+            .await
+            .context("Failed to get group")
+            .map_err(service_error)?;
+        Ok(versioned_json(&group, group.version))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/groups/{group_id}/members",
+        tag = "groups",
+        params(
+            ("group_id" = String, Path, description = "Id of the group"),
+            ("If-Match" = Option<String>, Header, description = "ETag of the group the change is based on, added unconditionally if missing"),
+        ),
+        request_body = GroupUser,
+        responses(
+            (status = 200, description = "Group with the new member", body = Group),
+            (status = 404, description = "No such group"),
+            (status = 412, description = "Group was changed since the ETag, with its current state", body = Group),
+        )
+    )]
+    pub async fn add_member(
+        group_id: String,
+        expected_version: Option<i64>,
+        group_user: GroupUser,
+        storage: Storage,
     ) -> Result<impl warp::Reply, warp::Rejection> {
-        let group = GroupApiMongoAdapter::new_with(client)
-            .create_group(create_group_spec)
+        let result = storage
+            .groups
+            .add_member(group_id, group_user, expected_version)
             .await
-            .expect("Failed to create group");
-        Ok(warp::reply::json(&group))
+            .context("Failed to add member")
+            .map_err(service_error)?;
+        Ok(conditional_json(result, |group| group.version))
     }
 }
@@ -0,0 +1,62 @@
+use crate::route::with_storage;
+use crate::service::storage::Storage;
+use warp::Filter;
+
+pub fn health(
+    storage: Storage,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    // `/health` is kept for probes configured before the split
+    let live = warp::path!("health" / "live")
+        .or(warp::path!("health"))
+        .unify()
+        .and(warp::get())
+        .map(handlers::live);
+    let ready = warp::path!("health" / "ready")
+        .and(warp::get())
+        .and(with_storage(storage))
+        .and_then(handlers::ready);
+    live.or(ready)
+}
+
+pub(super) mod handlers {
+    use crate::service::storage::Storage;
+    use std::time::Duration;
+    use warp::http::StatusCode;
+
+    /// How long the database may take to answer before the server is reported as not ready.
+    const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+    #[utoipa::path(
+        get,
+        path = "/health/live",
+        tag = "health",
+        responses((status = 200, description = "Server is running"))
+    )]
+    pub fn live() -> impl warp::Reply {
+        StatusCode::OK
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/health/ready",
+        tag = "health",
+        responses(
+            (status = 200, description = "Database answers"),
+            (status = 503, description = "Database is unavailable"),
+        )
+    )]
+    pub async fn ready(storage: Storage) -> Result<impl warp::Reply, warp::Rejection> {
+        let status = match tokio::time::timeout(PING_TIMEOUT, storage.health.ping()).await {
+            Ok(Ok(())) => StatusCode::OK,
+            Ok(Err(error)) => {
+                tracing::warn!("Database is not ready: {:?}", error);
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Err(_) => {
+                tracing::warn!("Database did not answer within {:?}", PING_TIMEOUT);
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        };
+        Ok(status)
+    }
+}
@@ -0,0 +1,223 @@
+use crate::route::request::service_error;
+use crate::service::idempotency::{IdempotencyRecord, StoredResponse};
+use crate::service::storage::Storage;
+use anyhow::Context;
+use chrono::Utc;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use warp::http::header::CONTENT_TYPE;
+use warp::http::{HeaderValue, StatusCode};
+use warp::reject::Reject;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Set on a response which was stored for an earlier request with the same key.
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// The `Idempotency-Key` header is empty or too long.
+#[derive(Debug)]
+pub struct InvalidIdempotencyKey;
+
+impl Reject for InvalidIdempotencyKey {}
+
+impl fmt::Display for InvalidIdempotencyKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Idempotency-Key must have 1 to {} characters",
+            MAX_KEY_LENGTH
+        )
+    }
+}
+
+/// The `Idempotency-Key` is used by another request.
+#[derive(Debug, Clone, Copy)]
+pub enum IdempotencyConflict {
+    /// The key was used for a request with a different route or body.
+    Reused,
+    /// The request the key was used for is still being handled.
+    InProgress,
+}
+
+impl Reject for IdempotencyConflict {}
+
+impl fmt::Display for IdempotencyConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdempotencyConflict::Reused => {
+                write!(
+                    f,
+                    "Idempotency-Key was already used for a different request"
+                )
+            }
+            IdempotencyConflict::InProgress => {
+                write!(
+                    f,
+                    "Request with this Idempotency-Key is still being handled"
+                )
+            }
+        }
+    }
+}
+
+/// `Idempotency-Key` of a create request. Retries with the same key get the response of the
+/// first request instead of creating a duplicate.
+#[derive(Debug, Clone)]
+pub struct Idempotency {
+    key: Option<String>,
+
+    /// How long the response is kept for retries.
+    ttl: Duration,
+
+    storage: Storage,
+}
+
+pub fn idempotency(
+    storage: Storage,
+    ttl: Duration,
+) -> impl Filter<Extract = (Idempotency,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(IDEMPOTENCY_KEY_HEADER).and_then(move |key: Option<String>| {
+        let storage = storage.clone();
+        async move {
+            match key {
+                Some(key) if key.is_empty() || key.len() > MAX_KEY_LENGTH => {
+                    Err(warp::reject::custom(InvalidIdempotencyKey))
+                }
+                key => Ok(Idempotency { key, ttl, storage }),
+            }
+        }
+    })
+}
+
+impl Idempotency {
+    /// Handles `request` with `handle` unless the key was used before. A retry of the same request
+    /// gets the stored response, a different request with the same key is rejected.
+    pub async fn run<T, F, Fut, R>(
+        self,
+        route: &str,
+        request: T,
+        handle: F,
+    ) -> Result<Response, Rejection>
+    where
+        T: Serialize,
+        F: FnOnce(T) -> Fut,
+        Fut: Future<Output = Result<R, Rejection>>,
+        R: Reply,
+    {
+        let Some(key) = self.key else {
+            return handle(request).await.map(Reply::into_response);
+        };
+        let request_hash = request_hash(route, &request);
+        let ttl = chrono::Duration::from_std(self.ttl).expect("Idempotency TTL is out of range");
+        let record = self
+            .storage
+            .idempotency
+            .reserve(key.clone(), request_hash.clone(), Utc::now() + ttl)
+            .await
+            .context("Failed to reserve idempotency key")
+            .map_err(service_error)?;
+        match record {
+            Some(record) if record.request_hash != request_hash => {
+                Err(warp::reject::custom(IdempotencyConflict::Reused))
+            }
+            Some(IdempotencyRecord {
+                response: Some(response),
+                ..
+            }) => Ok(replay(response)),
+            Some(_) => Err(warp::reject::custom(IdempotencyConflict::InProgress)),
+            None => {
+                let mut reservation = Reservation {
+                    storage: self.storage.clone(),
+                    key: Some(key),
+                };
+                let response = handle(request).await?.into_response();
+                let (parts, body) = response.into_parts();
+                let body = warp::hyper::body::to_bytes(body)
+                    .await
+                    .context("Failed to read response")
+                    .map_err(service_error)?;
+                let stored = StoredResponse {
+                    status: parts.status.as_u16(),
+                    body: String::from_utf8_lossy(&body).into_owned(),
+                };
+                if let Some(key) = reservation.key.take() {
+                    self.storage
+                        .idempotency
+                        .complete(key, stored)
+                        .await
+                        .context("Failed to store idempotent response")
+                        .map_err(service_error)?;
+                }
+                Ok(Response::from_parts(parts, body.into()))
+            }
+        }
+    }
+}
+
+/// Hash of the route and the JSON of the request, so that a key can't be reused for another route.
+fn request_hash<T: Serialize>(route: &str, request: &T) -> String {
+    let body = serde_json::to_vec(request).expect("Failed to serialize request");
+    let hash = Sha256::new()
+        .chain_update(route.as_bytes())
+        .chain_update(b"\n")
+        .chain_update(body)
+        .finalize();
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn replay(response: StoredResponse) -> Response {
+    let mut reply = Response::new(response.body.into());
+    *reply.status_mut() =
+        StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let headers = reply.headers_mut();
+    let _previous = headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    let _previous = headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    reply
+}
+
+/// Key reserved for a request. Released if the request is rejected or its handler panics, so
+/// that the request can be retried with the same key.
+struct Reservation {
+    storage: Storage,
+
+    key: Option<String>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let storage = self.storage.clone();
+            let _release = tokio::spawn(async move {
+                if let Err(error) = storage.idempotency.release(key).await {
+                    tracing::warn!("Failed to release idempotency key: {:?}", error);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::request_hash;
+
+    #[test]
+    fn hash_route_and_body() {
+        let hash = request_hash("POST /groups", &serde_json::json!({"name": "Trip"}));
+        assert_eq!(hash.len(), 64);
+        assert_eq!(
+            hash,
+            request_hash("POST /groups", &serde_json::json!({"name": "Trip"}))
+        );
+        assert_ne!(
+            hash,
+            request_hash("POST /expenses", &serde_json::json!({"name": "Trip"}))
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::route::with_storage;
+use crate::service::import::ImportRequest;
+use crate::service::storage::Storage;
+use std::fmt;
+use warp::reject::Reject;
+use warp::Filter;
+
+/// The imported CSV can't be read, or its header lacks a mapped column.
+#[derive(Debug)]
+pub struct InvalidCsv(String);
+
+impl Reject for InvalidCsv {}
+
+impl fmt::Display for InvalidCsv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid CSV: {}", self.0)
+    }
+}
+
+/// The Splitwise export can't be read, or has shares of users who are not members of the group.
+#[derive(Debug)]
+pub struct InvalidSplitwiseExport(String);
+
+impl Reject for InvalidSplitwiseExport {}
+
+impl fmt::Display for InvalidSplitwiseExport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid Splitwise export: {}", self.0)
+    }
+}
+
+pub fn imports(
+    storage: Storage,
+    import_limit: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let csv = warp::path!("groups" / String / "import")
+        .and(warp::post())
+        .and(warp::query::<ImportRequest>())
+        .and(warp::body::content_length_limit(import_limit))
+        .and(warp::body::bytes())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::import_expenses);
+    let splitwise = warp::path!("import" / "splitwise")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(import_limit))
+        .and(warp::body::json())
+        .and(with_storage(storage))
+        .and_then(handlers::import_splitwise);
+    csv.or(splitwise)
+}
+
+pub(super) mod handlers {
+    use super::{InvalidCsv, InvalidSplitwiseExport};
+    use crate::route::request::service_error;
+    use crate::service::expense::ListExpensesRequest;
+    use crate::service::import::{balances, read_rows, ImportReport, ImportRequest};
+    use crate::service::splitwise::{import, SplitwiseExport, SplitwiseImportReport};
+    use crate::service::storage::Storage;
+    use anyhow::Context;
+    use warp::http::StatusCode;
+    use warp::hyper::body::Bytes;
+
+    #[utoipa::path(
+        post,
+        path = "/groups/{group_id}/import",
+        tag = "import",
+        params(("group_id" = String, Path, description = "Id of the group"), ImportRequest),
+        request_body(content = String, description = "CSV with a header and an expense per row", content_type = "text/csv"),
+        responses(
+            (status = 200, description = "Every row is valid, the expenses were stored unless it's a dry run", body = ImportReport),
+            (status = 400, description = "The CSV can't be read or lacks a mapped column"),
+            (status = 422, description = "Some rows are invalid, nothing was stored", body = ImportReport),
+        )
+    )]
+    pub async fn import_expenses(
+        group_id: String,
+        request: ImportRequest,
+        body: Bytes,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let csv = String::from_utf8(body.to_vec())
+            .map_err(|error| warp::reject::custom(InvalidCsv(error.to_string())))?;
+        let group = storage
+            .groups
+            .get_group(group_id.clone())
+            .await
+            .context("Failed to get group")
+            .map_err(service_error)?;
+        let mut rows = read_rows(&group, &request, &csv)
+            .map_err(|error| warp::reject::custom(InvalidCsv(error.to_string())))?;
+        let mut expenses = storage
+            .expenses
+            .list_expenses(ListExpensesRequest {
+                group_id: Some(group_id),
+                ..ListExpensesRequest::default()
+            })
+            .await
+            .context("Failed to list expenses")
+            .map_err(service_error)?
+            .expenses;
+        expenses.extend(rows.iter().filter_map(|row| row.expense.clone()));
+        let balances = balances(&group, &expenses)
+            .context("Failed to compute balances")
+            .map_err(service_error)?;
+
+        let valid = rows.iter().all(|row| row.errors.is_empty());
+        let committed = valid && !request.dry_run();
+        if committed {
+            let imported = storage
+                .expenses
+                .import_expenses(rows.iter().filter_map(|row| row.expense.clone()).collect())
+                .await
+                .context("Failed to import expenses")
+                .map_err(service_error)?;
+            for (row, entity) in rows.iter_mut().zip(imported) {
+                row.id = entity.id.map(|id| id.to_hex());
+            }
+        }
+        let report = ImportReport {
+            committed,
+            rows,
+            balances,
+        };
+        let status = if valid {
+            StatusCode::OK
+        } else {
+            StatusCode::UNPROCESSABLE_ENTITY
+        };
+        Ok(warp::reply::with_status(warp::reply::json(&report), status))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/import/splitwise",
+        tag = "import",
+        request_body = SplitwiseExport,
+        responses(
+            (status = 200, description = "Created group with the users it was mapped to and the balances which differ from Splitwise's", body = SplitwiseImportReport),
+            (status = 400, description = "The export can't be read or has shares of users who are not members"),
+        )
+    )]
+    pub async fn import_splitwise(
+        export: SplitwiseExport,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let dump = export
+            .into_dump()
+            .map_err(|error| warp::reject::custom(InvalidSplitwiseExport(error.to_string())))?;
+        let report = import(&storage, dump)
+            .await
+            .context("Failed to import Splitwise export")
+            .map_err(service_error)?;
+        Ok(warp::reply::json(&report))
+    }
+}
@@ -0,0 +1,32 @@
+use warp::Filter;
+
+pub fn metrics() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("metrics")
+        .and(warp::get())
+        .and_then(handlers::export)
+}
+
+pub(super) mod handlers {
+    use crate::metrics::metrics;
+    use warp::http::header::CONTENT_TYPE;
+    use warp::http::StatusCode;
+    use warp::Reply;
+
+    #[utoipa::path(
+        get,
+        path = "/metrics",
+        tag = "metrics",
+        responses((status = 200, description = "Metrics in the Prometheus text format", content_type = "text/plain"))
+    )]
+    pub async fn export() -> Result<impl warp::Reply, warp::Rejection> {
+        let response = match metrics().encode() {
+            Ok(body) => warp::reply::with_header(body, CONTENT_TYPE, prometheus::TEXT_FORMAT)
+                .into_response(),
+            Err(error) => {
+                tracing::error!("Failed to encode metrics: {:?}", error);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+        Ok(response)
+    }
+}
@@ -1,26 +1,97 @@
+mod account;
+mod activity;
+mod category;
+mod comment;
+mod events;
 mod expense;
+mod export;
 mod group;
+mod health;
+mod idempotency;
+mod import;
+mod metrics;
+mod openapi;
+mod precondition;
+mod receipt;
+mod report;
+mod request;
 
-use mongodb::Client;
+use crate::config::ServerConfig;
+use crate::metrics::metrics;
+use crate::service::storage::Storage;
+use std::convert::Infallible;
+use std::time::Duration;
 use warp::Filter;
 
+/// All routes, each request handled in a span with its `X-Request-Id`. Rejections are turned into
+/// JSON error responses carrying the request id.
 pub fn routes(
-    client: Client,
+    storage: Storage,
+    config: &ServerConfig,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    group::groups(client.clone())
-        .or(expense::expenses(client))
-        .or(health())
+    let idempotency_ttl = Duration::from_secs(config.idempotency_ttl);
+    let api = group::groups(storage.clone(), config.body_limit, idempotency_ttl)
+        .or(expense::expenses(
+            storage.clone(),
+            config.body_limit,
+            idempotency_ttl,
+        ))
+        .or(comment::comments(storage.clone(), config.body_limit))
+        .or(category::categories(storage.clone(), config.body_limit))
+        .or(activity::activity(storage.clone()))
+        .or(report::reports(
+            storage.clone(),
+            config.exchange_rates.clone(),
+        ))
+        .or(events::events(storage.clone()))
+        .or(export::exports(storage.clone(), config.body_limit))
+        .or(import::imports(storage.clone(), config.import_limit))
+        .or(account::accounts(storage.clone()))
+        .or(receipt::receipts(storage.clone(), config.receipt_limit))
+        .or(health::health(storage))
+        .or(metrics::metrics())
+        .or(openapi::openapi(config.swagger_ui))
+        .map(|reply| Ok(warp::Reply::into_response(reply)))
+        .or_else(|rejection| async move { Ok::<_, Infallible>((Err(rejection),)) });
+    request::request_id()
+        .and(api)
+        .map(request::respond)
+        .with(cors(config))
+        .with(warp::log::custom(|info| {
+            metrics().record_request(
+                info.method().as_str(),
+                info.path(),
+                info.status().as_u16(),
+                info.elapsed(),
+            )
+        }))
+        .with(warp::trace(request::span))
 }
 
-fn health() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("health")
-        .and(warp::get())
-        .map(|| warp::http::StatusCode::OK)
-        .with(warp::cors().allow_any_origin())
+/// Allows the configured origins, or any origin if none are configured.
+fn cors(config: &ServerConfig) -> warp::cors::Builder {
+    let cors = warp::cors()
+        .allow_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
+        .allow_headers(vec![
+            "content-type",
+            request::REQUEST_ID_HEADER,
+            idempotency::IDEMPOTENCY_KEY_HEADER,
+            precondition::IF_MATCH_HEADER,
+        ])
+        .expose_headers(vec![
+            request::REQUEST_ID_HEADER,
+            idempotency::IDEMPOTENT_REPLAYED_HEADER,
+            "etag",
+        ]);
+    if config.cors_origins.is_empty() {
+        cors.allow_any_origin()
+    } else {
+        cors.allow_origins(config.cors_origins.iter().map(String::as_str))
+    }
 }
 
-fn with_client(
-    client: Client,
-) -> impl Filter<Extract = (Client,), Error = std::convert::Infallible> + Clone {
-    warp::any().map(move || client.clone())
+fn with_storage(
+    storage: Storage,
+) -> impl Filter<Extract = (Storage,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || storage.clone())
 }
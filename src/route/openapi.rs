@@ -0,0 +1,176 @@
+use crate::route::request::ErrorResponse;
+use crate::route::{
+    account, activity, category, comment, events, expense, export, group, health, import, metrics,
+    receipt, report,
+};
+use utoipa::openapi::path::Operation;
+use utoipa::openapi::{Deprecated, RefOr, Response};
+use utoipa::{Modify, OpenApi};
+use warp::Filter;
+
+/// OpenAPI document of every route, the schemas are derived from the serde types of the services.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "swc", description = "Shared expenses of groups of users"),
+    paths(
+        group::handlers::create_group,
+        group::handlers::get_group,
+        group::handlers::add_member,
+        expense::handlers::create_expense,
+        expense::handlers::get_expense,
+        expense::handlers::update_expense,
+        expense::handlers::delete_expense,
+        expense::handlers::get_expense_history,
+        expense::handlers::purge_expense,
+        receipt::handlers::upload_receipt,
+        receipt::handlers::get_receipt,
+        receipt::handlers::get_receipt_thumbnail,
+        receipt::handlers::delete_receipt,
+        category::handlers::get_categories,
+        category::handlers::create_category,
+        comment::handlers::get_comments,
+        comment::handlers::create_comment,
+        comment::handlers::delete_comment,
+        activity::handlers::get_group_activity,
+        activity::handlers::get_user_activity,
+        activity::handlers::mark_read,
+        report::handlers::get_group_report,
+        report::handlers::get_user_summary,
+        events::handlers::group_events,
+        export::handlers::export_group,
+        export::handlers::export_user,
+        export::handlers::export_ledger,
+        import::handlers::import_expenses,
+        import::handlers::import_splitwise,
+        account::handlers::export_account,
+        account::handlers::delete_account,
+        health::handlers::live,
+        health::handlers::ready,
+        metrics::handlers::export,
+        handlers::openapi_json,
+        handlers::swagger_ui,
+    ),
+    components(schemas(ErrorResponse)),
+    modifiers(&ErrorResponses, &LegacyHealth)
+)]
+struct ApiDoc;
+
+/// Rejections are answered with an [`ErrorResponse`] by every route.
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for operation in operations(openapi) {
+            let _previous = operation.responses.responses.insert(
+                "default".to_string(),
+                RefOr::T(
+                    Response::builder()
+                        .description("Error, with the request id to quote when reporting it")
+                        .content(
+                            "application/json",
+                            utoipa::openapi::Content::new(Some(RefOr::Ref(
+                                utoipa::openapi::Ref::from_schema_name("ErrorResponse"),
+                            ))),
+                        )
+                        .build(),
+                ),
+            );
+        }
+    }
+}
+
+/// `/health` answers like `/health/live`, kept for probes configured before the split.
+struct LegacyHealth;
+
+impl Modify for LegacyHealth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let Some(mut item) = openapi.paths.paths.get("/health/live").cloned() else {
+            return;
+        };
+        if let Some(operation) = item.get.as_mut() {
+            operation.operation_id = Some("legacy_live".to_string());
+            operation.deprecated = Some(Deprecated::True);
+        }
+        let _previous = openapi.paths.paths.insert("/health".to_string(), item);
+    }
+}
+
+fn operations(openapi: &mut utoipa::openapi::OpenApi) -> impl Iterator<Item = &mut Operation> {
+    openapi.paths.paths.values_mut().flat_map(|item| {
+        [
+            item.get.as_mut(),
+            item.post.as_mut(),
+            item.put.as_mut(),
+            item.patch.as_mut(),
+            item.delete.as_mut(),
+        ]
+        .into_iter()
+        .flatten()
+    })
+}
+
+/// Serves the document at `/openapi.json`, and Swagger UI at `/docs` if `swagger_ui` is set.
+pub fn openapi(
+    swagger_ui: bool,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let document = warp::path!("openapi.json")
+        .and(warp::get())
+        .map(handlers::openapi_json);
+    let docs = warp::path!("docs")
+        .and(warp::get())
+        .and_then(move || async move {
+            if swagger_ui {
+                Ok(handlers::swagger_ui())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        });
+    document.or(docs)
+}
+
+pub(super) mod handlers {
+    use super::ApiDoc;
+    use utoipa::OpenApi;
+
+    /// The OpenAPI document of the API.
+    #[utoipa::path(
+        get,
+        path = "/openapi.json",
+        tag = "docs",
+        responses((status = 200, description = "OpenAPI 3 document", content_type = "application/json"))
+    )]
+    pub fn openapi_json() -> impl warp::Reply {
+        warp::reply::json(&ApiDoc::openapi())
+    }
+
+    /// Swagger UI for the OpenAPI document, only served if enabled in the configuration.
+    #[utoipa::path(
+        get,
+        path = "/docs",
+        tag = "docs",
+        responses((status = 200, description = "Swagger UI", content_type = "text/html"))
+    )]
+    pub fn swagger_ui() -> impl warp::Reply {
+        warp::reply::html(SWAGGER_UI)
+    }
+
+    /// Loads Swagger UI from a CDN rather than bundling its assets into the binary.
+    const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>swc API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
+}
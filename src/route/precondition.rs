@@ -0,0 +1,112 @@
+use crate::service::version::{Conditional, UNVERSIONED};
+use serde::Serialize;
+use std::fmt;
+use warp::http::header::ETAG;
+use warp::http::{HeaderValue, StatusCode};
+use warp::reject::Reject;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+pub const IF_MATCH_HEADER: &str = "if-match";
+
+/// `If-Match` is required to change the resource but missing.
+#[derive(Debug)]
+pub struct MissingIfMatch;
+
+impl Reject for MissingIfMatch {}
+
+impl fmt::Display for MissingIfMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "If-Match with the ETag of the resource is required")
+    }
+}
+
+/// `If-Match` is neither `*` nor a single ETag sent by this server.
+#[derive(Debug)]
+pub struct InvalidIfMatch;
+
+impl Reject for InvalidIfMatch {}
+
+impl fmt::Display for InvalidIfMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "If-Match must be `*` or the ETag of the resource")
+    }
+}
+
+/// Version the resource is expected at from the `If-Match` header, which must be present.
+/// `If-Match: *` matches any version and extracts `None`.
+pub fn if_match() -> impl Filter<Extract = (Option<i64>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(IF_MATCH_HEADER).and_then(|value: Option<String>| async move {
+        match value {
+            Some(value) => parse(&value).map_err(warp::reject::custom),
+            None => Err(warp::reject::custom(MissingIfMatch)),
+        }
+    })
+}
+
+/// Like [`if_match`], but changes without `If-Match` are made unconditionally.
+pub fn optional_if_match() -> impl Filter<Extract = (Option<i64>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(IF_MATCH_HEADER).and_then(|value: Option<String>| async move {
+        match value {
+            Some(value) => parse(&value).map_err(warp::reject::custom),
+            None => Ok::<_, Rejection>(None),
+        }
+    })
+}
+
+fn parse(value: &str) -> Result<Option<i64>, InvalidIfMatch> {
+    let value = value.trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .and_then(|version| version.parse().ok())
+        .map(Some)
+        .ok_or(InvalidIfMatch)
+}
+
+/// Strong ETag of the resource at `version`.
+pub fn etag(version: Option<i64>) -> String {
+    format!("\"{}\"", version.unwrap_or(UNVERSIONED))
+}
+
+/// JSON of the resource with its version as `ETag`.
+pub fn versioned_json<T: Serialize>(resource: &T, version: Option<i64>) -> Response {
+    let mut response = warp::reply::json(resource).into_response();
+    if let Ok(etag) = HeaderValue::from_str(&etag(version)) {
+        let _previous = response.headers_mut().insert(ETAG, etag);
+    }
+    response
+}
+
+/// The changed resource, or `412 Precondition Failed` with the current one if it was changed
+/// since the version in `If-Match`.
+pub fn conditional_json<T: Serialize>(
+    result: Conditional<T>,
+    version: impl Fn(&T) -> Option<i64>,
+) -> Response {
+    match result {
+        Conditional::Applied(resource) => versioned_json(&resource, version(&resource)),
+        Conditional::Stale(current) => {
+            let mut response = versioned_json(&current, version(&current));
+            *response.status_mut() = StatusCode::PRECONDITION_FAILED;
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{etag, parse};
+
+    #[test]
+    fn parse_if_match() {
+        assert_eq!(parse(&etag(Some(3))).unwrap(), Some(3));
+        assert_eq!(parse("*").unwrap(), None);
+        assert!(parse("3").is_err());
+        assert!(parse("W/\"3\"").is_err());
+        assert!(parse("\"1\", \"2\"").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::route::precondition::optional_if_match;
+use crate::route::with_storage;
+use crate::service::storage::Storage;
+use std::fmt;
+use warp::reject::Reject;
+use warp::Filter;
+
+/// The upload has no `file` part, or it can't be read.
+#[derive(Debug)]
+pub struct InvalidReceipt(String);
+
+impl Reject for InvalidReceipt {}
+
+impl fmt::Display for InvalidReceipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid receipt: {}", self.0)
+    }
+}
+
+/// The uploaded file is not a JPEG, PNG or PDF, or the image can't be decoded.
+#[derive(Debug)]
+pub struct UnsupportedReceipt(String);
+
+impl Reject for UnsupportedReceipt {}
+
+impl fmt::Display for UnsupportedReceipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unsupported receipt: {}", self.0)
+    }
+}
+
+pub fn receipts(
+    storage: Storage,
+    receipt_limit: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let upload = warp::path!("expenses" / String / "receipt")
+        .and(warp::post())
+        .and(optional_if_match())
+        .and(warp::multipart::form().max_length(receipt_limit))
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::upload_receipt);
+    let download = warp::path!("expenses" / String / "receipt")
+        .and(warp::get())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_receipt);
+    let thumbnail = warp::path!("expenses" / String / "receipt" / "thumbnail")
+        .and(warp::get())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_receipt_thumbnail);
+    let delete = warp::path!("expenses" / String / "receipt")
+        .and(warp::delete())
+        .and(optional_if_match())
+        .and(with_storage(storage))
+        .and_then(handlers::delete_receipt);
+    upload.or(download).or(thumbnail).or(delete)
+}
+
+pub(super) mod handlers {
+    use super::{InvalidReceipt, UnsupportedReceipt};
+    use crate::route::precondition::conditional_json;
+    use crate::route::request::{service_error, NotFound};
+    use crate::service::blob::{validate_key, Blob};
+    use crate::service::expense::Expense;
+    use crate::service::receipt::{
+        attach, detach, receipt_key, thumbnail_key, ReceiptFile, ReceiptUpload,
+    };
+    use crate::service::storage::Storage;
+    use crate::service::version::Conditional;
+    use anyhow::Context;
+    use tokio_stream::StreamExt;
+    use warp::http::header::CONTENT_TYPE;
+    use warp::http::StatusCode;
+    use warp::hyper::body::Buf;
+    use warp::multipart::FormData;
+    use warp::Reply;
+
+    #[utoipa::path(
+        post,
+        path = "/expenses/{id}/receipt",
+        tag = "receipts",
+        params(
+            ("id" = String, Path, description = "Id of the expense"),
+            ("If-Match" = Option<String>, Header, description = "ETag of the expense the receipt is attached to, attached unconditionally if missing"),
+        ),
+        request_body(content = ReceiptUpload, content_type = "multipart/form-data"),
+        responses(
+            (status = 200, description = "Expense with the receipt attached, replacing the previous one", body = Expense),
+            (status = 400, description = "The form has no `file` part"),
+            (status = 413, description = "The upload is larger than the receipt limit"),
+            (status = 404, description = "No such expense"),
+            (status = 412, description = "Expense was changed since the ETag, with its current state", body = Expense),
+            (status = 415, description = "The file is not a JPEG, PNG or PDF"),
+        )
+    )]
+    pub async fn upload_receipt(
+        id: String,
+        expected_version: Option<i64>,
+        mut form: FormData,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let invalid = |error: warp::Error| warp::reject::custom(InvalidReceipt(error.to_string()));
+        let mut upload = None;
+        while let Some(part) = form.next().await {
+            let mut part = part.map_err(invalid)?;
+            if part.name() != "file" {
+                continue;
+            }
+            let filename = part.filename().map(String::from);
+            let mut data = Vec::new();
+            while let Some(chunk) = part.data().await {
+                data.extend_from_slice(chunk.map_err(invalid)?.chunk());
+            }
+            upload = Some((filename, data));
+        }
+        let (filename, data) = upload
+            .ok_or_else(|| warp::reject::custom(InvalidReceipt("missing `file` part".into())))?;
+        let file = ReceiptFile::new(filename, data)
+            .map_err(|error| warp::reject::custom(UnsupportedReceipt(error.to_string())))?;
+        let result = attach(&storage, id, file, expected_version)
+            .await
+            .context("Failed to attach receipt")
+            .map_err(service_error)?;
+        Ok(conditional_json(result, |expense| expense.version))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/expenses/{id}/receipt",
+        tag = "receipts",
+        params(("id" = String, Path, description = "Id of the expense")),
+        responses(
+            (status = 200, description = "Receipt as uploaded, a JPEG, PNG or PDF file", body = Vec<u8>, content_type = "application/octet-stream"),
+            (status = 404, description = "The expense has no receipt"),
+        )
+    )]
+    pub async fn get_receipt(
+        id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let missing = format!("Expense {} has no receipt", id);
+        blob(&storage, receipt_key(&id), missing).await
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/expenses/{id}/receipt/thumbnail",
+        tag = "receipts",
+        params(("id" = String, Path, description = "Id of the expense")),
+        responses(
+            (status = 200, description = "Thumbnail of the receipt", body = Vec<u8>, content_type = "image/jpeg"),
+            (status = 404, description = "The expense has no receipt, or it is not an image"),
+        )
+    )]
+    pub async fn get_receipt_thumbnail(
+        id: String,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let missing = format!("Expense {} has no receipt thumbnail", id);
+        blob(&storage, thumbnail_key(&id), missing).await
+    }
+
+    #[utoipa::path(
+        delete,
+        path = "/expenses/{id}/receipt",
+        tag = "receipts",
+        params(
+            ("id" = String, Path, description = "Id of the expense"),
+            ("If-Match" = Option<String>, Header, description = "ETag of the expense the receipt is removed from, removed unconditionally if missing"),
+        ),
+        responses(
+            (status = 204, description = "Receipt removed"),
+            (status = 404, description = "No such expense"),
+            (status = 412, description = "Expense was changed since the ETag, with its current state", body = Expense),
+        )
+    )]
+    pub async fn delete_receipt(
+        id: String,
+        expected_version: Option<i64>,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let result = detach(&storage, id, expected_version)
+            .await
+            .context("Failed to remove receipt")
+            .map_err(service_error)?;
+        Ok(match result {
+            Conditional::Applied(_detached) => StatusCode::NO_CONTENT.into_response(),
+            stale => conditional_json(stale, |expense| expense.version),
+        })
+    }
+
+    /// Content of the blob under `key`, rejected as not found with the `missing` message.
+    async fn blob(
+        storage: &Storage,
+        key: String,
+        missing: String,
+    ) -> Result<warp::reply::Response, warp::Rejection> {
+        // ids which can't be keys are no expense ids either
+        if validate_key(&key).is_err() {
+            return Err(warp::reject::custom(NotFound(missing)));
+        }
+        let Blob { content_type, data } = storage
+            .blobs
+            .get_blob(key)
+            .await
+            .context("Failed to get receipt")
+            .map_err(service_error)?
+            .ok_or_else(|| warp::reject::custom(NotFound(missing)))?;
+        Ok(warp::reply::with_header(data, CONTENT_TYPE, content_type).into_response())
+    }
+}
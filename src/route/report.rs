@@ -0,0 +1,81 @@
+use crate::route::with_storage;
+use crate::service::currency::ExchangeRates;
+use crate::service::report::ReportRequest;
+use crate::service::storage::Storage;
+use warp::Filter;
+
+pub fn reports(
+    storage: Storage,
+    rates: ExchangeRates,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let group = warp::path!("groups" / String / "reports")
+        .and(warp::get())
+        .and(warp::query::<ReportRequest>())
+        .and(with_storage(storage.clone()))
+        .and_then(handlers::get_group_report);
+    let user = warp::path!("users" / String / "summary")
+        .and(warp::get())
+        .and(warp::query::<ReportRequest>())
+        .and(with_storage(storage))
+        .and(warp::any().map(move || rates.clone()))
+        .and_then(handlers::get_user_summary);
+    group.or(user)
+}
+
+pub(super) mod handlers {
+    use crate::route::request::service_error;
+    use crate::service::currency::ExchangeRates;
+    use crate::service::report::{user_summary, GroupReport, ReportRequest, UserSummary};
+    use crate::service::storage::Storage;
+    use anyhow::Context;
+
+    #[utoipa::path(
+        get,
+        path = "/groups/{group_id}/reports",
+        tag = "reports",
+        params(("group_id" = String, Path, description = "Id of the group"), ReportRequest),
+        responses(
+            (status = 200, description = "Spending of the group per month, category, member and currency, payments and deleted expenses left out", body = GroupReport),
+            (status = 404, description = "No such group"),
+        )
+    )]
+    pub async fn get_group_report(
+        group_id: String,
+        request: ReportRequest,
+        storage: Storage,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let _group = storage
+            .groups
+            .get_group(group_id.clone())
+            .await
+            .context("Failed to get group")
+            .map_err(service_error)?;
+        let report = storage
+            .reports
+            .group_report(group_id, request)
+            .await
+            .context("Failed to compute group report")
+            .map_err(service_error)?;
+        Ok(warp::reply::json(&report))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/users/{user_id}/summary",
+        tag = "reports",
+        params(("user_id" = String, Path, description = "Id of the user"), ReportRequest),
+        responses((status = 200, description = "Shares the user owes per month and group across all their groups, converted to their default currency where a rate is configured", body = UserSummary))
+    )]
+    pub async fn get_user_summary(
+        user_id: String,
+        request: ReportRequest,
+        storage: Storage,
+        rates: ExchangeRates,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let summary = user_summary(&storage, &rates, user_id, request)
+            .await
+            .context("Failed to compute user summary")
+            .map_err(service_error)?;
+        Ok(warp::reply::json(&summary))
+    }
+}
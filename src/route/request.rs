@@ -0,0 +1,207 @@
+use crate::route::activity::{InvalidCursor, InvalidLimit};
+use crate::route::category::InvalidCategory;
+use crate::route::expense::{ExpenseNotDeleted, InvalidExpense, InvalidSplit};
+use crate::route::idempotency::{IdempotencyConflict, InvalidIdempotencyKey};
+use crate::route::import::{InvalidCsv, InvalidSplitwiseExport};
+use crate::route::precondition::{InvalidIfMatch, MissingIfMatch};
+use crate::route::receipt::{InvalidReceipt, UnsupportedReceipt};
+use crate::service;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::fmt;
+use tracing::Span;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use warp::body::BodyDeserializeError;
+use warp::http::{HeaderMap, HeaderValue, StatusCode};
+use warp::reject::{
+    InvalidHeader, InvalidQuery, LengthRequired, MethodNotAllowed, MissingHeader, PayloadTooLarge,
+    Reject, UnsupportedMediaType,
+};
+use warp::reply::Response;
+use warp::trace::Info;
+use warp::{Filter, Rejection, Reply};
+
+/// Header carrying the id of the request, set by the client or a proxy in front of the server.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longer ids from clients are replaced by a generated one.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Id of the request, taken from the `X-Request-Id` header or generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn from_header(value: Option<&str>) -> Self {
+        match value {
+            Some(id)
+                if !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LENGTH
+                    && id.bytes().all(|byte| byte.is_ascii_graphic()) =>
+            {
+                Self(id.to_string())
+            }
+            _ => Self(Uuid::new_v4().to_string()),
+        }
+    }
+}
+
+/// The resource the request is about doesn't exist.
+#[derive(Debug)]
+pub struct NotFound(pub(super) String);
+
+impl Reject for NotFound {}
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A service failed to handle the request. The cause is logged, the client only gets the request
+/// id to report it with.
+#[derive(Debug)]
+pub struct ServiceError;
+
+impl Reject for ServiceError {}
+
+/// Rejection for an error returned by a service: not found for an entity that doesn't exist,
+/// otherwise a service error logged in the span of the request.
+pub(super) fn service_error(error: anyhow::Error) -> Rejection {
+    if let Some(not_found) = error.downcast_ref::<service::NotFound>() {
+        return warp::reject::custom(NotFound(not_found.to_string()));
+    }
+    tracing::error!("{:#}", error);
+    warp::reject::custom(ServiceError)
+}
+
+/// Body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ErrorResponse {
+    error: String,
+
+    /// To be quoted when reporting the error, it's on every log line of the request.
+    request_id: String,
+}
+
+/// Span every request is handled in, the request id is recorded by [`request_id`].
+pub fn span(info: Info<'_>) -> Span {
+    tracing::info_span!(
+        "request",
+        method = %info.method(),
+        path = info.path(),
+        request_id = tracing::field::Empty,
+    )
+}
+
+pub fn request_id() -> impl Filter<Extract = (RequestId,), Error = Infallible> + Clone {
+    warp::header::headers_cloned().map(|headers: HeaderMap| {
+        let id = RequestId::from_header(
+            headers
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok()),
+        );
+        let _span = Span::current().record("request_id", id.0.as_str());
+        id
+    })
+}
+
+/// Turns the rejection into an error response and echoes the request id in a header.
+pub fn respond(id: RequestId, result: Result<Response, Rejection>) -> Response {
+    let mut response = result.unwrap_or_else(|rejection| error_response(&id, rejection));
+    if let Ok(value) = HeaderValue::from_str(&id.0) {
+        let _previous = response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+fn error_response(id: &RequestId, rejection: Rejection) -> Response {
+    let (status, error) = if rejection.is_not_found() {
+        (StatusCode::NOT_FOUND, None)
+    } else if let Some(error) = rejection.find::<NotFound>() {
+        (StatusCode::NOT_FOUND, Some(error.to_string()))
+    } else if rejection.find::<ServiceError>().is_some() {
+        (StatusCode::INTERNAL_SERVER_ERROR, None)
+    } else if let Some(error) = rejection.find::<BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidQuery>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<MissingHeader>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidHeader>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidIdempotencyKey>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(conflict) = rejection.find::<IdempotencyConflict>() {
+        (StatusCode::CONFLICT, Some(conflict.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidIfMatch>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<MissingIfMatch>() {
+        (StatusCode::PRECONDITION_REQUIRED, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidCsv>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidSplitwiseExport>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidReceipt>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<UnsupportedReceipt>() {
+        (StatusCode::UNSUPPORTED_MEDIA_TYPE, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<ExpenseNotDeleted>() {
+        (StatusCode::CONFLICT, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidExpense>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidSplit>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidCategory>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidLimit>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if let Some(error) = rejection.find::<InvalidCursor>() {
+        (StatusCode::BAD_REQUEST, Some(error.to_string()))
+    } else if rejection.find::<PayloadTooLarge>().is_some() {
+        (StatusCode::PAYLOAD_TOO_LARGE, None)
+    } else if rejection.find::<LengthRequired>().is_some() {
+        (StatusCode::LENGTH_REQUIRED, None)
+    } else if rejection.find::<UnsupportedMediaType>().is_some() {
+        (StatusCode::UNSUPPORTED_MEDIA_TYPE, None)
+    } else if rejection.find::<MethodNotAllowed>().is_some() {
+        (StatusCode::METHOD_NOT_ALLOWED, None)
+    } else {
+        tracing::error!("Unhandled rejection: {:?}", rejection);
+        (StatusCode::INTERNAL_SERVER_ERROR, None)
+    };
+    let body = ErrorResponse {
+        error: error.unwrap_or_else(|| {
+            status
+                .canonical_reason()
+                .unwrap_or("Unknown error")
+                .to_string()
+        }),
+        request_id: id.0.clone(),
+    };
+    warp::reply::with_status(warp::reply::json(&body), status).into_response()
+}
+
+#[cfg(test)]
+mod test {
+    use super::RequestId;
+
+    #[test]
+    fn propagate_valid_request_id() {
+        assert_eq!(
+            RequestId::from_header(Some("a1b2-c3")),
+            RequestId("a1b2-c3".to_string())
+        );
+    }
+
+    #[test]
+    fn generate_request_id() {
+        let too_long = "a".repeat(129);
+        for header in [None, Some(""), Some("with space"), Some(too_long.as_str())] {
+            let RequestId(id) = RequestId::from_header(header);
+            assert_eq!(id.len(), 36, "{:?}", header);
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use crate::service::activity::{Activity, ActivityRequest};
+use crate::service::comment::Comment;
+use crate::service::expense::{Expense, ListExpensesRequest, User};
+use crate::service::group::{Group, User as Member};
+use crate::service::history::ExpenseRevision;
+use crate::service::storage::Storage;
+use crate::service::user;
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Bson};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Name deleted users are shown under in the groups and expenses they took part in.
+pub const DELETED_USER_NAME: &str = "Deleted user";
+
+/// Page size used to read the whole activity feed of the user.
+const ACTIVITY_PAGE_SIZE: i64 = 100;
+
+/// Everything stored about a user, as downloaded from `GET /users/{id}/export`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountExport {
+    pub user_id: String,
+
+    pub exported_at: DateTime<Utc>,
+
+    /// `None` for users who only exist as members of groups.
+    #[schema(value_type = Option<Object>)]
+    pub profile: Option<user::User>,
+
+    pub groups: Vec<Group>,
+
+    /// Expenses the user has a share in, created or last updated, including deleted ones.
+    pub expenses: Vec<Expense>,
+
+    /// Comments written by the user.
+    pub comments: Vec<Comment>,
+
+    /// Activity feed of the user, newest first.
+    pub activity: Vec<Activity>,
+}
+
+/// What `DELETE /users/{id}` changed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDeletion {
+    /// Whether the user had a profile, which is now deleted.
+    pub profile_deleted: bool,
+
+    /// Number of groups the user is now anonymised in.
+    pub groups: u64,
+
+    /// Number of expenses the user is now anonymised in.
+    pub expenses: u64,
+
+    /// Number of comments the user is now anonymised in.
+    pub comments: u64,
+
+    /// Number of revisions in the history of expenses the user is now anonymised in.
+    pub revisions: u64,
+
+    /// Number of activities the user is now anonymised in.
+    pub activities: u64,
+}
+
+/// Collects the profile, groups, expenses, comments and activity of the user.
+pub async fn export(storage: &Storage, user_id: String) -> Result<AccountExport, Error> {
+    let profile = storage.users.find_user(user_id.clone()).await?;
+    let groups = storage.groups.get_user_group(user_id.clone()).await?;
+
+    let mut expenses = storage
+        .expenses
+        .list_expenses(ListExpensesRequest {
+            user_id: Some(user_id.clone()),
+            ..ListExpensesRequest::default()
+        })
+        .await?
+        .expenses;
+    // expenses the user created or updated without having a share are only found in their groups
+    for group in &groups {
+        let request = ListExpensesRequest {
+            group_id: group.id.clone(),
+            ..ListExpensesRequest::default()
+        };
+        expenses.extend(
+            storage
+                .expenses
+                .list_expenses(request)
+                .await?
+                .expenses
+                .into_iter()
+                .filter(|expense| {
+                    expense.share_of(&user_id).is_none() && involves(expense, &user_id)
+                }),
+        );
+    }
+    expenses.sort_by_key(|expense| (expense.date, expense.created_at));
+
+    let comments = storage.comments.get_user_comments(user_id.clone()).await?;
+
+    let mut activity = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = storage
+            .activity
+            .get_user_activity(
+                user_id.clone(),
+                ActivityRequest {
+                    user_id: None,
+                    cursor,
+                    limit: Some(ACTIVITY_PAGE_SIZE),
+                },
+            )
+            .await?;
+        activity.extend(page.activities);
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+
+    Ok(AccountExport {
+        user_id,
+        exported_at: Utc::now(),
+        profile,
+        groups,
+        expenses,
+        comments,
+        activity,
+    })
+}
+
+/// Deletes the profile of the user and anonymises them in the groups, expenses, comments, history
+/// and activity they took part in. Their id is kept, so that the shares and balances of the
+/// remaining members don't change.
+pub async fn delete(storage: &Storage, user_id: String) -> Result<AccountDeletion, Error> {
+    let groups = storage.groups.anonymise_member(user_id.clone()).await?;
+    let expenses = storage.expenses.anonymise_user(user_id.clone()).await?;
+    let comments = storage.comments.anonymise_user(user_id.clone()).await?;
+    let revisions = storage.history.anonymise_user(user_id.clone()).await?;
+    let activities = storage.activity.anonymise_user(user_id.clone()).await?;
+    let profile_deleted = storage.users.delete_user(user_id).await?;
+    Ok(AccountDeletion {
+        profile_deleted,
+        groups,
+        expenses,
+        comments,
+        revisions,
+        activities,
+    })
+}
+
+/// Whether the user has a share in the expense, created, updated or deleted it.
+pub(crate) fn involves(expense: &Expense, user_id: &str) -> bool {
+    let is_user =
+        |user: &Option<User>| matches!(user, Some(User { id: Some(id), .. }) if id == user_id);
+    expense.share_of(user_id).is_some()
+        || is_user(&expense.created_by)
+        || is_user(&expense.updated_by)
+        || is_user(&expense.deleted_by)
+}
+
+/// Removes the personal data of the member with `user_id` from the group. Returns whether they
+/// are a member.
+pub(crate) fn anonymise_member(group: &mut Group, user_id: &str) -> bool {
+    let mut anonymised = false;
+    for member in group.members.iter_mut().flatten() {
+        if member.id.as_deref() == Some(user_id) {
+            *member = Member {
+                id: member.id.take(),
+                first_name: Some(DELETED_USER_NAME.to_string()),
+                balance: member.balance.take(),
+                ..Member::default()
+            };
+            anonymised = true;
+        }
+    }
+    anonymised
+}
+
+/// Removes the personal data of the user with `user_id` from the expense, leaving their shares
+/// untouched. Returns whether they are involved in it.
+pub(crate) fn anonymise_expense(expense: &mut Expense, user_id: &str) -> bool {
+    if !involves(expense, user_id) {
+        return false;
+    }
+    let users = expense
+        .users
+        .iter_mut()
+        .flatten()
+        .filter_map(|share| share.user.as_mut());
+    let actors = [
+        &mut expense.created_by,
+        &mut expense.updated_by,
+        &mut expense.deleted_by,
+    ];
+    for user in users.chain(actors.into_iter().flatten()) {
+        let _anonymised = anonymise_user(user, user_id);
+    }
+    true
+}
+
+/// Removes the personal data of the user with `user_id` from the comment. Returns whether they
+/// wrote it.
+pub(crate) fn anonymise_comment(comment: &mut Comment, user_id: &str) -> bool {
+    comment
+        .created_by
+        .as_mut()
+        .is_some_and(|user| anonymise_user(user, user_id))
+}
+
+/// Removes the personal data of the user with `user_id` from the revision, both as the user who
+/// made the change and from the changed values. Returns whether they are found in it.
+pub(crate) fn anonymise_revision(revision: &mut ExpenseRevision, user_id: &str) -> bool {
+    let mut anonymised = revision
+        .changed_by
+        .as_mut()
+        .is_some_and(|user| anonymise_user(user, user_id));
+    let values = revision
+        .changes
+        .iter_mut()
+        .flat_map(|change| [&mut change.from, &mut change.to])
+        .flatten();
+    for value in values {
+        anonymised |= anonymise_value(value, user_id);
+    }
+    anonymised
+}
+
+/// Replaces the name of the user with `user_id` in the summary of the activity they did. Returns
+/// whether they did it.
+pub(crate) fn anonymise_activity(activity: &mut Activity, user_id: &str) -> bool {
+    if activity.actor_id.as_deref() != Some(user_id) {
+        return false;
+    }
+    activity.rename_actor(DELETED_USER_NAME);
+    true
+}
+
+fn anonymise_user(user: &mut User, user_id: &str) -> bool {
+    if user.id.as_deref() != Some(user_id) {
+        return false;
+    }
+    *user = User {
+        id: user.id.take(),
+        first_name: Some(DELETED_USER_NAME.to_string()),
+        balance: user.balance.take(),
+        ..User::default()
+    };
+    true
+}
+
+/// `anonymise_user` for the users nested anywhere in a value stored in the history, e.g. in the
+/// shares of an expense.
+fn anonymise_value(value: &mut Bson, user_id: &str) -> bool {
+    match value {
+        Bson::Document(document) if document.get_str("id").ok() == Some(user_id) => {
+            let mut anonymised = doc! {"id": user_id, "firstName": DELETED_USER_NAME};
+            if let Some(balance) = document.get("balance") {
+                let _previous = anonymised.insert("balance", balance.clone());
+            }
+            *document = anonymised;
+            true
+        }
+        Bson::Document(document) => {
+            let fields = document.keys().cloned().collect::<Vec<_>>();
+            let mut found = false;
+            for field in fields {
+                if let Some(value) = document.get_mut(&field) {
+                    found |= anonymise_value(value, user_id);
+                }
+            }
+            found
+        }
+        Bson::Array(values) => values.iter_mut().fold(false, |found, value| {
+            anonymise_value(value, user_id) | found
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{anonymise_expense, anonymise_member, anonymise_revision, DELETED_USER_NAME};
+    use crate::service::expense::{Expense, ShareCalculator, User, UserShare};
+    use crate::service::group::{Group, User as Member};
+    use crate::service::history::{diff, ExpenseAction, ExpenseRevision};
+    use chrono::Utc;
+    use mongodb::bson;
+
+    #[test]
+    fn anonymise_user_in_expense_but_keep_shares() {
+        let user = |id: &str, name: &str| User {
+            id: Some(id.to_string()),
+            first_name: Some(name.to_string()),
+            email: Some(format!("{}@example.com", name)),
+            ..User::default()
+        };
+        let shares = ShareCalculator::new()
+            .equal_share(
+                "30.00".to_string(),
+                "1".to_string(),
+                vec!["1".to_string(), "2".to_string()],
+            )
+            .into_iter()
+            .map(|share| {
+                let id = share.user.and_then(|user| user.id).unwrap_or_default();
+                let name = if id == "1" { "alice" } else { "bob" };
+                UserShare {
+                    user: Some(user(&id, name)),
+                    ..share
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut expense = Expense {
+            created_by: Some(user("1", "alice")),
+            updated_by: Some(user("2", "bob")),
+            users: Some(shares),
+            ..Expense::default()
+        };
+
+        assert!(anonymise_expense(&mut expense, "1"));
+        let alice = expense.share_of("1").unwrap();
+        assert_eq!(alice.net_balance.as_deref(), Some("15.00"));
+        let anonymised = alice.user.as_ref().unwrap();
+        assert_eq!(anonymised.first_name.as_deref(), Some(DELETED_USER_NAME));
+        assert_eq!(anonymised.email, None);
+        assert_eq!(
+            expense.created_by.unwrap().first_name.as_deref(),
+            Some(DELETED_USER_NAME)
+        );
+        assert_eq!(
+            expense.updated_by.unwrap().first_name.as_deref(),
+            Some("bob")
+        );
+        assert!(!anonymise_expense(&mut Expense::default(), "1"));
+    }
+
+    #[test]
+    fn anonymise_only_the_member() {
+        let member = |id: &str, name: &str| Member {
+            id: Some(id.to_string()),
+            first_name: Some(name.to_string()),
+            last_name: Some("Smith".to_string()),
+            email: Some(format!("{}@example.com", name)),
+            ..Member::default()
+        };
+        let mut group = Group {
+            members: Some(vec![member("1", "alice"), member("2", "bob")]),
+            ..Group::default()
+        };
+
+        assert!(anonymise_member(&mut group, "1"));
+        let members = group.members.unwrap();
+        assert_eq!(members[0].id.as_deref(), Some("1"));
+        assert_eq!(members[0].first_name.as_deref(), Some(DELETED_USER_NAME));
+        assert_eq!(members[0].last_name, None);
+        assert_eq!(members[0].email, None);
+        assert_eq!(members[1].email.as_deref(), Some("bob@example.com"));
+    }
+
+    #[test]
+    fn anonymise_user_in_revision() {
+        let alice = User {
+            id: Some("1".to_string()),
+            first_name: Some("alice".to_string()),
+            email: Some("alice@example.com".to_string()),
+            ..User::default()
+        };
+        let before = Expense {
+            users: Some(vec![UserShare {
+                user: Some(alice.clone()),
+                ..UserShare::default()
+            }]),
+            ..Expense::default()
+        };
+        let after = Expense {
+            created_by: Some(alice.clone()),
+            ..before.clone()
+        };
+        let mut revision = ExpenseRevision {
+            expense_id: "e1".to_string(),
+            version: 1,
+            action: ExpenseAction::Created,
+            changed_by: Some(alice),
+            changed_at: Utc::now(),
+            changes: diff(
+                &bson::to_document(&Expense::default()).unwrap(),
+                &bson::to_document(&after).unwrap(),
+            ),
+        };
+
+        assert!(anonymise_revision(&mut revision, "1"));
+        let revision = serde_json::to_string(&revision).unwrap();
+        assert!(!revision.contains("alice"), "{}", revision);
+        assert!(revision.contains(DELETED_USER_NAME));
+    }
+}
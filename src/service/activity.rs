@@ -0,0 +1,490 @@
+use crate::service::account::anonymise_activity;
+use crate::service::expense::{Expense, User};
+use crate::service::group::Group;
+use crate::service::history::ExpenseAction;
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use mongodb::options::{FindOneOptions, FindOptions, UpdateOptions};
+use mongodb::{bson, Client, Database};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tokio_stream::StreamExt;
+use utoipa::{IntoParams, ToSchema};
+
+pub(crate) const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// Largest page of activity returned at once.
+pub(crate) const MAX_PAGE_SIZE: i64 = 100;
+
+#[async_trait]
+pub trait ActivityApi {
+    async fn record(&self, activity: Activity) -> Result<Activity, Error>;
+    /// Activity of the group, newest first.
+    async fn get_group_activity(
+        &self,
+        group_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error>;
+    /// Activity involving the user across all groups, newest first.
+    async fn get_user_activity(
+        &self,
+        user_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error>;
+    /// Marks everything in the user's feed up to now as read.
+    async fn mark_read(&self, user_id: String) -> Result<(), Error>;
+    /// Removes the name of the user from the summaries of the activity they did, see
+    /// [`anonymise_activity`]. Returns the number of activities changed.
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ActivityApiMongoAdapter {
+    db: Database,
+}
+
+impl ActivityApiMongoAdapter {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+
+    async fn find_page(
+        &self,
+        mut filter: Document,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        if let Some(cursor) = &request.cursor {
+            let _ = filter.insert("_id", doc! {"$lt": ObjectId::from_str(cursor)?});
+        }
+        let limit = request.page_size();
+        let options = FindOptions::builder()
+            .sort(doc! {"_id": -1})
+            .limit(limit)
+            .build();
+        let last_read = match &request.user_id {
+            Some(user_id) => Some(self.last_read(user_id).await?),
+            None => None,
+        };
+        let mut cursor = self
+            .db
+            .collection::<Document>("activity")
+            .find(filter, options)
+            .await?;
+        let mut activities = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            let id = document.get_object_id("_id")?;
+            let activity = Activity::from_document(document)?;
+            activities.push(Activity {
+                unread: last_read.map(|last_read| !matches!(last_read, Some(last) if id <= last)),
+                ..activity
+            });
+        }
+        let next_cursor = if activities.len() as i64 == limit {
+            activities.last().and_then(|activity| activity.id.clone())
+        } else {
+            None
+        };
+        Ok(ActivityPage {
+            activities,
+            next_cursor,
+        })
+    }
+
+    async fn last_read(&self, user_id: &str) -> Result<Option<ObjectId>, Error> {
+        let marker = self
+            .db
+            .collection::<Document>("activity_reads")
+            .find_one(doc! {"userId": user_id}, None)
+            .await?;
+        Ok(marker.and_then(|marker| marker.get_object_id("lastReadId").ok()))
+    }
+}
+
+#[async_trait]
+impl ActivityApi for ActivityApiMongoAdapter {
+    async fn record(&self, activity: Activity) -> Result<Activity, Error> {
+        let inserted = self
+            .db
+            .collection::<Document>("activity")
+            .insert_one(bson::to_document(&activity)?, None)
+            .await?;
+        Ok(Activity {
+            id: inserted.inserted_id.as_object_id().map(|id| id.to_hex()),
+            ..activity
+        })
+    }
+
+    async fn get_group_activity(
+        &self,
+        group_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        self.find_page(doc! {"groupId": group_id}, request).await
+    }
+
+    async fn get_user_activity(
+        &self,
+        user_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        let request = ActivityRequest {
+            user_id: Some(user_id.clone()),
+            ..request
+        };
+        self.find_page(doc! {"userIds": user_id}, request).await
+    }
+
+    async fn mark_read(&self, user_id: String) -> Result<(), Error> {
+        let options = FindOneOptions::builder().sort(doc! {"_id": -1}).build();
+        let latest = self
+            .db
+            .collection::<Document>("activity")
+            .find_one(doc! {"userIds": &user_id}, options)
+            .await?;
+        if let Some(latest) = latest {
+            let _update_result = self
+                .db
+                .collection::<Document>("activity_reads")
+                .update_one(
+                    doc! {"userId": &user_id},
+                    doc! {"$set": {"lastReadId": latest.get_object_id("_id")?}},
+                    UpdateOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        let collection = self.db.collection::<Document>("activity");
+        let mut activities = Vec::new();
+        let mut cursor = collection.find(doc! {"actorId": &user_id}, None).await?;
+        while let Some(document) = cursor.try_next().await? {
+            activities.push(Activity::from_document(document)?);
+        }
+        let mut anonymised = 0;
+        for mut activity in activities {
+            if !anonymise_activity(&mut activity, &user_id) {
+                continue;
+            }
+            let id = activity.id.as_deref().unwrap_or_default();
+            let _updated = collection
+                .update_one(
+                    doc! {"_id": ObjectId::from_str(id)?},
+                    doc! {"$set": {"summary": &activity.summary}},
+                    None,
+                )
+                .await?;
+            anonymised += 1;
+        }
+        Ok(anonymised)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Activity {
+    /// Hex representation of the activity's `ObjectId`, used as the pagination cursor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    pub activity_type: ActivityType,
+
+    pub group_id: Option<String>,
+
+    /// Users who see the activity in their feed.
+    pub user_ids: Vec<String>,
+
+    pub actor_id: Option<String>,
+
+    pub expense_id: Option<String>,
+
+    /// Human readable description, e.g. "Bob added Groceries 42.00".
+    pub summary: String,
+
+    pub created_at: DateTime<Utc>,
+
+    /// Whether the activity is newer than what the user has read. Only set when the feed is
+    /// requested on behalf of a user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unread: Option<bool>,
+}
+
+impl Activity {
+    fn from_document(mut document: Document) -> Result<Self, Error> {
+        let id = document.get_object_id("_id")?.to_hex();
+        let _id = document.remove("_id");
+        let activity: Activity = bson::from_document(document)?;
+        Ok(Activity {
+            id: Some(id),
+            ..activity
+        })
+    }
+
+    /// Activity describing a change of an expense made by `actor`.
+    pub fn for_expense(
+        expense_id: String,
+        action: ExpenseAction,
+        actor: Option<&User>,
+        expense: &Expense,
+    ) -> Self {
+        let actor_name = actor.map_or_else(|| "Someone".to_string(), display_name);
+        let description = expense.description.as_deref().unwrap_or("an expense");
+        let cost = expense.cost.as_deref().unwrap_or_default();
+        let is_payment = expense.payment == Some(true);
+        let (activity_type, object) = match action {
+            ExpenseAction::Created if is_payment => {
+                (ActivityType::PaymentRecorded, cost.to_string())
+            }
+            ExpenseAction::Created => (
+                ActivityType::ExpenseAdded,
+                format!("{} {}", description, cost),
+            ),
+            ExpenseAction::Updated => (ActivityType::ExpenseUpdated, description.to_string()),
+            ExpenseAction::Deleted => (ActivityType::ExpenseDeleted, description.to_string()),
+            ExpenseAction::Restored => (ActivityType::ExpenseRestored, description.to_string()),
+        };
+        let summary = format!(
+            "{} {} {}",
+            actor_name,
+            activity_type.verb().unwrap_or_default(),
+            object
+        );
+        let mut user_ids = expense
+            .users
+            .iter()
+            .flatten()
+            .filter_map(|share| share.user.as_ref().and_then(|user| user.id.clone()))
+            .chain(actor.and_then(|actor| actor.id.clone()))
+            .collect::<Vec<_>>();
+        user_ids.sort();
+        user_ids.dedup();
+        Activity {
+            id: None,
+            activity_type,
+            group_id: expense.group_id.clone(),
+            user_ids,
+            actor_id: actor.and_then(|actor| actor.id.clone()),
+            expense_id: Some(expense_id),
+            summary,
+            created_at: Utc::now(),
+            unread: None,
+        }
+    }
+
+    pub fn group_created(group: &Group) -> Self {
+        Activity {
+            id: None,
+            activity_type: ActivityType::GroupCreated,
+            group_id: group.id.clone(),
+            user_ids: group.member_ids(),
+            actor_id: None,
+            expense_id: None,
+            summary: format!(
+                "Group {} was created",
+                group.name.clone().unwrap_or_default()
+            ),
+            created_at: Utc::now(),
+            unread: None,
+        }
+    }
+
+    pub fn member_joined(group: &Group, user_id: String, name: String) -> Self {
+        Activity {
+            id: None,
+            activity_type: ActivityType::MemberJoined,
+            group_id: group.id.clone(),
+            user_ids: group.member_ids(),
+            actor_id: Some(user_id),
+            expense_id: None,
+            summary: format!(
+                "{} {}",
+                name,
+                ActivityType::MemberJoined.verb().unwrap_or_default()
+            ),
+            created_at: Utc::now(),
+            unread: None,
+        }
+    }
+
+    /// Shows the actor under `name` in the summary, which starts with the name of the actor.
+    pub(crate) fn rename_actor(&mut self, name: &str) {
+        let verb = match self.activity_type.verb() {
+            Some(verb) => format!(" {}", verb),
+            None => return,
+        };
+        if let Some(end) = self.summary.find(&verb) {
+            self.summary.replace_range(..end, name);
+        }
+    }
+}
+
+fn display_name(user: &User) -> String {
+    user.first_name
+        .clone()
+        .or_else(|| user.id.clone())
+        .unwrap_or_else(|| "Someone".to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ActivityType {
+    ExpenseAdded,
+    ExpenseUpdated,
+    ExpenseDeleted,
+    ExpenseRestored,
+    PaymentRecorded,
+    GroupCreated,
+    MemberJoined,
+}
+
+impl ActivityType {
+    /// What the actor did, following their name in the summary. `None` if the summary names no
+    /// actor.
+    fn verb(self) -> Option<&'static str> {
+        match self {
+            ActivityType::ExpenseAdded => Some("added"),
+            ActivityType::ExpenseUpdated => Some("updated"),
+            ActivityType::ExpenseDeleted => Some("deleted"),
+            ActivityType::ExpenseRestored => Some("restored"),
+            ActivityType::PaymentRecorded => Some("settled up"),
+            ActivityType::GroupCreated => None,
+            ActivityType::MemberJoined => Some("joined"),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityRequest {
+    /// User whose unread markers are applied to the returned activity.
+    pub user_id: Option<String>,
+
+    /// `id` of the last activity of the previous page.
+    pub cursor: Option<String>,
+
+    /// Maximum number of activities to return, from 1 to 100.
+    /// Default: `20`
+    pub limit: Option<i64>,
+}
+
+impl ActivityRequest {
+    /// Size of the page, the requested limit brought within `1..=MAX_PAGE_SIZE`.
+    pub(crate) fn page_size(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityPage {
+    pub activities: Vec<Activity>,
+
+    /// Cursor of the next page, `None` if this is the last page.
+    pub next_cursor: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Activity, ActivityRequest, ActivityType, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+    use crate::service::expense::{Expense, User, UserShare};
+    use crate::service::history::ExpenseAction;
+
+    fn user(id: &str, first_name: &str) -> User {
+        User {
+            id: Some(id.to_string()),
+            first_name: Some(first_name.to_string()),
+            ..User::default()
+        }
+    }
+
+    #[test]
+    fn expense_added_activity() {
+        let bob = user("2", "Bob");
+        let expense = Expense {
+            cost: Some("42.00".to_string()),
+            description: Some("Groceries".to_string()),
+            group_id: Some("1".to_string()),
+            users: Some(vec![
+                UserShare {
+                    user: Some(user("3", "Alice")),
+                    ..UserShare::default()
+                },
+                UserShare {
+                    user: Some(bob.clone()),
+                    ..UserShare::default()
+                },
+            ]),
+            ..Expense::default()
+        };
+        let activity = Activity::for_expense(
+            "e1".to_string(),
+            ExpenseAction::Created,
+            Some(&bob),
+            &expense,
+        );
+        assert_eq!(activity.activity_type, ActivityType::ExpenseAdded);
+        assert_eq!(activity.summary, "Bob added Groceries 42.00");
+        assert_eq!(activity.user_ids, vec!["2".to_string(), "3".to_string()]);
+        assert_eq!(activity.group_id, Some("1".to_string()));
+    }
+
+    #[test]
+    fn payment_activity() {
+        let alice = user("3", "Alice");
+        let expense = Expense {
+            cost: Some("20.00".to_string()),
+            payment: Some(true),
+            ..Expense::default()
+        };
+        let activity = Activity::for_expense(
+            "e1".to_string(),
+            ExpenseAction::Created,
+            Some(&alice),
+            &expense,
+        );
+        assert_eq!(activity.activity_type, ActivityType::PaymentRecorded);
+        assert_eq!(activity.summary, "Alice settled up 20.00");
+    }
+
+    #[test]
+    fn rename_actor() {
+        let mut activity = Activity::for_expense(
+            "e1".to_string(),
+            ExpenseAction::Updated,
+            Some(&user("2", "Bob Jr")),
+            &Expense {
+                description: Some("Bob's groceries".to_string()),
+                ..Expense::default()
+            },
+        );
+        activity.rename_actor("Deleted user");
+        assert_eq!(activity.summary, "Deleted user updated Bob's groceries");
+    }
+
+    #[test]
+    fn clamp_page_size() {
+        let page_size = |limit| {
+            ActivityRequest {
+                limit,
+                ..ActivityRequest::default()
+            }
+            .page_size()
+        };
+        assert_eq!(page_size(None), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(Some(-5)), 1);
+        assert_eq!(page_size(Some(0)), 1);
+        assert_eq!(page_size(Some(1000)), MAX_PAGE_SIZE);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::service::DEFAULT_DATABASE_NAME;
 use anyhow::Error;
 use async_trait::async_trait;
 use mongodb::bson;
 use mongodb::bson::{doc, Document};
+use mongodb::Client;
 use serde::{Deserialize, Serialize};
 
 #[async_trait]
@@ -14,6 +16,16 @@ pub struct BalanceApiMongoAdapter {
     db: mongodb::Database,
 }
 
+impl BalanceApiMongoAdapter {
+    pub fn new(db: mongodb::Database) -> Self {
+        Self { db }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+}
+
 #[async_trait]
 impl BalanceApi for BalanceApiMongoAdapter {
     async fn get_user_balance(&self, user_id: String) -> Result<Balance, Error> {
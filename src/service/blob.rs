@@ -0,0 +1,260 @@
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::{bail, Error};
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::spec::BinarySubtype;
+use mongodb::bson::{doc, Binary, Document};
+use mongodb::options::{FindOneOptions, FindOptions};
+use mongodb::{bson, Client, Database};
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio_stream::StreamExt;
+
+/// Size of the chunks files are split into in GridFS, the default of the MongoDB drivers.
+const GRIDFS_CHUNK_SIZE: usize = 255 * 1024;
+
+/// Binary content such as receipts, stored apart from the documents which refer to it by key.
+#[async_trait]
+pub trait BlobStore {
+    /// Stores the blob under `key`, replacing the blob stored under it before.
+    async fn put_blob(&self, key: String, blob: Blob) -> Result<(), Error>;
+    async fn get_blob(&self, key: String) -> Result<Option<Blob>, Error>;
+    /// Removes the blob, returns whether there was one.
+    async fn delete_blob(&self, key: String) -> Result<bool, Error>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Blob {
+    pub content_type: String,
+
+    pub data: Vec<u8>,
+}
+
+/// Keys end up in file names, they are limited to ASCII letters, digits, `-`, `_` and `.` and
+/// may not start with `.`.
+pub(crate) fn validate_key(key: &str) -> Result<(), Error> {
+    let valid = !key.is_empty()
+        && !key.starts_with('.')
+        && key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'));
+    if !valid {
+        bail!("Invalid blob key {:?}", key);
+    }
+    Ok(())
+}
+
+/// Stores every blob as a file named after its key in a directory, with its content type in a
+/// `.type` file next to it.
+#[derive(Debug, Clone)]
+pub struct FileBlobStore {
+    dir: PathBuf,
+}
+
+impl FileBlobStore {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    fn paths(&self, key: &str) -> Result<(PathBuf, PathBuf), Error> {
+        validate_key(key)?;
+        Ok((self.dir.join(key), self.dir.join(format!("{}.type", key))))
+    }
+}
+
+#[async_trait]
+impl BlobStore for FileBlobStore {
+    async fn put_blob(&self, key: String, blob: Blob) -> Result<(), Error> {
+        let (data_path, type_path) = self.paths(&key)?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // written aside under names unique to this write and renamed, so that a blob is never
+        // read half written, the type first so that the new content is never read with the old one
+        let partial = format!(".{}.{}", key, ObjectId::new());
+        let partial_data = self.dir.join(format!("{}.partial", partial));
+        let partial_type = self.dir.join(format!("{}.type.partial", partial));
+        tokio::fs::write(&partial_data, &blob.data).await?;
+        tokio::fs::write(&partial_type, blob.content_type).await?;
+        tokio::fs::rename(&partial_type, &type_path).await?;
+        tokio::fs::rename(&partial_data, &data_path).await?;
+        Ok(())
+    }
+
+    async fn get_blob(&self, key: String) -> Result<Option<Blob>, Error> {
+        let (data_path, type_path) = self.paths(&key)?;
+        let data = match tokio::fs::read(&data_path).await {
+            Ok(data) => data,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let content_type = tokio::fs::read_to_string(&type_path).await?;
+        Ok(Some(Blob { content_type, data }))
+    }
+
+    async fn delete_blob(&self, key: String) -> Result<bool, Error> {
+        let (data_path, type_path) = self.paths(&key)?;
+        match tokio::fs::remove_file(&data_path).await {
+            Ok(()) => {}
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(error) => return Err(error.into()),
+        }
+        match tokio::fs::remove_file(&type_path).await {
+            Ok(()) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(true),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// Stores blobs in a GridFS bucket, as `<bucket>.files` and `<bucket>.chunks` collections laid out
+/// like the drivers and `mongofiles` expect. The key is the file name, the content type is kept
+/// in the metadata.
+#[derive(Debug, Clone)]
+pub struct GridFsBlobStore {
+    db: Database,
+
+    bucket: String,
+}
+
+impl GridFsBlobStore {
+    pub fn new(db: Database, bucket: &str) -> Self {
+        Self {
+            db,
+            bucket: bucket.to_string(),
+        }
+    }
+
+    pub fn new_with(client: Client, bucket: &str) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME), bucket)
+    }
+
+    fn files(&self) -> mongodb::Collection<Document> {
+        self.db.collection(&format!("{}.files", self.bucket))
+    }
+
+    fn chunks(&self) -> mongodb::Collection<Document> {
+        self.db.collection(&format!("{}.chunks", self.bucket))
+    }
+
+    /// Ids of the files stored under the key.
+    async fn file_ids(&self, key: &str) -> Result<Vec<ObjectId>, Error> {
+        let mut cursor = self.files().find(doc! {"filename": key}, None).await?;
+        let mut ids = Vec::new();
+        while let Some(file) = cursor.try_next().await? {
+            ids.push(file.get_object_id("_id")?);
+        }
+        Ok(ids)
+    }
+
+    async fn remove_files(&self, ids: &[ObjectId]) -> Result<(), Error> {
+        let _chunks = self
+            .chunks()
+            .delete_many(doc! {"files_id": {"$in": ids}}, None)
+            .await?;
+        let _files = self
+            .files()
+            .delete_many(doc! {"_id": {"$in": ids}}, None)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlobStore for GridFsBlobStore {
+    /// The chunks are inserted before the file document, readers never see a partial file.
+    async fn put_blob(&self, key: String, blob: Blob) -> Result<(), Error> {
+        let previous = self.file_ids(&key).await?;
+        let id = ObjectId::new();
+        let chunks = blob
+            .data
+            .chunks(GRIDFS_CHUNK_SIZE)
+            .enumerate()
+            .map(|(n, chunk)| {
+                doc! {
+                    "files_id": id,
+                    "n": n as i32,
+                    "data": Binary { subtype: BinarySubtype::Generic, bytes: chunk.to_vec() },
+                }
+            })
+            .collect::<Vec<_>>();
+        if !chunks.is_empty() {
+            let _inserted = self.chunks().insert_many(chunks, None).await?;
+        }
+        let file = doc! {
+            "_id": id,
+            "length": blob.data.len() as i64,
+            "chunkSize": GRIDFS_CHUNK_SIZE as i32,
+            "uploadDate": bson::DateTime::from_millis(Utc::now().timestamp_millis()),
+            "filename": &key,
+            "metadata": {"contentType": blob.content_type},
+        };
+        let _inserted = self.files().insert_one(file, None).await?;
+        self.remove_files(&previous).await
+    }
+
+    async fn get_blob(&self, key: String) -> Result<Option<Blob>, Error> {
+        let options = FindOneOptions::builder()
+            .sort(doc! {"uploadDate": -1})
+            .build();
+        let Some(file) = self
+            .files()
+            .find_one(doc! {"filename": &key}, options)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let id = file.get_object_id("_id")?;
+        let content_type = file
+            .get_document("metadata")
+            .ok()
+            .and_then(|metadata| metadata.get_str("contentType").ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        let options = FindOptions::builder().sort(doc! {"n": 1}).build();
+        let mut cursor = self.chunks().find(doc! {"files_id": id}, options).await?;
+        let mut data = Vec::new();
+        while let Some(chunk) = cursor.try_next().await? {
+            data.extend_from_slice(chunk.get_binary_generic("data")?);
+        }
+        Ok(Some(Blob { content_type, data }))
+    }
+
+    async fn delete_blob(&self, key: String) -> Result<bool, Error> {
+        let ids = self.file_ids(&key).await?;
+        self.remove_files(&ids).await?;
+        Ok(!ids.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{validate_key, Blob, BlobStore, FileBlobStore};
+
+    #[test]
+    fn reject_keys_escaping_the_directory() {
+        assert!(validate_key("receipt-64b7f0a1.thumbnail").is_ok());
+        for key in ["", "../secret", "a/b", ".hidden", "a b"] {
+            assert!(validate_key(key).is_err(), "{:?}", key);
+        }
+    }
+
+    #[tokio::test]
+    async fn store_blobs_as_files() {
+        let dir = std::env::temp_dir().join(format!("swc-blobs-{}", std::process::id()));
+        let store = FileBlobStore::new(&dir);
+        let blob = Blob {
+            content_type: "image/png".to_string(),
+            data: vec![1, 2, 3],
+        };
+        store.put_blob("a".to_string(), blob.clone()).await.unwrap();
+        assert_eq!(store.get_blob("a".to_string()).await.unwrap(), Some(blob));
+        assert!(store.delete_blob("a".to_string()).await.unwrap());
+        assert!(!store.delete_blob("a".to_string()).await.unwrap());
+        assert_eq!(store.get_blob("a".to_string()).await.unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+use crate::service::history::is_duplicate_key;
+use crate::service::storage::Storage;
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::Error;
+use async_trait::async_trait;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::{bson, Client, Database};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio_stream::StreamExt;
+use utoipa::{IntoParams, ToSchema};
+
+/// Categories every group can use, as `(id, name, parent id)`. Ids are stable, expenses refer
+/// to them.
+const DEFAULT_CATEGORIES: &[(&str, &str, Option<&str>)] = &[
+    ("food", "Food and drink", None),
+    ("groceries", "Groceries", Some("food")),
+    ("dining-out", "Dining out", Some("food")),
+    ("liquor", "Liquor", Some("food")),
+    ("home", "Home", None),
+    ("rent", "Rent", Some("home")),
+    ("household-supplies", "Household supplies", Some("home")),
+    ("furniture", "Furniture", Some("home")),
+    ("maintenance", "Maintenance", Some("home")),
+    ("utilities", "Utilities", None),
+    ("electricity", "Electricity", Some("utilities")),
+    ("heat-gas", "Heat and gas", Some("utilities")),
+    ("water", "Water", Some("utilities")),
+    ("internet", "Internet", Some("utilities")),
+    ("phone", "Phone", Some("utilities")),
+    ("transport", "Transport", None),
+    ("fuel", "Fuel", Some("transport")),
+    ("parking", "Parking", Some("transport")),
+    ("public-transport", "Public transport", Some("transport")),
+    ("taxi", "Taxi", Some("transport")),
+    ("flights", "Flights", Some("transport")),
+    ("entertainment", "Entertainment", None),
+    ("movies", "Movies", Some("entertainment")),
+    ("games", "Games", Some("entertainment")),
+    ("sports", "Sports", Some("entertainment")),
+    ("life", "Life", None),
+    ("medical", "Medical", Some("life")),
+    ("insurance", "Insurance", Some("life")),
+    ("clothing", "Clothing", Some("life")),
+    ("gifts", "Gifts", Some("life")),
+    ("education", "Education", Some("life")),
+    ("general", "General", None),
+];
+
+#[async_trait]
+pub trait CategoriesApi {
+    /// Custom categories of the group, oldest first.
+    async fn get_group_categories(&self, group_id: String) -> Result<Vec<Category>, Error>;
+    /// Fails with [`CategoryTaken`] if a category of the group with the same parent is named the
+    /// same, ignoring case.
+    async fn create_category(
+        &self,
+        group_id: String,
+        spec: CreateCategorySpec,
+    ) -> Result<Category, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryApiMongoAdapter {
+    db: Database,
+}
+
+impl CategoryApiMongoAdapter {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+}
+
+#[async_trait]
+impl CategoriesApi for CategoryApiMongoAdapter {
+    async fn get_group_categories(&self, group_id: String) -> Result<Vec<Category>, Error> {
+        let options = FindOptions::builder().sort(doc! {"_id": 1}).build();
+        let mut cursor = self
+            .db
+            .collection::<Document>("categories")
+            .find(doc! {"groupId": group_id}, options)
+            .await?;
+        let mut categories = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            categories.push(Category::from_document(document)?);
+        }
+        Ok(categories)
+    }
+
+    async fn create_category(
+        &self,
+        group_id: String,
+        spec: CreateCategorySpec,
+    ) -> Result<Category, Error> {
+        let category = Category::custom(String::new(), group_id, spec);
+        let mut document = bson::to_document(&category)?;
+        let _id = document.remove("id");
+        // the unique index on group, parent and name ignores case
+        let inserted = match self
+            .db
+            .collection::<Document>("categories")
+            .insert_one(&document, None)
+            .await
+        {
+            Ok(inserted) => inserted,
+            Err(error) if is_duplicate_key(&error) => {
+                return Err(CategoryTaken(category.name).into())
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let _id = document.insert("_id", inserted.inserted_id);
+        Category::from_document(document)
+    }
+}
+
+/// A category of the group with the same parent is named the same, holds the name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryTaken(pub String);
+
+impl fmt::Display for CategoryTaken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Category {:?} already exists", self.0)
+    }
+}
+
+impl std::error::Error for CategoryTaken {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Category {
+    /// Name of a default category such as `groceries`, or the hex representation of the
+    /// `ObjectId` of a custom category.
+    pub id: String,
+
+    pub name: String,
+
+    /// Category this is a subcategory of.
+    pub parent_id: Option<String>,
+
+    /// Group the custom category belongs to, `None` for default categories.
+    pub group_id: Option<String>,
+}
+
+impl Category {
+    pub(crate) fn custom(id: String, group_id: String, spec: CreateCategorySpec) -> Self {
+        Self {
+            id,
+            name: spec.name.trim().to_string(),
+            parent_id: spec.parent_id,
+            group_id: Some(group_id),
+        }
+    }
+
+    fn from_document(mut document: Document) -> Result<Self, Error> {
+        let id = document.get_object_id("_id")?.to_hex();
+        let _id = document.remove("_id");
+        let _previous = document.insert("id", id);
+        Ok(bson::from_document(document)?)
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCategorySpec {
+    pub name: String,
+
+    /// Default or custom top-level category of the group to nest the category under.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+}
+
+impl CreateCategorySpec {
+    /// Checks that the name is not empty nor taken by a sibling among the `existing` categories
+    /// of the group, and that the parent is a top-level one of them: categories are nested only
+    /// once.
+    pub fn validate(&self, existing: &[Category]) -> Result<(), Error> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(Error::msg("Categories must have a name"));
+        }
+        if let Some(parent_id) = &self.parent_id {
+            match existing.iter().find(|category| &category.id == parent_id) {
+                None => return Err(Error::msg(format!("Unknown category {:?}", parent_id))),
+                Some(parent) if parent.parent_id.is_some() => {
+                    return Err(Error::msg(format!(
+                        "{:?} is a subcategory, subcategories can't be nested",
+                        parent.name
+                    )))
+                }
+                Some(_) => {}
+            }
+        }
+        let taken = existing.iter().any(|category| {
+            category.parent_id == self.parent_id && category.name.eq_ignore_ascii_case(name)
+        });
+        if taken {
+            return Err(CategoryTaken(name.to_string()).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoriesRequest {
+    /// Group whose custom categories are listed along with the default ones.
+    pub group_id: Option<String>,
+}
+
+/// Categories every group can use.
+pub fn default_categories() -> Vec<Category> {
+    DEFAULT_CATEGORIES
+        .iter()
+        .map(|(id, name, parent_id)| Category {
+            id: id.to_string(),
+            name: name.to_string(),
+            parent_id: parent_id.map(String::from),
+            group_id: None,
+        })
+        .collect()
+}
+
+/// The default categories followed by the custom categories of the group, if any.
+pub async fn categories(
+    storage: &Storage,
+    group_id: Option<String>,
+) -> Result<Vec<Category>, Error> {
+    let mut categories = default_categories();
+    if let Some(group_id) = group_id {
+        categories.extend(storage.categories.get_group_categories(group_id).await?);
+    }
+    Ok(categories)
+}
+
+/// Checks that the category is one of `categories`, those of the group of the expense.
+pub fn validate_category(categories: &[Category], category_id: &str) -> Result<(), Error> {
+    if categories.iter().any(|category| category.id == category_id) {
+        Ok(())
+    } else {
+        Err(Error::msg(format!("Unknown category {:?}", category_id)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{default_categories, CreateCategorySpec};
+
+    #[test]
+    fn nest_default_categories_once() {
+        let categories = default_categories();
+        for category in &categories {
+            if let Some(parent_id) = &category.parent_id {
+                let parent = categories
+                    .iter()
+                    .find(|parent| &parent.id == parent_id)
+                    .unwrap();
+                assert_eq!(parent.parent_id, None, "{}", category.id);
+            }
+        }
+    }
+
+    #[test]
+    fn validate_custom_categories() {
+        let categories = default_categories();
+        let spec = |name: &str, parent_id: Option<&str>| CreateCategorySpec {
+            name: name.to_string(),
+            parent_id: parent_id.map(String::from),
+        };
+        assert!(spec("Pets", None).validate(&categories).is_ok());
+        assert!(spec("Bakery", Some("food")).validate(&categories).is_ok());
+        assert!(spec("  ", None).validate(&categories).is_err());
+        assert!(spec("groceries", Some("food"))
+            .validate(&categories)
+            .is_err());
+        assert!(spec("Organic", Some("groceries"))
+            .validate(&categories)
+            .is_err());
+        assert!(spec("Bakery", Some("unknown"))
+            .validate(&categories)
+            .is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::service::account::anonymise_comment;
+use crate::service::expense::User;
+use crate::service::{object_id, NotFound, DEFAULT_DATABASE_NAME};
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use mongodb::options::FindOptions;
+use mongodb::{bson, Client, Database};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+#[async_trait]
+pub trait CommentsApi {
+    async fn get_comments(&self, expense_id: String) -> Result<Vec<Comment>, Error>;
+    async fn create_comment(
+        &self,
+        expense_id: String,
+        spec: CreateCommentSpec,
+    ) -> Result<Comment, Error>;
+    /// Records a comment on behalf of the system, e.g. when the expense is edited.
+    async fn create_system_comment(
+        &self,
+        expense_id: String,
+        content: String,
+    ) -> Result<Comment, Error>;
+    /// Deletes the comment, returns `None` if there is no comment with such id.
+    async fn delete_comment(&self, id: String) -> Result<Option<Comment>, Error>;
+    /// Comments written by the user on any expense, oldest first.
+    async fn get_user_comments(&self, user_id: String) -> Result<Vec<Comment>, Error>;
+    /// Removes the personal data of the user from the comments they wrote, see
+    /// [`anonymise_comment`]. Returns the number of comments changed.
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct CommentApiMongoAdapter {
+    db: Database,
+}
+
+impl CommentApiMongoAdapter {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+
+    /// Stores the comment on its expense, which has to exist. The count is incremented first so
+    /// that no comment is left on a missing expense.
+    async fn insert_comment(&self, comment: Comment) -> Result<Comment, Error> {
+        if !self
+            .increment_comments_count(&comment.expense_id, 1)
+            .await?
+        {
+            return Err(NotFound::new("Expense", &comment.expense_id).into());
+        }
+        let inserted = match self
+            .db
+            .collection::<Document>("comments")
+            .insert_one(bson::to_document(&comment)?, None)
+            .await
+        {
+            Ok(inserted) => inserted,
+            Err(error) => {
+                let _decremented = self
+                    .increment_comments_count(&comment.expense_id, -1)
+                    .await?;
+                return Err(error.into());
+            }
+        };
+        Ok(Comment {
+            id: inserted.inserted_id.as_object_id().map(|id| id.to_hex()),
+            ..comment
+        })
+    }
+
+    /// Keeps `commentsCount` of the expense in sync with the comments collection. Returns whether
+    /// the expense exists.
+    async fn increment_comments_count(&self, expense_id: &str, by: i64) -> Result<bool, Error> {
+        let Ok(id) = object_id("Expense", expense_id) else {
+            return Ok(false);
+        };
+        let update_result = self
+            .db
+            .collection::<Document>("expenses")
+            .update_one(doc! {"_id": id}, doc! {"$inc": {"commentsCount": by}}, None)
+            .await?;
+        Ok(update_result.matched_count > 0)
+    }
+}
+
+#[async_trait]
+impl CommentsApi for CommentApiMongoAdapter {
+    async fn get_comments(&self, expense_id: String) -> Result<Vec<Comment>, Error> {
+        let options = FindOptions::builder().sort(doc! {"createdAt": 1}).build();
+        let mut cursor = self
+            .db
+            .collection::<Document>("comments")
+            .find(doc! {"expenseId": expense_id}, options)
+            .await?;
+        let mut comments = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            comments.push(Comment::from_document(document)?);
+        }
+        Ok(comments)
+    }
+
+    async fn create_comment(
+        &self,
+        expense_id: String,
+        spec: CreateCommentSpec,
+    ) -> Result<Comment, Error> {
+        self.insert_comment(Comment {
+            id: None,
+            expense_id,
+            content: spec.content,
+            comment_type: CommentType::User,
+            created_at: Some(Utc::now()),
+            created_by: Some(spec.user),
+        })
+        .await
+    }
+
+    async fn create_system_comment(
+        &self,
+        expense_id: String,
+        content: String,
+    ) -> Result<Comment, Error> {
+        self.insert_comment(Comment {
+            id: None,
+            expense_id,
+            content,
+            comment_type: CommentType::System,
+            created_at: Some(Utc::now()),
+            created_by: None,
+        })
+        .await
+    }
+
+    async fn delete_comment(&self, id: String) -> Result<Option<Comment>, Error> {
+        let deleted = self
+            .db
+            .collection::<Document>("comments")
+            .find_one_and_delete(doc! {"_id": ObjectId::from_str(&id)?}, None)
+            .await?;
+        match deleted {
+            Some(document) => {
+                let comment = Comment::from_document(document)?;
+                let _existed = self
+                    .increment_comments_count(&comment.expense_id, -1)
+                    .await?;
+                Ok(Some(comment))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn get_user_comments(&self, user_id: String) -> Result<Vec<Comment>, Error> {
+        let options = FindOptions::builder().sort(doc! {"createdAt": 1}).build();
+        let mut cursor = self
+            .db
+            .collection::<Document>("comments")
+            .find(doc! {"createdBy.id": user_id}, options)
+            .await?;
+        let mut comments = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            comments.push(Comment::from_document(document)?);
+        }
+        Ok(comments)
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        let collection = self.db.collection::<Document>("comments");
+        let mut anonymised = 0;
+        for mut comment in self.get_user_comments(user_id.clone()).await? {
+            if !anonymise_comment(&mut comment, &user_id) {
+                continue;
+            }
+            let id = comment.id.as_deref().unwrap_or_default();
+            let _updated = collection
+                .update_one(
+                    doc! {"_id": ObjectId::from_str(id)?},
+                    doc! {"$set": {"createdBy": bson::to_bson(&comment.created_by)?}},
+                    None,
+                )
+                .await?;
+            anonymised += 1;
+        }
+        Ok(anonymised)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    /// Hex representation of the comment's `ObjectId`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    pub expense_id: String,
+
+    pub content: String,
+
+    pub comment_type: CommentType,
+
+    pub created_at: Option<DateTime<Utc>>,
+
+    /// Author of the comment, `None` for system comments.
+    pub created_by: Option<User>,
+}
+
+impl Comment {
+    fn from_document(mut document: Document) -> Result<Self, Error> {
+        let id = document.get_object_id("_id")?.to_hex();
+        let _id = document.remove("_id");
+        let comment: Comment = bson::from_document(document)?;
+        Ok(Comment {
+            id: Some(id),
+            ..comment
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentType {
+    User,
+    System,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCommentSpec {
+    pub content: String,
+
+    pub user: User,
+}
@@ -0,0 +1,73 @@
+use anyhow::{bail, Context, Error};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Value of one unit of each currency in a common reference currency, e.g. `EUR = 1.0` and
+/// `USD = 0.92` with the euro as reference. Rates are configured rather than fetched, amounts in
+/// currencies without a rate are not converted.
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct ExchangeRates(BTreeMap<String, f64>);
+
+impl ExchangeRates {
+    pub fn new(rates: BTreeMap<String, f64>) -> Self {
+        Self(rates)
+    }
+
+    /// Parses comma separated `CODE=rate` pairs, such as `EUR=1,USD=0.92`.
+    pub fn parse(rates: &str) -> Result<Self, Error> {
+        rates
+            .split(',')
+            .map(str::trim)
+            .filter(|rate| !rate.is_empty())
+            .map(|rate| {
+                let (code, value) = rate
+                    .split_once('=')
+                    .with_context(|| format!("Expected `CODE=rate`, got `{}`", rate))?;
+                let value = value
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid rate of {}, got `{}`", code, value))?;
+                Ok((code.trim().to_string(), value))
+            })
+            .collect::<Result<_, Error>>()
+            .map(Self)
+    }
+
+    /// Checks that every rate is a positive number.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (code, rate) in &self.0 {
+            if !rate.is_finite() || *rate <= 0.0 {
+                bail!("Exchange rate of {} must be greater than 0", code);
+            }
+        }
+        Ok(())
+    }
+
+    /// `cents` in `from` converted to cents in `to`, `None` if either has no rate.
+    pub fn convert(&self, cents: i64, from: &str, to: &str) -> Option<i64> {
+        if from == to {
+            return Some(cents);
+        }
+        let from = self.0.get(from)?;
+        let to = self.0.get(to)?;
+        Some((cents as f64 * from / to).round() as i64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ExchangeRates;
+
+    #[test]
+    fn convert_through_reference_currency() {
+        let rates = ExchangeRates::parse("EUR=1, USD=0.8,GBP=1.25").unwrap();
+        rates.validate().unwrap();
+        assert_eq!(rates.convert(1000, "USD", "EUR"), Some(800));
+        assert_eq!(rates.convert(1000, "USD", "GBP"), Some(640));
+        assert_eq!(rates.convert(1000, "JPY", "JPY"), Some(1000));
+        assert_eq!(rates.convert(1000, "JPY", "EUR"), None);
+        assert!(ExchangeRates::parse("EUR").is_err());
+        assert!(ExchangeRates::parse("EUR=0").unwrap().validate().is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::service::expense::Expense;
+use crate::service::history::ExpenseAction;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+/// Number of events a slow subscriber may fall behind before it starts missing them.
+const DEFAULT_CAPACITY: usize = 256;
+
+/// In-process broadcast channel of changes made to groups. Services publish to it after a write
+/// succeeded, the `/groups/{id}/events` stream subscribes to it.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<GroupEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _receiver) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<GroupEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, event: GroupEvent) {
+        // an error only means that nobody is listening at the moment
+        let _ = self.sender.send(event);
+    }
+
+    /// Publishes the change of the expense followed by the balance change it causes.
+    pub fn publish_expense(&self, expense_id: String, action: ExpenseAction, expense: &Expense) {
+        let group_id = match &expense.group_id {
+            Some(group_id) => group_id.clone(),
+            None => return,
+        };
+        let event_type = match action {
+            ExpenseAction::Created => GroupEventType::ExpenseCreated,
+            ExpenseAction::Updated => GroupEventType::ExpenseUpdated,
+            ExpenseAction::Deleted => GroupEventType::ExpenseDeleted,
+            ExpenseAction::Restored => GroupEventType::ExpenseRestored,
+        };
+        let user_ids = expense
+            .users
+            .iter()
+            .flatten()
+            .filter_map(|share| share.user.as_ref().and_then(|user| user.id.clone()))
+            .collect::<Vec<_>>();
+        self.publish(GroupEvent {
+            event_type,
+            group_id: group_id.clone(),
+            expense_id: Some(expense_id),
+            expense: Some(expense.clone()),
+            user_ids: user_ids.clone(),
+        });
+        self.publish(GroupEvent {
+            event_type: GroupEventType::BalanceChanged,
+            group_id,
+            expense_id: None,
+            expense: None,
+            user_ids,
+        });
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupEvent {
+    pub event_type: GroupEventType,
+
+    pub group_id: String,
+
+    pub expense_id: Option<String>,
+
+    /// State of the expense after the change.
+    pub expense: Option<Expense>,
+
+    /// Users whose balance is affected by the change.
+    pub user_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum GroupEventType {
+    ExpenseCreated,
+    ExpenseUpdated,
+    ExpenseDeleted,
+    ExpenseRestored,
+    BalanceChanged,
+}
+
+impl GroupEventType {
+    /// Name of the server-sent event.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupEventType::ExpenseCreated => "expenseCreated",
+            GroupEventType::ExpenseUpdated => "expenseUpdated",
+            GroupEventType::ExpenseDeleted => "expenseDeleted",
+            GroupEventType::ExpenseRestored => "expenseRestored",
+            GroupEventType::BalanceChanged => "balanceChanged",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EventBus, GroupEventType};
+    use crate::service::expense::Expense;
+    use crate::service::history::ExpenseAction;
+
+    #[tokio::test]
+    async fn publish_expense_and_balance_change() {
+        let bus = EventBus::default();
+        let mut receiver = bus.subscribe();
+        let expense = Expense {
+            group_id: Some("1".to_string()),
+            ..Expense::default()
+        };
+        bus.publish_expense("e1".to_string(), ExpenseAction::Updated, &expense);
+
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.event_type, GroupEventType::ExpenseUpdated);
+        assert_eq!(event.group_id, "1");
+        assert_eq!(event.expense_id, Some("e1".to_string()));
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event.event_type, GroupEventType::BalanceChanged);
+    }
+
+    #[test]
+    fn skip_expense_without_group() {
+        let bus = EventBus::default();
+        let mut receiver = bus.subscribe();
+        bus.publish_expense(
+            "e1".to_string(),
+            ExpenseAction::Created,
+            &Expense::default(),
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -1,18 +1,32 @@
+use crate::service::account::anonymise_expense;
+use crate::service::activity::{Activity, ActivityApi, ActivityApiMongoAdapter};
+use crate::service::comment::{CommentApiMongoAdapter, CommentsApi};
+use crate::service::events::EventBus;
+use crate::service::export::{cents, format_cents};
+use crate::service::history::{
+    ExpenseAction, ExpenseHistoryApi, ExpenseHistoryMongoAdapter, ExpenseRevision, FieldChange,
+};
+use crate::service::receipt::Receipt;
+use crate::service::version;
+use crate::service::version::{version_filter, Conditional};
+use crate::service::{object_id, NotFound, DEFAULT_DATABASE_NAME};
 use anyhow::Error;
 use async_trait::async_trait;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Months, Utc};
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{doc, Document};
+use mongodb::bson::{doc, Bson, Document};
 use mongodb::{bson, Client, Database};
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::str::FromStr;
 use tokio_stream::StreamExt;
+use utoipa::ToSchema;
 
 #[derive(Debug, Clone)]
 pub struct ExpenseApiMongoAdapter {
     pub db: Database,
+
+    events: Option<EventBus>,
 }
 
 #[async_trait]
@@ -20,52 +34,203 @@ pub trait ExpensesApi {
     async fn get_expense(&self, id: String) -> Result<Expense, Error>;
     async fn list_expenses(&self, request: ListExpensesRequest) -> Result<ExpensesResponse, Error>;
     async fn create_expense(&self, expense: CreateExpenseSpec) -> Result<ExpenseEntity, Error>;
+    /// Updates the expense unless it was changed since `expected_version`.
     async fn update_expense(
         &self,
         id: String,
         spec: UpdateExpenseSpec,
-    ) -> Result<Vec<Expense>, Error>;
-    async fn delete_expense(&self, id: i64) -> Result<(), Error>;
-    async fn restore_expense(&self, id: i64) -> Result<(), Error>;
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error>;
+    /// Deletes the expense unless it was changed since `expected_version`.
+    async fn delete_expense(
+        &self,
+        id: String,
+        deleted_by: User,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error>;
+    async fn restore_expense(&self, id: String, restored_by: User) -> Result<(), Error>;
+
+    /// Stores all the expenses, or none of them if any fails to be stored.
+    async fn import_expenses(&self, expenses: Vec<Expense>) -> Result<Vec<ExpenseEntity>, Error>;
+
+    /// Removes the personal data of the user from every expense they are involved in, see
+    /// [`anonymise_expense`]. Their shares are kept and no history is recorded. Returns the number
+    /// of expenses changed.
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error>;
+
+    /// Sets or clears the receipt of the expense, recorded in its history like an update, unless
+    /// it was changed since `expected_version`.
+    async fn set_receipt(
+        &self,
+        id: String,
+        receipt: Option<Receipt>,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error>;
+
+    /// Removes the expense with its comments and history for good. Returns whether it existed.
+    async fn purge_expense(&self, id: String) -> Result<bool, Error>;
 }
 
 impl ExpenseApiMongoAdapter {
     pub fn new(db: Database) -> Self {
-        Self { db }
+        Self { db, events: None }
     }
 
     pub fn new_with(client: Client) -> Self {
-        Self::new(client.database("swc"))
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+
+    /// Publishes every change of an expense to the given bus.
+    pub fn with_events(self, events: EventBus) -> Self {
+        Self {
+            events: Some(events),
+            ..self
+        }
+    }
+
+    /// Sets the top level fields on the expense and increments its version, appends the resulting
+    /// diff to its history and records the change in the group activity. Returns the updated
+    /// expense document together with the recorded revision, or `None` if the expense was changed
+    /// since `expected_version`.
+    async fn apply_update(
+        &self,
+        id: &str,
+        action: ExpenseAction,
+        changed_by: Option<User>,
+        set_document: Document,
+        expected_version: Option<i64>,
+    ) -> Result<Option<(Document, ExpenseRevision)>, Error> {
+        let collection = self.db.collection::<Document>("expenses");
+        let filter = doc! {
+            "_id": object_id("Expense", id)?
+        };
+        let not_found = || Error::new(NotFound::new("Expense", id));
+        let mut conditional_filter = filter.clone();
+        if let Some(expected_version) = expected_version {
+            let _previous = conditional_filter.insert("version", version_filter(expected_version));
+        }
+        let update = doc! {
+            "$set": &set_document,
+            "$inc": {"version": 1_i64},
+        };
+        let before = match collection
+            .find_one_and_update(conditional_filter, update, None)
+            .await?
+        {
+            Some(before) => before,
+            None if collection.count_documents(filter, None).await? > 0 => return Ok(None),
+            None => return Err(not_found()),
+        };
+        // the update is applied to the document it was made on, rather than read again and
+        // possibly with a later change mixed in
+        let version = before
+            .get("version")
+            .and_then(Bson::as_i64)
+            .unwrap_or(version::UNVERSIONED);
+        let mut after = before.clone();
+        after.extend(set_document);
+        let _previous = after.insert("version", version + 1);
+        let revision = ExpenseHistoryMongoAdapter::new(self.db.clone())
+            .record_revision(id.to_string(), action, changed_by.clone(), &before, &after)
+            .await?;
+        let expense: Expense = bson::from_document(after.clone())?;
+        let _activity = ActivityApiMongoAdapter::new(self.db.clone())
+            .record(Activity::for_expense(
+                id.to_string(),
+                action,
+                changed_by.as_ref(),
+                &expense,
+            ))
+            .await?;
+        if let Some(events) = &self.events {
+            events.publish_expense(id.to_string(), action, &expense);
+        }
+        Ok(Some((after, revision)))
+    }
+
+    /// Records the creation of the expense stored under `id` in its history and the group
+    /// activity.
+    async fn record_creation(&self, id: String, expense: &Expense) -> Result<(), Error> {
+        self.record_created(id.clone(), expense).await?;
+        if let Some(events) = &self.events {
+            events.publish_expense(id, ExpenseAction::Created, expense);
+        }
+        Ok(())
+    }
+
+    /// [`Self::record_creation`] without publishing it.
+    async fn record_created(&self, id: String, expense: &Expense) -> Result<(), Error> {
+        let _revision = ExpenseHistoryMongoAdapter::new(self.db.clone())
+            .record_revision(
+                id.clone(),
+                ExpenseAction::Created,
+                expense.created_by.clone(),
+                &Document::new(),
+                &bson::to_document(expense)?,
+            )
+            .await?;
+        let _activity = ActivityApiMongoAdapter::new(self.db.clone())
+            .record(Activity::for_expense(
+                id.clone(),
+                ExpenseAction::Created,
+                expense.created_by.as_ref(),
+                expense,
+            ))
+            .await?;
+        Ok(())
+    }
+
+    /// Removes the expenses with whatever was recorded about them.
+    async fn remove_expenses(&self, ids: &[ObjectId]) -> Result<(), Error> {
+        let expense_ids = ids.iter().map(|id| id.to_hex()).collect::<Vec<_>>();
+        let _expenses = self
+            .db
+            .collection::<Document>("expenses")
+            .delete_many(doc! {"_id": {"$in": ids}}, None)
+            .await?;
+        for collection in ["expense_history", "activity"] {
+            let _recorded = self
+                .db
+                .collection::<Document>(collection)
+                .delete_many(doc! {"expenseId": {"$in": &expense_ids}}, None)
+                .await?;
+        }
+        Ok(())
     }
 }
 
 #[async_trait]
 impl ExpensesApi for ExpenseApiMongoAdapter {
     async fn get_expense(&self, id: String) -> Result<Expense, Error> {
-        let docs = self
+        let document = self
             .db
             .collection::<Document>("expenses")
-            .find_one(
-                doc! {
-                    "_id": ObjectId::from_str(&id).expect("Invalid id")
-                },
-                None,
-            )
-            .await?;
-        let document = docs.unwrap();
-        let expense: Result<Expense, _> = bson::from_document(document);
-        Ok(expense.unwrap())
+            .find_one(doc! {"_id": object_id("Expense", &id)?}, None)
+            .await?
+            .ok_or_else(|| NotFound::new("Expense", &id))?;
+        Ok(bson::from_document(document)?)
     }
 
-    async fn list_expenses(
-        &self,
-        _request: ListExpensesRequest,
-    ) -> Result<ExpensesResponse, Error> {
-        let mut cursor = self.db.collection("expenses").find(None, None).await?;
+    async fn list_expenses(&self, request: ListExpensesRequest) -> Result<ExpensesResponse, Error> {
+        let mut filter = Document::new();
+        if let Some(group_id) = &request.group_id {
+            let _previous = filter.insert("groupId", group_id);
+        }
+        if let Some(user_id) = &request.user_id {
+            let _previous = filter.insert("users.user.id", user_id);
+        }
+        let mut cursor = self
+            .db
+            .collection::<Expense>("expenses")
+            .find(filter, None)
+            .await?;
         let mut expenses = Vec::new();
         while let Some(result) = cursor.next().await {
             let expense = result?;
-            expenses.push(expense);
+            // dates are stored as strings, they are compared here rather than in the query
+            if request.matches(&expense) {
+                expenses.push(expense);
+            }
         }
         Ok(ExpensesResponse { expenses })
     }
@@ -73,17 +238,17 @@ impl ExpensesApi for ExpenseApiMongoAdapter {
     /// Create a new expense. expense is saved to the dedicated collection and record in the balance
     /// collection is updated
     async fn create_expense(&self, expense: CreateExpenseSpec) -> Result<ExpenseEntity, Error> {
-        let expense = ExpensesCalculator::new()
-            .create_expense(&expense)
-            .expect("Can not create expense");
+        let expense = ExpensesCalculator::new().create_expense(&expense)?;
         let (expense_document, option) = (bson::to_document(&expense)?, None);
         let expense_created = self
             .db
-            .collection("expenses")
-            .insert_one(expense_document, option)
+            .collection::<Document>("expenses")
+            .insert_one(&expense_document, option)
             .await?;
+        let id = expense_created.inserted_id.as_object_id().unwrap();
+        self.record_creation(id.to_hex(), &expense).await?;
         Ok(ExpenseEntity {
-            id: Some(expense_created.inserted_id.as_object_id().unwrap()),
+            id: Some(id),
             expense,
         })
     }
@@ -92,52 +257,267 @@ impl ExpensesApi for ExpenseApiMongoAdapter {
         &self,
         id: String,
         update_expense_spec: UpdateExpenseSpec,
-    ) -> Result<Vec<Expense>, Error> {
-        let filter = doc! {
-            "_id": ObjectId::from_str(&id).expect("Invalid id")
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        // the series of a repeating expense is anchored at the date of the expense, line items
+        // are split with its payer
+        let current = if update_expense_spec.needs_current() {
+            Some(self.get_expense(id.clone()).await?)
+        } else {
+            None
+        };
+        let set_document = update_expense_spec.to_set_document(current.as_ref())?;
+
+        let Some((document, revision)) = self
+            .apply_update(
+                &id,
+                ExpenseAction::Updated,
+                update_expense_spec.updated_by,
+                set_document,
+                expected_version,
+            )
+            .await?
+        else {
+            return Ok(Conditional::Stale(self.get_expense(id).await?));
         };
 
-        let set_bson = bson::to_bson(&update_expense_spec)?;
+        if !revision.changes.is_empty() {
+            let _comment = CommentApiMongoAdapter::new(self.db.clone())
+                .create_system_comment(id, describe_changes(&revision.changes))
+                .await?;
+        }
 
-        let update = doc! {
-            "$set": set_bson
-        };
+        Ok(Conditional::Applied(bson::from_document(document)?))
+    }
 
-        let option = None;
+    async fn delete_expense(
+        &self,
+        id: String,
+        deleted_by: User,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        let set_document = deletion_document(&deleted_by)?;
+        match self
+            .apply_update(
+                &id,
+                ExpenseAction::Deleted,
+                Some(deleted_by),
+                set_document,
+                expected_version,
+            )
+            .await?
+        {
+            Some((document, _revision)) => Ok(Conditional::Applied(bson::from_document(document)?)),
+            None => Ok(Conditional::Stale(self.get_expense(id).await?)),
+        }
+    }
 
-        let _update_result = self
-            .db
-            .collection::<Document>("expenses")
-            .update_one(filter, update, option)
+    async fn restore_expense(&self, id: String, restored_by: User) -> Result<(), Error> {
+        let set_document = restoration_document(&restored_by)?;
+        let _updated = self
+            .apply_update(
+                &id,
+                ExpenseAction::Restored,
+                Some(restored_by),
+                set_document,
+                None,
+            )
             .await?;
+        Ok(())
+    }
 
-        Ok(vec![])
+    /// Inserted in one `insertMany`. As transactions are not available on standalone servers, the
+    /// expenses are removed again with their history and activity if any of it fails, and their
+    /// creation is only published once all of it is stored.
+    async fn import_expenses(&self, expenses: Vec<Expense>) -> Result<Vec<ExpenseEntity>, Error> {
+        let ids = expenses.iter().map(|_| ObjectId::new()).collect::<Vec<_>>();
+        let documents = ids
+            .iter()
+            .zip(&expenses)
+            .map(|(id, expense)| {
+                let mut document = bson::to_document(expense)?;
+                let _previous = document.insert("_id", id);
+                Ok(document)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let stored = async {
+            let _inserted = self
+                .db
+                .collection::<Document>("expenses")
+                .insert_many(&documents, None)
+                .await?;
+            for (id, expense) in ids.iter().zip(&expenses) {
+                self.record_created(id.to_hex(), expense).await?;
+            }
+            Ok::<_, Error>(())
+        };
+        if let Err(error) = stored.await {
+            self.remove_expenses(&ids).await?;
+            return Err(error);
+        }
+        let mut imported = Vec::with_capacity(expenses.len());
+        for (id, expense) in ids.into_iter().zip(expenses) {
+            if let Some(events) = &self.events {
+                events.publish_expense(id.to_hex(), ExpenseAction::Created, &expense);
+            }
+            imported.push(ExpenseEntity {
+                id: Some(id),
+                expense,
+            });
+        }
+        Ok(imported)
     }
 
-    async fn delete_expense(&self, _id: i64) -> Result<(), Error> {
-        Ok(())
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        let collection = self.db.collection::<Document>("expenses");
+        let filter = doc! {"$or": [
+            {"users.user.id": &user_id},
+            {"createdBy.id": &user_id},
+            {"updatedBy.id": &user_id},
+            {"deletedBy.id": &user_id},
+        ]};
+        let mut documents = Vec::new();
+        let mut cursor = collection.find(filter, None).await?;
+        while let Some(document) = cursor.next().await {
+            documents.push(document?);
+        }
+        let mut anonymised = 0;
+        for document in documents {
+            let id = document.get_object_id("_id")?;
+            let mut expense: Expense = bson::from_document(document)?;
+            if !anonymise_expense(&mut expense, &user_id) {
+                continue;
+            }
+            let mut anonymised_document = bson::to_document(&expense)?;
+            let mut set_document = Document::new();
+            for field in ["users", "createdBy", "updatedBy", "deletedBy"] {
+                let value = anonymised_document.remove(field).unwrap_or(Bson::Null);
+                let _previous = set_document.insert(field, value);
+            }
+            let _updated = collection
+                .update_one(
+                    doc! {"_id": id},
+                    doc! {"$set": set_document, "$inc": {"version": 1_i64}},
+                    None,
+                )
+                .await?;
+            anonymised += 1;
+        }
+        Ok(anonymised)
     }
 
-    async fn restore_expense(&self, _id: i64) -> Result<(), Error> {
-        Ok(())
+    async fn set_receipt(
+        &self,
+        id: String,
+        receipt: Option<Receipt>,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        let set_document = doc! {"receipt": bson::to_bson(&receipt)?};
+        match self
+            .apply_update(
+                &id,
+                ExpenseAction::Updated,
+                None,
+                set_document,
+                expected_version,
+            )
+            .await?
+        {
+            Some((document, _revision)) => Ok(Conditional::Applied(bson::from_document(document)?)),
+            None => Ok(Conditional::Stale(self.get_expense(id).await?)),
+        }
     }
+
+    async fn purge_expense(&self, id: String) -> Result<bool, Error> {
+        let deleted = self
+            .db
+            .collection::<Document>("expenses")
+            .delete_one(doc! {"_id": object_id("Expense", &id)?}, None)
+            .await?;
+        for collection in ["comments", "expense_history", "activity"] {
+            let _recorded = self
+                .db
+                .collection::<Document>(collection)
+                .delete_many(doc! {"expenseId": &id}, None)
+                .await?;
+        }
+        Ok(deleted.deleted_count > 0)
+    }
+}
+
+/// Fields to set when the expense is deleted.
+pub(crate) fn deletion_document(deleted_by: &User) -> Result<Document, Error> {
+    let now = bson::to_bson(&Utc::now())?;
+    let deleted_by = bson::to_bson(deleted_by)?;
+    Ok(doc! {
+        "deletedAt": &now,
+        "deletedBy": &deleted_by,
+        "updatedAt": now,
+        "updatedBy": deleted_by,
+    })
+}
+
+/// Fields to set when the expense is restored.
+pub(crate) fn restoration_document(restored_by: &User) -> Result<Document, Error> {
+    Ok(doc! {
+        "deletedAt": null,
+        "deletedBy": null,
+        "updatedAt": bson::to_bson(&Utc::now())?,
+        "updatedBy": bson::to_bson(restored_by)?,
+    })
+}
+
+/// Sets the fields of `set_document` on the expense the way `$set` does, for backends which
+/// can't apply the update themselves. Returns the expense documents before and after the update.
+pub(crate) fn merge_update(
+    expense: &Expense,
+    set_document: Document,
+) -> Result<(Document, Document), Error> {
+    let before = bson::to_document(expense)?;
+    let mut after = before.clone();
+    after.extend(set_document);
+    Ok((before, after))
+}
+
+/// Human readable summary of an update, used for system comments.
+pub(crate) fn describe_changes(changes: &[FieldChange]) -> String {
+    let display = |value: &Bson| match value {
+        Bson::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+    let changes = changes
+        .iter()
+        .map(|change| match (&change.from, &change.to) {
+            (Some(from), Some(to)) => {
+                format!("{} from {} to {}", change.field, display(from), display(to))
+            }
+            (None, Some(to)) => format!("{} to {}", change.field, display(to)),
+            (_, None) => format!("{} to nothing", change.field),
+        })
+        .collect::<Vec<_>>();
+    format!("Updated {}", changes.join(", "))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ExpenseEntity {
+    /// Extended JSON of the `ObjectId`, `{"$oid": "<hex>"}`.
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
     pub id: Option<ObjectId>,
 
     pub expense: Expense,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Expense {
     pub cost: Option<String>,
 
     pub description: Option<String>,
 
+    /// Id of the category, see `GET /categories`.
+    pub category: Option<String>,
+
     pub date: Option<DateTime<Utc>>,
 
     pub repeat_interval: Option<RepeatInterval>,
@@ -179,13 +559,60 @@ pub struct Expense {
     pub deleted_by: Option<User>,
 
     pub users: Option<Vec<UserShare>>,
+
+    /// Id of the repeating expense this expense was generated from.
+    pub series_id: Option<String>,
+
+    /// When the next occurrence of a repeating expense is due.
+    pub next_repeat: Option<DateTime<Utc>>,
+
+    /// Number of comments left on the expense.
+    pub comments_count: Option<i64>,
+
+    /// Incremented on every change, sent as `ETag` to detect concurrent edits. `None` for
+    /// expenses stored before versioning.
+    pub version: Option<i64>,
+
+    /// Receipt uploaded through `POST /expenses/{id}/receipt`.
+    pub receipt: Option<Receipt>,
+
+    /// Line items the shares were computed from, for itemized expenses.
+    pub itemized: Option<ItemizedSplit>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+impl Expense {
+    /// Share of the user with `user_id` in the expense.
+    pub fn share_of(&self, user_id: &str) -> Option<&UserShare> {
+        self.users
+            .iter()
+            .flatten()
+            .find(|share| matches!(&share.user, Some(User { id: Some(id), .. }) if id == user_id))
+    }
+
+    /// Id of the user who paid the expense, the creator if nobody paid anything yet. `None` if
+    /// several users paid it.
+    pub fn payer_id(&self) -> Result<Option<String>, Error> {
+        let mut payers = Vec::new();
+        for share in self.users.iter().flatten() {
+            if cents(share.paid_share.as_deref())? > 0 {
+                payers.push(share);
+            }
+        }
+        Ok(match payers.as_slice() {
+            [] => self.created_by.as_ref().and_then(|user| user.id.clone()),
+            [payer] => payer.user.as_ref().and_then(|user| user.id.clone()),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Debt {
-    pub from: Option<i64>,
+    /// Id of the user who owes.
+    pub from: Option<String>,
 
-    pub to: Option<i64>,
+    /// Id of the user who is owed.
+    pub to: Option<String>,
 
     pub amount: Option<String>,
 
@@ -197,11 +624,14 @@ pub struct ExpensesResponse {
     pub expenses: Vec<Expense>,
 }
 
-#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct ListExpensesRequest {
     /// If provided, only expenses in that group will be returned, and
     /// `friend_id` will be ignored.
-    pub group_id: Option<i64>,
+    pub group_id: Option<String>,
+
+    /// If provided, only expenses the user has a share in will be returned.
+    pub user_id: Option<String>,
 
     /// ID of another user. If provided, only expenses between the current and
     /// provided user will be returned.
@@ -228,7 +658,31 @@ pub struct ListExpensesRequest {
     pub offset: Option<i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ListExpensesRequest {
+    /// Whether the expense passes the group, user and date filters of the request.
+    pub(crate) fn matches(&self, expense: &Expense) -> bool {
+        let within = |date: Option<DateTime<Utc>>,
+                      after: Option<DateTime<Utc>>,
+                      before: Option<DateTime<Utc>>| {
+            match date {
+                Some(date) => {
+                    after.is_none_or(|after| date > after)
+                        && before.is_none_or(|before| date < before)
+                }
+                None => after.is_none() && before.is_none(),
+            }
+        };
+        (self.group_id.is_none() || expense.group_id == self.group_id)
+            && self
+                .user_id
+                .as_ref()
+                .is_none_or(|user_id| expense.share_of(user_id).is_some())
+            && within(expense.date, self.dated_after, self.dated_before)
+            && within(expense.updated_at, self.updated_after, self.updated_before)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateExpenseSpec {
     /// A string representation of a decimal value, limited to 2 decimal places.
@@ -237,6 +691,37 @@ pub struct CreateExpenseSpec {
     pub group_id: String,
 
     pub user: User,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Id of a default category or a custom category of the group, see `GET /categories`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+
+    /// Whether this is a payment between users rather than an expense.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment: Option<bool>,
+
+    /// Cadence at which the expense repeats. Defaults to `never`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_interval: Option<RepeatInterval>,
+
+    /// Line items to split the expense by, rather than equally. `cost` must be their total with
+    /// tax and tip.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub itemized: Option<ItemizedSplit>,
+}
+
+impl CreateExpenseSpec {
+    /// Checks that the cost is a positive amount and that the paying user has an id.
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_cost(&self.cost)?;
+        if self.user.id.is_none() {
+            return Err(Error::msg("The user paying the expense has no id"));
+        }
+        Ok(())
+    }
 }
 
 impl Default for CreateExpenseSpec {
@@ -245,11 +730,17 @@ impl Default for CreateExpenseSpec {
             cost: "0.00".to_string(),
             group_id: "".to_string(),
             user: User::default(),
+            description: None,
+            category: None,
+            payment: None,
+            repeat_interval: None,
+            itemized: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
 pub enum RepeatInterval {
     Never,
     Weekly,
@@ -264,6 +755,33 @@ impl fmt::Display for RepeatInterval {
     }
 }
 
+impl RepeatInterval {
+    pub fn repeats(&self) -> bool {
+        !matches!(self, RepeatInterval::Never)
+    }
+
+    /// The `n`-th occurrence of a series starting at `anchor`. Monthly and yearly series keep the
+    /// day of month of the anchor and fall back to the last day of shorter months.
+    pub fn occurrence(&self, anchor: DateTime<Utc>, n: u32) -> Option<DateTime<Utc>> {
+        match self {
+            RepeatInterval::Never => None,
+            RepeatInterval::Weekly => anchor.checked_add_signed(Duration::weeks(n.into())),
+            RepeatInterval::Fortnightly => {
+                anchor.checked_add_signed(Duration::weeks(2 * i64::from(n)))
+            }
+            RepeatInterval::Monthly => anchor.checked_add_months(Months::new(n)),
+            RepeatInterval::Yearly => anchor.checked_add_months(Months::new(12 * n)),
+        }
+    }
+
+    /// The first occurrence of a series starting at `anchor` which falls strictly after `after`.
+    pub fn next_after(&self, anchor: DateTime<Utc>, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        (1..)
+            .map_while(|n| self.occurrence(anchor, n))
+            .find(|date| *date > after)
+    }
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct GroupBalance {
     pub group_id: Option<i64>,
@@ -271,13 +789,13 @@ pub struct GroupBalance {
     pub balance: Option<Vec<Balance>>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Balance {
     pub currency_code: Option<String>,
     pub amount: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UpdateExpenseSpec {
     /// A string representation of a decimal value, limited to 2 decimal places.
@@ -290,34 +808,81 @@ pub struct UpdateExpenseSpec {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub details: Option<String>,
 
+    /// Id of a default category or a custom category of the group, see `GET /categories`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+
     /// The date and time the expense took place. May differ from `created_at`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub date: Option<DateTime<Utc>>,
 
-    // TODO: Make this an enum
-    /// Cadence at which the expense repeats. One of:
+    /// Cadence at which the expense repeats, `never` stops the series while the occurrences
+    /// generated so far are kept. One of:
     /// - `never`
     /// - `weekly`
     /// - `fortnightly`
     /// - `monthly`
     /// - `yearly`
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub repeat_interval: Option<String>,
+    pub repeat_interval: Option<RepeatInterval>,
 
     /// A currency code. Must be in the list from `get_currencies`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub currency_code: Option<String>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub group_id: Option<String>,
 
+    /// User making the change.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_by: Option<User>,
+
+    /// Line items to split the expense by again, the cost and shares are computed from them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub itemized: Option<ItemizedSplit>,
+
     /// Users by share if not splitting the expense equally.
     #[serde(flatten)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub users: Option<Vec<UserShare>>,
 }
 
+impl UpdateExpenseSpec {
+    /// Whether [`UpdateExpenseSpec::to_set_document`] needs the expense before the update.
+    pub(crate) fn needs_current(&self) -> bool {
+        (self.repeat_interval.is_some() && self.date.is_none()) || self.itemized.is_some()
+    }
+
+    /// Fields to set on the expense. `current` is the expense before the update: changing the
+    /// interval edits the series going forward from its date while occurrences which were
+    /// already generated are left untouched, and new line items are split with its payer.
+    pub(crate) fn to_set_document(&self, current: Option<&Expense>) -> Result<Document, Error> {
+        let mut set_document = bson::to_document(self)?;
+        let _ = set_document.insert("updatedAt", bson::to_bson(&Utc::now())?);
+        if let Some(itemized) = &self.itemized {
+            itemized.validate(self.cost.as_deref())?;
+            let payer_id = current
+                .map(Expense::payer_id)
+                .transpose()?
+                .flatten()
+                .ok_or_else(|| Error::msg("Itemized expenses must be paid by a single user"))?;
+            let shares = ShareCalculator::new().itemized_share(itemized, payer_id)?;
+            let _ = set_document.insert("cost", format_cents(itemized.total()?));
+            let _ = set_document.insert("users", bson::to_bson(&shares)?);
+        }
+        if let Some(repeat_interval) = self.repeat_interval {
+            let current_date = current.and_then(|expense| expense.date);
+            let anchor = self.date.or(current_date).unwrap_or_else(Utc::now);
+            let next_repeat = repeat_interval.next_after(anchor, Utc::now());
+            let _ = set_document.insert("repeats", repeat_interval.repeats());
+            let _ = set_document.insert("nextRepeat", bson::to_bson(&next_repeat)?);
+        }
+        Ok(set_document)
+    }
+}
+
 /// User with share information associated with the expense.
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UserShare {
     pub user: Option<User>,
@@ -329,7 +894,81 @@ pub struct UserShare {
     pub net_balance: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+/// Split of a bill by line items: every item is shared equally by the users who had it, tax and
+/// tip are shared in proportion to what each user had.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemizedSplit {
+    pub items: Vec<LineItem>,
+
+    /// A string representation of a decimal value, limited to 2 decimal places.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tax: Option<String>,
+
+    /// A string representation of a decimal value, limited to 2 decimal places.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tip: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LineItem {
+    pub name: String,
+
+    /// A string representation of a decimal value, limited to 2 decimal places.
+    pub price: String,
+
+    /// Ids of the users who had the item.
+    pub user_ids: Vec<String>,
+}
+
+impl ItemizedSplit {
+    /// Total of the items, tax and tip in cents.
+    pub fn total(&self) -> Result<i64, Error> {
+        self.items
+            .iter()
+            .map(|item| cents(Some(&item.price)))
+            .chain([self.tax.as_deref(), self.tip.as_deref()].map(cents))
+            .sum()
+    }
+
+    /// Checks the prices, tax and tip and that every item was had by someone. `cost`, if given,
+    /// must be the total.
+    pub fn validate(&self, cost: Option<&str>) -> Result<(), Error> {
+        if self.items.is_empty() {
+            return Err(Error::msg("Itemized expenses need at least one item"));
+        }
+        for item in &self.items {
+            validate_cost(&item.price)?;
+            let mut user_ids = item.user_ids.clone();
+            user_ids.sort();
+            user_ids.dedup();
+            if user_ids.len() != item.user_ids.len() || user_ids.is_empty() {
+                return Err(Error::msg(format!(
+                    "Item {:?} must be had by distinct users",
+                    item.name
+                )));
+            }
+        }
+        for amount in [&self.tax, &self.tip].into_iter().flatten() {
+            // tax and tip may be zero, unlike costs
+            if amount.parse::<f64>() != Ok(0.0) {
+                validate_cost(amount)?;
+            }
+        }
+        let total = self.total()?;
+        match cost {
+            Some(cost) if cents(Some(cost))? != total => Err(Error::msg(format!(
+                "Cost {} is not the itemized total {}",
+                cost,
+                format_cents(total)
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct User {
     pub id: Option<String>,
@@ -363,26 +1002,64 @@ pub struct ShareCalculator {}
 
 impl Expenses for ExpensesCalculator {
     fn create_expense(&self, create_expense_spec: &CreateExpenseSpec) -> Result<Expense, Error> {
+        create_expense_spec.validate()?;
         let user = create_expense_spec.user.clone();
-        let payer_id = user.id.as_ref().expect("No user id");
-        let share = ShareCalculator::new().equal_share(
-            create_expense_spec.cost.clone(),
-            payer_id.clone(),
-            vec![payer_id.clone()],
-        );
+        let date = Utc::now();
+        let repeat_interval = create_expense_spec
+            .repeat_interval
+            .unwrap_or(RepeatInterval::Never);
+        let payer_id = user
+            .id
+            .as_ref()
+            .ok_or_else(|| Error::msg("The user paying the expense has no id"))?;
+        let share = match &create_expense_spec.itemized {
+            Some(itemized) => {
+                itemized.validate(Some(&create_expense_spec.cost))?;
+                ShareCalculator::new().itemized_share(itemized, payer_id.clone())?
+            }
+            None => ShareCalculator::new().equal_share(
+                create_expense_spec.cost.clone(),
+                payer_id.clone(),
+                vec![payer_id.clone()],
+            ),
+        };
         Ok(Expense {
             cost: Some(create_expense_spec.cost.clone()),
+            description: create_expense_spec.description.clone(),
+            category: create_expense_spec.category.clone(),
+            payment: Some(create_expense_spec.payment.unwrap_or(false)),
             group_id: Some(create_expense_spec.group_id.parse()?),
             users: Some(share),
             created_at: Some(Utc::now()),
             updated_at: Some(Utc::now()),
             created_by: Some(user),
-            date: Some(Utc::now()),
+            date: Some(date),
+            repeat_interval: Some(repeat_interval),
+            repeats: Some(repeat_interval.repeats()),
+            next_repeat: repeat_interval.next_after(date, date),
+            comments_count: Some(0),
+            version: Some(1),
+            itemized: create_expense_spec.itemized.clone(),
             ..Expense::default()
         })
     }
 }
 
+/// Checks that `cost` is a positive amount with at most 2 decimal places.
+pub(crate) fn validate_cost(cost: &str) -> Result<(), Error> {
+    let (units, decimals) = cost.split_once('.').unwrap_or((cost, "0"));
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    let positive = cost.parse::<f64>().is_ok_and(|cost| cost > 0.0);
+    if digits(units) && digits(decimals) && decimals.len() <= 2 && positive {
+        Ok(())
+    } else {
+        Err(Error::msg(format!(
+            "Cost {:?} is not a positive amount with at most 2 decimal places",
+            cost
+        )))
+    }
+}
+
 impl ShareCalculator {
     pub fn new() -> Self {
         ShareCalculator {}
@@ -413,7 +1090,7 @@ impl ShareCalculator {
             net_balance: Some(format!("{0:.2}", payer_net)),
         };
 
-        let debt_net = common_share * -1_f64;
+        let debt_net = -common_share;
         let remainder_share = group
             .iter()
             .filter(|&user_id| user_id != &payer_id)
@@ -433,6 +1110,66 @@ impl ShareCalculator {
             .collect::<Vec<UserShare>>();
         remainder_share
     }
+
+    /// Shares of an itemized bill paid by `payer_id`, in the order the users first appear in the
+    /// items. Cents which can't be divided evenly go to the users listed first, and to the largest
+    /// remainders for tax and tip, so that the owed shares add up to the total exactly.
+    pub fn itemized_share(
+        &self,
+        itemized: &ItemizedSplit,
+        payer_id: String,
+    ) -> Result<Vec<UserShare>, Error> {
+        itemized.validate(None)?;
+        let mut owed: Vec<(String, i64)> = Vec::new();
+        for item in &itemized.items {
+            let price = cents(Some(&item.price))?;
+            let users = item.user_ids.len() as i64;
+            for (position, user_id) in item.user_ids.iter().enumerate() {
+                let share = price / users + i64::from((position as i64) < price % users);
+                match owed.iter_mut().find(|(id, _)| id == user_id) {
+                    Some((_, owed)) => *owed += share,
+                    None => owed.push((user_id.clone(), share)),
+                }
+            }
+        }
+
+        let subtotal = owed.iter().map(|(_, owed)| owed).sum::<i64>();
+        let extra = cents(itemized.tax.as_deref())? + cents(itemized.tip.as_deref())?;
+        let mut extras = owed
+            .iter()
+            .map(|(_, owed)| extra * owed / subtotal)
+            .collect::<Vec<_>>();
+        let mut by_remainder = (0..owed.len()).collect::<Vec<_>>();
+        by_remainder
+            .sort_by_key(|&position| std::cmp::Reverse(extra * owed[position].1 % subtotal));
+        let missing = extra - extras.iter().sum::<i64>();
+        for &position in by_remainder.iter().take(missing as usize) {
+            extras[position] += 1;
+        }
+        for ((_, owed), extra) in owed.iter_mut().zip(extras) {
+            *owed += extra;
+        }
+        if !owed.iter().any(|(id, _)| id == &payer_id) {
+            owed.push((payer_id.clone(), 0));
+        }
+
+        let total = itemized.total()?;
+        Ok(owed
+            .into_iter()
+            .map(|(user_id, owed)| {
+                let paid = if user_id == payer_id { total } else { 0 };
+                UserShare {
+                    user: Some(User {
+                        id: Some(user_id),
+                        ..User::default()
+                    }),
+                    paid_share: Some(format_cents(paid)),
+                    owed_share: Some(format_cents(owed)),
+                    net_balance: Some(format_cents(paid - owed)),
+                }
+            })
+            .collect())
+    }
 }
 
 mod test {
@@ -560,6 +1297,7 @@ mod test {
                     id: Some("1".to_string()),
                     ..Default::default()
                 },
+                ..CreateExpenseSpec::default()
             })
             .expect("Failed to create expense");
         assert_eq!(expense.cost, Some("42.00".to_string()));
@@ -578,5 +1316,133 @@ mod test {
         assert_eq!(share.paid_share, Some("42.00".to_string()));
         assert_eq!(share.owed_share, Some("42.00".to_string()));
         assert_eq!(share.net_balance, Some("0.00".to_string()));
+        assert_eq!(expense.repeats, Some(false));
+        assert!(expense.next_repeat.is_none());
+    }
+
+    #[test]
+    fn split_itemized_bill_proportionally() {
+        use super::{ItemizedSplit, LineItem, ShareCalculator};
+        let item = |name: &str, price: &str, user_ids: &[&str]| LineItem {
+            name: name.to_string(),
+            price: price.to_string(),
+            user_ids: user_ids.iter().map(|id| id.to_string()).collect(),
+        };
+        let itemized = ItemizedSplit {
+            items: vec![
+                item("Pasta", "20.00", &["1"]),
+                item("Steak", "30.00", &["2"]),
+                item("Wine", "10.00", &["1", "2", "3"]),
+            ],
+            tax: Some("6.00".to_string()),
+            tip: Some("9.01".to_string()),
+        };
+        assert_eq!(itemized.total().unwrap(), 7501);
+
+        let shares = ShareCalculator::new()
+            .itemized_share(&itemized, "4".to_string())
+            .unwrap();
+        let owed = shares
+            .iter()
+            .map(|share| {
+                (
+                    share.user.as_ref().unwrap().id.clone().unwrap(),
+                    share.owed_share.clone().unwrap(),
+                    share.net_balance.clone().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("1", "29.18", "-29.18"),
+            ("2", "41.67", "-41.67"),
+            ("3", "4.16", "-4.16"),
+            ("4", "0.00", "75.01"),
+        ]
+        .map(|(id, owed, net)| (id.to_string(), owed.to_string(), net.to_string()));
+        assert_eq!(owed, expected);
+        assert_eq!(shares[3].paid_share.as_deref(), Some("75.01"));
+    }
+
+    #[test]
+    fn reject_invalid_itemized_bill() {
+        use super::{ItemizedSplit, LineItem};
+        let itemized = |user_ids: &[&str], tip: &str| ItemizedSplit {
+            items: vec![LineItem {
+                name: "Pizza".to_string(),
+                price: "12.50".to_string(),
+                user_ids: user_ids.iter().map(|id| id.to_string()).collect(),
+            }],
+            tax: None,
+            tip: Some(tip.to_string()),
+        };
+        assert!(itemized(&["1"], "0").validate(Some("12.50")).is_ok());
+        assert!(itemized(&["1"], "2.50").validate(Some("15")).is_ok());
+        assert!(itemized(&["1"], "2.50").validate(Some("12.50")).is_err());
+        assert!(itemized(&["1"], "-1").validate(None).is_err());
+        assert!(itemized(&[], "0").validate(None).is_err());
+        assert!(itemized(&["1", "1"], "0").validate(None).is_err());
+        assert!(ItemizedSplit::default().validate(None).is_err());
+    }
+
+    #[test]
+    fn validate_cost() {
+        use super::validate_cost;
+        for cost in ["42", "42.5", "0.01", "1000.00"] {
+            assert!(validate_cost(cost).is_ok(), "{}", cost);
+        }
+        for cost in ["", "0", "0.00", "-5", "4.567", "4.", ".5", "1e3", "12,50"] {
+            assert!(validate_cost(cost).is_err(), "{}", cost);
+        }
+    }
+
+    #[test]
+    fn monthly_occurrence_keeps_day_of_month() {
+        use super::RepeatInterval;
+        use chrono::{TimeZone, Utc};
+        let anchor = Utc.with_ymd_and_hms(2022, 1, 31, 9, 0, 0).unwrap();
+        let interval = RepeatInterval::Monthly;
+        assert_eq!(
+            interval.occurrence(anchor, 1),
+            Some(Utc.with_ymd_and_hms(2022, 2, 28, 9, 0, 0).unwrap())
+        );
+        assert_eq!(
+            interval.occurrence(anchor, 2),
+            Some(Utc.with_ymd_and_hms(2022, 3, 31, 9, 0, 0).unwrap())
+        );
+        assert_eq!(
+            interval.next_after(anchor, Utc.with_ymd_and_hms(2022, 4, 1, 0, 0, 0).unwrap()),
+            Some(Utc.with_ymd_and_hms(2022, 4, 30, 9, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn never_has_no_occurrence() {
+        use super::RepeatInterval;
+        use chrono::Utc;
+        let anchor = Utc::now();
+        assert_eq!(RepeatInterval::Never.occurrence(anchor, 1), None);
+        assert_eq!(RepeatInterval::Never.next_after(anchor, anchor), None);
+    }
+
+    #[test]
+    fn create_repeating_expense() {
+        use super::{CreateExpenseSpec, Expenses, ExpensesCalculator, RepeatInterval, User};
+        let expense = ExpensesCalculator::new()
+            .create_expense(&CreateExpenseSpec {
+                cost: "42.00".to_string(),
+                group_id: "1".to_string(),
+                user: User {
+                    id: Some("1".to_string()),
+                    ..Default::default()
+                },
+                repeat_interval: Some(RepeatInterval::Weekly),
+                ..CreateExpenseSpec::default()
+            })
+            .expect("Failed to create expense");
+        assert_eq!(expense.repeats, Some(true));
+        assert_eq!(
+            expense.next_repeat,
+            Some(expense.date.unwrap() + chrono::Duration::weeks(1))
+        );
     }
 }
@@ -0,0 +1,337 @@
+use crate::service::expense::{Expense, ListExpensesRequest, UserShare};
+use crate::service::group::Group;
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Utc};
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use utoipa::IntoParams;
+
+/// Date range of an export, the date filters of [`ListExpensesRequest`].
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRequest {
+    /// Only expenses dated after this date.
+    pub dated_after: Option<DateTime<Utc>>,
+
+    /// Only expenses dated before this date.
+    pub dated_before: Option<DateTime<Utc>>,
+}
+
+impl ExportRequest {
+    pub fn for_group(self, group_id: String) -> ListExpensesRequest {
+        ListExpensesRequest {
+            group_id: Some(group_id),
+            dated_after: self.dated_after,
+            dated_before: self.dated_before,
+            ..ListExpensesRequest::default()
+        }
+    }
+
+    pub fn for_user(self, user_id: String) -> ListExpensesRequest {
+        ListExpensesRequest {
+            user_id: Some(user_id),
+            dated_after: self.dated_after,
+            dated_before: self.dated_before,
+            ..ListExpensesRequest::default()
+        }
+    }
+}
+
+/// A row per expense of the group with the owed share and net balance of every member, followed
+/// by the final balance of every member per currency. Deleted expenses are left out. Rows are
+/// written to `csv` one by one.
+pub fn group_csv(csv: impl Write, group: &Group, expenses: &[Expense]) -> Result<(), Error> {
+    let expenses = exported(expenses);
+    let members = participants(group, &expenses);
+
+    let mut writer = Writer::from_writer(csv);
+    let mut header = ["Date", "Description", "Cost", "Currency", "Paid by"]
+        .map(String::from)
+        .to_vec();
+    for (_, name) in &members {
+        header.push(text(&format!("{} owed", name)));
+        header.push(text(&format!("{} net", name)));
+    }
+    writer.write_record(&header)?;
+    let mut balances = BTreeMap::<(usize, String), i64>::new();
+    for expense in &expenses {
+        let mut record = common_fields(expense, &members)?;
+        for (position, (id, _)) in members.iter().enumerate() {
+            let Some(share) = expense.share_of(id) else {
+                record.extend([String::new(), String::new()]);
+                continue;
+            };
+            let net = cents(share.net_balance.as_deref())?;
+            *balances.entry((position, currency(expense))).or_default() += net;
+            record.push(format_cents(cents(share.owed_share.as_deref())?));
+            record.push(format_cents(net));
+        }
+        writer.write_record(&record)?;
+    }
+
+    let mut summary = summary(writer)?;
+    summary.write_record(["Member", "Currency", "Balance"])?;
+    for ((position, currency), balance) in balances {
+        summary.write_record([
+            text(&members[position].1),
+            text(&currency),
+            format_cents(balance),
+        ])?;
+    }
+    summary.flush()?;
+    Ok(())
+}
+
+/// A row per expense the user has a share in with their paid and owed share and net balance,
+/// followed by their final balance per currency. Deleted expenses are left out. Rows are written
+/// to `csv` one by one.
+pub fn user_csv(csv: impl Write, user_id: &str, expenses: &[Expense]) -> Result<(), Error> {
+    let mut writer = Writer::from_writer(csv);
+    writer.write_record([
+        "Date",
+        "Description",
+        "Cost",
+        "Currency",
+        "Paid by",
+        "Group",
+        "Paid share",
+        "Owed share",
+        "Net balance",
+    ])?;
+    let mut balances = BTreeMap::<String, i64>::new();
+    for expense in exported(expenses) {
+        let Some(share) = expense.share_of(user_id) else {
+            continue;
+        };
+        let net = cents(share.net_balance.as_deref())?;
+        *balances.entry(currency(expense)).or_default() += net;
+        let mut record = common_fields(expense, &[])?;
+        record.extend([
+            text(expense.group_id.as_deref().unwrap_or_default()),
+            format_cents(cents(share.paid_share.as_deref())?),
+            format_cents(cents(share.owed_share.as_deref())?),
+            format_cents(net),
+        ]);
+        writer.write_record(&record)?;
+    }
+
+    let mut summary = summary(writer)?;
+    summary.write_record(["Currency", "Balance"])?;
+    for (currency, balance) in balances {
+        summary.write_record([text(&currency), format_cents(balance)])?;
+    }
+    summary.flush()?;
+    Ok(())
+}
+
+/// Id and name of the members of the group first, then of everyone else with a share in the
+/// expenses.
+pub(crate) fn participants(group: &Group, expenses: &[&Expense]) -> Vec<(String, String)> {
+    let mut members: Vec<(String, String)> = group
+        .members
+        .iter()
+        .flatten()
+        .filter_map(|member| {
+            let id = member.id.clone()?;
+            let name = member.first_name.clone().unwrap_or_else(|| id.clone());
+            Some((id, name))
+        })
+        .collect();
+    for share in expenses.iter().flat_map(|expense| shares(expense)) {
+        if let Some((id, name)) = participant(share) {
+            if !members.iter().any(|(member_id, _)| *member_id == id) {
+                members.push((id, name));
+            }
+        }
+    }
+    members
+}
+
+/// Expenses which are not deleted, oldest first.
+pub(crate) fn exported(expenses: &[Expense]) -> Vec<&Expense> {
+    let mut exported = expenses
+        .iter()
+        .filter(|expense| expense.deleted_at.is_none())
+        .collect::<Vec<_>>();
+    exported.sort_by_key(|expense| (expense.date, expense.created_at));
+    exported
+}
+
+fn shares(expense: &Expense) -> impl Iterator<Item = &UserShare> {
+    expense.users.iter().flatten()
+}
+
+/// Id and name of the user the share belongs to.
+fn participant(share: &UserShare) -> Option<(String, String)> {
+    let user = share.user.as_ref()?;
+    let id = user.id.clone()?;
+    let name = user.first_name.clone().unwrap_or_else(|| id.clone());
+    Some((id, name))
+}
+
+pub(crate) fn currency(expense: &Expense) -> String {
+    expense.currency_code.clone().unwrap_or_default()
+}
+
+/// Date, description, cost, currency and payers of the expense, payers named after their entry
+/// in `members` or the user who created the expense if they have no name in their share.
+fn common_fields(expense: &Expense, members: &[(String, String)]) -> Result<Vec<String>, Error> {
+    let creator = expense
+        .created_by
+        .as_ref()
+        .and_then(|user| Some((user.id.clone()?, user.first_name.clone()?)));
+    let mut payers = Vec::new();
+    for share in shares(expense) {
+        if cents(share.paid_share.as_deref())? <= 0 {
+            continue;
+        }
+        let Some((id, name)) = participant(share) else {
+            continue;
+        };
+        payers.push(
+            members
+                .iter()
+                .chain(creator.iter())
+                .find(|(known_id, _)| *known_id == id)
+                .map_or(name, |(_, known_name)| known_name.clone()),
+        );
+    }
+    Ok(vec![
+        expense
+            .date
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default(),
+        text(expense.description.as_deref().unwrap_or_default()),
+        format_cents(cents(expense.cost.as_deref())?),
+        text(&currency(expense)),
+        text(&payers.join("; ")),
+    ])
+}
+
+/// Text cell, prefixed with `'` if it starts like a formula so that spreadsheets show it as is
+/// rather than evaluate it.
+fn text(value: &str) -> String {
+    if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Amounts are summed in cents so that the balances add up exactly. A missing amount is none.
+pub(crate) fn cents(amount: Option<&str>) -> Result<i64, Error> {
+    let Some(amount) = amount.filter(|amount| !amount.trim().is_empty()) else {
+        return Ok(0);
+    };
+    let parsed = amount
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|amount| amount.is_finite())
+        .ok_or_else(|| anyhow!("Invalid amount {:?}", amount))?;
+    Ok((parsed * 100.0).round() as i64)
+}
+
+pub(crate) fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+/// Writer of the summary following the rows written by `rows`, separated by an empty line.
+fn summary<W: Write>(rows: Writer<W>) -> Result<Writer<W>, Error> {
+    let mut csv = rows.into_inner().map_err(|error| error.into_error())?;
+    csv.write_all(b"\n")?;
+    Ok(Writer::from_writer(csv))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cents, format_cents, group_csv, user_csv};
+    use crate::service::expense::{Expense, ShareCalculator};
+    use crate::service::group::{Group, User};
+    use chrono::{TimeZone, Utc};
+
+    fn member(id: &str, name: &str) -> User {
+        User {
+            id: Some(id.to_string()),
+            first_name: Some(name.to_string()),
+            ..User::default()
+        }
+    }
+
+    fn expense(day: u32, description: &str, cost: &str, payer: &str) -> Expense {
+        Expense {
+            date: Utc.with_ymd_and_hms(2024, 1, day, 12, 0, 0).single(),
+            description: Some(description.to_string()),
+            cost: Some(cost.to_string()),
+            currency_code: Some("EUR".to_string()),
+            users: Some(ShareCalculator::new().equal_share(
+                cost.to_string(),
+                payer.to_string(),
+                vec!["1".to_string(), "2".to_string()],
+            )),
+            ..Expense::default()
+        }
+    }
+
+    #[test]
+    fn export_group_expenses_and_balances() {
+        let group = Group {
+            members: Some(vec![member("1", "Alice"), member("2", "Bob")]),
+            ..Group::default()
+        };
+        let deleted = Expense {
+            deleted_at: Some(Utc::now()),
+            ..expense(3, "Cancelled", "99.00", "1")
+        };
+        let expenses = vec![
+            expense(2, "Groceries", "30.00", "2"),
+            expense(1, "Rent, January", "100.00", "1"),
+            deleted,
+        ];
+        let mut csv = Vec::new();
+        group_csv(&mut csv, &group, &expenses).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "Date,Description,Cost,Currency,Paid by,Alice owed,Alice net,Bob owed,Bob net\n\
+             2024-01-01,\"Rent, January\",100.00,EUR,Alice,50.00,50.00,50.00,-50.00\n\
+             2024-01-02,Groceries,30.00,EUR,Bob,15.00,-15.00,15.00,15.00\n\
+             \n\
+             Member,Currency,Balance\n\
+             Alice,EUR,35.00\n\
+             Bob,EUR,-35.00\n"
+        );
+    }
+
+    #[test]
+    fn format_negative_cents() {
+        assert_eq!(format_cents(-5), "-0.05");
+        assert_eq!(format_cents(1234), "12.34");
+    }
+
+    #[test]
+    fn reject_invalid_amounts() {
+        assert_eq!(cents(Some(" 12.5")).unwrap(), 1250);
+        assert_eq!(cents(None).unwrap(), 0);
+        assert!(cents(Some("twelve")).is_err());
+        let mut broken = expense(1, "Rent", "100.00", "1");
+        broken.cost = Some("a lot".to_string());
+        assert!(user_csv(Vec::new(), "1", &[broken]).is_err());
+    }
+
+    #[test]
+    fn escape_formulas() {
+        let expenses = vec![Expense {
+            currency_code: Some("@EUR".to_string()),
+            ..expense(1, "=HYPERLINK(\"http://evil\")", "10.00", "1")
+        }];
+        let mut csv = Vec::new();
+        user_csv(&mut csv, "2", &expenses).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.contains("\"'=HYPERLINK(\"\"http://evil\"\")\",10.00,'@EUR,"));
+        assert!(csv.ends_with("'@EUR,-5.00\n"));
+    }
+}
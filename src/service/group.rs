@@ -1,17 +1,35 @@
+use crate::service::account::anonymise_member;
+use crate::service::activity::{Activity, ActivityApi, ActivityApiMongoAdapter};
+use crate::service::version::{version_filter, Conditional};
+use crate::service::{object_id, NotFound, DEFAULT_DATABASE_NAME};
 use anyhow::Error;
 use async_trait::async_trait;
-use mongodb::bson::doc;
 use mongodb::bson::oid::ObjectId;
-use mongodb::Client;
+use mongodb::bson::{doc, Document};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use mongodb::{bson, Client};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use tokio_stream::StreamExt;
+use utoipa::ToSchema;
 
 #[async_trait]
 pub trait GroupApi {
-    async fn get_group(&self, id: i32) -> Result<Group, Error>;
+    async fn get_group(&self, id: String) -> Result<Group, Error>;
     async fn create_group(&self, group: CreateGroupSpec) -> Result<Group, Error>;
     async fn get_user_group(&self, user_id: String) -> Result<Vec<Group>, Error>;
+    /// Adds the member unless the group was changed since `expected_version`.
+    async fn add_member(
+        &self,
+        group_id: String,
+        user: GroupUser,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Group>, Error>;
+    /// Removes the personal data of the member from every group they belong to, see
+    /// [`anonymise_member`]. Returns the number of groups changed.
+    async fn anonymise_member(&self, user_id: String) -> Result<u64, Error>;
+    /// Removes the group with its activity for good. Returns whether it existed.
+    async fn delete_group(&self, id: String) -> Result<bool, Error>;
 }
 
 #[derive(Debug)]
@@ -25,63 +43,129 @@ impl GroupApiMongoAdapter {
     }
 
     pub fn new_with(client: Client) -> Self {
-        Self::new(client.database("swc"))
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
     }
 }
 
 #[async_trait]
 impl GroupApi for GroupApiMongoAdapter {
-    async fn get_group(&self, id: i32) -> Result<Group, Error> {
-        let collection = self.db.collection("groups");
-        let object_id = ObjectId::from_str(&id.to_string())?;
-        let filter = doc! {"_id": object_id};
-        let group = collection.find_one(filter, None).await?;
-        Ok(group.expect("Group not found"))
+    async fn get_group(&self, id: String) -> Result<Group, Error> {
+        let collection = self.db.collection::<Document>("groups");
+        let filter = doc! {"_id": object_id("Group", &id)?};
+        let document = collection
+            .find_one(filter, None)
+            .await?
+            .ok_or_else(|| NotFound::new("Group", &id))?;
+        Group::from_document(document)
     }
 
     async fn create_group(&self, create_spec: CreateGroupSpec) -> Result<Group, Error> {
         let collection = self.db.collection("groups");
-        let members = create_spec
-            .users
-            .map(|users| users.into_iter().map(User::from).collect::<Vec<_>>());
-        let group = Group {
-            id: None,
-            name: Some(create_spec.name),
-            simplify_by_default: Some(true),
-            members,
-            original_debts: Some(vec![]),
-            simplified_debts: Some(vec![]),
-            ..Group::default()
-        };
+        let group = Group::from(create_spec);
         let inserted_group = collection.insert_one(group.clone(), None).await?;
         let group = Group {
             id: Some(inserted_group.inserted_id.as_object_id().unwrap().to_hex()),
             ..group
         };
+        let _activity = ActivityApiMongoAdapter::new(self.db.clone())
+            .record(Activity::group_created(&group))
+            .await?;
         Ok(group)
     }
 
     async fn get_user_group(&self, user_id: String) -> Result<Vec<Group>, Error> {
         let collection = self.db.collection::<mongodb::bson::Document>("groups");
-        let filter = doc! {
-            "members": {
-                "$elemMatch": {
-                    "user_id": user_id
-                }
-            }
-        };
+        let filter = doc! {"members.id": user_id};
         let mut cursor = collection.find(filter, None).await?;
 
         let mut groups = Vec::new();
         while let Some(group) = cursor.try_next().await? {
-            let group: Group = mongodb::bson::from_document(group).unwrap();
-            groups.push(group);
+            groups.push(Group::from_document(group)?);
         }
         Ok(groups)
     }
+
+    async fn add_member(
+        &self,
+        group_id: String,
+        user: GroupUser,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Group>, Error> {
+        let user_id = user.user_id.clone();
+        let name = user.first_name.clone().unwrap_or_else(|| user_id.clone());
+        let member = bson::to_bson(&User::from(user))?;
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let mut filter = doc! {"_id": object_id("Group", &group_id)?};
+        if let Some(expected_version) = expected_version {
+            let _previous = filter.insert("version", version_filter(expected_version));
+        }
+        let document = self
+            .db
+            .collection::<Document>("groups")
+            .find_one_and_update(
+                filter,
+                doc! {"$push": {"members": member}, "$inc": {"version": 1_i64}},
+                options,
+            )
+            .await?;
+        let Some(document) = document else {
+            // either there is no such group or it is at another version
+            return Ok(Conditional::Stale(self.get_group(group_id).await?));
+        };
+        let group = Group::from_document(document)?;
+        let _activity = ActivityApiMongoAdapter::new(self.db.clone())
+            .record(Activity::member_joined(&group, user_id, name))
+            .await?;
+        Ok(Conditional::Applied(group))
+    }
+
+    async fn anonymise_member(&self, user_id: String) -> Result<u64, Error> {
+        let collection = self.db.collection::<Document>("groups");
+        let mut groups = Vec::new();
+        let mut cursor = collection.find(doc! {"members.id": &user_id}, None).await?;
+        while let Some(document) = cursor.try_next().await? {
+            groups.push(Group::from_document(document)?);
+        }
+        let mut anonymised = 0;
+        for mut group in groups {
+            if !anonymise_member(&mut group, &user_id) {
+                continue;
+            }
+            let id = ObjectId::from_str(group.id.as_deref().unwrap_or_default())?;
+            let members = bson::to_bson(&group.members)?;
+            let _updated = collection
+                .update_one(
+                    doc! {"_id": id},
+                    doc! {"$set": {"members": members}, "$inc": {"version": 1_i64}},
+                    None,
+                )
+                .await?;
+            anonymised += 1;
+        }
+        Ok(anonymised)
+    }
+
+    async fn delete_group(&self, id: String) -> Result<bool, Error> {
+        let Ok(object_id) = ObjectId::from_str(&id) else {
+            return Ok(false);
+        };
+        let deleted = self
+            .db
+            .collection::<Document>("groups")
+            .delete_one(doc! {"_id": object_id}, None)
+            .await?;
+        let _activity = self
+            .db
+            .collection::<Document>("activity")
+            .delete_many(doc! {"groupId": &id}, None)
+            .await?;
+        Ok(deleted.deleted_count > 0)
+    }
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Group {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -106,10 +190,35 @@ pub struct Group {
     pub original_debts: Option<Vec<Debt>>,
 
     pub simplified_debts: Option<Vec<Debt>>,
+
+    /// Incremented on every change, sent as `ETag` to detect concurrent edits. `None` for groups
+    /// stored before versioning.
+    pub version: Option<i64>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+impl Group {
+    fn from_document(mut document: Document) -> Result<Self, Error> {
+        let id = document.get_object_id("_id")?.to_hex();
+        let _id = document.remove("_id");
+        let group: Group = bson::from_document(document)?;
+        Ok(Group {
+            id: Some(id),
+            ..group
+        })
+    }
+
+    pub fn member_ids(&self) -> Vec<String> {
+        self.members
+            .iter()
+            .flatten()
+            .filter_map(|member| member.id.clone())
+            .collect()
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = GroupMember)]
 pub struct User {
     pub id: Option<String>,
 
@@ -128,6 +237,24 @@ pub struct User {
     pub balance: Option<Vec<Balance>>,
 }
 
+impl From<CreateGroupSpec> for Group {
+    fn from(create_spec: CreateGroupSpec) -> Group {
+        let members = create_spec
+            .users
+            .map(|users| users.into_iter().map(User::from).collect::<Vec<_>>());
+        Group {
+            id: None,
+            name: Some(create_spec.name),
+            simplify_by_default: Some(true),
+            members,
+            original_debts: Some(vec![]),
+            simplified_debts: Some(vec![]),
+            version: Some(1),
+            ..Group::default()
+        }
+    }
+}
+
 impl From<GroupUser> for User {
     fn from(group_user: GroupUser) -> User {
         Self {
@@ -138,24 +265,28 @@ impl From<GroupUser> for User {
     }
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(as = MemberBalance)]
 pub struct Balance {
     pub currency_code: Option<String>,
     pub amount: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(as = GroupDebt)]
 pub struct Debt {
-    pub from: Option<i64>,
+    /// Id of the user who owes.
+    pub from: Option<String>,
 
-    pub to: Option<i64>,
+    /// Id of the user who is owed.
+    pub to: Option<String>,
 
     pub amount: Option<String>,
 
     pub currency_code: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateGroupSpec {
     pub name: String,
@@ -164,7 +295,7 @@ pub struct CreateGroupSpec {
     pub users: Option<Vec<GroupUser>>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct GroupUser {
     pub user_id: String,
@@ -0,0 +1,34 @@
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::Error;
+use async_trait::async_trait;
+use mongodb::bson::doc;
+use mongodb::{Client, Database};
+
+#[async_trait]
+pub trait HealthApi {
+    /// Checks that the database can serve requests.
+    async fn ping(&self) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthMongoAdapter {
+    db: Database,
+}
+
+impl HealthMongoAdapter {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+}
+
+#[async_trait]
+impl HealthApi for HealthMongoAdapter {
+    async fn ping(&self) -> Result<(), Error> {
+        let _reply = self.db.run_command(doc! {"ping": 1}, None).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use crate::service::account::anonymise_revision;
+use crate::service::expense::User;
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::{anyhow, Error};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{FindOneOptions, FindOptions};
+use mongodb::{bson, Client, Database};
+use serde::{Deserialize, Serialize};
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+/// Times a revision is numbered again when a concurrent change took its number.
+const RECORD_ATTEMPTS: usize = 5;
+
+/// Code of the error MongoDB fails a write with when it violates a unique index.
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Bookkeeping fields which change on every write and are left out of the diff.
+const IGNORED_FIELDS: [&str; 5] = ["_id", "updatedAt", "updatedBy", "commentsCount", "version"];
+
+#[async_trait]
+pub trait ExpenseHistoryApi {
+    /// Revisions of the expense, oldest first.
+    async fn get_history(&self, expense_id: String) -> Result<Vec<ExpenseRevision>, Error>;
+    /// Appends a revision with the field-level diff between two states of the expense document.
+    async fn record_revision(
+        &self,
+        expense_id: String,
+        action: ExpenseAction,
+        changed_by: Option<User>,
+        before: &Document,
+        after: &Document,
+    ) -> Result<ExpenseRevision, Error>;
+    /// Removes the personal data of the user from the revisions they made or are found in, see
+    /// [`anonymise_revision`]. Returns the number of revisions changed.
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpenseHistoryMongoAdapter {
+    db: Database,
+}
+
+impl ExpenseHistoryMongoAdapter {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+}
+
+#[async_trait]
+impl ExpenseHistoryApi for ExpenseHistoryMongoAdapter {
+    async fn get_history(&self, expense_id: String) -> Result<Vec<ExpenseRevision>, Error> {
+        let options = FindOptions::builder()
+            .sort(doc! {"version": 1})
+            .projection(doc! {"_id": 0})
+            .build();
+        let mut cursor = self
+            .db
+            .collection::<ExpenseRevision>("expense_history")
+            .find(doc! {"expenseId": expense_id}, options)
+            .await?;
+        let mut revisions = Vec::new();
+        while let Some(revision) = cursor.try_next().await? {
+            revisions.push(revision);
+        }
+        Ok(revisions)
+    }
+
+    async fn record_revision(
+        &self,
+        expense_id: String,
+        action: ExpenseAction,
+        changed_by: Option<User>,
+        before: &Document,
+        after: &Document,
+    ) -> Result<ExpenseRevision, Error> {
+        let collection = self.db.collection::<ExpenseRevision>("expense_history");
+        let mut revision = ExpenseRevision {
+            expense_id,
+            version: 0,
+            action,
+            changed_by,
+            changed_at: Utc::now(),
+            changes: diff(before, after),
+        };
+        for _attempt in 0..RECORD_ATTEMPTS {
+            let options = FindOneOptions::builder()
+                .sort(doc! {"version": -1})
+                .projection(doc! {"_id": 0})
+                .build();
+            let latest = collection
+                .find_one(doc! {"expenseId": &revision.expense_id}, options)
+                .await?;
+            revision.version = latest.map_or(1, |latest| latest.version + 1);
+            // the unique index on expense and version rejects a number taken concurrently
+            match collection.insert_one(&revision, None).await {
+                Ok(_inserted) => return Ok(revision),
+                Err(error) if is_duplicate_key(&error) => continue,
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Err(anyhow!(
+            "Expense {} kept changing while its history was recorded",
+            revision.expense_id
+        ))
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        let collection = self.db.collection::<Document>("expense_history");
+        // users are found in the shares of an expense or as one of its actors
+        let filter = doc! {"$or": [
+            {"changedBy.id": &user_id},
+            {"changes.from.id": &user_id},
+            {"changes.to.id": &user_id},
+            {"changes.from.user.id": &user_id},
+            {"changes.to.user.id": &user_id},
+        ]};
+        let mut documents = Vec::new();
+        let mut cursor = collection.find(filter, None).await?;
+        while let Some(document) = cursor.try_next().await? {
+            documents.push(document);
+        }
+        let mut anonymised = 0;
+        for mut document in documents {
+            let id = document.get_object_id("_id")?;
+            let _id = document.remove("_id");
+            let mut revision: ExpenseRevision = bson::from_document(document)?;
+            if !anonymise_revision(&mut revision, &user_id) {
+                continue;
+            }
+            let set_document = doc! {
+                "changedBy": bson::to_bson(&revision.changed_by)?,
+                "changes": bson::to_bson(&revision.changes)?,
+            };
+            let _updated = collection
+                .update_one(doc! {"_id": id}, doc! {"$set": set_document}, None)
+                .await?;
+            anonymised += 1;
+        }
+        Ok(anonymised)
+    }
+}
+
+/// Whether the write failed on a unique index.
+pub(crate) fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(error)) if error.code == DUPLICATE_KEY
+    )
+}
+
+/// Field-level diff of the top level fields of two expense documents.
+pub fn diff(before: &Document, after: &Document) -> Vec<FieldChange> {
+    let removed = before
+        .keys()
+        .filter(|field| !after.contains_key(field.as_str()));
+    after
+        .keys()
+        .chain(removed)
+        .filter(|field| !IGNORED_FIELDS.contains(&field.as_str()))
+        .filter_map(|field| {
+            let from = before.get(field).filter(|value| **value != Bson::Null);
+            let to = after.get(field).filter(|value| **value != Bson::Null);
+            if from == to {
+                return None;
+            }
+            Some(FieldChange {
+                field: field.clone(),
+                from: from.cloned(),
+                to: to.cloned(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpenseRevision {
+    pub expense_id: String,
+
+    /// Sequence number of the revision, starting from `1` for the creation of the expense.
+    pub version: i64,
+
+    pub action: ExpenseAction,
+
+    pub changed_by: Option<User>,
+
+    pub changed_at: DateTime<Utc>,
+
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpenseAction {
+    Created,
+    Updated,
+    Deleted,
+    Restored,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FieldChange {
+    pub field: String,
+
+    /// Previous value, `None` if the field was not set.
+    #[schema(value_type = Option<Value>)]
+    pub from: Option<Bson>,
+
+    /// New value, `None` if the field was unset.
+    #[schema(value_type = Option<Value>)]
+    pub to: Option<Bson>,
+}
+
+#[cfg(test)]
+mod test {
+    use mongodb::bson::{doc, Bson};
+
+    #[test]
+    fn diff_only_changed_fields() {
+        let before = doc! {"_id": 1, "cost": "100", "description": "dinner", "updatedAt": "a"};
+        let after = doc! {"_id": 1, "cost": "30", "description": "dinner", "updatedAt": "b"};
+        let changes = super::diff(&before, &after);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "cost");
+        assert_eq!(changes[0].from, Some(Bson::String("100".to_string())));
+        assert_eq!(changes[0].to, Some(Bson::String("30".to_string())));
+    }
+
+    #[test]
+    fn diff_treats_null_as_unset() {
+        let before = doc! {"cost": "100", "deletedAt": Bson::Null};
+        let after = doc! {"cost": "100", "deletedAt": "2022-10-29T00:00:00Z", "details": "x"};
+        let changes = super::diff(&before, &after);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].field, "deletedAt");
+        assert_eq!(changes[0].from, None);
+        assert_eq!(changes[1].field, "details");
+
+        let removed = super::diff(&after, &before);
+        assert_eq!(removed.len(), 2);
+        assert_eq!(removed[0].to, None);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::{anyhow, Error};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use mongodb::bson::{doc, Document};
+use mongodb::options::UpdateOptions;
+use mongodb::{bson, Client, Database};
+use serde::{Deserialize, Serialize};
+
+#[async_trait]
+pub trait IdempotencyApi {
+    /// Reserves `key` for the request with `request_hash` until `expires_at`. Returns `None` if the
+    /// key was reserved, otherwise the record of the request the key is already used by.
+    async fn reserve(
+        &self,
+        key: String,
+        request_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, Error>;
+    /// Stores the response of the request the key was reserved for.
+    async fn complete(&self, key: String, response: StoredResponse) -> Result<(), Error>;
+    /// Frees the key of a request which did not complete, so that the request can be retried.
+    async fn release(&self, key: String) -> Result<(), Error>;
+}
+
+/// Request an `Idempotency-Key` was used for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdempotencyRecord {
+    pub key: String,
+
+    /// Hash of the route and the body of the request.
+    pub request_hash: String,
+
+    /// `None` while the request is being handled.
+    pub response: Option<StoredResponse>,
+
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Response returned again when a request is retried with the same key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredResponse {
+    pub status: u16,
+
+    pub body: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct IdempotencyMongoAdapter {
+    db: Database,
+}
+
+impl IdempotencyMongoAdapter {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+}
+
+/// `expiresAt` is stored as a BSON date, which the TTL index removing expired keys requires.
+fn bson_date(date: DateTime<Utc>) -> bson::DateTime {
+    bson::DateTime::from_millis(date.timestamp_millis())
+}
+
+#[async_trait]
+impl IdempotencyApi for IdempotencyMongoAdapter {
+    async fn reserve(
+        &self,
+        key: String,
+        request_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, Error> {
+        let collection = self.db.collection::<Document>("idempotency_keys");
+        // the TTL monitor only runs once a minute, a key is free again as soon as it expires
+        let _deleted = collection
+            .delete_one(
+                doc! {"_id": &key, "expiresAt": {"$lte": bson_date(Utc::now())}},
+                None,
+            )
+            .await?;
+        let options = UpdateOptions::builder().upsert(true).build();
+        let update_result = collection
+            .update_one(
+                doc! {"_id": &key},
+                doc! {"$setOnInsert": {
+                    "requestHash": &request_hash,
+                    "expiresAt": bson_date(expires_at),
+                }},
+                options,
+            )
+            .await?;
+        if update_result.upserted_id.is_some() {
+            return Ok(None);
+        }
+        let document = collection
+            .find_one(doc! {"_id": &key}, None)
+            .await?
+            .ok_or_else(|| anyhow!("Idempotency key {} was released concurrently", key))?;
+        let response = match document.get_document("response") {
+            Ok(response) => Some(bson::from_document(response.clone())?),
+            Err(_) => None,
+        };
+        let expires_at = document.get_datetime("expiresAt")?.timestamp_millis();
+        Ok(Some(IdempotencyRecord {
+            request_hash: document.get_str("requestHash")?.to_string(),
+            response,
+            expires_at: Utc
+                .timestamp_millis_opt(expires_at)
+                .single()
+                .ok_or_else(|| anyhow!("Invalid expiry of idempotency key {}", key))?,
+            key,
+        }))
+    }
+
+    async fn complete(&self, key: String, response: StoredResponse) -> Result<(), Error> {
+        let _updated = self
+            .db
+            .collection::<Document>("idempotency_keys")
+            .update_one(
+                doc! {"_id": key},
+                doc! {"$set": {"response": bson::to_document(&response)?}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn release(&self, key: String) -> Result<(), Error> {
+        let _deleted = self
+            .db
+            .collection::<Document>("idempotency_keys")
+            .delete_one(doc! {"_id": key, "response": {"$exists": false}}, None)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+use crate::service::expense::{
+    validate_cost, CreateExpenseSpec, Expense, Expenses, ExpensesCalculator, ShareCalculator, User,
+};
+use crate::service::export::{cents, currency, exported, format_cents, participants};
+use crate::service::group::{Group, User as Member};
+use anyhow::Error;
+use chrono::{DateTime, NaiveDate, Utc};
+use csv::{ReaderBuilder, StringRecord, Trim};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+/// Columns the fields of the imported expenses are read from, and whether to only preview the
+/// import. Column names are matched case-insensitively.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportRequest {
+    /// Only validate the rows and report the resulting balances, nothing is stored.
+    pub dry_run: Option<bool>,
+
+    /// Date of the expense as `YYYY-MM-DD` or RFC 3339. Defaults to `Date`.
+    pub date_column: Option<String>,
+
+    /// Defaults to `Description`, optional.
+    pub description_column: Option<String>,
+
+    /// Defaults to `Cost`.
+    pub cost_column: Option<String>,
+
+    /// Defaults to `Currency`, optional.
+    pub currency_column: Option<String>,
+
+    /// Member who paid the expense by id, email or name. Defaults to `Paid by`.
+    pub paid_by_column: Option<String>,
+
+    /// Members splitting the expense equally separated by `;`, by id, email or name. Defaults to
+    /// `Split between`, every member of the group if the column is missing or empty.
+    pub split_between_column: Option<String>,
+}
+
+impl ImportRequest {
+    pub fn dry_run(&self) -> bool {
+        self.dry_run.unwrap_or(false)
+    }
+}
+
+/// Outcome of an import, the rows are only stored if none of them has errors.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    /// Whether the expenses were stored, never for a dry run or if any row has errors.
+    pub committed: bool,
+
+    pub rows: Vec<ImportedRow>,
+
+    /// Balance of every member per currency once the valid rows are added to the group.
+    pub balances: Vec<UserBalance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedRow {
+    /// Line of the row in the CSV, the header being on line 1.
+    pub line: u64,
+
+    /// Expense the row is imported as, if it is valid.
+    pub expense: Option<Expense>,
+
+    /// Id of the stored expense, once committed.
+    pub id: Option<String>,
+
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserBalance {
+    pub user_id: String,
+
+    pub name: String,
+
+    pub currency_code: String,
+
+    /// Positive if the member is owed money.
+    pub amount: String,
+}
+
+/// Index of every mapped column in the header, `None` for optional columns which are missing.
+struct Columns {
+    date: usize,
+    description: Option<usize>,
+    cost: usize,
+    currency: Option<usize>,
+    paid_by: usize,
+    split_between: Option<usize>,
+}
+
+impl Columns {
+    fn new(request: &ImportRequest, header: &StringRecord) -> Result<Self, Error> {
+        let find = |column: &Option<String>, default: &str| {
+            let name = column.as_deref().unwrap_or(default);
+            header
+                .iter()
+                .position(|field| field.eq_ignore_ascii_case(name))
+                .ok_or_else(|| Error::msg(format!("No {:?} column in the header", name)))
+        };
+        Ok(Self {
+            date: find(&request.date_column, "Date")?,
+            description: find(&request.description_column, "Description").ok(),
+            cost: find(&request.cost_column, "Cost")?,
+            currency: find(&request.currency_column, "Currency").ok(),
+            paid_by: find(&request.paid_by_column, "Paid by")?,
+            split_between: find(&request.split_between_column, "Split between").ok(),
+        })
+    }
+}
+
+/// Reads an expense of the group from every non-empty row of `csv`, each validated and split
+/// like one created through `POST /expenses`. Fails if the header lacks a required column.
+pub fn read_rows(
+    group: &Group,
+    request: &ImportRequest,
+    csv: &str,
+) -> Result<Vec<ImportedRow>, Error> {
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(csv.as_bytes());
+    let columns = Columns::new(request, reader.headers()?)?;
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        let line = record.position().map_or(0, |position| position.line());
+        rows.push(match read_expense(group, &columns, &record) {
+            Ok(expense) => ImportedRow {
+                line,
+                expense: Some(expense),
+                id: None,
+                errors: Vec::new(),
+            },
+            Err(errors) => ImportedRow {
+                line,
+                expense: None,
+                id: None,
+                errors,
+            },
+        });
+    }
+    Ok(rows)
+}
+
+fn read_expense(
+    group: &Group,
+    columns: &Columns,
+    record: &StringRecord,
+) -> Result<Expense, Vec<String>> {
+    let field = |index: Option<usize>| {
+        index
+            .and_then(|index| record.get(index))
+            .filter(|value| !value.is_empty())
+    };
+    let mut errors = Vec::new();
+    let date = parse_date(field(Some(columns.date)).unwrap_or_default())
+        .map_err(|error| errors.push(error))
+        .ok();
+    let cost = field(Some(columns.cost)).unwrap_or_default().to_string();
+    if let Err(error) = validate_cost(&cost) {
+        errors.push(error.to_string());
+    }
+    let payer = member(group, field(Some(columns.paid_by)).unwrap_or_default())
+        .map_err(|error| errors.push(error))
+        .ok();
+    let split_between = match field(columns.split_between) {
+        Some(names) => names
+            .split(';')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .filter_map(|name| member(group, name).map_err(|error| errors.push(error)).ok())
+            .collect::<Vec<_>>(),
+        None => group.members.iter().flatten().collect(),
+    };
+    let (Some(date), Some(payer)) = (date, payer) else {
+        return Err(errors);
+    };
+    let payer_id = payer.id.clone().unwrap_or_default();
+    let user_ids = split_between
+        .iter()
+        .filter_map(|member| member.id.clone())
+        .collect::<Vec<_>>();
+    if !user_ids.contains(&payer_id) {
+        errors.push(format!(
+            "{} paid but is not splitting the expense",
+            name(payer)
+        ));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let spec = CreateExpenseSpec {
+        cost: cost.clone(),
+        group_id: group.id.clone().unwrap_or_default(),
+        user: User {
+            id: payer.id.clone(),
+            first_name: payer.first_name.clone(),
+            email: payer.email.clone(),
+            ..User::default()
+        },
+        description: field(columns.description).map(String::from),
+        category: None,
+        payment: Some(false),
+        repeat_interval: None,
+        itemized: None,
+    };
+    let expense = ExpensesCalculator::new()
+        .create_expense(&spec)
+        .map_err(|error| vec![error.to_string()])?;
+    Ok(Expense {
+        date: Some(date),
+        currency_code: field(columns.currency).map(str::to_uppercase),
+        users: Some(ShareCalculator::new().equal_share(cost, payer_id, user_ids)),
+        ..expense
+    })
+}
+
+fn parse_date(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|date| date.and_time(Default::default()).and_utc())
+        })
+        .map_err(|_| format!("Date {:?} is neither YYYY-MM-DD nor RFC 3339", value))
+}
+
+/// The member of the group `value` is the id, email or name of.
+fn member<'g>(group: &'g Group, value: &str) -> Result<&'g Member, String> {
+    let matching = group
+        .members
+        .iter()
+        .flatten()
+        .filter(|member| {
+            [
+                member.id.clone(),
+                member.email.clone(),
+                member.first_name.clone(),
+                Some(name(member)),
+            ]
+            .iter()
+            .flatten()
+            .any(|candidate| candidate.eq_ignore_ascii_case(value))
+        })
+        .collect::<Vec<_>>();
+    match matching.as_slice() {
+        [] if value.is_empty() => Err("Missing member".to_string()),
+        [] => Err(format!("{:?} is not a member of the group", value)),
+        [member] => Ok(member),
+        _ => Err(format!("{:?} matches several members of the group", value)),
+    }
+}
+
+fn name(member: &Member) -> String {
+    let names = [&member.first_name, &member.last_name]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        member.id.clone().unwrap_or_default()
+    } else {
+        names.join(" ")
+    }
+}
+
+/// Balance of every participant per currency over the expenses which are not deleted.
+pub fn balances(group: &Group, expenses: &[Expense]) -> Result<Vec<UserBalance>, Error> {
+    let expenses = exported(expenses);
+    let members = participants(group, &expenses);
+    let mut balances = BTreeMap::<(usize, String), i64>::new();
+    for expense in &expenses {
+        for (position, (id, _)) in members.iter().enumerate() {
+            if let Some(share) = expense.share_of(id) {
+                *balances.entry((position, currency(expense))).or_default() +=
+                    cents(share.net_balance.as_deref())?;
+            }
+        }
+    }
+    Ok(balances
+        .into_iter()
+        .map(|((position, currency_code), balance)| UserBalance {
+            user_id: members[position].0.clone(),
+            name: members[position].1.clone(),
+            currency_code,
+            amount: format_cents(balance),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{balances, read_rows, ImportRequest};
+    use crate::service::group::{Group, User};
+
+    fn group() -> Group {
+        let member = |id: &str, name: &str, email: &str| User {
+            id: Some(id.to_string()),
+            first_name: Some(name.to_string()),
+            email: Some(email.to_string()),
+            ..User::default()
+        };
+        Group {
+            id: Some("g1".to_string()),
+            members: Some(vec![
+                member("1", "Alice", "alice@example.com"),
+                member("2", "Bob", "bob@example.com"),
+                member("3", "Carol", "carol@example.com"),
+            ]),
+            ..Group::default()
+        }
+    }
+
+    #[test]
+    fn import_rows_split_between_members() {
+        let csv = "Date,Description,Cost,Currency,Paid by,Split between\n\
+                   2024-01-01,Rent,90.00,eur,alice@example.com,\n\
+                   2024-01-02,Taxi,20,EUR,Bob,Alice; Bob\n";
+        let rows = read_rows(&group(), &ImportRequest::default(), csv).unwrap();
+        assert!(rows.iter().all(|row| row.errors.is_empty()));
+        let expenses = rows
+            .into_iter()
+            .filter_map(|row| row.expense)
+            .collect::<Vec<_>>();
+        assert_eq!(expenses[0].users.as_ref().unwrap().len(), 3);
+        assert_eq!(expenses[0].currency_code, Some("EUR".to_string()));
+        assert_eq!(expenses[1].group_id, Some("g1".to_string()));
+
+        let balances = balances(&group(), &expenses)
+            .unwrap()
+            .into_iter()
+            .map(|balance| (balance.name, balance.amount))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            balances,
+            [
+                ("Alice".to_string(), "50.00".to_string()),
+                ("Bob".to_string(), "-20.00".to_string()),
+                ("Carol".to_string(), "-30.00".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_every_error_of_a_row() {
+        let csv = "When,Amount,Who\n\
+                   yesterday,12.345,Dave\n";
+        let request = ImportRequest {
+            date_column: Some("When".to_string()),
+            cost_column: Some("Amount".to_string()),
+            paid_by_column: Some("Who".to_string()),
+            ..ImportRequest::default()
+        };
+        let rows = read_rows(&group(), &request, csv).unwrap();
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].errors.len(), 3);
+        assert!(rows[0].expense.is_none());
+    }
+
+    #[test]
+    fn require_mapped_columns() {
+        let error = read_rows(&group(), &ImportRequest::default(), "Date,Cost\n").unwrap_err();
+        assert_eq!(error.to_string(), "No \"Paid by\" column in the header");
+    }
+}
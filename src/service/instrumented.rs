@@ -0,0 +1,449 @@
+use crate::metrics::metrics;
+use crate::service::activity::{Activity, ActivityApi, ActivityPage, ActivityRequest};
+use crate::service::balance::{Balance, BalanceApi};
+use crate::service::blob::{Blob, BlobStore};
+use crate::service::category::{CategoriesApi, Category, CreateCategorySpec};
+use crate::service::comment::{Comment, CommentsApi, CreateCommentSpec};
+use crate::service::expense::{
+    CreateExpenseSpec, Expense, ExpenseEntity, ExpensesApi, ExpensesResponse, ListExpensesRequest,
+    UpdateExpenseSpec, User,
+};
+use crate::service::group::{CreateGroupSpec, Group, GroupApi, GroupUser};
+use crate::service::health::HealthApi;
+use crate::service::history::{ExpenseAction, ExpenseHistoryApi, ExpenseRevision};
+use crate::service::idempotency::{IdempotencyApi, IdempotencyRecord, StoredResponse};
+use crate::service::receipt::Receipt;
+use crate::service::recurring::RecurringExpensesApi;
+use crate::service::report::{GroupReport, ReportRequest, ReportsApi, UserSpending};
+use crate::service::user::{CreateUserSpec, User as UserAccount, UserApi};
+use crate::service::version::Conditional;
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::Document;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::Instrument;
+
+/// Wraps a service of the [`crate::service::storage::Storage`], runs every call in a `storage` span
+/// and records its latency in the storage metrics, labelled with `service` and the name of the
+/// method.
+pub struct Instrumented<T: ?Sized> {
+    service: &'static str,
+    inner: Arc<T>,
+}
+
+impl<T: ?Sized> Instrumented<T> {
+    pub fn new(service: &'static str, inner: Arc<T>) -> Self {
+        Self { service, inner }
+    }
+
+    async fn timed<R>(
+        &self,
+        operation: &'static str,
+        call: impl Future<Output = Result<R, Error>>,
+    ) -> Result<R, Error> {
+        let start = Instant::now();
+        let span = tracing::info_span!("storage", service = self.service, operation);
+        let result = call.instrument(span).await;
+        metrics().record_storage_call(self.service, operation, start.elapsed(), result.is_err());
+        result
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for Instrumented<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Instrumented")
+            .field("service", &self.service)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl ExpensesApi for Instrumented<dyn ExpensesApi + Send + Sync> {
+    async fn get_expense(&self, id: String) -> Result<Expense, Error> {
+        self.timed("get_expense", self.inner.get_expense(id)).await
+    }
+
+    async fn list_expenses(&self, request: ListExpensesRequest) -> Result<ExpensesResponse, Error> {
+        self.timed("list_expenses", self.inner.list_expenses(request))
+            .await
+    }
+
+    async fn create_expense(&self, expense: CreateExpenseSpec) -> Result<ExpenseEntity, Error> {
+        let payment = expense.payment.unwrap_or(false);
+        let created = self
+            .timed("create_expense", self.inner.create_expense(expense))
+            .await?;
+        if payment {
+            metrics().payments_recorded.inc();
+        } else {
+            metrics().expenses_created.inc();
+        }
+        Ok(created)
+    }
+
+    async fn update_expense(
+        &self,
+        id: String,
+        spec: UpdateExpenseSpec,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        self.timed(
+            "update_expense",
+            self.inner.update_expense(id, spec, expected_version),
+        )
+        .await
+    }
+
+    async fn delete_expense(
+        &self,
+        id: String,
+        deleted_by: User,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        self.timed(
+            "delete_expense",
+            self.inner.delete_expense(id, deleted_by, expected_version),
+        )
+        .await
+    }
+
+    async fn restore_expense(&self, id: String, restored_by: User) -> Result<(), Error> {
+        self.timed(
+            "restore_expense",
+            self.inner.restore_expense(id, restored_by),
+        )
+        .await
+    }
+
+    async fn import_expenses(&self, expenses: Vec<Expense>) -> Result<Vec<ExpenseEntity>, Error> {
+        let imported = self
+            .timed("import_expenses", self.inner.import_expenses(expenses))
+            .await?;
+        for entity in &imported {
+            if entity.expense.payment == Some(true) {
+                metrics().payments_recorded.inc();
+            } else {
+                metrics().expenses_created.inc();
+            }
+        }
+        Ok(imported)
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        self.timed("anonymise_user", self.inner.anonymise_user(user_id))
+            .await
+    }
+    async fn set_receipt(
+        &self,
+        id: String,
+        receipt: Option<Receipt>,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        self.timed(
+            "set_receipt",
+            self.inner.set_receipt(id, receipt, expected_version),
+        )
+        .await
+    }
+
+    async fn purge_expense(&self, id: String) -> Result<bool, Error> {
+        self.timed("purge_expense", self.inner.purge_expense(id))
+            .await
+    }
+}
+
+#[async_trait]
+impl GroupApi for Instrumented<dyn GroupApi + Send + Sync> {
+    async fn get_group(&self, id: String) -> Result<Group, Error> {
+        self.timed("get_group", self.inner.get_group(id)).await
+    }
+
+    async fn create_group(&self, group: CreateGroupSpec) -> Result<Group, Error> {
+        self.timed("create_group", self.inner.create_group(group))
+            .await
+    }
+
+    async fn get_user_group(&self, user_id: String) -> Result<Vec<Group>, Error> {
+        self.timed("get_user_group", self.inner.get_user_group(user_id))
+            .await
+    }
+
+    async fn add_member(
+        &self,
+        group_id: String,
+        user: GroupUser,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Group>, Error> {
+        self.timed(
+            "add_member",
+            self.inner.add_member(group_id, user, expected_version),
+        )
+        .await
+    }
+    async fn anonymise_member(&self, user_id: String) -> Result<u64, Error> {
+        self.timed("anonymise_member", self.inner.anonymise_member(user_id))
+            .await
+    }
+    async fn delete_group(&self, id: String) -> Result<bool, Error> {
+        self.timed("delete_group", self.inner.delete_group(id))
+            .await
+    }
+}
+
+#[async_trait]
+impl UserApi for Instrumented<dyn UserApi + Send + Sync> {
+    async fn get_user(&self, id: i32) -> Result<UserAccount, Error> {
+        self.timed("get_user", self.inner.get_user(id)).await
+    }
+
+    async fn create_user(&self, user: CreateUserSpec) -> Result<String, Error> {
+        self.timed("create_user", self.inner.create_user(user))
+            .await
+    }
+
+    async fn find_user_by_email(&self, email: String) -> Result<Option<UserAccount>, Error> {
+        self.timed("find_user_by_email", self.inner.find_user_by_email(email))
+            .await
+    }
+    async fn find_user(&self, id: String) -> Result<Option<UserAccount>, Error> {
+        self.timed("find_user", self.inner.find_user(id)).await
+    }
+
+    async fn delete_user(&self, id: String) -> Result<bool, Error> {
+        self.timed("delete_user", self.inner.delete_user(id)).await
+    }
+}
+
+#[async_trait]
+impl BalanceApi for Instrumented<dyn BalanceApi + Send + Sync> {
+    async fn get_user_balance(&self, user_id: String) -> Result<Balance, Error> {
+        self.timed("get_user_balance", self.inner.get_user_balance(user_id))
+            .await
+    }
+}
+
+#[async_trait]
+impl CommentsApi for Instrumented<dyn CommentsApi + Send + Sync> {
+    async fn get_comments(&self, expense_id: String) -> Result<Vec<Comment>, Error> {
+        self.timed("get_comments", self.inner.get_comments(expense_id))
+            .await
+    }
+
+    async fn create_comment(
+        &self,
+        expense_id: String,
+        spec: CreateCommentSpec,
+    ) -> Result<Comment, Error> {
+        let comment = self
+            .timed(
+                "create_comment",
+                self.inner.create_comment(expense_id, spec),
+            )
+            .await?;
+        metrics().comments_created.inc();
+        Ok(comment)
+    }
+
+    async fn create_system_comment(
+        &self,
+        expense_id: String,
+        content: String,
+    ) -> Result<Comment, Error> {
+        self.timed(
+            "create_system_comment",
+            self.inner.create_system_comment(expense_id, content),
+        )
+        .await
+    }
+
+    async fn delete_comment(&self, id: String) -> Result<Option<Comment>, Error> {
+        self.timed("delete_comment", self.inner.delete_comment(id))
+            .await
+    }
+    async fn get_user_comments(&self, user_id: String) -> Result<Vec<Comment>, Error> {
+        self.timed("get_user_comments", self.inner.get_user_comments(user_id))
+            .await
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        self.timed("anonymise_comments", self.inner.anonymise_user(user_id))
+            .await
+    }
+}
+
+#[async_trait]
+impl ExpenseHistoryApi for Instrumented<dyn ExpenseHistoryApi + Send + Sync> {
+    async fn get_history(&self, expense_id: String) -> Result<Vec<ExpenseRevision>, Error> {
+        self.timed("get_history", self.inner.get_history(expense_id))
+            .await
+    }
+
+    async fn record_revision(
+        &self,
+        expense_id: String,
+        action: ExpenseAction,
+        changed_by: Option<User>,
+        before: &Document,
+        after: &Document,
+    ) -> Result<ExpenseRevision, Error> {
+        self.timed(
+            "record_revision",
+            self.inner
+                .record_revision(expense_id, action, changed_by, before, after),
+        )
+        .await
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        self.timed("anonymise_history", self.inner.anonymise_user(user_id))
+            .await
+    }
+}
+
+#[async_trait]
+impl ActivityApi for Instrumented<dyn ActivityApi + Send + Sync> {
+    async fn record(&self, activity: Activity) -> Result<Activity, Error> {
+        self.timed("record", self.inner.record(activity)).await
+    }
+
+    async fn get_group_activity(
+        &self,
+        group_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        self.timed(
+            "get_group_activity",
+            self.inner.get_group_activity(group_id, request),
+        )
+        .await
+    }
+
+    async fn get_user_activity(
+        &self,
+        user_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        self.timed(
+            "get_user_activity",
+            self.inner.get_user_activity(user_id, request),
+        )
+        .await
+    }
+
+    async fn mark_read(&self, user_id: String) -> Result<(), Error> {
+        self.timed("mark_read", self.inner.mark_read(user_id)).await
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        self.timed("anonymise_activity", self.inner.anonymise_user(user_id))
+            .await
+    }
+}
+
+#[async_trait]
+impl RecurringExpensesApi for Instrumented<dyn RecurringExpensesApi + Send + Sync> {
+    async fn generate_due_expenses(&self, now: DateTime<Utc>) -> Result<Vec<Expense>, Error> {
+        let generated = self
+            .timed(
+                "generate_due_expenses",
+                self.inner.generate_due_expenses(now),
+            )
+            .await?;
+        metrics()
+            .recurring_expenses_generated
+            .inc_by(generated.len() as u64);
+        Ok(generated)
+    }
+}
+
+#[async_trait]
+impl IdempotencyApi for Instrumented<dyn IdempotencyApi + Send + Sync> {
+    async fn reserve(
+        &self,
+        key: String,
+        request_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, Error> {
+        self.timed("reserve", self.inner.reserve(key, request_hash, expires_at))
+            .await
+    }
+
+    async fn complete(&self, key: String, response: StoredResponse) -> Result<(), Error> {
+        self.timed("complete", self.inner.complete(key, response))
+            .await
+    }
+
+    async fn release(&self, key: String) -> Result<(), Error> {
+        self.timed("release", self.inner.release(key)).await
+    }
+}
+
+#[async_trait]
+impl BlobStore for Instrumented<dyn BlobStore + Send + Sync> {
+    async fn put_blob(&self, key: String, blob: Blob) -> Result<(), Error> {
+        self.timed("put_blob", self.inner.put_blob(key, blob)).await
+    }
+
+    async fn get_blob(&self, key: String) -> Result<Option<Blob>, Error> {
+        self.timed("get_blob", self.inner.get_blob(key)).await
+    }
+
+    async fn delete_blob(&self, key: String) -> Result<bool, Error> {
+        self.timed("delete_blob", self.inner.delete_blob(key)).await
+    }
+}
+
+#[async_trait]
+impl CategoriesApi for Instrumented<dyn CategoriesApi + Send + Sync> {
+    async fn get_group_categories(&self, group_id: String) -> Result<Vec<Category>, Error> {
+        self.timed(
+            "get_group_categories",
+            self.inner.get_group_categories(group_id),
+        )
+        .await
+    }
+
+    async fn create_category(
+        &self,
+        group_id: String,
+        spec: CreateCategorySpec,
+    ) -> Result<Category, Error> {
+        self.timed(
+            "create_category",
+            self.inner.create_category(group_id, spec),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl ReportsApi for Instrumented<dyn ReportsApi + Send + Sync> {
+    async fn group_report(
+        &self,
+        group_id: String,
+        request: ReportRequest,
+    ) -> Result<GroupReport, Error> {
+        self.timed("group_report", self.inner.group_report(group_id, request))
+            .await
+    }
+
+    async fn user_spending(
+        &self,
+        user_id: String,
+        request: ReportRequest,
+    ) -> Result<Vec<UserSpending>, Error> {
+        self.timed("user_spending", self.inner.user_spending(user_id, request))
+            .await
+    }
+}
+
+#[async_trait]
+impl HealthApi for Instrumented<dyn HealthApi + Send + Sync> {
+    async fn ping(&self) -> Result<(), Error> {
+        self.timed("ping", self.inner.ping()).await
+    }
+}
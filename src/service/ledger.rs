@@ -0,0 +1,432 @@
+use crate::service::expense::{Expense, User};
+use crate::service::export::{cents, exported, format_cents};
+use crate::service::group::Group;
+use anyhow::Error;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use utoipa::ToSchema;
+
+/// Plain-text accounting format of the export.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerFormat {
+    /// Journal read by both ledger and hledger.
+    #[default]
+    #[serde(alias = "hledger")]
+    Ledger,
+    Beancount,
+}
+
+impl LedgerFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LedgerFormat::Ledger => "ledger",
+            LedgerFormat::Beancount => "beancount",
+        }
+    }
+}
+
+/// Accounts the transactions of a user are posted to. One's share of an expense is posted to
+/// the expense account of its group, what one paid to the funding account, and the difference
+/// to the account of every counterparty, positive while they owe you and negative while you owe
+/// them.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerRequest {
+    #[serde(default)]
+    pub format: LedgerFormat,
+
+    /// Only expenses dated after this date.
+    pub dated_after: Option<DateTime<Utc>>,
+
+    /// Only expenses dated before this date.
+    pub dated_before: Option<DateTime<Utc>>,
+
+    /// Account of one's share of expenses in groups without their own. Defaults to
+    /// `Expenses:Shared`.
+    pub expense_account: Option<String>,
+
+    /// Account expenses are paid from and payments are made from and to. Defaults to
+    /// `Assets:Cash`.
+    pub funding_account: Option<String>,
+
+    /// Commodity of expenses without a currency. Defaults to `USD`.
+    pub currency: Option<String>,
+
+    /// Expense account by group id.
+    #[serde(default)]
+    pub group_accounts: HashMap<String, String>,
+
+    /// Receivable/payable account by user id of the counterparty. Defaults to
+    /// `Assets:Receivable:<name>`.
+    #[serde(default)]
+    pub counterparty_accounts: HashMap<String, String>,
+}
+
+/// A transaction per expense and payment the user has a share in, oldest first. Deleted
+/// expenses are left out. `groups` name the counterparties which have no name in the shares.
+/// Fails on shares with an amount which is not a number.
+pub fn user_ledger(
+    user_id: &str,
+    expenses: &[Expense],
+    groups: &[Group],
+    request: &LedgerRequest,
+) -> Result<String, Error> {
+    let transactions = exported(expenses)
+        .into_iter()
+        .filter_map(|expense| transaction(user_id, expense, groups, request).transpose())
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut journal = String::new();
+    if request.format == LedgerFormat::Beancount {
+        // beancount rejects postings to accounts which were not opened before
+        let accounts = transactions
+            .iter()
+            .flat_map(|transaction| &transaction.postings)
+            .map(|(account, _, _)| account.as_str())
+            .collect::<BTreeSet<_>>();
+        if let Some(first) = transactions.first() {
+            for account in &accounts {
+                let _ = writeln!(journal, "{} open {}", first.date, account);
+            }
+            journal.push('\n');
+        }
+    }
+    for transaction in &transactions {
+        transaction.write(&mut journal, request.format);
+    }
+    Ok(journal)
+}
+
+struct Transaction {
+    date: NaiveDate,
+    description: String,
+    group_id: Option<String>,
+    /// Account, amount in cents and commodity.
+    postings: Vec<(String, i64, String)>,
+}
+
+impl Transaction {
+    fn write(&self, journal: &mut String, format: LedgerFormat) {
+        let width = self
+            .postings
+            .iter()
+            .map(|(account, _, _)| account.len())
+            .max()
+            .unwrap_or(0);
+        let _ = match format {
+            LedgerFormat::Ledger => writeln!(journal, "{} {}", self.date, self.description),
+            LedgerFormat::Beancount => writeln!(
+                journal,
+                "{} * \"{}\"",
+                self.date,
+                self.description.replace('\\', "\\\\").replace('"', "\\\"")
+            ),
+        };
+        if let Some(group_id) = &self.group_id {
+            let _ = match format {
+                LedgerFormat::Ledger => writeln!(journal, "    ; group: {}", group_id),
+                LedgerFormat::Beancount => writeln!(journal, "  group: \"{}\"", group_id),
+            };
+        }
+        let indent = match format {
+            LedgerFormat::Ledger => "    ",
+            LedgerFormat::Beancount => "  ",
+        };
+        for (account, amount, commodity) in &self.postings {
+            let amount = format_cents(*amount);
+            let _ = writeln!(
+                journal,
+                "{}{:<width$}  {:>10} {}",
+                indent, account, amount, commodity
+            );
+        }
+        journal.push('\n');
+    }
+}
+
+fn transaction(
+    user_id: &str,
+    expense: &Expense,
+    groups: &[Group],
+    request: &LedgerRequest,
+) -> Result<Option<Transaction>, Error> {
+    let Some(share) = expense.share_of(user_id) else {
+        return Ok(None);
+    };
+    let paid = cents(share.paid_share.as_deref())?;
+    let owed = cents(share.owed_share.as_deref())?;
+    let net = cents(share.net_balance.as_deref())?;
+    let commodity = expense
+        .currency_code
+        .clone()
+        .or_else(|| request.currency.clone())
+        .unwrap_or_else(|| "USD".to_string());
+    let funding = request
+        .funding_account
+        .clone()
+        .unwrap_or_else(|| "Assets:Cash".to_string());
+    let group = expense
+        .group_id
+        .as_ref()
+        .and_then(|id| groups.iter().find(|group| group.id.as_ref() == Some(id)));
+
+    let mut postings = Vec::new();
+    if expense.payment == Some(true) {
+        // money changing hands, nothing is spent
+        postings.push((funding, owed - paid));
+    } else {
+        let expense_account = expense
+            .group_id
+            .as_ref()
+            .and_then(|id| request.group_accounts.get(id))
+            .or(request.expense_account.as_ref())
+            .cloned()
+            .unwrap_or_else(|| "Expenses:Shared".to_string());
+        postings.push((expense_account, owed));
+        postings.push((funding, -paid));
+    }
+    for (counterparty, amount) in counterparties(user_id, expense, net)? {
+        let account = request
+            .counterparty_accounts
+            .get(&counterparty)
+            .cloned()
+            .unwrap_or_else(|| {
+                let name = name(&counterparty, expense, group);
+                format!("Assets:Receivable:{}", account_component(&name))
+            });
+        postings.push((account, amount));
+    }
+    let postings = postings
+        .into_iter()
+        .filter(|(_, amount)| *amount != 0)
+        .map(|(account, amount)| (account, amount, commodity.clone()))
+        .collect::<Vec<_>>();
+    let Some(date) = expense.date.or(expense.created_at) else {
+        return Ok(None);
+    };
+    if postings.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Transaction {
+        date: date.date_naive(),
+        description: expense
+            .description
+            .clone()
+            .unwrap_or_else(|| "Expense".to_string()),
+        group_id: expense.group_id.clone(),
+        postings,
+    }))
+}
+
+/// Splits the net balance of the user over the counterparties with a net balance of the
+/// opposite sign, in proportion to theirs. The last one gets the cents lost to rounding.
+fn counterparties(user_id: &str, expense: &Expense, net: i64) -> Result<Vec<(String, i64)>, Error> {
+    let opposite = expense
+        .users
+        .iter()
+        .flatten()
+        .filter_map(|share| {
+            let id = share.user.as_ref()?.id.clone()?;
+            let their_net = match cents(share.net_balance.as_deref()) {
+                Ok(their_net) => their_net,
+                Err(error) => return Some(Err(error)),
+            };
+            (id != user_id && their_net.signum() == -net.signum() && their_net != 0)
+                .then_some(Ok((id, their_net.abs())))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let total = opposite.iter().map(|(_, weight)| weight).sum::<i64>();
+    let mut remaining = net;
+    let count = opposite.len();
+    Ok(opposite
+        .into_iter()
+        .enumerate()
+        .map(|(position, (id, weight))| {
+            let amount = if position + 1 == count {
+                remaining
+            } else {
+                net * weight / total
+            };
+            remaining -= amount;
+            (id, amount)
+        })
+        .collect())
+}
+
+/// Name of the user with `id`, from the shares or the creator of the expense, or the members of
+/// its group.
+fn name(id: &str, expense: &Expense, group: Option<&Group>) -> String {
+    let from_user = |user: &User| {
+        (user.id.as_deref() == Some(id))
+            .then(|| user.first_name.clone())
+            .flatten()
+    };
+    expense
+        .users
+        .iter()
+        .flatten()
+        .filter_map(|share| share.user.as_ref())
+        .chain(expense.created_by.as_ref())
+        .find_map(from_user)
+        .or_else(|| {
+            group?
+                .members
+                .iter()
+                .flatten()
+                .find(|member| member.id.as_deref() == Some(id))?
+                .first_name
+                .clone()
+        })
+        .unwrap_or_else(|| id.to_string())
+}
+
+/// `name` as a component of an account name valid in both formats, which beancount requires to
+/// start with a capital letter or digit and to hold only letters, digits and dashes.
+fn account_component(name: &str) -> String {
+    let component = name
+        .split(|character: char| !character.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut characters = word.chars();
+            characters
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + characters.as_str())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join("-");
+    if component.is_empty() {
+        "Unknown".to_string()
+    } else {
+        component
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{account_component, user_ledger, LedgerFormat, LedgerRequest};
+    use crate::service::expense::{Expense, ShareCalculator, User, UserShare};
+    use crate::service::group::{Group, User as Member};
+    use chrono::{TimeZone, Utc};
+    use std::collections::HashMap;
+
+    fn group() -> Group {
+        Group {
+            id: Some("g1".to_string()),
+            members: Some(vec![
+                Member {
+                    id: Some("2".to_string()),
+                    first_name: Some("Bob".to_string()),
+                    ..Member::default()
+                },
+                Member {
+                    id: Some("3".to_string()),
+                    first_name: Some("Carol Ann".to_string()),
+                    ..Member::default()
+                },
+            ]),
+            ..Group::default()
+        }
+    }
+
+    fn expenses() -> Vec<Expense> {
+        let date = |day| Utc.with_ymd_and_hms(2024, 1, day, 12, 0, 0).single();
+        let payment = vec![
+            UserShare {
+                user: Some(User {
+                    id: Some("2".to_string()),
+                    ..User::default()
+                }),
+                paid_share: Some("20.00".to_string()),
+                owed_share: Some("0.00".to_string()),
+                net_balance: Some("20.00".to_string()),
+            },
+            UserShare {
+                user: Some(User {
+                    id: Some("1".to_string()),
+                    ..User::default()
+                }),
+                paid_share: Some("0.00".to_string()),
+                owed_share: Some("20.00".to_string()),
+                net_balance: Some("-20.00".to_string()),
+            },
+        ];
+        vec![
+            Expense {
+                date: date(1),
+                description: Some("Dinner".to_string()),
+                group_id: Some("g1".to_string()),
+                currency_code: Some("EUR".to_string()),
+                users: Some(ShareCalculator::new().equal_share(
+                    "90.00".to_string(),
+                    "1".to_string(),
+                    vec!["1".to_string(), "2".to_string(), "3".to_string()],
+                )),
+                ..Expense::default()
+            },
+            Expense {
+                date: date(2),
+                description: Some("Bob paid back".to_string()),
+                group_id: Some("g1".to_string()),
+                currency_code: Some("EUR".to_string()),
+                payment: Some(true),
+                users: Some(payment),
+                ..Expense::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn post_share_against_counterparties() {
+        let request = LedgerRequest {
+            group_accounts: HashMap::from([("g1".to_string(), "Expenses:Trip".to_string())]),
+            ..LedgerRequest::default()
+        };
+        assert_eq!(
+            user_ledger("1", &expenses(), &[group()], &request).unwrap(),
+            "2024-01-01 Dinner\n\
+             \x20   ; group: g1\n\
+             \x20   Expenses:Trip                     30.00 EUR\n\
+             \x20   Assets:Cash                      -90.00 EUR\n\
+             \x20   Assets:Receivable:Bob             30.00 EUR\n\
+             \x20   Assets:Receivable:Carol-Ann       30.00 EUR\n\
+             \n\
+             2024-01-02 Bob paid back\n\
+             \x20   ; group: g1\n\
+             \x20   Assets:Cash                 20.00 EUR\n\
+             \x20   Assets:Receivable:Bob      -20.00 EUR\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn open_accounts_for_beancount() {
+        let request = LedgerRequest {
+            format: LedgerFormat::Beancount,
+            counterparty_accounts: HashMap::from([(
+                "1".to_string(),
+                "Liabilities:Alice".to_string(),
+            )]),
+            ..LedgerRequest::default()
+        };
+        let journal = user_ledger("2", &expenses(), &[], &request).unwrap();
+        assert!(journal.starts_with(
+            "2024-01-01 open Assets:Cash\n\
+             2024-01-01 open Expenses:Shared\n\
+             2024-01-01 open Liabilities:Alice\n\n\
+             2024-01-01 * \"Dinner\"\n\
+             \x20 group: \"g1\"\n\
+             \x20 Expenses:Shared         30.00 EUR\n\
+             \x20 Liabilities:Alice      -30.00 EUR\n"
+        ));
+    }
+
+    #[test]
+    fn sanitize_account_names() {
+        assert_eq!(account_component("carol ann"), "Carol-Ann");
+        assert_eq!(account_component("Zoë"), "Zo");
+        assert_eq!(account_component("!!"), "Unknown");
+    }
+}
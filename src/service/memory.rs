@@ -0,0 +1,880 @@
+use crate::service::account::{
+    anonymise_activity, anonymise_comment, anonymise_expense, anonymise_member, anonymise_revision,
+};
+use crate::service::activity::{Activity, ActivityApi, ActivityPage, ActivityRequest};
+use crate::service::balance::{Balance, BalanceApi};
+use crate::service::blob::{Blob, BlobStore};
+use crate::service::category::{CategoriesApi, Category, CategoryTaken, CreateCategorySpec};
+use crate::service::comment::{Comment, CommentType, CommentsApi, CreateCommentSpec};
+use crate::service::events::EventBus;
+use crate::service::expense::{
+    deletion_document, describe_changes, merge_update, restoration_document, CreateExpenseSpec,
+    Expense, ExpenseEntity, Expenses, ExpensesApi, ExpensesCalculator, ExpensesResponse,
+    ListExpensesRequest, UpdateExpenseSpec, User,
+};
+use crate::service::group::{CreateGroupSpec, Group, GroupApi, GroupUser};
+use crate::service::health::HealthApi;
+use crate::service::history::{diff, ExpenseAction, ExpenseHistoryApi, ExpenseRevision};
+use crate::service::idempotency::{IdempotencyApi, IdempotencyRecord, StoredResponse};
+use crate::service::receipt::Receipt;
+use crate::service::recurring::{occurrence, RecurringExpensesApi};
+use crate::service::report::{GroupReport, ReportRequest, ReportsApi, Totals, UserSpending};
+use crate::service::user;
+use crate::service::user::{CreateUserSpec, UserApi};
+use crate::service::version;
+use crate::service::version::Conditional;
+use crate::service::NotFound;
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::Document;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Implementation of every service trait which keeps the data in memory, so that the API can be
+/// run and tested without MongoDB. Writes behave like their MongoDB counterparts, including the
+/// history, comments and activity they record. Everything is lost when the process exits.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryStore {
+    state: Arc<Mutex<State>>,
+
+    events: Option<EventBus>,
+}
+
+/// Documents are keyed by the hex representation of an `ObjectId`, so that iterating a map
+/// yields them in insertion order.
+#[derive(Debug, Default)]
+struct State {
+    expenses: BTreeMap<String, Expense>,
+    groups: BTreeMap<String, Group>,
+    users: BTreeMap<String, user::User>,
+    balances: HashMap<String, Balance>,
+    comments: BTreeMap<String, Comment>,
+    history: Vec<ExpenseRevision>,
+    activity: BTreeMap<String, Activity>,
+    /// Id of the last read activity per user.
+    activity_reads: HashMap<String, String>,
+    idempotency_keys: HashMap<String, IdempotencyRecord>,
+    blobs: HashMap<String, Blob>,
+    categories: BTreeMap<String, Category>,
+}
+
+impl State {
+    /// Appends the next revision of the expense to its history.
+    fn push_revision(
+        &mut self,
+        expense_id: String,
+        action: ExpenseAction,
+        changed_by: Option<User>,
+        before: &Document,
+        after: &Document,
+    ) -> ExpenseRevision {
+        let latest = self
+            .history
+            .iter()
+            .filter(|revision| revision.expense_id == expense_id)
+            .map(|revision| revision.version)
+            .max();
+        let revision = ExpenseRevision {
+            expense_id,
+            version: latest.unwrap_or(0) + 1,
+            action,
+            changed_by,
+            changed_at: Utc::now(),
+            changes: diff(before, after),
+        };
+        self.history.push(revision.clone());
+        revision
+    }
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publishes every change of an expense to the given bus.
+    pub fn with_events(self, events: EventBus) -> Self {
+        Self {
+            events: Some(events),
+            ..self
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("In-memory store is poisoned")
+    }
+
+    /// Merges the fields into the stored expense the way `$set` does, increments its version and
+    /// records the change. Returns `None` if the expense was changed since `expected_version`.
+    async fn apply_update(
+        &self,
+        id: &str,
+        action: ExpenseAction,
+        changed_by: Option<User>,
+        mut set_document: Document,
+        expected_version: Option<i64>,
+    ) -> Result<Option<(Expense, ExpenseRevision)>, Error> {
+        // the revision is numbered under the same lock as the change it records
+        let (expense, revision) = {
+            let mut state = self.state();
+            let expense = state
+                .expenses
+                .get_mut(id)
+                .ok_or_else(|| NotFound::new("Expense", id))?;
+            if !version::matches(expected_version, expense.version) {
+                return Ok(None);
+            }
+            let _previous = set_document.insert(
+                "version",
+                expense.version.unwrap_or(version::UNVERSIONED) + 1,
+            );
+            let (before, after) = merge_update(expense, set_document)?;
+            *expense = bson::from_document(after.clone())?;
+            let expense = expense.clone();
+            let revision =
+                state.push_revision(id.to_string(), action, changed_by.clone(), &before, &after);
+            (expense, revision)
+        };
+        let _activity = self
+            .record(Activity::for_expense(
+                id.to_string(),
+                action,
+                changed_by.as_ref(),
+                &expense,
+            ))
+            .await?;
+        if let Some(events) = &self.events {
+            events.publish_expense(id.to_string(), action, &expense);
+        }
+        Ok(Some((expense, revision)))
+    }
+
+    /// Records the creation of the expense stored under `id` in its history and the group
+    /// activity.
+    async fn record_creation(&self, id: String, expense: &Expense) -> Result<(), Error> {
+        let _revision = self
+            .record_revision(
+                id.clone(),
+                ExpenseAction::Created,
+                expense.created_by.clone(),
+                &Document::new(),
+                &bson::to_document(expense)?,
+            )
+            .await?;
+        let _activity = self
+            .record(Activity::for_expense(
+                id.clone(),
+                ExpenseAction::Created,
+                expense.created_by.as_ref(),
+                expense,
+            ))
+            .await?;
+        if let Some(events) = &self.events {
+            events.publish_expense(id, ExpenseAction::Created, expense);
+        }
+        Ok(())
+    }
+
+    /// Stores the comment on its expense, which has to exist.
+    fn insert_comment(&self, comment: Comment) -> Result<Comment, Error> {
+        let id = ObjectId::new().to_hex();
+        let comment = Comment {
+            id: Some(id.clone()),
+            ..comment
+        };
+        let mut state = self.state();
+        let expense = state
+            .expenses
+            .get_mut(&comment.expense_id)
+            .ok_or_else(|| NotFound::new("Expense", &comment.expense_id))?;
+        expense.comments_count = Some(expense.comments_count.unwrap_or(0) + 1);
+        let _previous = state.comments.insert(id, comment.clone());
+        Ok(comment)
+    }
+
+    fn find_page(
+        &self,
+        filter: impl Fn(&Activity) -> bool,
+        request: ActivityRequest,
+    ) -> ActivityPage {
+        let state = self.state();
+        let limit = request.page_size();
+        let last_read = request
+            .user_id
+            .as_ref()
+            .map(|user_id| state.activity_reads.get(user_id));
+        let activities = state
+            .activity
+            .iter()
+            .rev()
+            .filter(|(id, _)| !matches!(&request.cursor, Some(cursor) if *id >= cursor))
+            .filter(|(_, activity)| filter(activity))
+            .take(limit as usize)
+            .map(|(id, activity)| Activity {
+                unread: last_read.map(|last_read| !matches!(last_read, Some(last) if id <= last)),
+                ..activity.clone()
+            })
+            .collect::<Vec<_>>();
+        let next_cursor = if activities.len() as i64 == limit {
+            activities.last().and_then(|activity| activity.id.clone())
+        } else {
+            None
+        };
+        ActivityPage {
+            activities,
+            next_cursor,
+        }
+    }
+}
+
+#[async_trait]
+impl ExpensesApi for InMemoryStore {
+    async fn get_expense(&self, id: String) -> Result<Expense, Error> {
+        self.state()
+            .expenses
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| NotFound::new("Expense", &id).into())
+    }
+
+    async fn list_expenses(&self, request: ListExpensesRequest) -> Result<ExpensesResponse, Error> {
+        let expenses = self
+            .state()
+            .expenses
+            .values()
+            .filter(|expense| request.matches(expense))
+            .cloned()
+            .collect();
+        Ok(ExpensesResponse { expenses })
+    }
+
+    async fn create_expense(&self, expense: CreateExpenseSpec) -> Result<ExpenseEntity, Error> {
+        let expense = ExpensesCalculator::new().create_expense(&expense)?;
+        let id = ObjectId::new();
+        let _previous = self.state().expenses.insert(id.to_hex(), expense.clone());
+        self.record_creation(id.to_hex(), &expense).await?;
+        Ok(ExpenseEntity {
+            id: Some(id),
+            expense,
+        })
+    }
+
+    async fn update_expense(
+        &self,
+        id: String,
+        update_expense_spec: UpdateExpenseSpec,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        let current = self.get_expense(id.clone()).await?;
+        let set_document = update_expense_spec.to_set_document(Some(&current))?;
+        let Some((expense, revision)) = self
+            .apply_update(
+                &id,
+                ExpenseAction::Updated,
+                update_expense_spec.updated_by,
+                set_document,
+                expected_version,
+            )
+            .await?
+        else {
+            return Ok(Conditional::Stale(self.get_expense(id).await?));
+        };
+        if !revision.changes.is_empty() {
+            let _comment = self
+                .create_system_comment(id, describe_changes(&revision.changes))
+                .await?;
+        }
+        Ok(Conditional::Applied(expense))
+    }
+
+    async fn delete_expense(
+        &self,
+        id: String,
+        deleted_by: User,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        let set_document = deletion_document(&deleted_by)?;
+        match self
+            .apply_update(
+                &id,
+                ExpenseAction::Deleted,
+                Some(deleted_by),
+                set_document,
+                expected_version,
+            )
+            .await?
+        {
+            Some((expense, _revision)) => Ok(Conditional::Applied(expense)),
+            None => Ok(Conditional::Stale(self.get_expense(id).await?)),
+        }
+    }
+
+    async fn restore_expense(&self, id: String, restored_by: User) -> Result<(), Error> {
+        let set_document = restoration_document(&restored_by)?;
+        let _updated = self
+            .apply_update(
+                &id,
+                ExpenseAction::Restored,
+                Some(restored_by),
+                set_document,
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn import_expenses(&self, expenses: Vec<Expense>) -> Result<Vec<ExpenseEntity>, Error> {
+        let imported = expenses
+            .into_iter()
+            .map(|expense| (ObjectId::new(), expense))
+            .collect::<Vec<_>>();
+        {
+            let mut state = self.state();
+            for (id, expense) in &imported {
+                let _previous = state.expenses.insert(id.to_hex(), expense.clone());
+            }
+        }
+        let mut entities = Vec::with_capacity(imported.len());
+        for (id, expense) in imported {
+            self.record_creation(id.to_hex(), &expense).await?;
+            entities.push(ExpenseEntity {
+                id: Some(id),
+                expense,
+            });
+        }
+        Ok(entities)
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        let mut anonymised = 0;
+        for expense in self.state().expenses.values_mut() {
+            if anonymise_expense(expense, &user_id) {
+                expense.version = Some(expense.version.unwrap_or(version::UNVERSIONED) + 1);
+                anonymised += 1;
+            }
+        }
+        Ok(anonymised)
+    }
+
+    async fn set_receipt(
+        &self,
+        id: String,
+        receipt: Option<Receipt>,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        let mut set_document = Document::new();
+        let _previous = set_document.insert("receipt", bson::to_bson(&receipt)?);
+        match self
+            .apply_update(
+                &id,
+                ExpenseAction::Updated,
+                None,
+                set_document,
+                expected_version,
+            )
+            .await?
+        {
+            Some((expense, _revision)) => Ok(Conditional::Applied(expense)),
+            None => Ok(Conditional::Stale(self.get_expense(id).await?)),
+        }
+    }
+
+    async fn purge_expense(&self, id: String) -> Result<bool, Error> {
+        let mut state = self.state();
+        state.comments.retain(|_, comment| comment.expense_id != id);
+        state.history.retain(|revision| revision.expense_id != id);
+        state
+            .activity
+            .retain(|_, activity| activity.expense_id.as_ref() != Some(&id));
+        Ok(state.expenses.remove(&id).is_some())
+    }
+}
+
+#[async_trait]
+impl ExpenseHistoryApi for InMemoryStore {
+    async fn get_history(&self, expense_id: String) -> Result<Vec<ExpenseRevision>, Error> {
+        let mut revisions = self
+            .state()
+            .history
+            .iter()
+            .filter(|revision| revision.expense_id == expense_id)
+            .cloned()
+            .collect::<Vec<_>>();
+        revisions.sort_by_key(|revision| revision.version);
+        Ok(revisions)
+    }
+
+    async fn record_revision(
+        &self,
+        expense_id: String,
+        action: ExpenseAction,
+        changed_by: Option<User>,
+        before: &Document,
+        after: &Document,
+    ) -> Result<ExpenseRevision, Error> {
+        Ok(self
+            .state()
+            .push_revision(expense_id, action, changed_by, before, after))
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        Ok(self
+            .state()
+            .history
+            .iter_mut()
+            .map(|revision| u64::from(anonymise_revision(revision, &user_id)))
+            .sum())
+    }
+}
+
+#[async_trait]
+impl CommentsApi for InMemoryStore {
+    async fn get_comments(&self, expense_id: String) -> Result<Vec<Comment>, Error> {
+        Ok(self
+            .state()
+            .comments
+            .values()
+            .filter(|comment| comment.expense_id == expense_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn create_comment(
+        &self,
+        expense_id: String,
+        spec: CreateCommentSpec,
+    ) -> Result<Comment, Error> {
+        self.insert_comment(Comment {
+            id: None,
+            expense_id,
+            content: spec.content,
+            comment_type: CommentType::User,
+            created_at: Some(Utc::now()),
+            created_by: Some(spec.user),
+        })
+    }
+
+    async fn create_system_comment(
+        &self,
+        expense_id: String,
+        content: String,
+    ) -> Result<Comment, Error> {
+        self.insert_comment(Comment {
+            id: None,
+            expense_id,
+            content,
+            comment_type: CommentType::System,
+            created_at: Some(Utc::now()),
+            created_by: None,
+        })
+    }
+
+    async fn delete_comment(&self, id: String) -> Result<Option<Comment>, Error> {
+        let mut state = self.state();
+        let comment = state.comments.remove(&id);
+        if let Some(comment) = &comment {
+            if let Some(expense) = state.expenses.get_mut(&comment.expense_id) {
+                expense.comments_count = Some(expense.comments_count.unwrap_or(0) - 1);
+            }
+        }
+        Ok(comment)
+    }
+
+    async fn get_user_comments(&self, user_id: String) -> Result<Vec<Comment>, Error> {
+        Ok(self
+            .state()
+            .comments
+            .values()
+            .filter(|comment| {
+                matches!(&comment.created_by, Some(User { id: Some(id), .. }) if *id == user_id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        Ok(self
+            .state()
+            .comments
+            .values_mut()
+            .map(|comment| u64::from(anonymise_comment(comment, &user_id)))
+            .sum())
+    }
+}
+
+#[async_trait]
+impl ActivityApi for InMemoryStore {
+    async fn record(&self, activity: Activity) -> Result<Activity, Error> {
+        let id = ObjectId::new().to_hex();
+        let activity = Activity {
+            id: Some(id.clone()),
+            ..activity
+        };
+        let _previous = self.state().activity.insert(id, activity.clone());
+        Ok(activity)
+    }
+
+    async fn get_group_activity(
+        &self,
+        group_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        Ok(self.find_page(
+            |activity| activity.group_id.as_ref() == Some(&group_id),
+            request,
+        ))
+    }
+
+    async fn get_user_activity(
+        &self,
+        user_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        let request = ActivityRequest {
+            user_id: Some(user_id.clone()),
+            ..request
+        };
+        Ok(self.find_page(|activity| activity.user_ids.contains(&user_id), request))
+    }
+
+    async fn mark_read(&self, user_id: String) -> Result<(), Error> {
+        let mut state = self.state();
+        let latest = state
+            .activity
+            .iter()
+            .rev()
+            .find(|(_, activity)| activity.user_ids.contains(&user_id))
+            .map(|(id, _)| id.clone());
+        if let Some(latest) = latest {
+            let _previous = state.activity_reads.insert(user_id, latest);
+        }
+        Ok(())
+    }
+
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        Ok(self
+            .state()
+            .activity
+            .values_mut()
+            .map(|activity| u64::from(anonymise_activity(activity, &user_id)))
+            .sum())
+    }
+}
+
+#[async_trait]
+impl GroupApi for InMemoryStore {
+    async fn get_group(&self, id: String) -> Result<Group, Error> {
+        self.state()
+            .groups
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| NotFound::new("Group", &id).into())
+    }
+
+    async fn create_group(&self, create_spec: CreateGroupSpec) -> Result<Group, Error> {
+        let id = ObjectId::new().to_hex();
+        let group = Group {
+            id: Some(id.clone()),
+            ..Group::from(create_spec)
+        };
+        let _previous = self.state().groups.insert(id, group.clone());
+        let _activity = self.record(Activity::group_created(&group)).await?;
+        Ok(group)
+    }
+
+    async fn get_user_group(&self, user_id: String) -> Result<Vec<Group>, Error> {
+        Ok(self
+            .state()
+            .groups
+            .values()
+            .filter(|group| group.member_ids().contains(&user_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn add_member(
+        &self,
+        group_id: String,
+        user: GroupUser,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Group>, Error> {
+        let user_id = user.user_id.clone();
+        let name = user.first_name.clone().unwrap_or_else(|| user_id.clone());
+        let group = {
+            let mut state = self.state();
+            let group = state
+                .groups
+                .get_mut(&group_id)
+                .ok_or_else(|| NotFound::new("Group", &group_id))?;
+            if !version::matches(expected_version, group.version) {
+                return Ok(Conditional::Stale(group.clone()));
+            }
+            group.members.get_or_insert_with(Vec::new).push(user.into());
+            group.version = Some(group.version.unwrap_or(version::UNVERSIONED) + 1);
+            group.clone()
+        };
+        let _activity = self
+            .record(Activity::member_joined(&group, user_id, name))
+            .await?;
+        Ok(Conditional::Applied(group))
+    }
+
+    async fn anonymise_member(&self, user_id: String) -> Result<u64, Error> {
+        let mut anonymised = 0;
+        for group in self.state().groups.values_mut() {
+            if anonymise_member(group, &user_id) {
+                group.version = Some(group.version.unwrap_or(version::UNVERSIONED) + 1);
+                anonymised += 1;
+            }
+        }
+        Ok(anonymised)
+    }
+
+    async fn delete_group(&self, id: String) -> Result<bool, Error> {
+        let mut state = self.state();
+        state
+            .activity
+            .retain(|_, activity| activity.group_id.as_ref() != Some(&id));
+        Ok(state.groups.remove(&id).is_some())
+    }
+}
+
+#[async_trait]
+impl UserApi for InMemoryStore {
+    async fn get_user(&self, id: i32) -> Result<user::User, Error> {
+        self.state()
+            .users
+            .get(&id.to_string())
+            .cloned()
+            .ok_or_else(|| NotFound::new("User", id).into())
+    }
+
+    async fn create_user(&self, create_spec: CreateUserSpec) -> Result<String, Error> {
+        let id = ObjectId::new().to_hex();
+        let user = user::User {
+            id: Some(id.clone()),
+            ..user::User::from(create_spec)
+        };
+        let _previous = self.state().users.insert(id.clone(), user);
+        Ok(id)
+    }
+
+    async fn find_user_by_email(&self, email: String) -> Result<Option<user::User>, Error> {
+        Ok(self
+            .state()
+            .users
+            .values()
+            .find(|user| {
+                user.email
+                    .as_ref()
+                    .is_some_and(|user_email| user_email.eq_ignore_ascii_case(&email))
+            })
+            .cloned())
+    }
+    async fn find_user(&self, id: String) -> Result<Option<user::User>, Error> {
+        Ok(self.state().users.get(&id).cloned())
+    }
+
+    async fn delete_user(&self, id: String) -> Result<bool, Error> {
+        Ok(self.state().users.remove(&id).is_some())
+    }
+}
+
+#[async_trait]
+impl BalanceApi for InMemoryStore {
+    async fn get_user_balance(&self, user_id: String) -> Result<Balance, Error> {
+        self.state()
+            .balances
+            .get(&user_id)
+            .cloned()
+            .ok_or_else(|| Error::msg(format!("No balance for user {}", user_id)))
+    }
+}
+
+#[async_trait]
+impl RecurringExpensesApi for InMemoryStore {
+    async fn generate_due_expenses(&self, now: DateTime<Utc>) -> Result<Vec<Expense>, Error> {
+        let series = self
+            .state()
+            .expenses
+            .iter()
+            .filter(|(_, expense)| expense.repeats == Some(true) && expense.deleted_at.is_none())
+            .map(|(id, expense)| (id.clone(), expense.clone()))
+            .collect::<Vec<_>>();
+
+        let mut generated = Vec::new();
+        for (id, template) in series {
+            let (interval, anchor) = match (template.repeat_interval, template.date) {
+                (Some(interval), Some(anchor)) => (interval, anchor),
+                _ => continue,
+            };
+            let mut next_repeat = template.next_repeat;
+            while let Some(date) = next_repeat.filter(|date| *date <= now) {
+                let occurrence = occurrence(&template, id.clone(), date, now);
+                let exists = self.state().expenses.values().any(|expense| {
+                    expense.series_id == occurrence.series_id && expense.date == occurrence.date
+                });
+                if !exists {
+                    let occurrence_id = ObjectId::new().to_hex();
+                    let _previous = self
+                        .state()
+                        .expenses
+                        .insert(occurrence_id.clone(), occurrence.clone());
+                    let _revision = self
+                        .record_revision(
+                            occurrence_id.clone(),
+                            ExpenseAction::Created,
+                            None,
+                            &Document::new(),
+                            &bson::to_document(&occurrence)?,
+                        )
+                        .await?;
+                    let _activity = self
+                        .record(Activity::for_expense(
+                            occurrence_id.clone(),
+                            ExpenseAction::Created,
+                            None,
+                            &occurrence,
+                        ))
+                        .await?;
+                    if let Some(events) = &self.events {
+                        events.publish_expense(occurrence_id, ExpenseAction::Created, &occurrence);
+                    }
+                    generated.push(occurrence);
+                }
+                next_repeat = interval.next_after(anchor, date);
+            }
+            if let Some(expense) = self.state().expenses.get_mut(&id) {
+                expense.next_repeat = next_repeat;
+            }
+        }
+        Ok(generated)
+    }
+}
+
+#[async_trait]
+impl BlobStore for InMemoryStore {
+    async fn put_blob(&self, key: String, blob: Blob) -> Result<(), Error> {
+        let _previous = self.state().blobs.insert(key, blob);
+        Ok(())
+    }
+
+    async fn get_blob(&self, key: String) -> Result<Option<Blob>, Error> {
+        Ok(self.state().blobs.get(&key).cloned())
+    }
+
+    async fn delete_blob(&self, key: String) -> Result<bool, Error> {
+        Ok(self.state().blobs.remove(&key).is_some())
+    }
+}
+
+#[async_trait]
+impl CategoriesApi for InMemoryStore {
+    async fn get_group_categories(&self, group_id: String) -> Result<Vec<Category>, Error> {
+        Ok(self
+            .state()
+            .categories
+            .values()
+            .filter(|category| category.group_id.as_ref() == Some(&group_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn create_category(
+        &self,
+        group_id: String,
+        spec: CreateCategorySpec,
+    ) -> Result<Category, Error> {
+        let id = ObjectId::new().to_hex();
+        let category = Category::custom(id.clone(), group_id, spec);
+        let mut state = self.state();
+        let taken = state.categories.values().any(|existing| {
+            existing.group_id == category.group_id
+                && existing.parent_id == category.parent_id
+                && existing.name.eq_ignore_ascii_case(&category.name)
+        });
+        if taken {
+            return Err(CategoryTaken(category.name).into());
+        }
+        let _previous = state.categories.insert(id, category.clone());
+        Ok(category)
+    }
+}
+
+#[async_trait]
+impl ReportsApi for InMemoryStore {
+    async fn group_report(
+        &self,
+        group_id: String,
+        request: ReportRequest,
+    ) -> Result<GroupReport, Error> {
+        let totals = Totals::of(
+            self.state()
+                .expenses
+                .values()
+                .filter(|expense| expense.group_id.as_ref() == Some(&group_id)),
+            &request,
+        )?;
+        Ok(totals.report(group_id, request))
+    }
+
+    async fn user_spending(
+        &self,
+        user_id: String,
+        request: ReportRequest,
+    ) -> Result<Vec<UserSpending>, Error> {
+        UserSpending::of(self.state().expenses.values(), &user_id, &request)
+    }
+}
+
+#[async_trait]
+impl HealthApi for InMemoryStore {
+    async fn ping(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdempotencyApi for InMemoryStore {
+    async fn reserve(
+        &self,
+        key: String,
+        request_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, Error> {
+        let mut state = self.state();
+        match state.idempotency_keys.get(&key) {
+            Some(record) if record.expires_at > Utc::now() => Ok(Some(record.clone())),
+            _ => {
+                let _previous = state.idempotency_keys.insert(
+                    key.clone(),
+                    IdempotencyRecord {
+                        key,
+                        request_hash,
+                        response: None,
+                        expires_at,
+                    },
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    async fn complete(&self, key: String, response: StoredResponse) -> Result<(), Error> {
+        if let Some(record) = self.state().idempotency_keys.get_mut(&key) {
+            record.response = Some(response);
+        }
+        Ok(())
+    }
+
+    async fn release(&self, key: String) -> Result<(), Error> {
+        let mut state = self.state();
+        if matches!(state.idempotency_keys.get(&key), Some(record) if record.response.is_none()) {
+            let _removed = state.idempotency_keys.remove(&key);
+        }
+        Ok(())
+    }
+}
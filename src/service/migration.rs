@@ -0,0 +1,303 @@
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::bson::{doc, Document};
+use mongodb::options::{Collation, CollationStrength, IndexOptions};
+use mongodb::{bson, Client, Database, IndexModel};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+/// Versioned change of the MongoDB schema: indexes to create or documents to rewrite.
+#[async_trait]
+trait Migration: Send + Sync {
+    /// Migrations are applied in the order of their versions, each one only once.
+    fn version(&self) -> i64;
+    fn description(&self) -> &'static str;
+    async fn up(&self, db: &Database) -> Result<(), Error>;
+}
+
+/// Applies the migrations which were not applied yet and records them in the `migrations`
+/// collection.
+#[derive(Debug, Clone)]
+pub struct MongoMigrator {
+    db: Database,
+}
+
+impl MongoMigrator {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+
+    /// Runs the pending migrations, returns the versions which were applied.
+    pub async fn run(&self) -> Result<Vec<i64>, Error> {
+        let collection = self.db.collection::<Document>("migrations");
+        let mut cursor = collection.find(None, None).await?;
+        let mut applied = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            applied.push(document.get_i64("version")?);
+        }
+
+        let mut migrations = migrations();
+        migrations.sort_by_key(|migration| migration.version());
+        let mut newly_applied = Vec::new();
+        for migration in migrations {
+            if applied.contains(&migration.version()) {
+                continue;
+            }
+            tracing::info!(
+                "Applying migration {}: {}",
+                migration.version(),
+                migration.description()
+            );
+            migration.up(&self.db).await?;
+            let _inserted = collection
+                .insert_one(
+                    doc! {
+                        "version": migration.version(),
+                        "description": migration.description(),
+                        "appliedAt": bson::to_bson(&Utc::now())?,
+                    },
+                    None,
+                )
+                .await?;
+            newly_applied.push(migration.version());
+        }
+        Ok(newly_applied)
+    }
+}
+
+fn migrations() -> Vec<Box<dyn Migration>> {
+    vec![
+        Box::new(CreateIndexes),
+        Box::new(DebtUserIdsToStrings),
+        Box::new(ExpireIdempotencyKeys),
+        Box::new(IndexCategories),
+    ]
+}
+
+fn index(keys: Document) -> IndexModel {
+    IndexModel::builder().keys(keys).build()
+}
+
+/// Unique index, only covering the documents matching `partial_filter` if given.
+fn unique_index(keys: Document, partial_filter: Option<Document>) -> IndexModel {
+    IndexModel::builder()
+        .keys(keys)
+        .options(
+            IndexOptions::builder()
+                .unique(true)
+                .partial_filter_expression(partial_filter)
+                .build(),
+        )
+        .build()
+}
+
+/// Indexes backing the queries of the services.
+struct CreateIndexes;
+
+#[async_trait]
+impl Migration for CreateIndexes {
+    fn version(&self) -> i64 {
+        1
+    }
+
+    fn description(&self) -> &'static str {
+        "create indexes"
+    }
+
+    async fn up(&self, db: &Database) -> Result<(), Error> {
+        let indexes = vec![
+            (
+                "expenses",
+                vec![
+                    index(doc! {"groupId": 1}),
+                    // scheduler looking for repeating expenses
+                    index(doc! {"repeats": 1, "deletedAt": 1}),
+                    // at most one occurrence of a repeating expense per date
+                    unique_index(
+                        doc! {"seriesId": 1, "date": 1},
+                        Some(doc! {"seriesId": {"$type": "string"}}),
+                    ),
+                ],
+            ),
+            (
+                "expense_history",
+                vec![unique_index(doc! {"expenseId": 1, "version": 1}, None)],
+            ),
+            ("comments", vec![index(doc! {"expenseId": 1})]),
+            (
+                "activity",
+                vec![
+                    index(doc! {"groupId": 1, "_id": -1}),
+                    index(doc! {"userIds": 1, "_id": -1}),
+                ],
+            ),
+            (
+                "activity_reads",
+                vec![unique_index(doc! {"userId": 1}, None)],
+            ),
+            ("groups", vec![index(doc! {"members.id": 1})]),
+            ("balance", vec![index(doc! {"user_id": 1})]),
+        ];
+        for (collection, models) in indexes {
+            let _created = db
+                .collection::<Document>(collection)
+                .create_indexes(models, None)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// `Debt::from` and `Debt::to` used to be numbers while user ids are strings.
+struct DebtUserIdsToStrings;
+
+/// Pipeline stage converting `from` and `to` of every debt in the array `field` to strings.
+fn debts_to_strings(field: &str) -> Document {
+    doc! {
+        "$set": {
+            field: {
+                "$map": {
+                    "input": format!("${}", field),
+                    "as": "debt",
+                    "in": {
+                        "$mergeObjects": [
+                            "$$debt",
+                            {
+                                "from": {"$toString": "$$debt.from"},
+                                "to": {"$toString": "$$debt.to"},
+                            },
+                        ]
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Migration for DebtUserIdsToStrings {
+    fn version(&self) -> i64 {
+        2
+    }
+
+    fn description(&self) -> &'static str {
+        "store user ids of debts as strings"
+    }
+
+    async fn up(&self, db: &Database) -> Result<(), Error> {
+        let numeric = doc! {"$type": ["int", "long", "double"]};
+        for field in ["originalDebts", "simplifiedDebts"] {
+            let _updated = db
+                .collection::<Document>("groups")
+                .update_many(
+                    doc! {"$or": [
+                        {format!("{}.from", field): &numeric},
+                        {format!("{}.to", field): &numeric},
+                    ]},
+                    vec![debts_to_strings(field)],
+                    None,
+                )
+                .await?;
+        }
+        let _updated = db
+            .collection::<Document>("expenses")
+            .update_many(
+                doc! {"$or": [
+                    {"repayments.from": &numeric},
+                    {"repayments.to": &numeric},
+                ]},
+                vec![debts_to_strings("repayments")],
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Removes `Idempotency-Key`s once they expire.
+struct ExpireIdempotencyKeys;
+
+#[async_trait]
+impl Migration for ExpireIdempotencyKeys {
+    fn version(&self) -> i64 {
+        3
+    }
+
+    fn description(&self) -> &'static str {
+        "expire idempotency keys"
+    }
+
+    async fn up(&self, db: &Database) -> Result<(), Error> {
+        let model = IndexModel::builder()
+            .keys(doc! {"expiresAt": 1})
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        let _created = db
+            .collection::<Document>("idempotency_keys")
+            .create_index(model, None)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Custom categories are listed by group, their names are unique among the categories with the
+/// same parent, ignoring case.
+struct IndexCategories;
+
+#[async_trait]
+impl Migration for IndexCategories {
+    fn version(&self) -> i64 {
+        4
+    }
+
+    fn description(&self) -> &'static str {
+        "index categories"
+    }
+
+    async fn up(&self, db: &Database) -> Result<(), Error> {
+        let ignore_case = Collation::builder()
+            .locale("en")
+            .strength(CollationStrength::Secondary)
+            .build();
+        let unique_name = IndexModel::builder()
+            .keys(doc! {"groupId": 1, "parentId": 1, "name": 1})
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .collation(ignore_case)
+                    .build(),
+            )
+            .build();
+        let _created = db
+            .collection::<Document>("categories")
+            .create_indexes(vec![index(doc! {"groupId": 1}), unique_name], None)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::migrations;
+
+    #[test]
+    fn migration_versions_are_unique() {
+        let mut versions = migrations()
+            .iter()
+            .map(|migration| migration.version())
+            .collect::<Vec<_>>();
+        versions.sort();
+        versions.dedup();
+        assert_eq!(versions.len(), migrations().len());
+    }
+}
@@ -1,4 +1,64 @@
+pub mod account;
+pub mod activity;
 pub mod balance;
+pub mod blob;
+pub mod category;
+pub mod comment;
+pub mod currency;
+pub mod events;
 pub mod expense;
+pub mod export;
 pub mod group;
+pub mod health;
+pub mod history;
+pub mod idempotency;
+pub mod import;
+pub mod instrumented;
+pub mod ledger;
+pub mod memory;
+pub mod migration;
+pub mod receipt;
+pub mod recurring;
+pub mod report;
+pub mod splitwise;
+#[cfg(feature = "sql")]
+pub mod sql;
+pub mod storage;
 pub mod user;
+pub mod version;
+
+/// MongoDB database used unless configured otherwise.
+pub const DEFAULT_DATABASE_NAME: &str = "swc";
+
+/// A stored entity that doesn't exist, returned by the storage APIs so callers can tell it apart
+/// from a failing store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotFound {
+    pub entity: &'static str,
+    pub id: String,
+}
+
+impl NotFound {
+    pub fn new(entity: &'static str, id: impl ToString) -> Self {
+        Self {
+            entity,
+            id: id.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} not found", self.entity, self.id)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// The object id stored entities are keyed by in MongoDB, an id that isn't one can't be stored.
+pub(crate) fn object_id(
+    entity: &'static str,
+    id: &str,
+) -> Result<mongodb::bson::oid::ObjectId, NotFound> {
+    id.parse().map_err(|_| NotFound::new(entity, id))
+}
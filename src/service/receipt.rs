@@ -0,0 +1,228 @@
+use crate::service::blob::Blob;
+use crate::service::expense::Expense;
+use crate::service::storage::Storage;
+use crate::service::version;
+use crate::service::version::Conditional;
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use image::imageops::FilterType;
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use utoipa::ToSchema;
+
+/// Longest side of a thumbnail in pixels.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Receipt attached to an expense, the content is kept in the [`crate::service::blob::BlobStore`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Receipt {
+    /// One of `image/jpeg`, `image/png` or `application/pdf`, detected from the content.
+    pub content_type: String,
+
+    /// Size in bytes.
+    pub size: u64,
+
+    /// Name of the uploaded file.
+    pub filename: Option<String>,
+
+    pub uploaded_at: DateTime<Utc>,
+
+    /// Whether a JPEG thumbnail is available, only for images.
+    pub thumbnail: bool,
+}
+
+/// File uploaded as receipt, the `file` part of the multipart form.
+#[derive(Debug, Clone, ToSchema)]
+pub struct ReceiptUpload {
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+/// Key of the receipt of the expense in the blob store.
+pub fn receipt_key(expense_id: &str) -> String {
+    format!("receipt-{}", expense_id)
+}
+
+/// Key of the thumbnail of the receipt of the expense in the blob store.
+pub fn thumbnail_key(expense_id: &str) -> String {
+    format!("receipt-{}.thumbnail", expense_id)
+}
+
+/// Content type of a supported receipt, detected from its first bytes rather than trusting what
+/// the client claims. `None` for any other content.
+pub fn detect_content_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else {
+        None
+    }
+}
+
+/// JPEG of the image scaled down to fit in [`THUMBNAIL_SIZE`], images which are smaller are not
+/// scaled up.
+pub fn thumbnail(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let image = image::load_from_memory(data)?;
+    let image = if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+        image.resize(THUMBNAIL_SIZE, THUMBNAIL_SIZE, FilterType::Triangle)
+    } else {
+        image
+    };
+    let mut thumbnail = Cursor::new(Vec::new());
+    image
+        .to_rgb8()
+        .write_to(&mut thumbnail, ImageFormat::Jpeg)?;
+    Ok(thumbnail.into_inner())
+}
+
+/// Uploaded receipt with its content and thumbnail, ready to be attached.
+#[derive(Debug, Clone)]
+pub struct ReceiptFile {
+    pub receipt: Receipt,
+
+    pub content: Blob,
+
+    /// JPEG thumbnail, for images only.
+    pub thumbnail: Option<Blob>,
+}
+
+impl ReceiptFile {
+    /// Fails if the content is not a supported receipt or the image can't be decoded.
+    pub fn new(filename: Option<String>, data: Vec<u8>) -> Result<Self, Error> {
+        let content_type = detect_content_type(&data)
+            .ok_or_else(|| Error::msg("Receipts must be JPEG, PNG or PDF files"))?;
+        let thumbnail = match content_type {
+            "application/pdf" => None,
+            _ => Some(Blob {
+                content_type: "image/jpeg".to_string(),
+                data: thumbnail(&data)?,
+            }),
+        };
+        Ok(Self {
+            receipt: Receipt {
+                content_type: content_type.to_string(),
+                size: data.len() as u64,
+                filename,
+                uploaded_at: Utc::now(),
+                thumbnail: thumbnail.is_some(),
+            },
+            content: Blob {
+                content_type: content_type.to_string(),
+                data,
+            },
+            thumbnail,
+        })
+    }
+}
+
+/// Stores the receipt of the expense, replacing the previous one, unless the expense was changed
+/// since `expected_version`.
+pub async fn attach(
+    storage: &Storage,
+    expense_id: String,
+    file: ReceiptFile,
+    expected_version: Option<i64>,
+) -> Result<Conditional<Expense>, Error> {
+    // fails before anything is stored for an expense which doesn't exist or is stale
+    let expense = storage.expenses.get_expense(expense_id.clone()).await?;
+    if !version::matches(expected_version, expense.version) {
+        return Ok(Conditional::Stale(expense));
+    }
+    storage
+        .blobs
+        .put_blob(receipt_key(&expense_id), file.content)
+        .await?;
+    match file.thumbnail {
+        Some(thumbnail) => {
+            storage
+                .blobs
+                .put_blob(thumbnail_key(&expense_id), thumbnail)
+                .await?
+        }
+        None => {
+            let _deleted = storage
+                .blobs
+                .delete_blob(thumbnail_key(&expense_id))
+                .await?;
+        }
+    }
+    // the version read above, so that a change made since is not overwritten
+    storage
+        .expenses
+        .set_receipt(
+            expense_id,
+            Some(file.receipt),
+            Some(expense.version.unwrap_or(version::UNVERSIONED)),
+        )
+        .await
+}
+
+/// Detaches the receipt from the expense and removes its content, unless the expense was changed
+/// since `expected_version`.
+pub async fn detach(
+    storage: &Storage,
+    expense_id: String,
+    expected_version: Option<i64>,
+) -> Result<Conditional<Expense>, Error> {
+    let result = storage
+        .expenses
+        .set_receipt(expense_id.clone(), None, expected_version)
+        .await?;
+    if let Conditional::Applied(_) = &result {
+        remove_blobs(storage, &expense_id).await?;
+    }
+    Ok(result)
+}
+
+/// Permanently removes the expense with its receipt. Returns `None` if the expense is not
+/// deleted yet, only deleted expenses are purged so that a deletion can be undone until then.
+pub async fn purge(storage: &Storage, expense_id: String) -> Result<Option<Expense>, Error> {
+    let expense = storage.expenses.get_expense(expense_id.clone()).await?;
+    if expense.deleted_at.is_none() {
+        return Ok(None);
+    }
+    let _purged = storage.expenses.purge_expense(expense_id.clone()).await?;
+    remove_blobs(storage, &expense_id).await?;
+    Ok(Some(expense))
+}
+
+async fn remove_blobs(storage: &Storage, expense_id: &str) -> Result<(), Error> {
+    let _receipt = storage.blobs.delete_blob(receipt_key(expense_id)).await?;
+    let _thumbnail = storage.blobs.delete_blob(thumbnail_key(expense_id)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detect_content_type, thumbnail};
+    use image::{ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut png = Cursor::new(Vec::new());
+        RgbImage::new(width, height)
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+        png.into_inner()
+    }
+
+    #[test]
+    fn detect_receipt_types() {
+        assert_eq!(detect_content_type(&png(1, 1)), Some("image/png"));
+        assert_eq!(detect_content_type(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(detect_content_type(b"<html>"), None);
+    }
+
+    #[test]
+    fn scale_thumbnails_down_only() {
+        let scaled = image::load_from_memory(&thumbnail(&png(1024, 512)).unwrap()).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (256, 128));
+        let small = image::load_from_memory(&thumbnail(&png(10, 20)).unwrap()).unwrap();
+        assert_eq!((small.width(), small.height()), (10, 20));
+    }
+}
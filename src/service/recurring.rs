@@ -0,0 +1,187 @@
+use crate::service::activity::{Activity, ActivityApi, ActivityApiMongoAdapter};
+use crate::service::events::EventBus;
+use crate::service::expense::{Expense, RepeatInterval};
+use crate::service::history::{ExpenseAction, ExpenseHistoryApi, ExpenseHistoryMongoAdapter};
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use mongodb::options::UpdateOptions;
+use mongodb::{bson, Client, Database};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio_stream::StreamExt;
+
+#[async_trait]
+pub trait RecurringExpensesApi {
+    /// Materialises every occurrence of a repeating expense which is due at `now`. Returns the
+    /// newly generated expenses, occurrences that already exist are not generated again.
+    async fn generate_due_expenses(&self, now: DateTime<Utc>) -> Result<Vec<Expense>, Error>;
+}
+
+#[derive(Debug, Clone)]
+pub struct RecurringExpensesMongoAdapter {
+    db: Database,
+
+    events: Option<EventBus>,
+}
+
+impl RecurringExpensesMongoAdapter {
+    pub fn new(db: Database) -> Self {
+        Self { db, events: None }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+
+    /// Publishes every generated expense to the given bus.
+    pub fn with_events(self, events: EventBus) -> Self {
+        Self {
+            events: Some(events),
+            ..self
+        }
+    }
+
+    /// Inserts the occurrence unless the series already has an expense on that date, so that
+    /// generation can safely be repeated after a restart, and records it in the history and group
+    /// activity. Returns the id of the inserted expense.
+    async fn insert_occurrence(&self, occurrence: &Expense) -> Result<Option<ObjectId>, Error> {
+        let filter = doc! {
+            "seriesId": bson::to_bson(&occurrence.series_id)?,
+            "date": bson::to_bson(&occurrence.date)?,
+        };
+        let occurrence_document = bson::to_document(occurrence)?;
+        let update = doc! {
+            "$setOnInsert": &occurrence_document
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        let update_result = self
+            .db
+            .collection::<Document>("expenses")
+            .update_one(filter, update, options)
+            .await?;
+        let id = update_result.upserted_id.and_then(|id| id.as_object_id());
+        if let Some(id) = id {
+            let _revision = ExpenseHistoryMongoAdapter::new(self.db.clone())
+                .record_revision(
+                    id.to_hex(),
+                    ExpenseAction::Created,
+                    None,
+                    &Document::new(),
+                    &occurrence_document,
+                )
+                .await?;
+            let _activity = ActivityApiMongoAdapter::new(self.db.clone())
+                .record(Activity::for_expense(
+                    id.to_hex(),
+                    ExpenseAction::Created,
+                    None,
+                    occurrence,
+                ))
+                .await?;
+            if let Some(events) = &self.events {
+                events.publish_expense(id.to_hex(), ExpenseAction::Created, occurrence);
+            }
+        }
+        Ok(id)
+    }
+}
+
+#[async_trait]
+impl RecurringExpensesApi for RecurringExpensesMongoAdapter {
+    async fn generate_due_expenses(&self, now: DateTime<Utc>) -> Result<Vec<Expense>, Error> {
+        let collection = self.db.collection::<Document>("expenses");
+        let filter = doc! {
+            "repeats": true,
+            "deletedAt": null,
+        };
+        let mut cursor = collection.find(filter, None).await?;
+        let mut series = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            let id = document.get_object_id("_id")?;
+            let template: Expense = bson::from_document(document)?;
+            series.push((id, template));
+        }
+
+        let mut generated = Vec::new();
+        for (id, template) in series {
+            let (interval, anchor) = match (template.repeat_interval, template.date) {
+                (Some(interval), Some(anchor)) => (interval, anchor),
+                _ => continue,
+            };
+            let mut next_repeat = template.next_repeat;
+            // catch up on every occurrence missed while the server was down
+            while let Some(date) = next_repeat.filter(|date| *date <= now) {
+                let occurrence = occurrence(&template, id.to_hex(), date, now);
+                if self.insert_occurrence(&occurrence).await?.is_some() {
+                    generated.push(occurrence);
+                }
+                next_repeat = interval.next_after(anchor, date);
+            }
+            if next_repeat != template.next_repeat {
+                let _update_result = collection
+                    .update_one(
+                        doc! {"_id": id},
+                        doc! {"$set": {"nextRepeat": bson::to_bson(&next_repeat)?}},
+                        None,
+                    )
+                    .await?;
+            }
+        }
+        Ok(generated)
+    }
+}
+
+/// Occurrence of the repeating expense `template` due on `date`.
+pub(crate) fn occurrence(
+    template: &Expense,
+    series_id: String,
+    date: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Expense {
+    Expense {
+        date: Some(date),
+        series_id: Some(series_id),
+        repeat_interval: Some(RepeatInterval::Never),
+        repeats: Some(false),
+        next_repeat: None,
+        comments_count: Some(0),
+        version: Some(1),
+        created_at: Some(now),
+        updated_at: Some(now),
+        updated_by: None,
+        ..template.clone()
+    }
+}
+
+/// Periodically generates the occurrences of repeating expenses which became due, until
+/// `shutdown` turns `true`. A generation in progress is finished first.
+pub async fn run_scheduler(
+    api: Arc<dyn RecurringExpensesApi + Send + Sync>,
+    period: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut interval = tokio::time::interval(period);
+    while !*shutdown.borrow() {
+        tokio::select! {
+            _instant = interval.tick() => {}
+            changed = shutdown.changed() => match changed {
+                Ok(()) => continue,
+                // the sender was dropped, so the server is gone
+                Err(_) => break,
+            },
+        }
+        match api.generate_due_expenses(Utc::now()).await {
+            Ok(generated) if !generated.is_empty() => {
+                tracing::info!("Generated {} recurring expenses", generated.len())
+            }
+            Ok(_) => {}
+            Err(error) => tracing::error!("Failed to generate recurring expenses: {:?}", error),
+        }
+    }
+    tracing::info!("Stopped recurring expenses scheduler");
+}
@@ -0,0 +1,793 @@
+use crate::service::currency::ExchangeRates;
+use crate::service::expense::Expense;
+use crate::service::export::{cents, currency, format_cents};
+use crate::service::storage::Storage;
+use crate::service::DEFAULT_DATABASE_NAME;
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::{Client, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tokio_stream::StreamExt;
+use utoipa::{IntoParams, ToSchema};
+
+/// Format of the dates compared in the pipelines, the prefix of the RFC 3339 strings dates are
+/// stored as.
+const DATE_PREFIX_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[async_trait]
+pub trait ReportsApi {
+    /// Spending of the group over the expenses dated within the request, payments and deleted
+    /// expenses left out.
+    async fn group_report(
+        &self,
+        group_id: String,
+        request: ReportRequest,
+    ) -> Result<GroupReport, Error>;
+
+    /// Owed shares of the user per month, group and currency over the expenses of all their
+    /// groups, left out like in the group reports. Oldest month first.
+    async fn user_spending(
+        &self,
+        user_id: String,
+        request: ReportRequest,
+    ) -> Result<Vec<UserSpending>, Error>;
+}
+
+/// Computes the reports with aggregation pipelines over the `expenses` collection, amounts are
+/// summed as decimals rather than floats.
+#[derive(Debug, Clone)]
+pub struct ReportApiMongoAdapter {
+    db: Database,
+}
+
+impl ReportApiMongoAdapter {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
+}
+
+#[async_trait]
+impl ReportsApi for ReportApiMongoAdapter {
+    async fn group_report(
+        &self,
+        group_id: String,
+        request: ReportRequest,
+    ) -> Result<GroupReport, Error> {
+        let pipeline = vec![
+            doc! {"$match": expense_filter(doc! {"groupId": &group_id}, &request)},
+            doc! {"$set": {
+                "month": {"$substrCP": ["$date", 0, 7]},
+                "currency": {"$ifNull": ["$currencyCode", ""]},
+                "cents": cents_of("$cost"),
+            }},
+            doc! {"$facet": {
+                "months": [
+                    {"$group": {
+                        "_id": {"month": "$month", "currency": "$currency"},
+                        "total": {"$sum": "$cents"},
+                        "count": {"$sum": 1},
+                    }},
+                    {"$sort": {"_id.month": 1, "_id.currency": 1}},
+                ],
+                "categories": [
+                    {"$group": {
+                        "_id": {"category": "$category", "currency": "$currency"},
+                        "total": {"$sum": "$cents"},
+                        "count": {"$sum": 1},
+                    }},
+                    {"$sort": {"_id.category": 1, "_id.currency": 1}},
+                ],
+                "members": [
+                    {"$unwind": "$users"},
+                    {"$match": {"users.user.id": {"$ne": null}}},
+                    {"$group": {
+                        "_id": {"userId": "$users.user.id", "currency": "$currency"},
+                        "paid": {"$sum": cents_of("$users.paidShare")},
+                        "consumed": {"$sum": cents_of("$users.owedShare")},
+                    }},
+                    {"$sort": {"_id.userId": 1, "_id.currency": 1}},
+                ],
+                "currencies": [
+                    {"$group": {
+                        "_id": "$currency",
+                        "total": {"$sum": "$cents"},
+                        "count": {"$sum": 1},
+                    }},
+                    {"$sort": {"_id": 1}},
+                ],
+            }},
+        ];
+        let mut cursor = self
+            .db
+            .collection::<Document>("expenses")
+            .aggregate(pipeline, None)
+            .await?;
+        let facets = cursor.try_next().await?.unwrap_or_default();
+        // missing facets are empty, as when the group has no expenses
+        let facet = |name: &str| {
+            facets
+                .get_array(name)
+                .map(|documents| {
+                    documents
+                        .iter()
+                        .filter_map(Bson::as_document)
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+
+        let mut totals = Totals::default();
+        for document in facet("months") {
+            let id = document.get_document("_id")?;
+            let key = (string(id, "month"), string(id, "currency"));
+            let _previous = totals.months.insert(key, sum(&document));
+        }
+        for document in facet("categories") {
+            let id = document.get_document("_id")?;
+            let key = (
+                id.get_str("category").ok().map(String::from),
+                string(id, "currency"),
+            );
+            let _previous = totals.categories.insert(key, sum(&document));
+        }
+        for document in facet("members") {
+            let id = document.get_document("_id")?;
+            let key = (string(id, "userId"), string(id, "currency"));
+            let shares = (long(&document, "paid"), long(&document, "consumed"));
+            let _previous = totals.members.insert(key, shares);
+        }
+        for document in facet("currencies") {
+            let _previous = totals
+                .currencies
+                .insert(string(&document, "_id"), sum(&document));
+        }
+        Ok(totals.report(group_id, request))
+    }
+
+    async fn user_spending(
+        &self,
+        user_id: String,
+        request: ReportRequest,
+    ) -> Result<Vec<UserSpending>, Error> {
+        let pipeline = vec![
+            doc! {"$match": expense_filter(doc! {"users.user.id": &user_id}, &request)},
+            doc! {"$unwind": "$users"},
+            doc! {"$match": {"users.user.id": &user_id}},
+            doc! {"$group": {
+                "_id": {
+                    "month": {"$substrCP": ["$date", 0, 7]},
+                    "groupId": {"$ifNull": ["$groupId", ""]},
+                    "currency": {"$ifNull": ["$currencyCode", ""]},
+                },
+                "total": {"$sum": cents_of("$users.owedShare")},
+                "count": {"$sum": 1},
+            }},
+            doc! {"$sort": {"_id.month": 1, "_id.groupId": 1, "_id.currency": 1}},
+        ];
+        let mut cursor = self
+            .db
+            .collection::<Document>("expenses")
+            .aggregate(pipeline, None)
+            .await?;
+        let mut spending = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            let id = document.get_document("_id")?;
+            let (owed, count) = sum(&document);
+            spending.push(UserSpending {
+                month: string(id, "month"),
+                group_id: string(id, "groupId"),
+                currency_code: string(id, "currency"),
+                owed,
+                count,
+            });
+        }
+        Ok(spending)
+    }
+}
+
+/// `filter` restricted to the expenses of the report: not deleted, not payments, dated within
+/// the request.
+pub(crate) fn expense_filter(mut filter: Document, request: &ReportRequest) -> Document {
+    let _previous = filter.insert("deletedAt", Bson::Null);
+    let _previous = filter.insert("payment", doc! {"$ne": true});
+    let date = doc! {"$substrCP": ["$date", 0, 19]};
+    let mut bounds = Vec::new();
+    if let Some(from) = request.from {
+        bounds.push(doc! {"$gte": [&date, from.format(DATE_PREFIX_FORMAT).to_string()]});
+    }
+    if let Some(to) = request.to {
+        bounds.push(doc! {"$lt": [&date, to.format(DATE_PREFIX_FORMAT).to_string()]});
+    }
+    if !bounds.is_empty() {
+        let _previous = filter.insert("$expr", doc! {"$and": bounds});
+    }
+    filter
+}
+
+/// Expression converting the decimal string at `path` to cents.
+pub(crate) fn cents_of(path: &str) -> Document {
+    doc! {"$toLong": {"$round": [
+        {"$multiply": [{"$toDecimal": {"$ifNull": [path, "0"]}}, 100]},
+        0,
+    ]}}
+}
+
+fn string(document: &Document, key: &str) -> String {
+    document.get_str(key).unwrap_or_default().to_string()
+}
+
+/// `$sum` yields an `Int32` or an `Int64` depending on the size of the result.
+fn long(document: &Document, key: &str) -> i64 {
+    match document.get(key) {
+        Some(Bson::Int32(value)) => i64::from(*value),
+        Some(Bson::Int64(value)) => *value,
+        _ => 0,
+    }
+}
+
+fn sum(document: &Document) -> (i64, u64) {
+    (
+        long(document, "total"),
+        long(document, "count").try_into().unwrap_or_default(),
+    )
+}
+
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportRequest {
+    /// Only expenses dated at or after this date, RFC 3339.
+    pub from: Option<DateTime<Utc>>,
+
+    /// Only expenses dated before this date, RFC 3339.
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl ReportRequest {
+    /// Whether the expense is reported: not deleted, not a payment and dated within the request.
+    pub(crate) fn matches(&self, expense: &Expense) -> bool {
+        let dated_within = match expense.date {
+            Some(date) => {
+                self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date < to)
+            }
+            None => self.from.is_none() && self.to.is_none(),
+        };
+        expense.deleted_at.is_none() && expense.payment != Some(true) && dated_within
+    }
+}
+
+/// Spending of a group, amounts are decimal strings and never added up across currencies.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupReport {
+    pub group_id: String,
+
+    pub from: Option<DateTime<Utc>>,
+
+    pub to: Option<DateTime<Utc>>,
+
+    /// Totals per month and currency, oldest first.
+    pub months: Vec<MonthTotal>,
+
+    /// Totals per category and currency, uncategorised expenses first.
+    pub categories: Vec<CategoryTotal>,
+
+    /// What every member paid and consumed per currency.
+    pub members: Vec<MemberTotal>,
+
+    /// Totals per currency.
+    pub currencies: Vec<CurrencyTotal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthTotal {
+    /// `YYYY-MM`.
+    pub month: String,
+
+    pub currency_code: String,
+
+    pub total: String,
+
+    /// Number of expenses.
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryTotal {
+    /// Id of the category, `None` for uncategorised expenses.
+    pub category: Option<String>,
+
+    pub currency_code: String,
+
+    pub total: String,
+
+    /// Number of expenses.
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberTotal {
+    pub user_id: String,
+
+    pub currency_code: String,
+
+    /// Sum of the paid shares of the member.
+    pub paid: String,
+
+    /// Sum of the owed shares of the member, what they consumed.
+    pub consumed: String,
+
+    /// `paid` minus `consumed`.
+    pub net: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CurrencyTotal {
+    pub currency_code: String,
+
+    pub total: String,
+
+    /// Number of expenses.
+    pub count: u64,
+}
+
+/// What a user consumed in a group during a month in one currency, in cents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserSpending {
+    /// `YYYY-MM`.
+    pub month: String,
+
+    pub group_id: String,
+
+    pub currency_code: String,
+
+    /// Sum of the owed shares of the user.
+    pub owed: i64,
+
+    /// Number of expenses.
+    pub count: u64,
+}
+
+impl UserSpending {
+    /// Owed shares of the user in the expenses the request matches, for backends without
+    /// aggregation pipelines.
+    pub(crate) fn of<'e>(
+        expenses: impl IntoIterator<Item = &'e Expense>,
+        user_id: &str,
+        request: &ReportRequest,
+    ) -> Result<Vec<Self>, Error> {
+        let mut totals = BTreeMap::<_, (i64, u64)>::new();
+        for expense in expenses {
+            if !request.matches(expense) {
+                continue;
+            }
+            let Some(share) = expense.share_of(user_id) else {
+                continue;
+            };
+            let month = expense
+                .date
+                .map(|date| date.format("%Y-%m").to_string())
+                .unwrap_or_default();
+            let key = (
+                month,
+                expense.group_id.clone().unwrap_or_default(),
+                currency(expense),
+            );
+            let (owed, count) = totals.entry(key).or_default();
+            *owed += cents(share.owed_share.as_deref())?;
+            *count += 1;
+        }
+        Ok(totals
+            .into_iter()
+            .map(
+                |((month, group_id, currency_code), (owed, count))| UserSpending {
+                    month,
+                    group_id,
+                    currency_code,
+                    owed,
+                    count,
+                },
+            )
+            .collect())
+    }
+}
+
+/// What a user consumed across their groups, their owed shares rather than what they paid.
+/// Amounts are converted to the default currency of the user when there is a rate for both
+/// currencies, and kept in their own currency otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSummary {
+    pub user_id: String,
+
+    /// Default currency of the user, `None` if they have no profile or no default currency.
+    pub currency_code: Option<String>,
+
+    pub from: Option<DateTime<Utc>>,
+
+    pub to: Option<DateTime<Utc>>,
+
+    /// Totals per month, group and currency, oldest first.
+    pub months: Vec<UserMonthTotal>,
+
+    /// Totals per currency, the default currency of the user unless some amounts could not be
+    /// converted.
+    pub currencies: Vec<CurrencyTotal>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserMonthTotal {
+    /// `YYYY-MM`.
+    pub month: String,
+
+    pub group_id: String,
+
+    pub currency_code: String,
+
+    pub total: String,
+
+    /// Number of expenses.
+    pub count: u64,
+
+    /// Whether some of the total was converted from another currency.
+    pub converted: bool,
+}
+
+impl UserSummary {
+    /// Summary of the spending, converted to `currency_code` with the `rates` where needed.
+    pub fn new(
+        user_id: String,
+        currency_code: Option<String>,
+        spending: Vec<UserSpending>,
+        rates: &ExchangeRates,
+        request: ReportRequest,
+    ) -> Self {
+        let mut months = BTreeMap::<_, (i64, u64, bool)>::new();
+        let mut currencies = BTreeMap::<_, (i64, u64)>::new();
+        for spending in spending {
+            let converted = currency_code.as_ref().and_then(|to| {
+                rates
+                    .convert(spending.owed, &spending.currency_code, to)
+                    .map(|owed| (to.clone(), owed))
+            });
+            let was_converted = converted
+                .as_ref()
+                .is_some_and(|(to, _)| *to != spending.currency_code);
+            let (currency, owed) = converted.unwrap_or((spending.currency_code, spending.owed));
+            let (total, count, any_converted) = months
+                .entry((spending.month, spending.group_id, currency.clone()))
+                .or_default();
+            *total += owed;
+            *count += spending.count;
+            *any_converted |= was_converted;
+            let (total, count) = currencies.entry(currency).or_default();
+            *total += owed;
+            *count += spending.count;
+        }
+        Self {
+            user_id,
+            currency_code,
+            from: request.from,
+            to: request.to,
+            months: months
+                .into_iter()
+                .map(
+                    |((month, group_id, currency_code), (total, count, converted))| {
+                        UserMonthTotal {
+                            month,
+                            group_id,
+                            currency_code,
+                            total: format_cents(total),
+                            count,
+                            converted,
+                        }
+                    },
+                )
+                .collect(),
+            currencies: currencies
+                .into_iter()
+                .map(|(currency_code, (total, count))| CurrencyTotal {
+                    currency_code,
+                    total: format_cents(total),
+                    count,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Spending of the user in their default currency, if they have a profile with one.
+pub async fn user_summary(
+    storage: &Storage,
+    rates: &ExchangeRates,
+    user_id: String,
+    request: ReportRequest,
+) -> Result<UserSummary, Error> {
+    let currency_code = storage
+        .users
+        .find_user(user_id.clone())
+        .await?
+        .and_then(|user| user.default_currency)
+        .filter(|currency| !currency.is_empty());
+    let spending = storage
+        .reports
+        .user_spending(user_id.clone(), request)
+        .await?;
+    Ok(UserSummary::new(
+        user_id,
+        currency_code,
+        spending,
+        rates,
+        request,
+    ))
+}
+
+/// Totals in cents and counts of expenses, keyed and ordered like the facets of the pipeline.
+#[derive(Debug, Default)]
+pub(crate) struct Totals {
+    months: BTreeMap<(String, String), (i64, u64)>,
+    categories: BTreeMap<(Option<String>, String), (i64, u64)>,
+    /// Paid and consumed cents.
+    members: BTreeMap<(String, String), (i64, i64)>,
+    currencies: BTreeMap<String, (i64, u64)>,
+}
+
+impl Totals {
+    /// Totals of the expenses the request matches, for backends without aggregation pipelines.
+    pub(crate) fn of<'e>(
+        expenses: impl IntoIterator<Item = &'e Expense>,
+        request: &ReportRequest,
+    ) -> Result<Self, Error> {
+        let mut totals = Self::default();
+        let add = |(total, count): &mut (i64, u64), cost: i64| {
+            *total += cost;
+            *count += 1;
+        };
+        for expense in expenses {
+            if !request.matches(expense) {
+                continue;
+            }
+            let currency = currency(expense);
+            let cost = cents(expense.cost.as_deref())?;
+            let month = expense
+                .date
+                .map(|date| date.format("%Y-%m").to_string())
+                .unwrap_or_default();
+            add(
+                totals.months.entry((month, currency.clone())).or_default(),
+                cost,
+            );
+            add(
+                totals
+                    .categories
+                    .entry((expense.category.clone(), currency.clone()))
+                    .or_default(),
+                cost,
+            );
+            add(totals.currencies.entry(currency.clone()).or_default(), cost);
+            for share in expense.users.iter().flatten() {
+                let Some(user_id) = share.user.as_ref().and_then(|user| user.id.clone()) else {
+                    continue;
+                };
+                let (paid, consumed) = totals
+                    .members
+                    .entry((user_id, currency.clone()))
+                    .or_default();
+                *paid += cents(share.paid_share.as_deref())?;
+                *consumed += cents(share.owed_share.as_deref())?;
+            }
+        }
+        Ok(totals)
+    }
+
+    pub(crate) fn report(self, group_id: String, request: ReportRequest) -> GroupReport {
+        GroupReport {
+            group_id,
+            from: request.from,
+            to: request.to,
+            months: self
+                .months
+                .into_iter()
+                .map(|((month, currency_code), (total, count))| MonthTotal {
+                    month,
+                    currency_code,
+                    total: format_cents(total),
+                    count,
+                })
+                .collect(),
+            categories: self
+                .categories
+                .into_iter()
+                .map(
+                    |((category, currency_code), (total, count))| CategoryTotal {
+                        category,
+                        currency_code,
+                        total: format_cents(total),
+                        count,
+                    },
+                )
+                .collect(),
+            members: self
+                .members
+                .into_iter()
+                .map(|((user_id, currency_code), (paid, consumed))| MemberTotal {
+                    user_id,
+                    currency_code,
+                    paid: format_cents(paid),
+                    consumed: format_cents(consumed),
+                    net: format_cents(paid - consumed),
+                })
+                .collect(),
+            currencies: self
+                .currencies
+                .into_iter()
+                .map(|(currency_code, (total, count))| CurrencyTotal {
+                    currency_code,
+                    total: format_cents(total),
+                    count,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ReportRequest, Totals, UserSpending, UserSummary};
+    use crate::service::currency::ExchangeRates;
+    use crate::service::expense::{Expense, ShareCalculator};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn total_expenses_by_month_category_member_and_currency() {
+        let expense =
+            |day: u32, month: u32, cost: &str, currency: &str, category: Option<&str>| Expense {
+                cost: Some(cost.to_string()),
+                currency_code: Some(currency.to_string()),
+                category: category.map(String::from),
+                date: Some(Utc.with_ymd_and_hms(2024, month, day, 12, 0, 0).unwrap()),
+                users: Some(ShareCalculator::new().equal_share(
+                    cost.to_string(),
+                    "1".to_string(),
+                    vec!["1".to_string(), "2".to_string()],
+                )),
+                ..Expense::default()
+            };
+        let expenses = [
+            expense(5, 1, "30.00", "EUR", Some("groceries")),
+            expense(20, 1, "12.50", "EUR", None),
+            expense(2, 2, "40.00", "EUR", Some("groceries")),
+            expense(3, 2, "10.00", "USD", Some("groceries")),
+            Expense {
+                payment: Some(true),
+                ..expense(4, 2, "100.00", "EUR", None)
+            },
+            Expense {
+                deleted_at: Some(Utc::now()),
+                ..expense(4, 2, "100.00", "EUR", None)
+            },
+            expense(1, 3, "99.00", "EUR", None),
+        ];
+        let request = ReportRequest {
+            from: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+            to: Some(Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()),
+        };
+        let report = Totals::of(&expenses, &request)
+            .unwrap()
+            .report("1".to_string(), request);
+
+        let months = report
+            .months
+            .iter()
+            .map(|total| {
+                (
+                    total.month.as_str(),
+                    total.currency_code.as_str(),
+                    total.total.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            months,
+            [
+                ("2024-01", "EUR", "42.50"),
+                ("2024-02", "EUR", "40.00"),
+                ("2024-02", "USD", "10.00"),
+            ]
+        );
+        let categories = report
+            .categories
+            .iter()
+            .map(|total| {
+                (
+                    total.category.as_deref(),
+                    total.currency_code.as_str(),
+                    total.count,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            categories,
+            [
+                (None, "EUR", 1),
+                (Some("groceries"), "EUR", 2),
+                (Some("groceries"), "USD", 1),
+            ]
+        );
+        let bob = &report.members[2];
+        assert_eq!(
+            (
+                bob.user_id.as_str(),
+                bob.paid.as_str(),
+                bob.consumed.as_str(),
+                bob.net.as_str()
+            ),
+            ("2", "0.00", "41.25", "-41.25")
+        );
+        assert_eq!(report.currencies[0].total, "82.50");
+    }
+
+    #[test]
+    fn convert_user_spending_to_default_currency() {
+        let spending = |month: &str, group_id: &str, currency: &str, owed: i64| UserSpending {
+            month: month.to_string(),
+            group_id: group_id.to_string(),
+            currency_code: currency.to_string(),
+            owed,
+            count: 1,
+        };
+        let summary = UserSummary::new(
+            "2".to_string(),
+            Some("EUR".to_string()),
+            vec![
+                spending("2024-01", "1", "EUR", 1500),
+                spending("2024-01", "1", "USD", 1000),
+                spending("2024-01", "2", "JPY", 5000),
+                spending("2024-02", "1", "EUR", 250),
+            ],
+            &ExchangeRates::parse("EUR=1,USD=0.9").unwrap(),
+            ReportRequest::default(),
+        );
+        let months = summary
+            .months
+            .iter()
+            .map(|total| {
+                (
+                    total.month.as_str(),
+                    total.group_id.as_str(),
+                    total.currency_code.as_str(),
+                    total.total.as_str(),
+                    total.count,
+                    total.converted,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            months,
+            [
+                ("2024-01", "1", "EUR", "24.00", 2, true),
+                ("2024-01", "2", "JPY", "50.00", 1, false),
+                ("2024-02", "1", "EUR", "2.50", 1, false),
+            ]
+        );
+        let currencies = summary
+            .currencies
+            .iter()
+            .map(|total| (total.currency_code.as_str(), total.total.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(currencies, [("EUR", "26.50"), ("JPY", "50.00")]);
+    }
+}
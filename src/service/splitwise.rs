@@ -0,0 +1,687 @@
+use crate::service::comment::CreateCommentSpec;
+use crate::service::expense::{Expense, ListExpensesRequest, RepeatInterval, User, UserShare};
+use crate::service::export::{cents, format_cents};
+use crate::service::group::{CreateGroupSpec, Group, GroupUser};
+use crate::service::import::balances;
+use crate::service::storage::Storage;
+use crate::service::user::CreateUserSpec;
+use anyhow::{bail, Error};
+use chrono::{DateTime, NaiveDate, Utc};
+use csv::{ReaderBuilder, Trim};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use utoipa::ToSchema;
+
+/// A group exported from Splitwise, either as returned by its API or as the CSV of the group.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum SplitwiseExport {
+    /// Responses of `get_group`, `get_expenses` and `get_comments` of the group.
+    Json(SplitwiseDump),
+    /// CSV exported from the group page. It has no emails nor payers, see [`from_csv`].
+    Csv {
+        /// Name of the group, which is not part of the CSV.
+        name: String,
+
+        csv: String,
+
+        /// Email of the member of every column of the CSV, by column name. Members without one
+        /// are imported as new users.
+        #[serde(default)]
+        emails: HashMap<String, String>,
+    },
+}
+
+/// Group, expenses and comments in the shape of the Splitwise API.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SplitwiseDump {
+    pub group: SplitwiseGroup,
+
+    #[serde(default)]
+    pub expenses: Vec<SplitwiseExpense>,
+
+    /// Comments of the expenses, related to them by `relation_id`.
+    #[serde(default)]
+    pub comments: Vec<SplitwiseComment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SplitwiseGroup {
+    pub name: String,
+
+    #[serde(default)]
+    pub members: Vec<SplitwiseUser>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SplitwiseUser {
+    #[serde(deserialize_with = "splitwise_id")]
+    #[schema(value_type = String)]
+    pub id: String,
+
+    pub first_name: Option<String>,
+
+    pub last_name: Option<String>,
+
+    pub email: Option<String>,
+
+    /// Balance of the member in the group per currency, compared to the imported one.
+    pub balance: Option<Vec<SplitwiseBalance>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SplitwiseBalance {
+    pub currency_code: String,
+
+    pub amount: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SplitwiseExpense {
+    #[serde(deserialize_with = "splitwise_id")]
+    #[schema(value_type = String)]
+    pub id: String,
+
+    pub description: Option<String>,
+
+    #[serde(default)]
+    pub payment: bool,
+
+    pub cost: String,
+
+    pub currency_code: Option<String>,
+
+    pub date: DateTime<Utc>,
+
+    pub created_at: Option<DateTime<Utc>>,
+
+    pub created_by: Option<SplitwiseUser>,
+
+    /// Deleted expenses are not imported.
+    pub deleted_at: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub users: Vec<SplitwiseShare>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SplitwiseShare {
+    #[serde(deserialize_with = "splitwise_id")]
+    #[schema(value_type = String)]
+    pub user_id: String,
+
+    pub paid_share: String,
+
+    pub owed_share: String,
+
+    pub net_balance: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SplitwiseComment {
+    pub content: String,
+
+    /// `User` or `System`.
+    pub comment_type: Option<String>,
+
+    /// Id of the expense the comment is on.
+    #[serde(deserialize_with = "splitwise_id")]
+    #[schema(value_type = String)]
+    pub relation_id: String,
+
+    pub user: Option<SplitwiseUser>,
+}
+
+/// Splitwise ids are numbers, accepted as strings as well.
+fn splitwise_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(id) => Ok(id),
+        Value::Number(id) => Ok(id.to_string()),
+        other => Err(serde::de::Error::custom(format!(
+            "expected an id, got {}",
+            other
+        ))),
+    }
+}
+
+/// What was created by an import and how the resulting balances compare to Splitwise's.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitwiseImportReport {
+    pub group: Group,
+
+    pub users: Vec<ImportedUser>,
+
+    pub expenses: usize,
+
+    pub payments: usize,
+
+    pub comments: usize,
+
+    /// Deleted expenses and the comments on them, which are not imported.
+    pub skipped: usize,
+
+    /// Balances which differ from the ones in the export, empty if the import is exact.
+    pub mismatches: Vec<BalanceMismatch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedUser {
+    pub splitwise_id: String,
+
+    /// Id of the local user the member was mapped to.
+    pub user_id: String,
+
+    pub name: String,
+
+    pub email: Option<String>,
+
+    /// Whether no local user had the email, so that one was created.
+    pub created: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceMismatch {
+    pub user_id: String,
+
+    pub name: String,
+
+    pub currency_code: String,
+
+    /// Balance in the export.
+    pub expected: String,
+
+    /// Balance once imported.
+    pub actual: String,
+}
+
+impl SplitwiseExport {
+    /// The export as a dump of the API, checked to be importable.
+    pub fn into_dump(self) -> Result<SplitwiseDump, Error> {
+        let dump = match self {
+            SplitwiseExport::Json(dump) => dump,
+            SplitwiseExport::Csv { name, csv, emails } => from_csv(name, &csv, &emails)?,
+        };
+        dump.validate()?;
+        Ok(dump)
+    }
+}
+
+/// Reads the CSV of a Splitwise group: `Date,Description,Category,Cost,Currency` followed by a
+/// column per member with their net balance for the expense, and a `Total balance` row per
+/// currency. The CSV doesn't tell who paid, the member with the only positive net balance is
+/// taken to have paid all of the cost. Otherwise members are taken to have paid their positive
+/// net balance and to owe their negative one, which still yields the same balances.
+pub fn from_csv(
+    name: String,
+    csv: &str,
+    emails: &HashMap<String, String>,
+) -> Result<SplitwiseDump, Error> {
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(csv.as_bytes());
+    let header = reader.headers()?.clone();
+    if header.len() < 6 || !header[0].eq_ignore_ascii_case("Date") {
+        bail!("Expected a header Date,Description,Category,Cost,Currency followed by members");
+    }
+    let mut members = header
+        .iter()
+        .skip(5)
+        .enumerate()
+        .map(|(position, name)| SplitwiseUser {
+            id: format!("csv-{}", position + 1),
+            first_name: Some(name.to_string()),
+            last_name: None,
+            email: emails.get(name).cloned(),
+            balance: Some(Vec::new()),
+        })
+        .collect::<Vec<_>>();
+    let mut expenses = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        let line = record.position().map_or(0, |position| position.line());
+        let field = |index: usize| record.get(index).unwrap_or_default();
+        let currency_code = field(4).to_string();
+        let nets = members
+            .iter()
+            .enumerate()
+            .map(|(position, member)| (member.id.clone(), record.get(position + 5)))
+            .map(|(id, net)| Ok((id, cents(net)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        if field(1).eq_ignore_ascii_case("Total balance") {
+            for (member, (_, net)) in members.iter_mut().zip(&nets) {
+                member
+                    .balance
+                    .get_or_insert_with(Vec::new)
+                    .push(SplitwiseBalance {
+                        currency_code: currency_code.clone(),
+                        amount: format_cents(*net),
+                    });
+            }
+            continue;
+        }
+        let date = NaiveDate::parse_from_str(field(0), "%Y-%m-%d")
+            .map_err(|_| Error::msg(format!("Invalid date {:?} on line {}", field(0), line)))?
+            .and_time(Default::default())
+            .and_utc();
+        let cost = cents(Some(field(3)))?;
+        let payers = nets.iter().filter(|(_, net)| *net > 0).collect::<Vec<_>>();
+        let users = nets
+            .iter()
+            .filter(|(_, net)| *net != 0)
+            .map(|(user_id, net)| {
+                let paid = match payers.as_slice() {
+                    [(payer, _)] if payer == user_id => cost,
+                    [_] => 0,
+                    _ => (*net).max(0),
+                };
+                SplitwiseShare {
+                    user_id: user_id.clone(),
+                    paid_share: format_cents(paid),
+                    owed_share: format_cents(paid - net),
+                    net_balance: format_cents(*net),
+                }
+            })
+            .collect();
+        expenses.push(SplitwiseExpense {
+            id: format!("line-{}", line),
+            description: Some(field(1).to_string()).filter(|description| !description.is_empty()),
+            payment: field(2).eq_ignore_ascii_case("Payment"),
+            cost: format_cents(cost),
+            currency_code: Some(currency_code).filter(|currency| !currency.is_empty()),
+            date,
+            created_at: None,
+            created_by: None,
+            deleted_at: None,
+            users,
+        });
+    }
+    Ok(SplitwiseDump {
+        group: SplitwiseGroup { name, members },
+        expenses,
+        comments: Vec::new(),
+    })
+}
+
+impl SplitwiseDump {
+    /// Every share must be of a member of the group.
+    fn validate(&self) -> Result<(), Error> {
+        let members = self
+            .group
+            .members
+            .iter()
+            .map(|member| member.id.as_str())
+            .collect::<BTreeSet<_>>();
+        for expense in &self.expenses {
+            if let Some(share) = expense
+                .users
+                .iter()
+                .find(|share| !members.contains(share.user_id.as_str()))
+            {
+                bail!(
+                    "Expense {} has a share of user {} who is not a member of the group",
+                    expense.id,
+                    share.user_id
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SplitwiseExpense {
+    /// Id of the first user who paid a share of the expense.
+    fn payer_id(&self) -> Result<Option<String>, Error> {
+        for share in &self.users {
+            if cents(Some(&share.paid_share))? > 0 {
+                return Ok(Some(share.user_id.clone()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl SplitwiseUser {
+    fn name(&self) -> String {
+        let names = [&self.first_name, &self.last_name]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            self.id.clone()
+        } else {
+            names.join(" ")
+        }
+    }
+}
+
+/// Recreates the group of the dump with its members mapped to local users by email, creating
+/// the users who have none. Expenses and payments are stored with their shares as they are in
+/// Splitwise, then the comments on them. On failure, what was stored so far is removed again.
+pub async fn import(
+    storage: &Storage,
+    dump: SplitwiseDump,
+) -> Result<SplitwiseImportReport, Error> {
+    let mut created = Created::default();
+    let imported = import_into(storage, dump, &mut created).await;
+    if imported.is_err() {
+        if let Err(error) = created.remove(storage).await {
+            tracing::error!("Failed to remove a partial Splitwise import: {:?}", error);
+        }
+    }
+    imported
+}
+
+/// Users, group and expenses stored by an import.
+#[derive(Default)]
+struct Created {
+    user_ids: Vec<String>,
+    group_id: Option<String>,
+    expense_ids: Vec<String>,
+}
+
+impl Created {
+    /// Removes the expenses with their comments, then the group and the users.
+    async fn remove(self, storage: &Storage) -> Result<(), Error> {
+        for expense_id in self.expense_ids {
+            let _purged = storage.expenses.purge_expense(expense_id).await?;
+        }
+        if let Some(group_id) = self.group_id {
+            let _deleted = storage.groups.delete_group(group_id).await?;
+        }
+        for user_id in self.user_ids {
+            let _deleted = storage.users.delete_user(user_id).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn import_into(
+    storage: &Storage,
+    dump: SplitwiseDump,
+    created: &mut Created,
+) -> Result<SplitwiseImportReport, Error> {
+    let mut users = Vec::with_capacity(dump.group.members.len());
+    for member in &dump.group.members {
+        let name = member.name();
+        let existing = match &member.email {
+            Some(email) => storage.users.find_user_by_email(email.clone()).await?,
+            None => None,
+        };
+        let (user_id, created) = match existing.and_then(|user| user.id) {
+            Some(user_id) => (user_id, false),
+            None => {
+                let user_id = storage
+                    .users
+                    .create_user(CreateUserSpec {
+                        first_name: name.clone(),
+                        email: member.email.clone(),
+                        ..CreateUserSpec::default()
+                    })
+                    .await?;
+                created.user_ids.push(user_id.clone());
+                (user_id, true)
+            }
+        };
+        users.push(ImportedUser {
+            splitwise_id: member.id.clone(),
+            user_id,
+            name,
+            email: member.email.clone(),
+            created,
+        });
+    }
+    let local = |splitwise_id: &str| {
+        users
+            .iter()
+            .find(|user| user.splitwise_id == splitwise_id)
+            .map(|user| User {
+                id: Some(user.user_id.clone()),
+                first_name: Some(user.name.clone()),
+                email: user.email.clone(),
+                ..User::default()
+            })
+    };
+
+    let group = storage
+        .groups
+        .create_group(CreateGroupSpec {
+            name: dump.group.name.clone(),
+            users: Some(
+                users
+                    .iter()
+                    .map(|user| GroupUser {
+                        user_id: user.user_id.clone(),
+                        first_name: Some(user.name.clone()),
+                    })
+                    .collect(),
+            ),
+        })
+        .await?;
+    let group_id = group.id.clone().unwrap_or_default();
+    created.group_id = Some(group_id.clone());
+
+    let (imported, deleted): (Vec<_>, Vec<_>) = dump
+        .expenses
+        .iter()
+        .partition(|expense| expense.deleted_at.is_none());
+    let expenses = imported
+        .iter()
+        .map(|expense| {
+            let created_by = match &expense.created_by {
+                Some(user) => Some(user.id.clone()),
+                None => expense.payer_id()?,
+            }
+            .and_then(|id| local(&id));
+            let created_at = expense.created_at.unwrap_or(expense.date);
+            Ok(Expense {
+                cost: Some(expense.cost.clone()),
+                description: expense.description.clone(),
+                date: Some(expense.date),
+                repeat_interval: Some(RepeatInterval::Never),
+                currency_code: expense.currency_code.clone(),
+                group_id: Some(group_id.clone()),
+                repeats: Some(false),
+                payment: Some(expense.payment),
+                created_at: Some(created_at),
+                created_by,
+                updated_at: Some(created_at),
+                users: Some(
+                    expense
+                        .users
+                        .iter()
+                        .map(|share| UserShare {
+                            user: local(&share.user_id),
+                            paid_share: Some(share.paid_share.clone()),
+                            owed_share: Some(share.owed_share.clone()),
+                            net_balance: Some(share.net_balance.clone()),
+                        })
+                        .collect(),
+                ),
+                comments_count: Some(0),
+                version: Some(1),
+                ..Expense::default()
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let stored = storage.expenses.import_expenses(expenses.clone()).await?;
+    created.expense_ids = stored
+        .iter()
+        .filter_map(|entity| Some(entity.id?.to_hex()))
+        .collect();
+    let expense_ids = imported
+        .iter()
+        .zip(&stored)
+        .filter_map(|(expense, entity)| Some((expense.id.as_str(), entity.id?.to_hex())))
+        .collect::<HashMap<_, _>>();
+
+    let mut comments = 0;
+    for comment in &dump.comments {
+        let Some(expense_id) = expense_ids.get(comment.relation_id.as_str()) else {
+            continue;
+        };
+        let _comment = if comment.comment_type.as_deref() == Some("System") {
+            storage
+                .comments
+                .create_system_comment(expense_id.clone(), comment.content.clone())
+                .await?
+        } else {
+            let user = comment
+                .user
+                .as_ref()
+                .and_then(|user| local(&user.id))
+                .unwrap_or_default();
+            storage
+                .comments
+                .create_comment(
+                    expense_id.clone(),
+                    CreateCommentSpec {
+                        content: comment.content.clone(),
+                        user,
+                    },
+                )
+                .await?
+        };
+        comments += 1;
+    }
+
+    let payments = expenses
+        .iter()
+        .filter(|expense| expense.payment == Some(true))
+        .count();
+    // balances of what was stored, not of what was sent
+    let stored = storage
+        .expenses
+        .list_expenses(ListExpensesRequest {
+            group_id: Some(group_id),
+            ..ListExpensesRequest::default()
+        })
+        .await?
+        .expenses;
+    let mismatches = mismatches(&dump.group, &users, &group, &stored)?;
+    Ok(SplitwiseImportReport {
+        group,
+        expenses: expenses.len() - payments,
+        payments,
+        comments,
+        skipped: deleted.len() + dump.comments.len() - comments,
+        users,
+        mismatches,
+    })
+}
+
+/// Balances of the imported `expenses` which differ from the ones of the members in Splitwise.
+/// Members without balances in the export are not compared.
+fn mismatches(
+    source: &SplitwiseGroup,
+    users: &[ImportedUser],
+    group: &Group,
+    expenses: &[Expense],
+) -> Result<Vec<BalanceMismatch>, Error> {
+    let mut actual = BTreeMap::<(&str, String), i64>::new();
+    for balance in balances(group, expenses)? {
+        if let Some(user) = users.iter().find(|user| user.user_id == balance.user_id) {
+            let _previous = actual.insert(
+                (user.splitwise_id.as_str(), balance.currency_code),
+                cents(Some(&balance.amount))?,
+            );
+        }
+    }
+    let mut mismatches = Vec::new();
+    for (member, user) in source.members.iter().zip(users) {
+        let Some(expected) = &member.balance else {
+            continue;
+        };
+        let mut currencies = expected
+            .iter()
+            .map(|balance| balance.currency_code.clone())
+            .collect::<BTreeSet<_>>();
+        currencies.extend(
+            actual
+                .keys()
+                .filter(|(id, _)| *id == member.id)
+                .map(|(_, currency)| currency.clone()),
+        );
+        for currency_code in currencies {
+            let expected = expected
+                .iter()
+                .filter(|balance| balance.currency_code == currency_code)
+                .map(|balance| cents(Some(&balance.amount)))
+                .sum::<Result<i64, Error>>()?;
+            let actual = actual
+                .get(&(member.id.as_str(), currency_code.clone()))
+                .copied()
+                .unwrap_or(0);
+            if expected != actual {
+                mismatches.push(BalanceMismatch {
+                    user_id: user.user_id.clone(),
+                    name: user.name.clone(),
+                    currency_code,
+                    expected: format_cents(expected),
+                    actual: format_cents(actual),
+                });
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_csv, SplitwiseExport};
+    use std::collections::HashMap;
+
+    const CSV: &str = "Date,Description,Category,Cost,Currency,Alice Smith,Bob Jones\n\
+                       \n\
+                       2024-01-05,Groceries,Groceries,30.00,EUR,15.00,-15.00\n\
+                       2024-01-06,Bob paid Alice,Payment,15.00,EUR,-15.00,15.00\n\
+                       \n\
+                       2024-02-10,Total balance, , ,EUR,0.00,0.00\n";
+
+    #[test]
+    fn read_group_csv() {
+        let emails = HashMap::from([("Alice Smith".to_string(), "alice@example.com".to_string())]);
+        let dump = from_csv("Flat".to_string(), CSV, &emails).unwrap();
+        let members = &dump.group.members;
+        assert_eq!(members[0].email.as_deref(), Some("alice@example.com"));
+        assert_eq!(members[1].email, None);
+        assert_eq!(members[1].balance.as_ref().unwrap()[0].amount, "0.00");
+
+        assert_eq!(dump.expenses.len(), 2);
+        let groceries = &dump.expenses[0].users;
+        assert_eq!(groceries[0].paid_share, "30.00");
+        assert_eq!(groceries[0].owed_share, "15.00");
+        assert_eq!(groceries[1].paid_share, "0.00");
+        assert_eq!(groceries[1].owed_share, "15.00");
+        assert!(dump.expenses[1].payment);
+        assert_eq!(dump.expenses[1].users[1].paid_share, "15.00");
+    }
+
+    #[test]
+    fn accept_numeric_ids_and_reject_unknown_members() {
+        let export: SplitwiseExport = serde_json::from_str(
+            r#"{
+              "format": "json",
+              "group": {"name": "Trip", "members": [{"id": 1, "first_name": "Alice"}]},
+              "expenses": [{
+                "id": 10, "cost": "5.00", "date": "2024-01-01T00:00:00Z",
+                "users": [{"user_id": 2, "paid_share": "5.00", "owed_share": "0.00", "net_balance": "5.00"}]
+              }]
+            }"#,
+        )
+        .unwrap();
+        let error = export.into_dump().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Expense 10 has a share of user 2 who is not a member of the group"
+        );
+    }
+}
@@ -0,0 +1,1615 @@
+use crate::service::account::{
+    anonymise_activity, anonymise_comment, anonymise_expense, anonymise_member, anonymise_revision,
+};
+use crate::service::activity::{Activity, ActivityApi, ActivityPage, ActivityRequest};
+use crate::service::balance::{Balance, BalanceApi};
+use crate::service::blob::{Blob, BlobStore};
+use crate::service::category::{CategoriesApi, Category, CategoryTaken, CreateCategorySpec};
+use crate::service::comment::{Comment, CommentType, CommentsApi, CreateCommentSpec};
+use crate::service::events::EventBus;
+use crate::service::expense::{
+    deletion_document, describe_changes, merge_update, restoration_document, CreateExpenseSpec,
+    Expense, ExpenseEntity, Expenses, ExpensesApi, ExpensesCalculator, ExpensesResponse,
+    ListExpensesRequest, UpdateExpenseSpec, User,
+};
+use crate::service::group::{CreateGroupSpec, Group, GroupApi, GroupUser};
+use crate::service::health::HealthApi;
+use crate::service::history::{diff, ExpenseAction, ExpenseHistoryApi, ExpenseRevision};
+use crate::service::idempotency::{IdempotencyApi, IdempotencyRecord, StoredResponse};
+use crate::service::receipt::Receipt;
+use crate::service::recurring::{occurrence, RecurringExpensesApi};
+use crate::service::report::{GroupReport, ReportRequest, ReportsApi, Totals, UserSpending};
+use crate::service::user;
+use crate::service::user::{CreateUserSpec, UserApi};
+use crate::service::version;
+use crate::service::version::Conditional;
+use crate::service::NotFound;
+use anyhow::{anyhow, Error};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use mongodb::bson;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{Bson, Document};
+use sqlx::any::{AnyArguments, AnyPoolOptions};
+use sqlx::migrate::Migrator;
+use sqlx::query::Query;
+use sqlx::{Any, AnyConnection, AnyPool, Executor};
+
+/// Migrations creating the schema, embedded from the `migrations` directory.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// The users of inserted expenses are indexed in the same transaction, see [`insert_expense_users`].
+const INSERT_EXPENSE: &str = "INSERT INTO expenses \
+     (id, group_id, series_id, date, repeats, deleted, data, version, users_indexed) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 1) ON CONFLICT DO NOTHING";
+
+const UPDATE_EXPENSE: &str = "UPDATE expenses \
+     SET group_id = $2, series_id = $3, date = $4, repeats = $5, deleted = $6, data = $7, \
+     version = $8 WHERE id = $1";
+
+/// `UPDATE_EXPENSE` of an expense which is still at version `$9`.
+const UPDATE_EXPENSE_IF_VERSION: &str = "UPDATE expenses \
+     SET group_id = $2, series_id = $3, date = $4, repeats = $5, deleted = $6, data = $7, \
+     version = $8 WHERE id = $1 AND version = $9";
+
+/// Write which changes nothing, run first in a transaction to lock the expense until it ends so
+/// that concurrent changes wait rather than conflict.
+const LOCK_EXPENSE: &str = "UPDATE expenses SET version = version WHERE id = $1";
+
+/// `LOCK_EXPENSE` for groups.
+const LOCK_GROUP: &str = "UPDATE groups SET version = version WHERE id = $1";
+
+/// Times a change of an expense is attempted while it keeps being changed concurrently.
+const UPDATE_ATTEMPTS: usize = 5;
+
+/// `INSERT_EXPENSE` or `UPDATE_EXPENSE` with the columns of the expense bound.
+fn expense_query<'q>(
+    sql: &'q str,
+    id: &str,
+    expense: &Expense,
+) -> Result<Query<'q, Any, AnyArguments<'q>>, Error> {
+    Ok(sqlx::query(sql)
+        .bind(id.to_string())
+        .bind(expense.group_id.clone())
+        .bind(expense.series_id.clone())
+        .bind(expense.date.map(|date| date.to_rfc3339()))
+        .bind(i32::from(expense.repeats == Some(true)))
+        .bind(i32::from(expense.deleted_at.is_some()))
+        .bind(serde_json::to_string(expense)?)
+        .bind(expense.version.unwrap_or(version::UNVERSIONED)))
+}
+
+/// The stored expense, read through `executor` to take part in a transaction.
+async fn fetch_expense<'e>(
+    executor: impl Executor<'e, Database = Any>,
+    id: &str,
+) -> Result<Expense, Error> {
+    let data: Option<String> = sqlx::query_scalar("SELECT data FROM expenses WHERE id = $1")
+        .bind(id)
+        .fetch_optional(executor)
+        .await?;
+    let data = data.ok_or_else(|| NotFound::new("Expense", id))?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// The stored group, read through `executor` to take part in a transaction.
+async fn fetch_group<'e>(
+    executor: impl Executor<'e, Database = Any>,
+    id: &str,
+) -> Result<Group, Error> {
+    let data: Option<String> = sqlx::query_scalar("SELECT data FROM groups WHERE id = $1")
+        .bind(id)
+        .fetch_optional(executor)
+        .await?;
+    let data = data.ok_or_else(|| NotFound::new("Group", id))?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// Ids of the users the expense involves, see [`crate::service::account::involves`].
+fn expense_user_ids(expense: &Expense) -> Vec<String> {
+    let shares = expense
+        .users
+        .iter()
+        .flatten()
+        .filter_map(|share| share.user.as_ref());
+    let actors = [
+        &expense.created_by,
+        &expense.updated_by,
+        &expense.deleted_by,
+    ];
+    shares
+        .chain(actors.into_iter().flatten())
+        .filter_map(|user| user.id.clone())
+        .collect()
+}
+
+/// Ids of the users the revision mentions, the user who made the change and the users nested in
+/// the changed values, see [`anonymise_revision`].
+fn revision_user_ids(revision: &ExpenseRevision) -> Vec<String> {
+    fn nested(value: &Bson, ids: &mut Vec<String>) {
+        match value {
+            Bson::Document(document) => {
+                if let Ok(id) = document.get_str("id") {
+                    ids.push(id.to_string());
+                }
+                document.values().for_each(|value| nested(value, ids));
+            }
+            Bson::Array(values) => values.iter().for_each(|value| nested(value, ids)),
+            _ => {}
+        }
+    }
+    let mut ids = revision
+        .changed_by
+        .iter()
+        .filter_map(|user| user.id.clone())
+        .collect();
+    revision
+        .changes
+        .iter()
+        .flat_map(|change| [&change.from, &change.to])
+        .flatten()
+        .for_each(|value| nested(value, &mut ids));
+    ids
+}
+
+/// Adds the users to those the expense involves or has involved, in the transaction the expense
+/// is written in.
+async fn insert_expense_users(
+    connection: &mut AnyConnection,
+    expense_id: &str,
+    user_ids: Vec<String>,
+) -> Result<(), Error> {
+    for user_id in user_ids {
+        let _inserted = sqlx::query(
+            "INSERT INTO expense_users (expense_id, user_id) VALUES ($1, $2) \
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(expense_id)
+        .bind(user_id)
+        .execute(&mut *connection)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Author of the comment as stored in its `user_id` column, empty for system comments.
+fn comment_user_id(comment: &Comment) -> String {
+    comment
+        .created_by
+        .as_ref()
+        .and_then(|user| user.id.clone())
+        .unwrap_or_default()
+}
+
+/// Indexes the users of the expenses and the authors of the comments stored before they were
+/// indexed. The users of an expense are taken from its history as well, as if they had been
+/// indexed on every change.
+async fn index_stored_users(pool: &AnyPool) -> Result<(), Error> {
+    let expenses = sqlx::query_as::<_, (String, String)>(
+        "SELECT id, data FROM expenses WHERE users_indexed = 0",
+    )
+    .fetch_all(pool)
+    .await?;
+    for (id, data) in expenses {
+        let mut user_ids = expense_user_ids(&serde_json::from_str(&data)?);
+        let revisions: Vec<String> =
+            sqlx::query_scalar("SELECT data FROM expense_history WHERE expense_id = $1")
+                .bind(&id)
+                .fetch_all(pool)
+                .await?;
+        for revision in revisions {
+            user_ids.extend(revision_user_ids(&serde_json::from_str(&revision)?));
+        }
+        let mut transaction = pool.begin().await?;
+        insert_expense_users(&mut transaction, &id, user_ids).await?;
+        let _updated = sqlx::query("UPDATE expenses SET users_indexed = 1 WHERE id = $1")
+            .bind(&id)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+    }
+    let comments = sqlx::query_as::<_, (String, String)>(
+        "SELECT id, data FROM comments WHERE user_id IS NULL",
+    )
+    .fetch_all(pool)
+    .await?;
+    for (id, data) in comments {
+        let _updated = sqlx::query("UPDATE comments SET user_id = $2 WHERE id = $1")
+            .bind(id)
+            .bind(comment_user_id(&serde_json::from_str(&data)?))
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Inserts the activity with the users it is shown to.
+async fn insert_activity(
+    connection: &mut AnyConnection,
+    activity: Activity,
+) -> Result<Activity, Error> {
+    let id = ObjectId::new().to_hex();
+    let activity = Activity {
+        id: Some(id.clone()),
+        ..activity
+    };
+    let _inserted = sqlx::query("INSERT INTO activity (id, group_id, data) VALUES ($1, $2, $3)")
+        .bind(&id)
+        .bind(activity.group_id.clone())
+        .bind(serde_json::to_string(&activity)?)
+        .execute(&mut *connection)
+        .await?;
+    for user_id in &activity.user_ids {
+        let _inserted =
+            sqlx::query("INSERT INTO activity_users (activity_id, user_id) VALUES ($1, $2)")
+                .bind(&id)
+                .bind(user_id)
+                .execute(&mut *connection)
+                .await?;
+    }
+    Ok(activity)
+}
+
+/// Appends the next revision of the expense to its history. The revision is numbered again if a
+/// concurrent change took its number.
+async fn insert_revision(
+    connection: &mut AnyConnection,
+    expense_id: String,
+    action: ExpenseAction,
+    changed_by: Option<User>,
+    before: &Document,
+    after: &Document,
+) -> Result<ExpenseRevision, Error> {
+    let mut revision = ExpenseRevision {
+        expense_id,
+        version: 0,
+        action,
+        changed_by,
+        changed_at: Utc::now(),
+        changes: diff(before, after),
+    };
+    for _attempt in 0..UPDATE_ATTEMPTS {
+        let latest: Option<i64> = sqlx::query_scalar(
+            "SELECT version FROM expense_history WHERE expense_id = $1 \
+             ORDER BY version DESC LIMIT 1",
+        )
+        .bind(&revision.expense_id)
+        .fetch_optional(&mut *connection)
+        .await?;
+        revision.version = latest.unwrap_or(0) + 1;
+        let inserted = sqlx::query(
+            "INSERT INTO expense_history (expense_id, version, data) VALUES ($1, $2, $3) \
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(&revision.expense_id)
+        .bind(revision.version)
+        .bind(serde_json::to_string(&revision)?)
+        .execute(&mut *connection)
+        .await?;
+        if inserted.rows_affected() > 0 {
+            return Ok(revision);
+        }
+    }
+    Err(anyhow!(
+        "Expense {} kept changing while its history was recorded",
+        revision.expense_id
+    ))
+}
+
+/// Records the creation of the expense stored under `id` in its history and the group activity,
+/// in the transaction the expense is inserted in.
+async fn record_creation(
+    connection: &mut AnyConnection,
+    id: String,
+    expense: &Expense,
+) -> Result<(), Error> {
+    insert_expense_users(connection, &id, expense_user_ids(expense)).await?;
+    let _revision = insert_revision(
+        connection,
+        id.clone(),
+        ExpenseAction::Created,
+        expense.created_by.clone(),
+        &Document::new(),
+        &bson::to_document(expense)?,
+    )
+    .await?;
+    let _activity = insert_activity(
+        connection,
+        Activity::for_expense(
+            id,
+            ExpenseAction::Created,
+            expense.created_by.as_ref(),
+            expense,
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Saves the group with its members, only if it is still at `previous_version` when given.
+/// Returns whether it was saved.
+async fn save_group(
+    connection: &mut AnyConnection,
+    group: &Group,
+    previous_version: Option<i64>,
+) -> Result<bool, Error> {
+    let id = group.id.clone().unwrap_or_default();
+    let sql = match previous_version {
+        Some(_) => "UPDATE groups SET data = $2, version = $3 WHERE id = $1 AND version = $4",
+        None => {
+            "INSERT INTO groups (id, data, version) VALUES ($1, $2, $3) \
+             ON CONFLICT (id) DO UPDATE SET data = excluded.data, version = excluded.version"
+        }
+    };
+    let mut query = sqlx::query(sql)
+        .bind(&id)
+        .bind(serde_json::to_string(group)?)
+        .bind(group.version.unwrap_or(version::UNVERSIONED));
+    if let Some(previous_version) = previous_version {
+        query = query.bind(previous_version);
+    }
+    if query.execute(&mut *connection).await?.rows_affected() == 0 {
+        return Ok(false);
+    }
+    for user_id in group.member_ids() {
+        let _inserted = sqlx::query(
+            "INSERT INTO group_members (group_id, user_id) VALUES ($1, $2) \
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(&id)
+        .bind(user_id)
+        .execute(&mut *connection)
+        .await?;
+    }
+    Ok(true)
+}
+
+/// Connects to the SQLite or PostgreSQL database at `url` and applies pending migrations.
+pub async fn connect(url: &str) -> Result<AnyPool, Error> {
+    sqlx::any::install_default_drivers();
+    let mut options = AnyPoolOptions::new();
+    if url.contains(":memory:") || url.contains("mode=memory") {
+        // every connection opens its own in-memory database, so there must be exactly one
+        options = options
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None);
+    }
+    let pool = options.connect(url).await?;
+    MIGRATOR.run(&pool).await?;
+    index_stored_users(&pool).await?;
+    Ok(pool)
+}
+
+/// Implementation of every service trait on top of a relational database. Documents are stored
+/// as JSON next to the columns they are queried by, ids are generated like MongoDB's so that
+/// they can be used interchangeably.
+#[derive(Debug, Clone)]
+pub struct SqlStore {
+    pool: AnyPool,
+
+    events: Option<EventBus>,
+}
+
+impl SqlStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool, events: None }
+    }
+
+    /// Publishes every change of an expense to the given bus.
+    pub fn with_events(self, events: EventBus) -> Self {
+        Self {
+            events: Some(events),
+            ..self
+        }
+    }
+
+    async fn find_expense(&self, id: &str) -> Result<Expense, Error> {
+        fetch_expense(&self.pool, id).await
+    }
+
+    /// Sets the fields on the stored expense the way `$set` does, increments its version and
+    /// records the change, all in one transaction. The expense is written only if it is still at
+    /// the version it was read at, and read again if it was changed in between. Returns `None` if
+    /// the expense was changed since `expected_version`.
+    async fn apply_update(
+        &self,
+        id: &str,
+        action: ExpenseAction,
+        changed_by: Option<User>,
+        set_document: Document,
+        expected_version: Option<i64>,
+    ) -> Result<Option<(Expense, ExpenseRevision)>, Error> {
+        for _attempt in 0..UPDATE_ATTEMPTS {
+            let mut transaction = self.pool.begin().await?;
+            let _locked = sqlx::query(LOCK_EXPENSE)
+                .bind(id)
+                .execute(&mut *transaction)
+                .await?;
+            let expense = fetch_expense(&mut *transaction, id).await?;
+            if !version::matches(expected_version, expense.version) {
+                return Ok(None);
+            }
+            let previous_version = expense.version.unwrap_or(version::UNVERSIONED);
+            let mut set_document = set_document.clone();
+            let _previous = set_document.insert("version", previous_version + 1);
+            let (before, after) = merge_update(&expense, set_document)?;
+            let expense: Expense = bson::from_document(after.clone())?;
+            let updated = expense_query(UPDATE_EXPENSE_IF_VERSION, id, &expense)?
+                .bind(previous_version)
+                .execute(&mut *transaction)
+                .await?;
+            if updated.rows_affected() == 0 {
+                // changed since it was read
+                continue;
+            }
+            let revision = insert_revision(
+                &mut transaction,
+                id.to_string(),
+                action,
+                changed_by.clone(),
+                &before,
+                &after,
+            )
+            .await?;
+            let mut user_ids = expense_user_ids(&expense);
+            user_ids.extend(revision_user_ids(&revision));
+            insert_expense_users(&mut transaction, id, user_ids).await?;
+            let _activity = insert_activity(
+                &mut transaction,
+                Activity::for_expense(id.to_string(), action, changed_by.as_ref(), &expense),
+            )
+            .await?;
+            transaction.commit().await?;
+            if let Some(events) = &self.events {
+                events.publish_expense(id.to_string(), action, &expense);
+            }
+            return Ok(Some((expense, revision)));
+        }
+        Err(anyhow!("Expense {} kept changing while it was updated", id))
+    }
+
+    /// Keeps `commentsCount` of the expense in sync with the comments, without changing its
+    /// version. The expense is locked before it is read so that concurrent counts add up.
+    async fn increment_comments_count(&self, expense_id: &str, by: i64) -> Result<(), Error> {
+        let mut transaction = self.pool.begin().await?;
+        let locked = sqlx::query(LOCK_EXPENSE)
+            .bind(expense_id)
+            .execute(&mut *transaction)
+            .await?;
+        if locked.rows_affected() == 0 {
+            // comments may be left on expenses which are not stored here
+            return Ok(());
+        }
+        let mut expense = fetch_expense(&mut *transaction, expense_id).await?;
+        expense.comments_count = Some(expense.comments_count.unwrap_or(0) + by);
+        let _updated = sqlx::query("UPDATE expenses SET data = $2 WHERE id = $1")
+            .bind(expense_id)
+            .bind(serde_json::to_string(&expense)?)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Stores the comment on its expense, which has to exist.
+    async fn insert_comment(&self, comment: Comment) -> Result<Comment, Error> {
+        let _expense = self.find_expense(&comment.expense_id).await?;
+        let id = ObjectId::new().to_hex();
+        let comment = Comment {
+            id: Some(id.clone()),
+            ..comment
+        };
+        let _inserted = sqlx::query(
+            "INSERT INTO comments (id, expense_id, user_id, data) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(&id)
+        .bind(&comment.expense_id)
+        .bind(comment_user_id(&comment))
+        .bind(serde_json::to_string(&comment)?)
+        .execute(&self.pool)
+        .await?;
+        self.increment_comments_count(&comment.expense_id, 1)
+            .await?;
+        Ok(comment)
+    }
+
+    /// Page of the activity selected by `query`, which has to bind the filtered id to `$1`.
+    async fn find_page(
+        &self,
+        query: &str,
+        key: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        let limit = request.page_size();
+        let mut sql = query.to_string();
+        if request.cursor.is_some() {
+            sql.push_str(" AND activity.id < $2");
+        }
+        sql.push_str(&format!(" ORDER BY activity.id DESC LIMIT {}", limit));
+        let mut select = sqlx::query_as::<_, (String, String)>(&sql).bind(key);
+        if let Some(cursor) = request.cursor {
+            select = select.bind(cursor);
+        }
+        let rows = select.fetch_all(&self.pool).await?;
+
+        let last_read = match &request.user_id {
+            Some(user_id) => Some(
+                sqlx::query_scalar::<_, String>(
+                    "SELECT last_read_id FROM activity_reads WHERE user_id = $1",
+                )
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?,
+            ),
+            None => None,
+        };
+        let activities = rows
+            .into_iter()
+            .map(|(id, data)| {
+                let activity: Activity = serde_json::from_str(&data)?;
+                Ok(Activity {
+                    unread: last_read
+                        .as_ref()
+                        .map(|last_read| !matches!(last_read, Some(last) if id <= *last)),
+                    ..activity
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let next_cursor = if activities.len() as i64 == limit {
+            activities.last().and_then(|activity| activity.id.clone())
+        } else {
+            None
+        };
+        Ok(ActivityPage {
+            activities,
+            next_cursor,
+        })
+    }
+
+    async fn find_group(&self, id: &str) -> Result<Group, Error> {
+        fetch_group(&self.pool, id).await
+    }
+}
+
+#[async_trait]
+impl ExpensesApi for SqlStore {
+    async fn get_expense(&self, id: String) -> Result<Expense, Error> {
+        self.find_expense(&id).await
+    }
+
+    async fn list_expenses(&self, request: ListExpensesRequest) -> Result<ExpensesResponse, Error> {
+        // the indexed filters select the rows, the other filters are applied to their documents
+        let rows: Vec<String> = match (&request.group_id, &request.user_id) {
+            (Some(group_id), _) => {
+                sqlx::query_scalar("SELECT data FROM expenses WHERE group_id = $1 ORDER BY id")
+                    .bind(group_id)
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+            (None, Some(user_id)) => {
+                sqlx::query_scalar(
+                    "SELECT expenses.data FROM expenses \
+                     JOIN expense_users ON expense_users.expense_id = expenses.id \
+                     WHERE expense_users.user_id = $1 ORDER BY expenses.id",
+                )
+                .bind(user_id)
+                .fetch_all(&self.pool)
+                .await?
+            }
+            (None, None) => {
+                sqlx::query_scalar("SELECT data FROM expenses ORDER BY id")
+                    .fetch_all(&self.pool)
+                    .await?
+            }
+        };
+        let expenses = rows
+            .iter()
+            .map(|data| serde_json::from_str(data))
+            .collect::<Result<Vec<Expense>, _>>()?
+            .into_iter()
+            .filter(|expense| request.matches(expense))
+            .collect();
+        Ok(ExpensesResponse { expenses })
+    }
+
+    async fn create_expense(&self, expense: CreateExpenseSpec) -> Result<ExpenseEntity, Error> {
+        let expense = ExpensesCalculator::new().create_expense(&expense)?;
+        let id = ObjectId::new();
+        let mut transaction = self.pool.begin().await?;
+        let _inserted = expense_query(INSERT_EXPENSE, &id.to_hex(), &expense)?
+            .execute(&mut *transaction)
+            .await?;
+        record_creation(&mut transaction, id.to_hex(), &expense).await?;
+        transaction.commit().await?;
+        if let Some(events) = &self.events {
+            events.publish_expense(id.to_hex(), ExpenseAction::Created, &expense);
+        }
+        Ok(ExpenseEntity {
+            id: Some(id),
+            expense,
+        })
+    }
+
+    async fn update_expense(
+        &self,
+        id: String,
+        update_expense_spec: UpdateExpenseSpec,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        let current = self.find_expense(&id).await?;
+        let set_document = update_expense_spec.to_set_document(Some(&current))?;
+        let Some((expense, revision)) = self
+            .apply_update(
+                &id,
+                ExpenseAction::Updated,
+                update_expense_spec.updated_by,
+                set_document,
+                expected_version,
+            )
+            .await?
+        else {
+            return Ok(Conditional::Stale(self.get_expense(id).await?));
+        };
+        if !revision.changes.is_empty() {
+            let _comment = self
+                .create_system_comment(id, describe_changes(&revision.changes))
+                .await?;
+        }
+        Ok(Conditional::Applied(expense))
+    }
+
+    async fn delete_expense(
+        &self,
+        id: String,
+        deleted_by: User,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        let set_document = deletion_document(&deleted_by)?;
+        match self
+            .apply_update(
+                &id,
+                ExpenseAction::Deleted,
+                Some(deleted_by),
+                set_document,
+                expected_version,
+            )
+            .await?
+        {
+            Some((expense, _revision)) => Ok(Conditional::Applied(expense)),
+            None => Ok(Conditional::Stale(self.get_expense(id).await?)),
+        }
+    }
+
+    async fn restore_expense(&self, id: String, restored_by: User) -> Result<(), Error> {
+        let set_document = restoration_document(&restored_by)?;
+        let _updated = self
+            .apply_update(
+                &id,
+                ExpenseAction::Restored,
+                Some(restored_by),
+                set_document,
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The expenses are inserted with their history and activity in one transaction.
+    async fn import_expenses(&self, expenses: Vec<Expense>) -> Result<Vec<ExpenseEntity>, Error> {
+        let imported = expenses
+            .into_iter()
+            .map(|expense| (ObjectId::new(), expense))
+            .collect::<Vec<_>>();
+        let mut transaction = self.pool.begin().await?;
+        for (id, expense) in &imported {
+            let _inserted = expense_query(INSERT_EXPENSE, &id.to_hex(), expense)?
+                .execute(&mut *transaction)
+                .await?;
+            record_creation(&mut transaction, id.to_hex(), expense).await?;
+        }
+        transaction.commit().await?;
+        let mut entities = Vec::with_capacity(imported.len());
+        for (id, expense) in imported {
+            if let Some(events) = &self.events {
+                events.publish_expense(id.to_hex(), ExpenseAction::Created, &expense);
+            }
+            entities.push(ExpenseEntity {
+                id: Some(id),
+                expense,
+            });
+        }
+        Ok(entities)
+    }
+
+    /// The expenses are rewritten in one transaction.
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        let rows = sqlx::query_as::<_, (String, String)>(
+            "SELECT expenses.id, expenses.data FROM expenses \
+             JOIN expense_users ON expense_users.expense_id = expenses.id \
+             WHERE expense_users.user_id = $1",
+        )
+        .bind(&user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut transaction = self.pool.begin().await?;
+        let mut anonymised = 0;
+        for (id, data) in rows {
+            let mut expense: Expense = serde_json::from_str(&data)?;
+            if !anonymise_expense(&mut expense, &user_id) {
+                continue;
+            }
+            expense.version = Some(expense.version.unwrap_or(version::UNVERSIONED) + 1);
+            let _updated = expense_query(UPDATE_EXPENSE, &id, &expense)?
+                .execute(&mut *transaction)
+                .await?;
+            anonymised += 1;
+        }
+        transaction.commit().await?;
+        Ok(anonymised)
+    }
+
+    async fn set_receipt(
+        &self,
+        id: String,
+        receipt: Option<Receipt>,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Expense>, Error> {
+        let mut set_document = Document::new();
+        let _previous = set_document.insert("receipt", bson::to_bson(&receipt)?);
+        match self
+            .apply_update(
+                &id,
+                ExpenseAction::Updated,
+                None,
+                set_document,
+                expected_version,
+            )
+            .await?
+        {
+            Some((expense, _revision)) => Ok(Conditional::Applied(expense)),
+            None => Ok(Conditional::Stale(self.get_expense(id).await?)),
+        }
+    }
+
+    /// The expense, its comments, history and activity are removed in one transaction.
+    async fn purge_expense(&self, id: String) -> Result<bool, Error> {
+        let mut transaction = self.pool.begin().await?;
+        let group_id: Option<Option<String>> =
+            sqlx::query_scalar("SELECT group_id FROM expenses WHERE id = $1")
+                .bind(&id)
+                .fetch_optional(&mut *transaction)
+                .await?;
+        let Some(group_id) = group_id else {
+            return Ok(false);
+        };
+        // the activity of the expense is found among the activity of its group
+        let activity = match &group_id {
+            Some(group_id) => {
+                sqlx::query_as::<_, (String, String)>(
+                    "SELECT id, data FROM activity WHERE group_id = $1",
+                )
+                .bind(group_id)
+                .fetch_all(&mut *transaction)
+                .await?
+            }
+            None => {
+                sqlx::query_as::<_, (String, String)>(
+                    "SELECT id, data FROM activity WHERE group_id IS NULL",
+                )
+                .fetch_all(&mut *transaction)
+                .await?
+            }
+        };
+        for (activity_id, data) in activity {
+            let activity: Activity = serde_json::from_str(&data)?;
+            if activity.expense_id.as_ref() != Some(&id) {
+                continue;
+            }
+            for sql in [
+                "DELETE FROM activity_users WHERE activity_id = $1",
+                "DELETE FROM activity WHERE id = $1",
+            ] {
+                let _deleted = sqlx::query(sql)
+                    .bind(&activity_id)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+        }
+        let _comments = sqlx::query("DELETE FROM comments WHERE expense_id = $1")
+            .bind(&id)
+            .execute(&mut *transaction)
+            .await?;
+        let _history = sqlx::query("DELETE FROM expense_history WHERE expense_id = $1")
+            .bind(&id)
+            .execute(&mut *transaction)
+            .await?;
+        let _users = sqlx::query("DELETE FROM expense_users WHERE expense_id = $1")
+            .bind(&id)
+            .execute(&mut *transaction)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM expenses WHERE id = $1")
+            .bind(&id)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl ExpenseHistoryApi for SqlStore {
+    async fn get_history(&self, expense_id: String) -> Result<Vec<ExpenseRevision>, Error> {
+        let rows: Vec<String> = sqlx::query_scalar(
+            "SELECT data FROM expense_history WHERE expense_id = $1 ORDER BY version",
+        )
+        .bind(expense_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|data| serde_json::from_str(data))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    async fn record_revision(
+        &self,
+        expense_id: String,
+        action: ExpenseAction,
+        changed_by: Option<User>,
+        before: &Document,
+        after: &Document,
+    ) -> Result<ExpenseRevision, Error> {
+        let mut transaction = self.pool.begin().await?;
+        let revision = insert_revision(
+            &mut transaction,
+            expense_id,
+            action,
+            changed_by,
+            before,
+            after,
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(revision)
+    }
+
+    /// The revisions are rewritten in one transaction.
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        let rows = sqlx::query_as::<_, (String, i64, String)>(
+            "SELECT expense_history.expense_id, expense_history.version, expense_history.data \
+             FROM expense_history \
+             JOIN expense_users ON expense_users.expense_id = expense_history.expense_id \
+             WHERE expense_users.user_id = $1",
+        )
+        .bind(&user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut transaction = self.pool.begin().await?;
+        let mut anonymised = 0;
+        for (expense_id, version, data) in rows {
+            let mut revision: ExpenseRevision = serde_json::from_str(&data)?;
+            if !anonymise_revision(&mut revision, &user_id) {
+                continue;
+            }
+            let _updated = sqlx::query(
+                "UPDATE expense_history SET data = $3 WHERE expense_id = $1 AND version = $2",
+            )
+            .bind(expense_id)
+            .bind(version)
+            .bind(serde_json::to_string(&revision)?)
+            .execute(&mut *transaction)
+            .await?;
+            anonymised += 1;
+        }
+        transaction.commit().await?;
+        Ok(anonymised)
+    }
+}
+
+#[async_trait]
+impl CommentsApi for SqlStore {
+    async fn get_comments(&self, expense_id: String) -> Result<Vec<Comment>, Error> {
+        let rows: Vec<String> =
+            sqlx::query_scalar("SELECT data FROM comments WHERE expense_id = $1 ORDER BY id")
+                .bind(expense_id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rows
+            .iter()
+            .map(|data| serde_json::from_str(data))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    async fn create_comment(
+        &self,
+        expense_id: String,
+        spec: CreateCommentSpec,
+    ) -> Result<Comment, Error> {
+        self.insert_comment(Comment {
+            id: None,
+            expense_id,
+            content: spec.content,
+            comment_type: CommentType::User,
+            created_at: Some(Utc::now()),
+            created_by: Some(spec.user),
+        })
+        .await
+    }
+
+    async fn create_system_comment(
+        &self,
+        expense_id: String,
+        content: String,
+    ) -> Result<Comment, Error> {
+        self.insert_comment(Comment {
+            id: None,
+            expense_id,
+            content,
+            comment_type: CommentType::System,
+            created_at: Some(Utc::now()),
+            created_by: None,
+        })
+        .await
+    }
+
+    async fn delete_comment(&self, id: String) -> Result<Option<Comment>, Error> {
+        let data: Option<String> = sqlx::query_scalar("SELECT data FROM comments WHERE id = $1")
+            .bind(&id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let comment: Comment = match data {
+            Some(data) => serde_json::from_str(&data)?,
+            None => return Ok(None),
+        };
+        let _deleted = sqlx::query("DELETE FROM comments WHERE id = $1")
+            .bind(&id)
+            .execute(&self.pool)
+            .await?;
+        self.increment_comments_count(&comment.expense_id, -1)
+            .await?;
+        Ok(Some(comment))
+    }
+
+    async fn get_user_comments(&self, user_id: String) -> Result<Vec<Comment>, Error> {
+        let rows: Vec<String> =
+            sqlx::query_scalar("SELECT data FROM comments WHERE user_id = $1 ORDER BY id")
+                .bind(user_id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rows
+            .iter()
+            .map(|data| serde_json::from_str(data))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// The comments are rewritten in one transaction.
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        let comments = self.get_user_comments(user_id.clone()).await?;
+        let mut transaction = self.pool.begin().await?;
+        let mut anonymised = 0;
+        for mut comment in comments {
+            if !anonymise_comment(&mut comment, &user_id) {
+                continue;
+            }
+            let _updated = sqlx::query("UPDATE comments SET data = $2 WHERE id = $1")
+                .bind(comment.id.clone())
+                .bind(serde_json::to_string(&comment)?)
+                .execute(&mut *transaction)
+                .await?;
+            anonymised += 1;
+        }
+        transaction.commit().await?;
+        Ok(anonymised)
+    }
+}
+
+#[async_trait]
+impl ActivityApi for SqlStore {
+    async fn record(&self, activity: Activity) -> Result<Activity, Error> {
+        let mut transaction = self.pool.begin().await?;
+        let activity = insert_activity(&mut transaction, activity).await?;
+        transaction.commit().await?;
+        Ok(activity)
+    }
+
+    async fn get_group_activity(
+        &self,
+        group_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        self.find_page(
+            "SELECT activity.id, activity.data FROM activity WHERE activity.group_id = $1",
+            group_id,
+            request,
+        )
+        .await
+    }
+
+    async fn get_user_activity(
+        &self,
+        user_id: String,
+        request: ActivityRequest,
+    ) -> Result<ActivityPage, Error> {
+        let request = ActivityRequest {
+            user_id: Some(user_id.clone()),
+            ..request
+        };
+        self.find_page(
+            "SELECT activity.id, activity.data FROM activity \
+             JOIN activity_users ON activity_users.activity_id = activity.id \
+             WHERE activity_users.user_id = $1",
+            user_id,
+            request,
+        )
+        .await
+    }
+
+    async fn mark_read(&self, user_id: String) -> Result<(), Error> {
+        let latest: Option<String> = sqlx::query_scalar(
+            "SELECT activity_id FROM activity_users WHERE user_id = $1 \
+             ORDER BY activity_id DESC LIMIT 1",
+        )
+        .bind(&user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        if let Some(latest) = latest {
+            let _upserted = sqlx::query(
+                "INSERT INTO activity_reads (user_id, last_read_id) VALUES ($1, $2) \
+                 ON CONFLICT (user_id) DO UPDATE SET last_read_id = excluded.last_read_id",
+            )
+            .bind(&user_id)
+            .bind(latest)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// The activities are rewritten in one transaction. The actor is always one of the users the
+    /// activity is shown to.
+    async fn anonymise_user(&self, user_id: String) -> Result<u64, Error> {
+        let rows = sqlx::query_as::<_, (String, String)>(
+            "SELECT activity.id, activity.data FROM activity \
+             JOIN activity_users ON activity_users.activity_id = activity.id \
+             WHERE activity_users.user_id = $1",
+        )
+        .bind(&user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut transaction = self.pool.begin().await?;
+        let mut anonymised = 0;
+        for (id, data) in rows {
+            let mut activity: Activity = serde_json::from_str(&data)?;
+            if !anonymise_activity(&mut activity, &user_id) {
+                continue;
+            }
+            let _updated = sqlx::query("UPDATE activity SET data = $2 WHERE id = $1")
+                .bind(id)
+                .bind(serde_json::to_string(&activity)?)
+                .execute(&mut *transaction)
+                .await?;
+            anonymised += 1;
+        }
+        transaction.commit().await?;
+        Ok(anonymised)
+    }
+}
+
+#[async_trait]
+impl GroupApi for SqlStore {
+    async fn get_group(&self, id: String) -> Result<Group, Error> {
+        self.find_group(&id).await
+    }
+
+    async fn create_group(&self, create_spec: CreateGroupSpec) -> Result<Group, Error> {
+        let group = Group {
+            id: Some(ObjectId::new().to_hex()),
+            ..Group::from(create_spec)
+        };
+        let mut transaction = self.pool.begin().await?;
+        let _saved = save_group(&mut transaction, &group, None).await?;
+        let _activity = insert_activity(&mut transaction, Activity::group_created(&group)).await?;
+        transaction.commit().await?;
+        Ok(group)
+    }
+
+    async fn get_user_group(&self, user_id: String) -> Result<Vec<Group>, Error> {
+        let rows: Vec<String> = sqlx::query_scalar(
+            "SELECT groups.data FROM groups \
+             JOIN group_members ON group_members.group_id = groups.id \
+             WHERE group_members.user_id = $1 ORDER BY groups.id",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .iter()
+            .map(|data| serde_json::from_str(data))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    async fn add_member(
+        &self,
+        group_id: String,
+        user: GroupUser,
+        expected_version: Option<i64>,
+    ) -> Result<Conditional<Group>, Error> {
+        let user_id = user.user_id.clone();
+        let name = user.first_name.clone().unwrap_or_else(|| user_id.clone());
+        for _attempt in 0..UPDATE_ATTEMPTS {
+            let mut transaction = self.pool.begin().await?;
+            let _locked = sqlx::query(LOCK_GROUP)
+                .bind(&group_id)
+                .execute(&mut *transaction)
+                .await?;
+            let mut group = fetch_group(&mut *transaction, &group_id).await?;
+            if !version::matches(expected_version, group.version) {
+                return Ok(Conditional::Stale(group));
+            }
+            let previous_version = group.version.unwrap_or(version::UNVERSIONED);
+            group
+                .members
+                .get_or_insert_with(Vec::new)
+                .push(user.clone().into());
+            group.version = Some(previous_version + 1);
+            if !save_group(&mut transaction, &group, Some(previous_version)).await? {
+                // changed since it was read, joining it again keeps the concurrent change
+                continue;
+            }
+            let _activity = insert_activity(
+                &mut transaction,
+                Activity::member_joined(&group, user_id.clone(), name.clone()),
+            )
+            .await?;
+            transaction.commit().await?;
+            return Ok(Conditional::Applied(group));
+        }
+        Err(anyhow!(
+            "Group {} kept changing while a member joined",
+            group_id
+        ))
+    }
+
+    async fn anonymise_member(&self, user_id: String) -> Result<u64, Error> {
+        let mut anonymised = 0;
+        for mut group in self.get_user_group(user_id.clone()).await? {
+            if anonymise_member(&mut group, &user_id) {
+                group.version = Some(group.version.unwrap_or(version::UNVERSIONED) + 1);
+                let mut connection = self.pool.acquire().await?;
+                let _saved = save_group(&mut connection, &group, None).await?;
+                anonymised += 1;
+            }
+        }
+        Ok(anonymised)
+    }
+
+    async fn delete_group(&self, id: String) -> Result<bool, Error> {
+        let mut transaction = self.pool.begin().await?;
+        let _readers = sqlx::query(
+            "DELETE FROM activity_users WHERE activity_id IN \
+             (SELECT id FROM activity WHERE group_id = $1)",
+        )
+        .bind(&id)
+        .execute(&mut *transaction)
+        .await?;
+        let _activity = sqlx::query("DELETE FROM activity WHERE group_id = $1")
+            .bind(&id)
+            .execute(&mut *transaction)
+            .await?;
+        let _members = sqlx::query("DELETE FROM group_members WHERE group_id = $1")
+            .bind(&id)
+            .execute(&mut *transaction)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM groups WHERE id = $1")
+            .bind(&id)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl UserApi for SqlStore {
+    async fn get_user(&self, id: i32) -> Result<user::User, Error> {
+        let data: Option<String> = sqlx::query_scalar("SELECT data FROM users WHERE id = $1")
+            .bind(id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        let data = data.ok_or_else(|| NotFound::new("User", id))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    async fn create_user(&self, create_spec: CreateUserSpec) -> Result<String, Error> {
+        let id = ObjectId::new().to_hex();
+        let user = user::User {
+            id: Some(id.clone()),
+            ..user::User::from(create_spec)
+        };
+        let _inserted = sqlx::query("INSERT INTO users (id, email, data) VALUES ($1, $2, $3)")
+            .bind(&id)
+            .bind(user.email.clone())
+            .bind(serde_json::to_string(&user)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(id)
+    }
+
+    async fn find_user_by_email(&self, email: String) -> Result<Option<user::User>, Error> {
+        let data: Option<String> = sqlx::query_scalar("SELECT data FROM users WHERE email = $1")
+            .bind(email.to_lowercase())
+            .fetch_optional(&self.pool)
+            .await?;
+        data.map(|data| serde_json::from_str(&data))
+            .transpose()
+            .map_err(Error::from)
+    }
+    async fn find_user(&self, id: String) -> Result<Option<user::User>, Error> {
+        let data: Option<String> = sqlx::query_scalar("SELECT data FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        data.map(|data| serde_json::from_str(&data))
+            .transpose()
+            .map_err(Error::from)
+    }
+
+    async fn delete_user(&self, id: String) -> Result<bool, Error> {
+        let deleted = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl BalanceApi for SqlStore {
+    async fn get_user_balance(&self, user_id: String) -> Result<Balance, Error> {
+        let balance: Option<i64> =
+            sqlx::query_scalar("SELECT balance FROM balances WHERE user_id = $1")
+                .bind(&user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        let balance =
+            balance.ok_or_else(|| Error::msg(format!("No balance for user {}", user_id)))?;
+        Ok(Balance { user_id, balance })
+    }
+}
+
+#[async_trait]
+impl RecurringExpensesApi for SqlStore {
+    async fn generate_due_expenses(&self, now: DateTime<Utc>) -> Result<Vec<Expense>, Error> {
+        let series: Vec<String> =
+            sqlx::query_scalar("SELECT id FROM expenses WHERE repeats = 1 AND deleted = 0")
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut generated = Vec::new();
+        for id in series {
+            // the template is locked so that a concurrent change is not overwritten with its
+            // next repeat, nor a series stopped meanwhile generated again
+            let mut transaction = self.pool.begin().await?;
+            let _locked = sqlx::query(LOCK_EXPENSE)
+                .bind(&id)
+                .execute(&mut *transaction)
+                .await?;
+            let mut template = fetch_expense(&mut *transaction, &id).await?;
+            if template.repeats != Some(true) || template.deleted_at.is_some() {
+                continue;
+            }
+            let (interval, anchor) = match (template.repeat_interval, template.date) {
+                (Some(interval), Some(anchor)) => (interval, anchor),
+                _ => continue,
+            };
+            let mut occurrences = Vec::new();
+            let mut next_repeat = template.next_repeat;
+            // catch up on every occurrence missed while the server was down
+            while let Some(date) = next_repeat.filter(|date| *date <= now) {
+                let occurrence = occurrence(&template, id.clone(), date, now);
+                let occurrence_id = ObjectId::new().to_hex();
+                // the unique index on series and date skips occurrences generated before
+                let inserted = expense_query(INSERT_EXPENSE, &occurrence_id, &occurrence)?
+                    .execute(&mut *transaction)
+                    .await?;
+                if inserted.rows_affected() > 0 {
+                    insert_expense_users(
+                        &mut transaction,
+                        &occurrence_id,
+                        expense_user_ids(&occurrence),
+                    )
+                    .await?;
+                    let _revision = insert_revision(
+                        &mut transaction,
+                        occurrence_id.clone(),
+                        ExpenseAction::Created,
+                        None,
+                        &Document::new(),
+                        &bson::to_document(&occurrence)?,
+                    )
+                    .await?;
+                    let _activity = insert_activity(
+                        &mut transaction,
+                        Activity::for_expense(
+                            occurrence_id.clone(),
+                            ExpenseAction::Created,
+                            None,
+                            &occurrence,
+                        ),
+                    )
+                    .await?;
+                    occurrences.push((occurrence_id, occurrence));
+                }
+                next_repeat = interval.next_after(anchor, date);
+            }
+            if next_repeat != template.next_repeat {
+                template.next_repeat = next_repeat;
+                let _updated = expense_query(UPDATE_EXPENSE, &id, &template)?
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            transaction.commit().await?;
+            for (occurrence_id, occurrence) in occurrences {
+                if let Some(events) = &self.events {
+                    events.publish_expense(occurrence_id, ExpenseAction::Created, &occurrence);
+                }
+                generated.push(occurrence);
+            }
+        }
+        Ok(generated)
+    }
+}
+
+#[async_trait]
+impl CategoriesApi for SqlStore {
+    async fn get_group_categories(&self, group_id: String) -> Result<Vec<Category>, Error> {
+        let rows: Vec<String> =
+            sqlx::query_scalar("SELECT data FROM categories WHERE group_id = $1 ORDER BY id")
+                .bind(group_id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(rows
+            .iter()
+            .map(|data| serde_json::from_str(data))
+            .collect::<Result<Vec<_>, _>>()?)
+    }
+
+    async fn create_category(
+        &self,
+        group_id: String,
+        spec: CreateCategorySpec,
+    ) -> Result<Category, Error> {
+        let category = Category::custom(ObjectId::new().to_hex(), group_id, spec);
+        // the unique index on group, parent and name in lower case skips taken names
+        let inserted = sqlx::query(
+            "INSERT INTO categories (id, group_id, parent_id, name_key, data) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+        )
+        .bind(&category.id)
+        .bind(&category.group_id)
+        .bind(category.parent_id.clone().unwrap_or_default())
+        .bind(category.name.to_ascii_lowercase())
+        .bind(serde_json::to_string(&category)?)
+        .execute(&self.pool)
+        .await?;
+        if inserted.rows_affected() == 0 {
+            return Err(CategoryTaken(category.name).into());
+        }
+        Ok(category)
+    }
+}
+
+/// Totals are computed from the expenses of the group or user rather than in SQL, the amounts are only
+/// stored in the JSON documents.
+#[async_trait]
+impl ReportsApi for SqlStore {
+    async fn group_report(
+        &self,
+        group_id: String,
+        request: ReportRequest,
+    ) -> Result<GroupReport, Error> {
+        let expenses = self
+            .list_expenses(ListExpensesRequest {
+                group_id: Some(group_id.clone()),
+                ..ListExpensesRequest::default()
+            })
+            .await?
+            .expenses;
+        Ok(Totals::of(&expenses, &request)?.report(group_id, request))
+    }
+
+    async fn user_spending(
+        &self,
+        user_id: String,
+        request: ReportRequest,
+    ) -> Result<Vec<UserSpending>, Error> {
+        let expenses = self
+            .list_expenses(ListExpensesRequest {
+                user_id: Some(user_id.clone()),
+                ..ListExpensesRequest::default()
+            })
+            .await?
+            .expenses;
+        UserSpending::of(&expenses, &user_id, &request)
+    }
+}
+
+#[async_trait]
+impl BlobStore for SqlStore {
+    async fn put_blob(&self, key: String, blob: Blob) -> Result<(), Error> {
+        let _saved = sqlx::query(
+            "INSERT INTO blobs (key, content_type, data) VALUES ($1, $2, $3) \
+             ON CONFLICT (key) DO UPDATE \
+             SET content_type = excluded.content_type, data = excluded.data",
+        )
+        .bind(key)
+        .bind(blob.content_type)
+        .bind(blob.data)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_blob(&self, key: String) -> Result<Option<Blob>, Error> {
+        let blob = sqlx::query_as::<_, (String, Vec<u8>)>(
+            "SELECT content_type, data FROM blobs WHERE key = $1",
+        )
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(blob.map(|(content_type, data)| Blob { content_type, data }))
+    }
+
+    async fn delete_blob(&self, key: String) -> Result<bool, Error> {
+        let deleted = sqlx::query("DELETE FROM blobs WHERE key = $1")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl HealthApi for SqlStore {
+    async fn ping(&self) -> Result<(), Error> {
+        let _result = sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdempotencyApi for SqlStore {
+    async fn reserve(
+        &self,
+        key: String,
+        request_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<IdempotencyRecord>, Error> {
+        let _deleted = sqlx::query(
+            "DELETE FROM idempotency_keys WHERE idempotency_key = $1 AND expires_at <= $2",
+        )
+        .bind(&key)
+        .bind(Utc::now().timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+        let inserted = sqlx::query(
+            "INSERT INTO idempotency_keys (idempotency_key, request_hash, expires_at) \
+             VALUES ($1, $2, $3) ON CONFLICT (idempotency_key) DO NOTHING",
+        )
+        .bind(&key)
+        .bind(&request_hash)
+        .bind(expires_at.timestamp_millis())
+        .execute(&self.pool)
+        .await?;
+        if inserted.rows_affected() == 1 {
+            return Ok(None);
+        }
+        let (request_hash, status, body, expires_at): (String, Option<i32>, Option<String>, i64) =
+            sqlx::query_as(
+                "SELECT request_hash, status, body, expires_at FROM idempotency_keys \
+                 WHERE idempotency_key = $1",
+            )
+            .bind(&key)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| anyhow!("Idempotency key {} was released concurrently", key))?;
+        let response = match (status, body) {
+            (Some(status), Some(body)) => Some(StoredResponse {
+                status: u16::try_from(status)?,
+                body,
+            }),
+            _ => None,
+        };
+        Ok(Some(IdempotencyRecord {
+            request_hash,
+            response,
+            expires_at: Utc
+                .timestamp_millis_opt(expires_at)
+                .single()
+                .ok_or_else(|| anyhow!("Invalid expiry of idempotency key {}", key))?,
+            key,
+        }))
+    }
+
+    async fn complete(&self, key: String, response: StoredResponse) -> Result<(), Error> {
+        let _updated = sqlx::query(
+            "UPDATE idempotency_keys SET status = $1, body = $2 WHERE idempotency_key = $3",
+        )
+        .bind(i32::from(response.status))
+        .bind(response.body)
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn release(&self, key: String) -> Result<(), Error> {
+        let _deleted = sqlx::query(
+            "DELETE FROM idempotency_keys WHERE idempotency_key = $1 AND status IS NULL",
+        )
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{connect, index_stored_users, SqlStore};
+    use crate::service::comment::{CommentsApi, CreateCommentSpec};
+    use crate::service::expense::{CreateExpenseSpec, ExpensesApi, ListExpensesRequest, User};
+
+    #[tokio::test]
+    async fn index_users_of_rows_stored_before() {
+        let pool = connect("sqlite::memory:").await.unwrap();
+        let store = SqlStore::new(pool.clone());
+        let alice = User {
+            id: Some("1".to_string()),
+            first_name: Some("Alice".to_string()),
+            ..User::default()
+        };
+        let created = store
+            .create_expense(CreateExpenseSpec {
+                cost: "10".to_string(),
+                group_id: "1".to_string(),
+                user: alice.clone(),
+                ..CreateExpenseSpec::default()
+            })
+            .await
+            .unwrap();
+        let expense_id = created.id.unwrap().to_hex();
+        let _comment = store
+            .create_comment(
+                expense_id,
+                CreateCommentSpec {
+                    content: "Paid".to_string(),
+                    user: alice,
+                },
+            )
+            .await
+            .unwrap();
+        let by_alice = || ListExpensesRequest {
+            user_id: Some("1".to_string()),
+            ..ListExpensesRequest::default()
+        };
+
+        // as stored before the users were indexed
+        for sql in [
+            "DELETE FROM expense_users",
+            "UPDATE expenses SET users_indexed = 0",
+            "UPDATE comments SET user_id = NULL",
+        ] {
+            let _updated = sqlx::query(sql).execute(&pool).await.unwrap();
+        }
+        let listed = store.list_expenses(by_alice()).await.unwrap();
+        assert!(listed.expenses.is_empty());
+
+        index_stored_users(&pool).await.unwrap();
+        let listed = store.list_expenses(by_alice()).await.unwrap();
+        assert_eq!(listed.expenses.len(), 1);
+        let comments = store.get_user_comments("1".to_string()).await.unwrap();
+        assert_eq!(comments.len(), 1);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::service::activity::{ActivityApi, ActivityApiMongoAdapter};
+use crate::service::balance::{BalanceApi, BalanceApiMongoAdapter};
+use crate::service::blob::{BlobStore, GridFsBlobStore};
+use crate::service::category::{CategoriesApi, CategoryApiMongoAdapter};
+use crate::service::comment::{CommentApiMongoAdapter, CommentsApi};
+use crate::service::events::EventBus;
+use crate::service::expense::{ExpenseApiMongoAdapter, ExpensesApi};
+use crate::service::group::{GroupApi, GroupApiMongoAdapter};
+use crate::service::health::{HealthApi, HealthMongoAdapter};
+use crate::service::history::{ExpenseHistoryApi, ExpenseHistoryMongoAdapter};
+use crate::service::idempotency::{IdempotencyApi, IdempotencyMongoAdapter};
+use crate::service::instrumented::Instrumented;
+use crate::service::memory::InMemoryStore;
+use crate::service::recurring::{RecurringExpensesApi, RecurringExpensesMongoAdapter};
+use crate::service::report::{ReportApiMongoAdapter, ReportsApi};
+#[cfg(feature = "sql")]
+use crate::service::sql::SqlStore;
+use crate::service::user::{UserApi, UserApiMongoAdapter};
+use mongodb::Database;
+use std::fmt;
+use std::sync::Arc;
+
+/// The services the API is built on. Routes only depend on the service traits, so the backend
+/// can be swapped without touching them.
+#[derive(Clone)]
+pub struct Storage {
+    pub expenses: Arc<dyn ExpensesApi + Send + Sync>,
+    pub groups: Arc<dyn GroupApi + Send + Sync>,
+    pub users: Arc<dyn UserApi + Send + Sync>,
+    pub balances: Arc<dyn BalanceApi + Send + Sync>,
+    pub comments: Arc<dyn CommentsApi + Send + Sync>,
+    pub categories: Arc<dyn CategoriesApi + Send + Sync>,
+    pub history: Arc<dyn ExpenseHistoryApi + Send + Sync>,
+    pub activity: Arc<dyn ActivityApi + Send + Sync>,
+    pub recurring: Arc<dyn RecurringExpensesApi + Send + Sync>,
+    pub reports: Arc<dyn ReportsApi + Send + Sync>,
+    pub idempotency: Arc<dyn IdempotencyApi + Send + Sync>,
+    pub health: Arc<dyn HealthApi + Send + Sync>,
+    pub blobs: Arc<dyn BlobStore + Send + Sync>,
+    pub events: EventBus,
+}
+
+impl Storage {
+    pub fn mongo(db: Database, events: EventBus) -> Self {
+        Self {
+            expenses: Arc::new(ExpenseApiMongoAdapter::new(db.clone()).with_events(events.clone())),
+            groups: Arc::new(GroupApiMongoAdapter::new(db.clone())),
+            users: Arc::new(UserApiMongoAdapter::new(db.clone())),
+            balances: Arc::new(BalanceApiMongoAdapter::new(db.clone())),
+            comments: Arc::new(CommentApiMongoAdapter::new(db.clone())),
+            categories: Arc::new(CategoryApiMongoAdapter::new(db.clone())),
+            history: Arc::new(ExpenseHistoryMongoAdapter::new(db.clone())),
+            activity: Arc::new(ActivityApiMongoAdapter::new(db.clone())),
+            recurring: Arc::new(
+                RecurringExpensesMongoAdapter::new(db.clone()).with_events(events.clone()),
+            ),
+            reports: Arc::new(ReportApiMongoAdapter::new(db.clone())),
+            idempotency: Arc::new(IdempotencyMongoAdapter::new(db.clone())),
+            health: Arc::new(HealthMongoAdapter::new(db.clone())),
+            blobs: Arc::new(GridFsBlobStore::new(db, "receipts")),
+            events,
+        }
+        .instrumented()
+    }
+
+    /// Storage which keeps everything in memory, for development and tests.
+    pub fn in_memory(events: EventBus) -> Self {
+        let store = InMemoryStore::new().with_events(events.clone());
+        Self {
+            expenses: Arc::new(store.clone()),
+            groups: Arc::new(store.clone()),
+            users: Arc::new(store.clone()),
+            balances: Arc::new(store.clone()),
+            comments: Arc::new(store.clone()),
+            categories: Arc::new(store.clone()),
+            history: Arc::new(store.clone()),
+            activity: Arc::new(store.clone()),
+            recurring: Arc::new(store.clone()),
+            reports: Arc::new(store.clone()),
+            idempotency: Arc::new(store.clone()),
+            health: Arc::new(store.clone()),
+            blobs: Arc::new(store),
+            events,
+        }
+        .instrumented()
+    }
+
+    /// Storage in the SQLite or PostgreSQL database behind `pool`, see [`crate::service::sql`].
+    #[cfg(feature = "sql")]
+    pub fn sql(pool: sqlx::AnyPool, events: EventBus) -> Self {
+        let store = SqlStore::new(pool).with_events(events.clone());
+        Self {
+            expenses: Arc::new(store.clone()),
+            groups: Arc::new(store.clone()),
+            users: Arc::new(store.clone()),
+            balances: Arc::new(store.clone()),
+            comments: Arc::new(store.clone()),
+            categories: Arc::new(store.clone()),
+            history: Arc::new(store.clone()),
+            activity: Arc::new(store.clone()),
+            recurring: Arc::new(store.clone()),
+            reports: Arc::new(store.clone()),
+            idempotency: Arc::new(store.clone()),
+            health: Arc::new(store.clone()),
+            blobs: Arc::new(store),
+            events,
+        }
+        .instrumented()
+    }
+
+    /// Keeps receipts in `blobs` rather than the database, e.g. a [`FileBlobStore`].
+    ///
+    /// [`FileBlobStore`]: crate::service::blob::FileBlobStore
+    pub fn with_blobs(self, blobs: Arc<dyn BlobStore + Send + Sync>) -> Self {
+        Self {
+            blobs: Arc::new(Instrumented::new("blobs", blobs)),
+            ..self
+        }
+    }
+
+    /// Records the latency of every call to the services, see [`crate::metrics`].
+    fn instrumented(self) -> Self {
+        Self {
+            expenses: Arc::new(Instrumented::new("expenses", self.expenses)),
+            groups: Arc::new(Instrumented::new("groups", self.groups)),
+            users: Arc::new(Instrumented::new("users", self.users)),
+            balances: Arc::new(Instrumented::new("balances", self.balances)),
+            comments: Arc::new(Instrumented::new("comments", self.comments)),
+            categories: Arc::new(Instrumented::new("categories", self.categories)),
+            history: Arc::new(Instrumented::new("history", self.history)),
+            activity: Arc::new(Instrumented::new("activity", self.activity)),
+            recurring: Arc::new(Instrumented::new("recurring", self.recurring)),
+            reports: Arc::new(Instrumented::new("reports", self.reports)),
+            idempotency: Arc::new(Instrumented::new("idempotency", self.idempotency)),
+            health: Arc::new(Instrumented::new("health", self.health)),
+            blobs: Arc::new(Instrumented::new("blobs", self.blobs)),
+            events: self.events,
+        }
+    }
+}
+
+impl fmt::Debug for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Storage")
+            .field("events", &self.events)
+            .finish_non_exhaustive()
+    }
+}
@@ -1,7 +1,10 @@
+use crate::service::{object_id, NotFound, DEFAULT_DATABASE_NAME};
 use anyhow::Error;
 use async_trait::async_trait;
-use mongodb::bson::doc;
+use mongodb::bson;
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use mongodb::Client;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
@@ -9,6 +12,15 @@ use std::str::FromStr;
 pub trait UserApi {
     async fn get_user(&self, id: i32) -> Result<User, Error>;
     async fn create_user(&self, user: CreateUserSpec) -> Result<String, Error>;
+
+    /// The user with `email`, compared case-insensitively.
+    async fn find_user_by_email(&self, email: String) -> Result<Option<User>, Error>;
+
+    /// The user with the hex id, `None` if there is none.
+    async fn find_user(&self, id: String) -> Result<Option<User>, Error>;
+
+    /// Deletes the profile of the user. Returns whether there was one.
+    async fn delete_user(&self, id: String) -> Result<bool, Error>;
 }
 
 #[derive(Debug)]
@@ -20,27 +32,63 @@ impl UserApiMongoAdapter {
     pub fn new(db: mongodb::Database) -> Self {
         Self { db }
     }
+
+    pub fn new_with(client: Client) -> Self {
+        Self::new(client.database(DEFAULT_DATABASE_NAME))
+    }
 }
 
 #[async_trait]
 impl UserApi for UserApiMongoAdapter {
     async fn get_user(&self, id: i32) -> Result<User, Error> {
         let collection = self.db.collection("users");
-        let object_id = ObjectId::from_str(&id.to_string())?;
-        let filter = doc! {"_id": object_id};
+        let filter = doc! {"_id": object_id("User", &id.to_string())?};
         let user = collection.find_one(filter, None).await?;
-        Ok(user.expect("User not found"))
+        Ok(user.ok_or_else(|| NotFound::new("User", id))?)
     }
 
     async fn create_user(&self, create_spec: CreateUserSpec) -> Result<String, Error> {
         let collection = self.db.collection("users");
         let user = User {
             id: None,
-            first_name: Some(create_spec.first_name),
-            ..User::default()
+            ..User::from(create_spec)
         };
         let user = collection.insert_one(user, None).await?;
-        Ok(user.inserted_id.to_string())
+        Ok(user
+            .inserted_id
+            .as_object_id()
+            .map_or_else(|| user.inserted_id.to_string(), |id| id.to_hex()))
+    }
+
+    async fn find_user_by_email(&self, email: String) -> Result<Option<User>, Error> {
+        let collection = self.db.collection::<Document>("users");
+        let filter = doc! {"email": email.to_lowercase()};
+        collection
+            .find_one(filter, None)
+            .await?
+            .map(User::from_document)
+            .transpose()
+    }
+
+    async fn find_user(&self, id: String) -> Result<Option<User>, Error> {
+        let Ok(object_id) = ObjectId::from_str(&id) else {
+            return Ok(None);
+        };
+        let collection = self.db.collection::<Document>("users");
+        collection
+            .find_one(doc! {"_id": object_id}, None)
+            .await?
+            .map(User::from_document)
+            .transpose()
+    }
+
+    async fn delete_user(&self, id: String) -> Result<bool, Error> {
+        let Ok(object_id) = ObjectId::from_str(&id) else {
+            return Ok(false);
+        };
+        let collection = self.db.collection::<Document>("users");
+        let deleted = collection.delete_one(doc! {"_id": object_id}, None).await?;
+        Ok(deleted.deleted_count > 0)
     }
 }
 
@@ -63,6 +111,17 @@ pub struct User {
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+impl User {
+    fn from_document(mut document: Document) -> Result<Self, Error> {
+        let id = document.remove("_id").and_then(|id| id.as_object_id());
+        let user: User = bson::from_document(document)?;
+        Ok(User {
+            id: id.map(|id| id.to_hex()),
+            ..user
+        })
+    }
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Balance {
     pub currency_code: Option<String>,
@@ -79,6 +138,22 @@ pub struct GroupBalance {
 #[derive(Default, Debug)]
 pub struct CreateUserSpec {
     pub first_name: String,
-    pub email: String,
+    pub email: Option<String>,
     pub default_currency: String,
 }
+
+/// User without an id, emails are stored in lower case so that they can be looked up exactly.
+impl From<CreateUserSpec> for User {
+    fn from(create_spec: CreateUserSpec) -> Self {
+        let non_empty = |value: String| Some(value).filter(|value| !value.is_empty());
+        Self {
+            first_name: Some(create_spec.first_name),
+            email: create_spec
+                .email
+                .map(|email| email.to_lowercase())
+                .and_then(non_empty),
+            default_currency: non_empty(create_spec.default_currency),
+            ..User::default()
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use mongodb::bson::Bson;
+
+/// Outcome of a change made on condition that the entity is still at the version the client
+/// read. Without an expected version the change is always applied.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Conditional<T> {
+    /// The change was made, holds the changed entity.
+    Applied(T),
+    /// The entity was changed since the expected version and is left as is, holds its current
+    /// state.
+    Stale(T),
+}
+
+impl<T> Conditional<T> {
+    /// The changed entity, or the current one if the change was not made.
+    pub fn into_inner(self) -> T {
+        match self {
+            Conditional::Applied(entity) | Conditional::Stale(entity) => entity,
+        }
+    }
+}
+
+/// Version of an entity stored before versioning was introduced.
+pub const UNVERSIONED: i64 = 0;
+
+/// Whether an entity at `version` may be changed by a client expecting `expected`.
+pub(crate) fn matches(expected: Option<i64>, version: Option<i64>) -> bool {
+    expected.is_none_or(|expected| expected == version.unwrap_or(UNVERSIONED))
+}
+
+/// Filter value matching the `version` field of documents at `version`, documents stored before
+/// versioning have no such field.
+pub(crate) fn version_filter(version: i64) -> Bson {
+    if version == UNVERSIONED {
+        Bson::Null
+    } else {
+        Bson::Int64(version)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::matches;
+
+    #[test]
+    fn match_expected_version() {
+        assert!(matches(None, Some(3)));
+        assert!(matches(Some(3), Some(3)));
+        assert!(matches(Some(0), None));
+        assert!(!matches(Some(2), Some(3)));
+        assert!(!matches(Some(1), None));
+    }
+}
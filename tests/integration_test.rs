@@ -3,18 +3,44 @@ use swc::service::expense::{CreateExpenseSpec, ExpenseApiMongoAdapter, ExpensesA
 use swc::service::user::{CreateUserSpec, UserApi, UserApiMongoAdapter};
 use testcontainers::{clients, images};
 
+#[macro_use]
+mod support;
+
 mod route {
+    mod account_it;
+    mod activity_it;
+    mod category_it;
+    mod comment_it;
     mod expense_it;
+    mod export_it;
     mod group_it;
+    mod health_it;
+    mod idempotency_it;
+    mod import_it;
+    mod metrics_it;
+    mod openapi_it;
+    mod precondition_it;
+    mod receipt_it;
+    mod report_it;
+    mod request_it;
 }
 mod service {
+    mod activity_it;
+    mod aggregate {
+        mod group_report_it;
+        mod user_spending_it;
+    }
+    mod comment_it;
     mod expense_it;
+    mod history_it;
+    mod migration_it;
+    mod recurring_it;
 }
 
 #[tokio::test]
 async fn calculation_split_equally_for_three_users() {
     let docker = clients::Cli::default();
-    let node = docker.run(images::mongo::Mongo::default());
+    let node = docker.run(images::mongo::Mongo);
     let host_port = node.get_host_port_ipv6(27017);
     let url = format!("mongodb://localhost:{}/", host_port);
     let database = mongodb::Client::with_uri_str(url)
@@ -36,6 +62,7 @@ async fn calculation_split_equally_for_three_users() {
                 first_name: Some("test".to_string()),
                 ..swc::service::expense::User::default()
             },
+            ..CreateExpenseSpec::default()
         })
         .await
         .unwrap();
@@ -53,7 +80,11 @@ async fn calculation_split_equally_for_three_users() {
     };
 
     expense_service
-        .update_expense(created_expense.id.unwrap().to_hex(), update_expense_spec)
+        .update_expense(
+            created_expense.id.unwrap().to_hex(),
+            update_expense_spec,
+            None,
+        )
         .await
         .unwrap();
 
@@ -0,0 +1,143 @@
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::account::{AccountDeletion, AccountExport, DELETED_USER_NAME};
+use swc::service::comment::CreateCommentSpec;
+use swc::service::expense::{Expense, ListExpensesRequest, ShareCalculator, User};
+use swc::service::group::{CreateGroupSpec, GroupUser};
+use swc::service::import::balances;
+use swc::service::storage::Storage;
+use swc::service::user::CreateUserSpec;
+use warp::test::request;
+
+storage_tests!(export_and_delete_account);
+
+async fn export_and_delete_account(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let bob_id = storage
+        .users
+        .create_user(CreateUserSpec {
+            first_name: "Bob".to_string(),
+            email: Some("bob@example.com".to_string()),
+            ..CreateUserSpec::default()
+        })
+        .await
+        .unwrap();
+    let bob = User {
+        id: Some(bob_id.clone()),
+        first_name: Some("Bob".to_string()),
+        email: Some("bob@example.com".to_string()),
+        ..User::default()
+    };
+    let group = storage
+        .groups
+        .create_group(CreateGroupSpec {
+            name: "Flat".to_string(),
+            users: Some(vec![
+                GroupUser {
+                    user_id: "1".to_string(),
+                    first_name: Some("Alice".to_string()),
+                },
+                GroupUser {
+                    user_id: bob_id.clone(),
+                    first_name: Some("Bob".to_string()),
+                },
+            ]),
+        })
+        .await
+        .unwrap();
+    let group_id = group.id.clone().expect("Group has no id");
+    let imported = storage
+        .expenses
+        .import_expenses(vec![Expense {
+            cost: Some("30.00".to_string()),
+            description: Some("Rent".to_string()),
+            group_id: Some(group_id.clone()),
+            created_by: Some(bob.clone()),
+            users: Some(ShareCalculator::new().equal_share(
+                "30.00".to_string(),
+                bob_id.clone(),
+                vec!["1".to_string(), bob_id.clone()],
+            )),
+            ..Expense::default()
+        }])
+        .await
+        .unwrap();
+    let expense_id = imported[0].id.expect("Expense has no id").to_hex();
+    let _comment = storage
+        .comments
+        .create_comment(
+            expense_id.clone(),
+            CreateCommentSpec {
+                content: "Paid".to_string(),
+                user: bob,
+            },
+        )
+        .await
+        .unwrap();
+    let group_expenses = || async {
+        storage
+            .expenses
+            .list_expenses(ListExpensesRequest {
+                group_id: Some(group_id.clone()),
+                ..ListExpensesRequest::default()
+            })
+            .await
+            .unwrap()
+            .expenses
+    };
+    let before = balances(&group, &group_expenses().await).unwrap();
+
+    let export_path = format!("/users/{}/export", bob_id);
+    let res = request().path(&export_path).reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let account: AccountExport = serde_json::from_slice(res.body()).unwrap();
+    let profile = account.profile.expect("Profile is not exported");
+    assert_eq!(profile.email.as_deref(), Some("bob@example.com"));
+    assert_eq!(account.groups.len(), 1);
+    assert_eq!(account.expenses.len(), 1);
+    assert_eq!(account.comments[0].content, "Paid");
+    assert!(!account.activity.is_empty());
+
+    let res = request()
+        .method("DELETE")
+        .path(&format!("/users/{}", bob_id))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    let deletion: AccountDeletion = serde_json::from_slice(res.body()).unwrap();
+    assert!(deletion.profile_deleted);
+    assert_eq!((deletion.groups, deletion.expenses), (1, 1));
+    assert_eq!(
+        (deletion.comments, deletion.revisions, deletion.activities),
+        (1, 1, 1)
+    );
+
+    let group = storage.groups.get_group(group_id.clone()).await.unwrap();
+    let members = group.members.clone().unwrap();
+    assert_eq!(members[1].id.as_deref(), Some(bob_id.as_str()));
+    assert_eq!(members[1].first_name.as_deref(), Some(DELETED_USER_NAME));
+    let expenses = group_expenses().await;
+    let created_by = expenses[0].created_by.clone().unwrap();
+    assert_eq!(created_by.first_name.as_deref(), Some(DELETED_USER_NAME));
+    assert_eq!(created_by.email, None);
+    let after = balances(&group, &expenses).unwrap();
+    assert_eq!(after[0], before[0]);
+    assert_eq!(after[1].amount, before[1].amount);
+    assert_eq!(after[1].name, DELETED_USER_NAME);
+
+    let res = request().path(&export_path).reply(&api).await;
+    let export = String::from_utf8(res.body().to_vec()).unwrap();
+    let account: AccountExport = serde_json::from_str(&export).unwrap();
+    assert!(account.profile.is_none());
+    assert_eq!(account.comments[0].content, "Paid");
+    assert!(!account.activity.is_empty());
+    let history_path = format!("/expenses/{}/history", expense_id);
+    let res = request().path(&history_path).reply(&api).await;
+    let history = String::from_utf8(res.body().to_vec()).unwrap();
+    for data in [export, history] {
+        assert!(!data.contains("Bob"), "{}", data);
+        assert!(!data.contains("bob@example.com"), "{}", data);
+    }
+    let res = request().path("/users/unknown/export").reply(&api).await;
+    assert_eq!(res.status(), 404);
+}
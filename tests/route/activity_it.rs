@@ -0,0 +1,73 @@
+use crate::support::alice;
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::activity::ActivityPage;
+use swc::service::expense::CreateExpenseSpec;
+use swc::service::storage::Storage;
+use warp::test::request;
+
+storage_tests!(mark_activity_read);
+
+async fn mark_activity_read(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let create_expense = |description: &str| CreateExpenseSpec {
+        cost: "10".to_string(),
+        group_id: "1".to_string(),
+        user: alice(),
+        description: Some(description.to_string()),
+        ..CreateExpenseSpec::default()
+    };
+    let _created = storage
+        .expenses
+        .create_expense(create_expense("Lunch"))
+        .await
+        .unwrap();
+    let res = request()
+        .method("PUT")
+        .path("/users/1/activity/read")
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 204);
+    let _created = storage
+        .expenses
+        .create_expense(create_expense("Dinner"))
+        .await
+        .unwrap();
+
+    let res = request().path("/users/1/activity").reply(&api).await;
+    let page: ActivityPage = serde_json::from_slice(res.body()).unwrap();
+    let unread = page
+        .activities
+        .iter()
+        .map(|activity| (activity.summary.as_str(), activity.unread))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        unread,
+        vec![
+            ("Alice added Dinner 10", Some(true)),
+            ("Alice added Lunch 10", Some(false))
+        ]
+    );
+
+    let res = request()
+        .path("/users/1/activity?limit=1")
+        .reply(&api)
+        .await;
+    let page: ActivityPage = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(page.activities.len(), 1);
+    assert!(page.next_cursor.is_some());
+    for limit in [-1, 0, 101] {
+        let res = request()
+            .path(&format!("/groups/1/activity?limit={}", limit))
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), 400, "limit {}", limit);
+    }
+    let res = request()
+        .path("/users/1/activity?cursor=nope")
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 400);
+    let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(error["error"], "Invalid cursor \"nope\"");
+}
@@ -0,0 +1,146 @@
+use crate::support::alice;
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::category::{Category, CreateCategorySpec};
+use swc::service::expense::{CreateExpenseSpec, Expense, ExpenseEntity, UpdateExpenseSpec};
+use swc::service::group::CreateGroupSpec;
+use swc::service::storage::Storage;
+use warp::test::request;
+
+storage_tests!(categorise_expenses, keep_category_names_unique);
+
+/// Path of the categories of a new group.
+async fn group_categories_path(storage: &Storage) -> String {
+    let group = storage
+        .groups
+        .create_group(CreateGroupSpec {
+            name: "Flat".to_string(),
+            users: None,
+        })
+        .await
+        .unwrap();
+    format!("/groups/{}/categories", group.id.expect("Group has no id"))
+}
+
+async fn categorise_expenses(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let path = group_categories_path(&storage).await;
+    let group_id = path.split('/').nth(2).unwrap().to_string();
+    let res = request().path("/categories").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let defaults: Vec<Category> = serde_json::from_slice(res.body()).unwrap();
+    let groceries = defaults
+        .iter()
+        .find(|category| category.id == "groceries")
+        .unwrap();
+    assert_eq!(groceries.parent_id.as_deref(), Some("food"));
+
+    let create_category = |name: &str, parent_id: Option<&str>| {
+        request()
+            .method("POST")
+            .path(&path)
+            .json(&CreateCategorySpec {
+                name: name.to_string(),
+                parent_id: parent_id.map(String::from),
+            })
+    };
+    let res = create_category("Pets", None).reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let pets: Category = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(pets.group_id.as_deref(), Some(group_id.as_str()));
+    let res = create_category("Vet", Some(&pets.id)).reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let vet: Category = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(
+        create_category("pets", None).reply(&api).await.status(),
+        400
+    );
+    let res = create_category("Vaccines", Some(&vet.id)).reply(&api).await;
+    assert_eq!(res.status(), 400);
+
+    let res = request()
+        .path(&format!("/categories?groupId={}", group_id))
+        .reply(&api)
+        .await;
+    let categories: Vec<Category> = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(categories.len(), defaults.len() + 2);
+    assert_eq!(categories[defaults.len()..], [pets, vet.clone()]);
+    let res = request().path("/categories?groupId=2").reply(&api).await;
+    let categories: Vec<Category> = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(categories.len(), defaults.len());
+
+    let create_expense = |group_id: &str, category: &str| {
+        request()
+            .method("POST")
+            .path("/expenses")
+            .json(&CreateExpenseSpec {
+                cost: "20".to_string(),
+                group_id: group_id.to_string(),
+                user: alice(),
+                category: Some(category.to_string()),
+                ..CreateExpenseSpec::default()
+            })
+    };
+    assert_eq!(
+        create_expense(&group_id, "unknown")
+            .reply(&api)
+            .await
+            .status(),
+        400
+    );
+    assert_eq!(create_expense("2", &vet.id).reply(&api).await.status(), 400);
+    let res = create_expense(&group_id, "groceries").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let created: ExpenseEntity = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(created.expense.category.as_deref(), Some("groceries"));
+    let expense_path = format!(
+        "/expenses/{}",
+        created.id.expect("Expense has no id").to_hex()
+    );
+
+    let update = |category: &str| {
+        request()
+            .method("PATCH")
+            .path(&expense_path)
+            .header("if-match", "*")
+            .json(&UpdateExpenseSpec {
+                category: Some(category.to_string()),
+                updated_by: Some(alice()),
+                ..UpdateExpenseSpec::default()
+            })
+    };
+    assert_eq!(update("unknown").reply(&api).await.status(), 400);
+    let res = update(&vet.id).reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let expense: Expense = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(expense.category, Some(vet.id));
+}
+
+async fn keep_category_names_unique(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let create_category = |path: &str, name: &str| {
+        request()
+            .method("POST")
+            .path(path)
+            .json(&CreateCategorySpec {
+                name: name.to_string(),
+                parent_id: None,
+            })
+    };
+    let res = create_category("/groups/nope/categories", "Pets")
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 404);
+
+    let path = group_categories_path(&storage).await;
+    let creates = ["Garden", "garden", "GARDEN", "Garden "]
+        .into_iter()
+        .map(|name| create_category(&path, name).reply(&api));
+    let statuses = futures::future::join_all(creates)
+        .await
+        .iter()
+        .map(|res| res.status().as_u16())
+        .collect::<Vec<_>>();
+    assert_eq!(statuses.iter().filter(|status| **status == 200).count(), 1);
+    assert_eq!(statuses.iter().filter(|status| **status == 400).count(), 3);
+}
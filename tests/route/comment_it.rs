@@ -0,0 +1,104 @@
+use crate::support::alice;
+use mongodb::Client;
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::comment::{Comment, CreateCommentSpec};
+use swc::service::events::EventBus;
+use swc::service::expense::{CreateExpenseSpec, ExpenseEntity, User};
+use swc::service::storage::Storage;
+use testcontainers::{clients, images};
+use warp::test::request;
+
+storage_tests!(reject_comments_on_unknown_expenses);
+
+#[tokio::test]
+async fn delete_missing_comment() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
+    let host_port = node.get_host_port_ipv6(27017);
+    let url = format!("mongodb://localhost:{}/", host_port);
+    let client = Client::with_uri_str(url)
+        .await
+        .expect("Failed to connect to mongo");
+    let res = request()
+        .method("DELETE")
+        .path("/comments/635d2a5f0b6a4c3e9c8f1a2b")
+        .reply(&routes(
+            Storage::mongo(client.database("swc"), EventBus::default()),
+            &ServerConfig::default(),
+        ))
+        .await;
+    assert_eq!(res.status(), 404);
+}
+
+#[tokio::test]
+async fn create_comment() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
+    let host_port = node.get_host_port_ipv6(27017);
+    let url = format!("mongodb://localhost:{}/", host_port);
+    let client = Client::with_uri_str(url)
+        .await
+        .expect("Failed to connect to mongo");
+    let api = routes(
+        Storage::mongo(client.database("swc"), EventBus::default()),
+        &ServerConfig::default(),
+    );
+    let user = User {
+        id: Some("1234".to_string()),
+        ..User::default()
+    };
+    let create_comment_spec = CreateCommentSpec {
+        content: "was this the dinner on Friday?".to_string(),
+        user: user.clone(),
+    };
+    let res = request()
+        .method("POST")
+        .path("/expenses/635d2a5f0b6a4c3e9c8f1a2b/comments")
+        .json(&create_comment_spec)
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 404);
+
+    let res = request()
+        .method("POST")
+        .path("/expenses")
+        .json(&CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user,
+            ..CreateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    let expense: ExpenseEntity = serde_json::from_slice(res.body()).unwrap();
+    let res = request()
+        .method("POST")
+        .path(&format!(
+            "/expenses/{}/comments",
+            expense.id.expect("Expense has no id").to_hex()
+        ))
+        .json(&create_comment_spec)
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+}
+
+async fn reject_comments_on_unknown_expenses(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let res = request()
+        .method("POST")
+        .path("/expenses/nope/comments")
+        .json(&CreateCommentSpec {
+            content: "Who paid?".to_string(),
+            user: alice(),
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 404);
+
+    let res = request().path("/expenses/nope/comments").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let comments: Vec<Comment> = serde_json::from_slice(res.body()).unwrap();
+    assert!(comments.is_empty());
+}
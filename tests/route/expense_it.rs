@@ -1,18 +1,67 @@
+use crate::support::alice;
 use mongodb::Client;
+use swc::config::ServerConfig;
 use swc::route::routes;
-use swc::service::expense::{CreateExpenseSpec, User};
+use swc::service::comment::{Comment, CommentType};
+use swc::service::events::{EventBus, GroupEventType};
+use swc::service::expense::{
+    CreateExpenseSpec, Expense, ExpenseEntity, ItemizedSplit, LineItem, UpdateExpenseSpec, User,
+    UserShare,
+};
+use swc::service::history::{ExpenseAction, ExpenseRevision};
+use swc::service::storage::Storage;
 use testcontainers::{clients, images};
 use warp::test::request;
 
+storage_tests!(
+    update_expense_records_history_and_comment,
+    reject_invalid_expenses,
+    number_concurrent_revisions,
+    split_itemized_bill,
+    answer_not_found_for_unknown_expenses
+);
+
 #[tokio::test]
 async fn create_expense() {
     let docker = clients::Cli::default();
-    let node = docker.run(images::mongo::Mongo::default());
+    let node = docker.run(images::mongo::Mongo);
+    let host_port = node.get_host_port_ipv6(27017);
+    let url = format!("mongodb://localhost:{}/", host_port);
+    let client = Client::with_uri_str(url)
+        .await
+        .expect("Failed to connect to mongo");
+    let create_expense_spec = CreateExpenseSpec {
+        cost: "30.00".to_string(),
+        group_id: "1234".to_string(),
+        user: User {
+            id: Some("1234".to_string()),
+            ..User::default()
+        },
+        ..CreateExpenseSpec::default()
+    };
+    let res = request()
+        .method("POST")
+        .path("/expenses")
+        .json(&create_expense_spec)
+        .reply(&routes(
+            Storage::mongo(client.database("swc"), EventBus::default()),
+            &ServerConfig::default(),
+        ))
+        .await;
+    assert_eq!(res.status(), 200);
+}
+
+#[tokio::test]
+async fn publish_created_expense() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
     let host_port = node.get_host_port_ipv6(27017);
     let url = format!("mongodb://localhost:{}/", host_port);
     let client = Client::with_uri_str(url)
         .await
         .expect("Failed to connect to mongo");
+    let events = EventBus::default();
+    let mut receiver = events.subscribe();
     let create_expense_spec = CreateExpenseSpec {
         cost: "30.00".to_string(),
         group_id: "1234".to_string(),
@@ -20,12 +69,326 @@ async fn create_expense() {
             id: Some("1234".to_string()),
             ..User::default()
         },
+        ..CreateExpenseSpec::default()
     };
     let res = request()
         .method("POST")
         .path("/expenses")
         .json(&create_expense_spec)
-        .reply(&routes(client))
+        .reply(&routes(
+            Storage::mongo(client.database("swc"), events),
+            &ServerConfig::default(),
+        ))
         .await;
     assert_eq!(res.status(), 200);
+    let event = receiver.recv().await.expect("No event published");
+    assert_eq!(event.event_type, GroupEventType::ExpenseCreated);
+    assert_eq!(event.group_id, "1234");
+    let event = receiver.recv().await.expect("No event published");
+    assert_eq!(event.event_type, GroupEventType::BalanceChanged);
+}
+
+async fn update_expense_records_history_and_comment(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let res = request()
+        .method("POST")
+        .path("/expenses")
+        .json(&CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: alice(),
+            ..CreateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    let created: ExpenseEntity = serde_json::from_slice(res.body()).unwrap();
+    let expense_id = created.id.expect("Expense has no id").to_hex();
+
+    let res = request()
+        .method("PATCH")
+        .path(&format!("/expenses/{}", expense_id))
+        .header("if-match", "\"1\"")
+        .json(&UpdateExpenseSpec {
+            cost: Some("30".to_string()),
+            updated_by: Some(alice()),
+            ..UpdateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    let expense: Expense = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(expense.cost, Some("30".to_string()));
+
+    let res = request()
+        .path(&format!("/expenses/{}/history", expense_id))
+        .reply(&api)
+        .await;
+    let history: Vec<ExpenseRevision> = serde_json::from_slice(res.body()).unwrap();
+    let actions = history
+        .iter()
+        .map(|revision| revision.action)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        actions,
+        vec![ExpenseAction::Created, ExpenseAction::Updated]
+    );
+
+    let res = request()
+        .path(&format!("/expenses/{}/comments", expense_id))
+        .reply(&api)
+        .await;
+    let comments: Vec<Comment> = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(comments.len(), 1);
+    assert_eq!(comments[0].comment_type, CommentType::System);
+    assert_eq!(comments[0].content, "Updated cost from 100 to 30");
+}
+
+async fn reject_invalid_expenses(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let create = |cost: &str, user: User| {
+        request()
+            .method("POST")
+            .path("/expenses")
+            .json(&CreateExpenseSpec {
+                cost: cost.to_string(),
+                group_id: "1".to_string(),
+                user,
+                ..CreateExpenseSpec::default()
+            })
+    };
+    for cost in ["abc", "0.00", "-5", "1.234"] {
+        let res = create(cost, alice()).reply(&api).await;
+        assert_eq!(res.status(), 400, "{}", cost);
+        let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert!(error["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid expense"));
+    }
+    let res = create("10", User::default()).reply(&api).await;
+    assert_eq!(res.status(), 400);
+    let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(
+        error["error"],
+        "Invalid expense: The user paying the expense has no id"
+    );
+
+    let res = create("10", alice()).reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let expense: ExpenseEntity = serde_json::from_slice(res.body()).unwrap();
+    let res = request()
+        .method("PATCH")
+        .path(&format!("/expenses/{}", expense.id.unwrap().to_hex()))
+        .header("if-match", "*")
+        .json(&UpdateExpenseSpec {
+            cost: Some("abc".to_string()),
+            updated_by: Some(alice()),
+            ..UpdateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 400);
+}
+
+async fn number_concurrent_revisions(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let res = request()
+        .method("POST")
+        .path("/expenses")
+        .json(&CreateExpenseSpec {
+            cost: "10".to_string(),
+            group_id: "1".to_string(),
+            user: alice(),
+            ..CreateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    let created: ExpenseEntity = serde_json::from_slice(res.body()).unwrap();
+    let path = format!(
+        "/expenses/{}",
+        created.id.expect("Expense has no id").to_hex()
+    );
+    let updates = (1..=8).map(|cost| {
+        request()
+            .method("PATCH")
+            .path(&path)
+            .header("if-match", "*")
+            .json(&UpdateExpenseSpec {
+                cost: Some(cost.to_string()),
+                updated_by: Some(alice()),
+                ..UpdateExpenseSpec::default()
+            })
+            .reply(&api)
+    });
+    for res in futures::future::join_all(updates).await {
+        assert_eq!(res.status(), 200);
+    }
+
+    let res = request().path(&path).reply(&api).await;
+    let expense: Expense = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(expense.version, Some(9));
+    let res = request()
+        .path(&format!("{}/history", path))
+        .reply(&api)
+        .await;
+    let history: Vec<ExpenseRevision> = serde_json::from_slice(res.body()).unwrap();
+    let versions = history
+        .iter()
+        .map(|revision| revision.version)
+        .collect::<Vec<_>>();
+    assert_eq!(versions, (1..=9).collect::<Vec<_>>());
+}
+
+async fn split_itemized_bill(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let item = |name: &str, price: &str, user_ids: &[&str]| LineItem {
+        name: name.to_string(),
+        price: price.to_string(),
+        user_ids: user_ids.iter().map(|id| id.to_string()).collect(),
+    };
+    let owed = |expense: &Expense, user_id: &str| {
+        expense
+            .share_of(user_id)
+            .and_then(|share| share.owed_share.clone())
+    };
+    let itemized = ItemizedSplit {
+        items: vec![
+            item("Burger", "15.00", &["1"]),
+            item("Salad", "10.00", &["2"]),
+            item("Fries", "5.00", &["1", "2"]),
+        ],
+        tax: Some("3.00".to_string()),
+        tip: Some("4.50".to_string()),
+    };
+    let create = |cost: &str| {
+        request()
+            .method("POST")
+            .path("/expenses")
+            .json(&CreateExpenseSpec {
+                cost: cost.to_string(),
+                group_id: "1".to_string(),
+                user: alice(),
+                itemized: Some(itemized.clone()),
+                ..CreateExpenseSpec::default()
+            })
+    };
+    let res = create("30.00").reply(&api).await;
+    assert_eq!(res.status(), 400);
+    let res = create("37.50").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let created: ExpenseEntity = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(owed(&created.expense, "1").as_deref(), Some("21.88"));
+    assert_eq!(owed(&created.expense, "2").as_deref(), Some("15.62"));
+    let path = format!(
+        "/expenses/{}",
+        created.id.expect("Expense has no id").to_hex()
+    );
+
+    // Bob had the fries alone and a dessert
+    let res = request()
+        .method("PATCH")
+        .path(&path)
+        .header("if-match", "*")
+        .json(&UpdateExpenseSpec {
+            updated_by: Some(alice()),
+            itemized: Some(ItemizedSplit {
+                items: vec![
+                    item("Burger", "15.00", &["1"]),
+                    item("Salad", "10.00", &["2"]),
+                    item("Fries", "5.00", &["2"]),
+                    item("Cake", "6.00", &["2"]),
+                ],
+                tax: Some("3.60".to_string()),
+                tip: None,
+            }),
+            ..UpdateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+
+    let res = request().path(&path).reply(&api).await;
+    let expense: Expense = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(expense.cost.as_deref(), Some("39.60"));
+    assert_eq!(owed(&expense, "1").as_deref(), Some("16.50"));
+    assert_eq!(owed(&expense, "2").as_deref(), Some("23.10"));
+    let alice_share = expense.share_of("1").unwrap();
+    assert_eq!(alice_share.paid_share.as_deref(), Some("39.60"));
+    assert_eq!(alice_share.net_balance.as_deref(), Some("23.10"));
+    assert_eq!(
+        expense.itemized.map(|itemized| itemized.items.len()),
+        Some(4)
+    );
+
+    // line items can't be split with several payers
+    let paid = |id: &str| UserShare {
+        user: Some(User {
+            id: Some(id.to_string()),
+            ..User::default()
+        }),
+        paid_share: Some("20.00".to_string()),
+        owed_share: Some("20.00".to_string()),
+        net_balance: Some("0.00".to_string()),
+    };
+    let imported = storage
+        .expenses
+        .import_expenses(vec![Expense {
+            cost: Some("40.00".to_string()),
+            group_id: Some("1".to_string()),
+            users: Some(vec![paid("1"), paid("2")]),
+            ..Expense::default()
+        }])
+        .await
+        .unwrap();
+    let res = request()
+        .method("PATCH")
+        .path(&format!(
+            "/expenses/{}",
+            imported[0].id.expect("Expense has no id").to_hex()
+        ))
+        .header("if-match", "*")
+        .json(&UpdateExpenseSpec {
+            updated_by: Some(alice()),
+            itemized: Some(itemized),
+            ..UpdateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 400);
+    let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(
+        error["error"],
+        "Invalid split: Itemized expenses must be paid by a single user"
+    );
+}
+
+async fn answer_not_found_for_unknown_expenses(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let res = request().path("/expenses/nope").reply(&api).await;
+    assert_eq!(res.status(), 404);
+    let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(error["error"], "Expense nope not found");
+
+    let res = request()
+        .method("PATCH")
+        .path("/expenses/nope")
+        .header("if-match", "*")
+        .json(&UpdateExpenseSpec {
+            description: Some("Dinner".to_string()),
+            updated_by: Some(alice()),
+            ..UpdateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 404);
+
+    let res = request()
+        .method("DELETE")
+        .path("/expenses/nope")
+        .header("if-match", "*")
+        .json(&alice())
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 404);
 }
@@ -0,0 +1,93 @@
+use crate::support::alice;
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::expense::CreateExpenseSpec;
+use swc::service::group::{CreateGroupSpec, GroupUser};
+use swc::service::storage::Storage;
+use warp::test::request;
+
+storage_tests!(export_csv);
+
+async fn export_csv(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let group = storage
+        .groups
+        .create_group(CreateGroupSpec {
+            name: "Flat".to_string(),
+            users: Some(vec![GroupUser {
+                user_id: "1".to_string(),
+                first_name: Some("Alice".to_string()),
+            }]),
+        })
+        .await
+        .unwrap();
+    let group_id = group.id.expect("Group has no id");
+    let _created = storage
+        .expenses
+        .create_expense(CreateExpenseSpec {
+            cost: "12.50".to_string(),
+            group_id: group_id.clone(),
+            user: alice(),
+            description: Some("Soap".to_string()),
+            ..CreateExpenseSpec::default()
+        })
+        .await
+        .unwrap();
+
+    let res = request()
+        .path(&format!("/groups/{}/export.csv", group_id))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["content-type"], "text/csv; charset=utf-8");
+    let csv = String::from_utf8(res.body().to_vec()).unwrap();
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines[0],
+        "Date,Description,Cost,Currency,Paid by,Alice owed,Alice net"
+    );
+    assert!(
+        lines[1].ends_with(",Soap,12.50,,Alice,12.50,0.00"),
+        "{}",
+        csv
+    );
+    assert_eq!(lines[3..], ["Member,Currency,Balance", "Alice,,0.00"]);
+
+    let res = request()
+        .path(&format!(
+            "/groups/{}/export.csv?datedAfter=2999-01-01T00:00:00Z",
+            group_id
+        ))
+        .reply(&api)
+        .await;
+    let csv = String::from_utf8(res.body().to_vec()).unwrap();
+    assert_eq!(csv.lines().count(), 3, "{}", csv);
+
+    let res = request().path("/groups/nope/export.csv").reply(&api).await;
+    assert_eq!(res.status(), 404);
+
+    let res = request().path("/users/1/export.csv").reply(&api).await;
+    let csv = String::from_utf8(res.body().to_vec()).unwrap();
+    assert!(csv.contains(&format!(",Soap,12.50,,Alice,{},12.50,12.50,0.00", group_id)));
+
+    let res = request()
+        .method("POST")
+        .path("/users/1/export.ledger")
+        .json(&serde_json::json!({"format": "beancount", "fundingAccount": "Assets:Bank"}))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(
+        res.headers()["content-disposition"],
+        "attachment; filename=\"user-1.beancount\""
+    );
+    let journal = String::from_utf8(res.body().to_vec()).unwrap();
+    assert!(journal.contains("* \"Soap\""), "{}", journal);
+    let posted = |posting: [&str; 3]| {
+        journal
+            .lines()
+            .any(|line| line.split_whitespace().eq(posting))
+    };
+    assert!(posted(["Expenses:Shared", "12.50", "USD"]), "{}", journal);
+    assert!(posted(["Assets:Bank", "-12.50", "USD"]), "{}", journal);
+}
@@ -1,13 +1,23 @@
 use mongodb::Client;
+use swc::config::ServerConfig;
 use swc::route::routes;
-use swc::service::group::{CreateGroupSpec, GroupUser};
+use swc::service::activity::ActivityPage;
+use swc::service::events::EventBus;
+use swc::service::group::{CreateGroupSpec, Group, GroupUser};
+use swc::service::storage::Storage;
 use testcontainers::{clients, images};
 use warp::test::request;
 
+storage_tests!(
+    create_group_and_add_member,
+    answer_not_found_for_unknown_groups,
+    keep_concurrently_joined_members,
+);
+
 #[tokio::test]
 async fn create_group() {
     let docker = clients::Cli::default();
-    let node = docker.run(images::mongo::Mongo::default());
+    let node = docker.run(images::mongo::Mongo);
     let host_port = node.get_host_port_ipv6(27017);
     let url = format!("mongodb://localhost:{}/", host_port);
     let client = Client::with_uri_str(url)
@@ -21,7 +31,111 @@ async fn create_group() {
         .method("POST")
         .path("/groups")
         .json(&create_group_spec)
-        .reply(&routes(client))
+        .reply(&routes(
+            Storage::mongo(client.database("swc"), EventBus::default()),
+            &ServerConfig::default(),
+        ))
+        .await;
+    assert_eq!(res.status(), 200);
+}
+
+async fn create_group_and_add_member(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let res = request()
+        .method("POST")
+        .path("/groups")
+        .json(&CreateGroupSpec {
+            name: "Flat".to_string(),
+            users: Some(vec![GroupUser {
+                user_id: "1".to_string(),
+                first_name: Some("Alice".to_string()),
+            }]),
+        })
+        .reply(&api)
         .await;
     assert_eq!(res.status(), 200);
+    let group: Group = serde_json::from_slice(res.body()).unwrap();
+    let group_id = group.id.expect("Group has no id");
+
+    let res = request()
+        .method("POST")
+        .path(&format!("/groups/{}/members", group_id))
+        .json(&GroupUser {
+            user_id: "2".to_string(),
+            first_name: Some("Bob".to_string()),
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    let group: Group = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(group.member_ids(), vec!["1".to_string(), "2".to_string()]);
+
+    let res = request()
+        .path(&format!("/groups/{}/activity", group_id))
+        .reply(&api)
+        .await;
+    let page: ActivityPage = serde_json::from_slice(res.body()).unwrap();
+    let summaries = page
+        .activities
+        .iter()
+        .map(|activity| activity.summary.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["Bob joined", "Group Flat was created"]);
+}
+
+async fn answer_not_found_for_unknown_groups(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let res = request().path("/groups/nope").reply(&api).await;
+    assert_eq!(res.status(), 404);
+    let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(error["error"], "Group nope not found");
+
+    let res = request()
+        .method("POST")
+        .path("/groups/nope/members")
+        .json(&GroupUser {
+            user_id: "2".to_string(),
+            first_name: Some("Bob".to_string()),
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 404);
+}
+
+async fn keep_concurrently_joined_members(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let res = request()
+        .method("POST")
+        .path("/groups")
+        .json(&CreateGroupSpec {
+            name: "Flat".to_string(),
+            users: None,
+        })
+        .reply(&api)
+        .await;
+    let group: Group = serde_json::from_slice(res.body()).unwrap();
+    let group_path = format!("/groups/{}", group.id.expect("Group has no id"));
+    let joins = (1..=8).map(|user_id| {
+        request()
+            .method("POST")
+            .path(&format!("{}/members", group_path))
+            .json(&GroupUser {
+                user_id: user_id.to_string(),
+                first_name: None,
+            })
+            .reply(&api)
+    });
+    for res in futures::future::join_all(joins).await {
+        assert_eq!(res.status(), 200);
+    }
+
+    let res = request().path(&group_path).reply(&api).await;
+    let group: Group = serde_json::from_slice(res.body()).unwrap();
+    let mut member_ids = group.member_ids();
+    member_ids.sort();
+    assert_eq!(
+        member_ids,
+        (1..=8).map(|id| id.to_string()).collect::<Vec<_>>()
+    );
+    assert_eq!(group.version, Some(9));
 }
@@ -0,0 +1,14 @@
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::storage::Storage;
+use warp::test::request;
+
+storage_tests!(report_ready);
+
+async fn report_ready(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    for path in ["/health", "/health/live", "/health/ready"] {
+        let res = request().path(path).reply(&api).await;
+        assert_eq!(res.status(), 200, "{}", path);
+    }
+}
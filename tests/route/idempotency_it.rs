@@ -0,0 +1,50 @@
+use crate::support::alice;
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::expense::CreateExpenseSpec;
+use swc::service::group::{CreateGroupSpec, Group};
+use swc::service::storage::Storage;
+use warp::test::request;
+
+storage_tests!(replay_idempotent_create);
+
+async fn replay_idempotent_create(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let create_group = |name: &str| {
+        request()
+            .method("POST")
+            .path("/groups")
+            .header("idempotency-key", "create-flat")
+            .json(&CreateGroupSpec {
+                name: name.to_string(),
+                users: None,
+            })
+    };
+    let res = create_group("Flat").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    assert!(res.headers().get("idempotent-replayed").is_none());
+    let created: Group = serde_json::from_slice(res.body()).unwrap();
+
+    let res = create_group("Flat").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["idempotent-replayed"], "true");
+    let replayed: Group = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(replayed.id, created.id);
+
+    let res = create_group("Trip").reply(&api).await;
+    assert_eq!(res.status(), 409);
+
+    let res = request()
+        .method("POST")
+        .path("/expenses")
+        .header("idempotency-key", "create-flat")
+        .json(&CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: alice(),
+            ..CreateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 409);
+}
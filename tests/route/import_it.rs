@@ -0,0 +1,234 @@
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::expense::ListExpensesRequest;
+use swc::service::group::{CreateGroupSpec, GroupUser};
+use swc::service::import::ImportReport;
+use swc::service::splitwise::SplitwiseImportReport;
+use swc::service::storage::Storage;
+use swc::service::user::CreateUserSpec;
+use warp::test::request;
+
+storage_tests!(import_csv, import_splitwise);
+
+async fn import_csv(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let group = storage
+        .groups
+        .create_group(CreateGroupSpec {
+            name: "Flat".to_string(),
+            users: Some(vec![
+                GroupUser {
+                    user_id: "1".to_string(),
+                    first_name: Some("Alice".to_string()),
+                },
+                GroupUser {
+                    user_id: "2".to_string(),
+                    first_name: Some("Bob".to_string()),
+                },
+            ]),
+        })
+        .await
+        .unwrap();
+    let group_id = group.id.expect("Group has no id");
+    let import = |query: &str, csv: &str| {
+        request()
+            .method("POST")
+            .path(&format!("/groups/{}/import?{}", group_id, query))
+            .header("content-type", "text/csv")
+            .body(csv)
+    };
+    let stored = || async {
+        storage
+            .expenses
+            .list_expenses(ListExpensesRequest {
+                group_id: Some(group_id.clone()),
+                ..ListExpensesRequest::default()
+            })
+            .await
+            .unwrap()
+            .expenses
+    };
+
+    let res = import(
+        "",
+        "Date,Description,Cost,Paid by\n2024-01-01,Rent,100,Alice\n2024-01-02,Pizza,abc,Eve\n",
+    )
+    .reply(&api)
+    .await;
+    assert_eq!(res.status(), 422);
+    let report: ImportReport = serde_json::from_slice(res.body()).unwrap();
+    assert!(!report.committed);
+    assert!(report.rows[0].errors.is_empty());
+    assert_eq!(report.rows[1].line, 3);
+    assert_eq!(report.rows[1].errors.len(), 2, "{:?}", report.rows[1]);
+    assert!(stored().await.is_empty());
+
+    let csv = "When,What,Amount,Who\n2024-01-01,Rent,100,Alice\n2024-01-02,Pizza,30.50,bob\n";
+    let mapping = "dateColumn=When&descriptionColumn=What&costColumn=Amount&paidByColumn=Who";
+    let res = import(&format!("{}&dryRun=true", mapping), csv)
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    let report: ImportReport = serde_json::from_slice(res.body()).unwrap();
+    assert!(!report.committed);
+    let balances = report
+        .balances
+        .iter()
+        .map(|balance| (balance.name.as_str(), balance.amount.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(balances, [("Alice", "34.75"), ("Bob", "-34.75")]);
+    assert!(stored().await.is_empty());
+
+    let res = import(mapping, csv).reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let report: ImportReport = serde_json::from_slice(res.body()).unwrap();
+    assert!(report.committed);
+    assert!(report.rows.iter().all(|row| row.id.is_some()));
+    let expenses = stored().await;
+    assert_eq!(expenses.len(), 2);
+    assert_eq!(expenses[1].description, Some("Pizza".to_string()));
+
+    let res = import("", "Cost\n1\n").reply(&api).await;
+    assert_eq!(res.status(), 400);
+}
+
+async fn import_splitwise(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let alice_id = storage
+        .users
+        .create_user(CreateUserSpec {
+            first_name: "Alice".to_string(),
+            email: Some("Alice@Example.com".to_string()),
+            ..CreateUserSpec::default()
+        })
+        .await
+        .unwrap();
+    let dump = serde_json::json!({
+        "format": "json",
+        "group": {
+            "name": "Trip",
+            "members": [
+                {"id": 1, "first_name": "Alice", "email": "alice@example.com",
+                 "balance": [{"currency_code": "EUR", "amount": "20.00"}]},
+                {"id": 2, "first_name": "Bob", "last_name": "Jones", "email": "bob@example.com",
+                 "balance": [{"currency_code": "EUR", "amount": "-25.00"}]},
+            ]
+        },
+        "expenses": [
+            {"id": 10, "description": "Hotel", "cost": "80.00", "currency_code": "EUR",
+             "date": "2024-03-01T00:00:00Z", "created_by": {"id": 1},
+             "users": [
+                {"user_id": 1, "paid_share": "80.00", "owed_share": "40.00", "net_balance": "40.00"},
+                {"user_id": 2, "paid_share": "0.00", "owed_share": "40.00", "net_balance": "-40.00"}
+             ]},
+            {"id": 11, "description": "Payment", "payment": true, "cost": "20.00",
+             "currency_code": "EUR", "date": "2024-03-02T00:00:00Z",
+             "users": [
+                {"user_id": 2, "paid_share": "20.00", "owed_share": "0.00", "net_balance": "20.00"},
+                {"user_id": 1, "paid_share": "0.00", "owed_share": "20.00", "net_balance": "-20.00"}
+             ]},
+            {"id": 12, "description": "Cancelled", "cost": "5.00", "currency_code": "EUR",
+             "date": "2024-03-03T00:00:00Z", "deleted_at": "2024-03-04T00:00:00Z",
+             "users": []}
+        ],
+        "comments": [
+            {"content": "Nice view", "comment_type": "User", "relation_id": 10, "user": {"id": 2}},
+            {"content": "Oops", "comment_type": "User", "relation_id": 12, "user": {"id": 1}}
+        ]
+    });
+    let res = request()
+        .method("POST")
+        .path("/import/splitwise")
+        .json(&dump)
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200, "{:?}", res.body());
+    let report: SplitwiseImportReport = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(report.users[0].user_id, alice_id);
+    assert!(!report.users[0].created);
+    assert!(report.users[1].created);
+    assert_eq!(report.users[1].name, "Bob Jones");
+    assert_eq!((report.expenses, report.payments), (1, 1));
+    assert_eq!((report.comments, report.skipped), (1, 2));
+    // Bob's balance in the export is off by 5.00
+    assert_eq!(report.mismatches.len(), 1);
+    assert_eq!(report.mismatches[0].name, "Bob Jones");
+    assert_eq!(report.mismatches[0].expected, "-25.00");
+    assert_eq!(report.mismatches[0].actual, "-20.00");
+
+    let bob = storage
+        .users
+        .find_user_by_email("BOB@example.com".to_string())
+        .await
+        .unwrap()
+        .expect("Bob was not created");
+    assert_eq!(bob.id, Some(report.users[1].user_id.clone()));
+    let group_id = report.group.id.expect("Group has no id");
+    let expenses = storage
+        .expenses
+        .list_expenses(ListExpensesRequest {
+            group_id: Some(group_id),
+            ..ListExpensesRequest::default()
+        })
+        .await
+        .unwrap()
+        .expenses;
+    assert_eq!(expenses.len(), 2);
+    let hotel = &expenses[0];
+    assert_eq!(hotel.comments_count, Some(1));
+    assert_eq!(
+        hotel.share_of(&alice_id).unwrap().net_balance,
+        Some("40.00".to_string())
+    );
+
+    let res = request()
+        .method("POST")
+        .path("/import/splitwise")
+        .json(&serde_json::json!({
+            "format": "csv",
+            "name": "Flat",
+            "csv": "Date,Description\n2024-01-01,Rent\n",
+        }))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 400);
+
+    // the amounts of the expense only fail once Carol and the group were stored
+    let groups = storage
+        .groups
+        .get_user_group(alice_id.clone())
+        .await
+        .unwrap();
+    let res = request()
+        .method("POST")
+        .path("/import/splitwise")
+        .json(&serde_json::json!({
+            "format": "json",
+            "group": {
+                "name": "Broken",
+                "members": [
+                    {"id": 1, "first_name": "Alice", "email": "alice@example.com"},
+                    {"id": 3, "first_name": "Carol", "email": "carol@example.com"},
+                ]
+            },
+            "expenses": [
+                {"id": 20, "description": "Taxi", "cost": "10.00", "currency_code": "EUR",
+                 "date": "2024-03-01T00:00:00Z",
+                 "users": [
+                    {"user_id": 3, "paid_share": "ten", "owed_share": "5.00", "net_balance": "5.00"}
+                 ]}
+            ],
+            "comments": []
+        }))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 500);
+    let carol = storage
+        .users
+        .find_user_by_email("carol@example.com".to_string())
+        .await
+        .unwrap();
+    assert!(carol.is_none(), "{:?}", carol);
+    let after = storage.groups.get_user_group(alice_id).await.unwrap();
+    assert_eq!(after.len(), groups.len());
+}
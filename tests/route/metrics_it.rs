@@ -0,0 +1,41 @@
+use crate::support::alice;
+use swc::config::ServerConfig;
+use swc::metrics::metrics;
+use swc::route::routes;
+use swc::service::events::EventBus;
+use swc::service::expense::CreateExpenseSpec;
+use swc::service::storage::Storage;
+use warp::test::request;
+
+#[tokio::test]
+async fn export_metrics() {
+    let storage = Storage::in_memory(EventBus::default());
+    let api = routes(storage, &ServerConfig::default());
+    // the metrics are global, other tests may create expenses at the same time
+    let expenses_created = metrics().expenses_created.get();
+    let res = request()
+        .method("POST")
+        .path("/expenses")
+        .json(&CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: alice(),
+            ..CreateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    assert!(metrics().expenses_created.get() > expenses_created);
+
+    let res = request().path("/metrics").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["content-type"], "text/plain; version=0.0.4");
+    let body = String::from_utf8(res.body().to_vec()).unwrap();
+    assert!(
+        body.contains(r#"swc_http_requests_total{method="POST",route="/expenses",status="200"}"#)
+    );
+    assert!(body.contains(
+        r#"swc_storage_operation_duration_seconds_count{operation="create_expense",service="expenses"}"#
+    ));
+    assert!(body.contains("swc_expenses_created_total"));
+}
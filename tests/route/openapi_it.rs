@@ -0,0 +1,132 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::events::EventBus;
+use swc::service::storage::Storage;
+use warp::test::request;
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+/// `(method, path)` of every `warp::path!` in the route modules, with parameters as `{}`.
+fn declared_routes() -> BTreeSet<(String, String)> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/route");
+    let mut routes = BTreeSet::new();
+    for entry in fs::read_dir(directory).unwrap() {
+        let source = fs::read_to_string(entry.unwrap().path()).unwrap();
+        for (start, _) in source.match_indices("warp::path!(") {
+            let arguments = &source[start + "warp::path!(".len()..];
+            let arguments = &arguments[..arguments.find(')').unwrap()];
+            let path = arguments
+                .split('/')
+                .map(str::trim)
+                .map(|segment| match segment.strip_prefix('"') {
+                    Some(literal) => literal.trim_end_matches('"'),
+                    None => "{}",
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            // the method filter following the path, `warp::path!` of an alias shares it
+            let method = METHODS
+                .iter()
+                .filter_map(|method| {
+                    source[start..]
+                        .find(&format!("warp::{}()", method))
+                        .map(|position| (position, method))
+                })
+                .min()
+                .map(|(_, method)| method.to_string())
+                .unwrap_or_else(|| panic!("No method for /{}", path));
+            let _inserted = routes.insert((method, format!("/{}", path)));
+        }
+    }
+    routes
+}
+
+/// `(method, path)` of every operation in the document, with parameters as `{}`.
+fn described_routes(document: &serde_json::Value) -> BTreeSet<(String, String)> {
+    let mut routes = BTreeSet::new();
+    for (path, item) in document["paths"].as_object().unwrap() {
+        let path = path
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with('{') {
+                    "{}"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        for method in item.as_object().unwrap().keys() {
+            let _inserted = routes.insert((method.clone(), path.clone()));
+        }
+    }
+    routes
+}
+
+#[tokio::test]
+async fn describe_every_route() {
+    let api = routes(
+        Storage::in_memory(EventBus::default()),
+        &ServerConfig::default(),
+    );
+    let res = request().path("/openapi.json").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let document: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+
+    let declared = declared_routes();
+    assert!(declared.contains(&("post".to_string(), "/expenses".to_string())));
+    let described = described_routes(&document);
+    let undescribed = declared.difference(&described).collect::<Vec<_>>();
+    assert!(
+        undescribed.is_empty(),
+        "Routes missing from the OpenAPI document: {:?}",
+        undescribed
+    );
+}
+
+#[tokio::test]
+async fn describe_schemas_of_services() {
+    let api = routes(
+        Storage::in_memory(EventBus::default()),
+        &ServerConfig::default(),
+    );
+    let res = request().path("/openapi.json").reply(&api).await;
+    let document: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    let schemas = &document["components"]["schemas"];
+    for schema in [
+        "CreateExpenseSpec",
+        "Expense",
+        "Group",
+        "CreateGroupSpec",
+        "ErrorResponse",
+    ] {
+        assert!(schemas[schema].is_object(), "{}", schema);
+    }
+    let create_expense = &schemas["CreateExpenseSpec"];
+    assert!(create_expense["properties"]["groupId"].is_object());
+    assert_eq!(
+        create_expense["required"],
+        serde_json::json!(["cost", "groupId", "user"])
+    );
+}
+
+#[tokio::test]
+async fn serve_swagger_ui_if_enabled() {
+    let storage = Storage::in_memory(EventBus::default());
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let res = request().path("/docs").reply(&api).await;
+    assert_eq!(res.status(), 404);
+
+    let config = ServerConfig {
+        swagger_ui: true,
+        ..ServerConfig::default()
+    };
+    let api = routes(storage, &config);
+    let res = request().path("/docs").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    assert!(String::from_utf8_lossy(res.body()).contains("/openapi.json"));
+}
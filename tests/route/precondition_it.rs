@@ -0,0 +1,100 @@
+use crate::support::alice;
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::expense::{CreateExpenseSpec, Expense, ExpenseEntity, UpdateExpenseSpec};
+use swc::service::group::{CreateGroupSpec, Group, GroupUser};
+use swc::service::storage::Storage;
+use warp::test::request;
+
+storage_tests!(reject_stale_changes);
+
+async fn reject_stale_changes(storage: Storage) {
+    let api = routes(storage, &ServerConfig::default());
+    let res = request()
+        .method("POST")
+        .path("/expenses")
+        .json(&CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: alice(),
+            ..CreateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    let created: ExpenseEntity = serde_json::from_slice(res.body()).unwrap();
+    let path = format!(
+        "/expenses/{}",
+        created.id.expect("Expense has no id").to_hex()
+    );
+
+    let res = request().path(&path).reply(&api).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["etag"], "\"1\"");
+
+    let update = |if_match: Option<&str>, cost: &str| {
+        let update = request()
+            .method("PATCH")
+            .path(&path)
+            .json(&UpdateExpenseSpec {
+                cost: Some(cost.to_string()),
+                updated_by: Some(alice()),
+                ..UpdateExpenseSpec::default()
+            });
+        match if_match {
+            Some(if_match) => update.header("if-match", if_match),
+            None => update,
+        }
+    };
+    let res = update(None, "30").reply(&api).await;
+    assert_eq!(res.status(), 428);
+    let res = update(Some("\"1\""), "30").reply(&api).await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["etag"], "\"2\"");
+
+    let res = update(Some("\"1\""), "50").reply(&api).await;
+    assert_eq!(res.status(), 412);
+    assert_eq!(res.headers()["etag"], "\"2\"");
+    let current: Expense = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(current.cost, Some("30".to_string()));
+    assert_eq!(current.version, Some(2));
+
+    let delete = |if_match: &str| {
+        request()
+            .method("DELETE")
+            .path(&path)
+            .header("if-match", if_match)
+            .json(&alice())
+    };
+    let res = delete("\"1\"").reply(&api).await;
+    assert_eq!(res.status(), 412);
+    let res = delete("\"2\"").reply(&api).await;
+    assert_eq!(res.status(), 204);
+
+    let res = request()
+        .method("POST")
+        .path("/groups")
+        .json(&CreateGroupSpec {
+            name: "Flat".to_string(),
+            users: None,
+        })
+        .reply(&api)
+        .await;
+    let group: Group = serde_json::from_slice(res.body()).unwrap();
+    let group_path = format!("/groups/{}", group.id.expect("Group has no id"));
+    let res = request().path(&group_path).reply(&api).await;
+    assert_eq!(res.headers()["etag"], "\"1\"");
+    for (user_id, status) in [("1", 200), ("2", 412)] {
+        let res = request()
+            .method("POST")
+            .path(&format!("{}/members", group_path))
+            .header("if-match", "\"1\"")
+            .json(&GroupUser {
+                user_id: user_id.to_string(),
+                first_name: None,
+            })
+            .reply(&api)
+            .await;
+        assert_eq!(res.status(), status);
+        assert_eq!(res.headers()["etag"], "\"2\"");
+    }
+}
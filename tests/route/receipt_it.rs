@@ -0,0 +1,170 @@
+use crate::support::alice;
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::activity::ActivityRequest;
+use swc::service::expense::{CreateExpenseSpec, Expense, ExpenseEntity};
+use swc::service::receipt::{receipt_key, thumbnail_key};
+use swc::service::storage::Storage;
+use warp::test::request;
+
+storage_tests!(attach_receipt);
+
+async fn attach_receipt(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let res = request()
+        .method("POST")
+        .path("/expenses")
+        .json(&CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: alice(),
+            ..CreateExpenseSpec::default()
+        })
+        .reply(&api)
+        .await;
+    let created: ExpenseEntity = serde_json::from_slice(res.body()).unwrap();
+    let expense_id = created.id.expect("Expense has no id").to_hex();
+    let path = format!("/expenses/{}", expense_id);
+
+    let mut png = std::io::Cursor::new(Vec::new());
+    image::RgbImage::new(600, 300)
+        .write_to(&mut png, image::ImageFormat::Png)
+        .unwrap();
+    let png = png.into_inner();
+    let upload = |filename: &str, content: &[u8]| {
+        let (content_type, body) = receipt_form(filename, content);
+        request()
+            .method("POST")
+            .path(&format!("{}/receipt", path))
+            .header("content-type", content_type)
+            .body(body)
+    };
+
+    let res = upload("receipt.png", &png).reply(&api).await;
+    assert_eq!(res.status(), 200);
+    let expense: Expense = serde_json::from_slice(res.body()).unwrap();
+    let receipt = expense.receipt.expect("Expense has no receipt");
+    assert_eq!(receipt.content_type, "image/png");
+    assert_eq!(receipt.size, png.len() as u64);
+    assert_eq!(receipt.filename.as_deref(), Some("receipt.png"));
+    assert!(receipt.thumbnail);
+
+    let res = request()
+        .path(&format!("{}/receipt", path))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["content-type"], "image/png");
+    assert_eq!(res.body().as_ref(), png.as_slice());
+    let res = request()
+        .path(&format!("{}/receipt/thumbnail", path))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers()["content-type"], "image/jpeg");
+    let thumbnail = image::load_from_memory(res.body()).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (256, 128));
+
+    // the receipt is part of the versioned expense
+    let etag = format!("\"{}\"", expense.version.expect("Expense has no version"));
+    let res = upload("receipt.png", &png)
+        .header("if-match", "\"1\"")
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 412);
+    assert_eq!(res.headers()["etag"], etag.as_str());
+    let res = request()
+        .method("DELETE")
+        .path(&format!("{}/receipt", path))
+        .header("if-match", etag)
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 204);
+    let detached = storage
+        .expenses
+        .get_expense(expense_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(detached.receipt, None);
+    assert_eq!(detached.version, expense.version.map(|version| version + 1));
+    let res = request()
+        .path(&format!("{}/receipt", path))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 404);
+
+    let res = upload("receipt.html", b"<html>").reply(&api).await;
+    assert_eq!(res.status(), 415);
+
+    let res = request()
+        .method("POST")
+        .path(&format!("{}/purge", path))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 409);
+
+    let res = request()
+        .method("DELETE")
+        .path(&path)
+        .header("if-match", "*")
+        .json(&alice())
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 204);
+    let res = request()
+        .method("POST")
+        .path(&format!("{}/purge", path))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 204);
+    assert!(storage
+        .expenses
+        .get_expense(expense_id.clone())
+        .await
+        .is_err());
+    for key in [receipt_key(&expense_id), thumbnail_key(&expense_id)] {
+        assert_eq!(storage.blobs.get_blob(key).await.unwrap(), None);
+    }
+    let history = storage
+        .history
+        .get_history(expense_id.clone())
+        .await
+        .unwrap();
+    assert!(history.is_empty());
+    let activity = storage
+        .activity
+        .get_group_activity("1".to_string(), ActivityRequest::default())
+        .await
+        .unwrap()
+        .activities;
+    assert!(activity
+        .iter()
+        .all(|activity| activity.expense_id.as_ref() != Some(&expense_id)));
+
+    let res = request()
+        .method("POST")
+        .path("/expenses/nope/purge")
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 404);
+    for path in [
+        "/expenses/a%20b/receipt",
+        "/expenses/a%20b/receipt/thumbnail",
+    ] {
+        let res = request().path(path).reply(&api).await;
+        assert_eq!(res.status(), 404, "{}", path);
+    }
+}
+
+/// Multipart form with the content as its `file` part.
+fn receipt_form(filename: &str, content: &[u8]) -> (String, Vec<u8>) {
+    let boundary = "receipt-boundary";
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\r\n",
+        boundary, filename
+    )
+    .into_bytes();
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
@@ -0,0 +1,231 @@
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::currency::ExchangeRates;
+use swc::service::expense::{Expense, ShareCalculator};
+use swc::service::group::CreateGroupSpec;
+use swc::service::report::{GroupReport, UserSummary};
+use swc::service::storage::Storage;
+use swc::service::user::CreateUserSpec;
+use warp::test::request;
+
+storage_tests!(report_group_spending, summarise_user_spending);
+
+async fn report_group_spending(storage: Storage) {
+    let api = routes(storage.clone(), &ServerConfig::default());
+    let group_id = storage
+        .groups
+        .create_group(CreateGroupSpec {
+            name: "Flat".to_string(),
+            users: None,
+        })
+        .await
+        .unwrap()
+        .id
+        .expect("Group has no id");
+    let expense = |date: &str, cost: &str, currency: &str, category: Option<&str>| Expense {
+        cost: Some(cost.to_string()),
+        currency_code: Some(currency.to_string()),
+        category: category.map(String::from),
+        group_id: Some(group_id.clone()),
+        date: Some(date.parse().unwrap()),
+        users: Some(ShareCalculator::new().equal_share(
+            cost.to_string(),
+            "1".to_string(),
+            vec!["1".to_string(), "2".to_string()],
+        )),
+        ..Expense::default()
+    };
+    let _imported = storage
+        .expenses
+        .import_expenses(vec![
+            expense("2024-01-05T12:00:00Z", "30.00", "EUR", Some("groceries")),
+            expense("2024-01-20T12:00:00Z", "12.50", "EUR", None),
+            expense("2024-02-02T12:00:00Z", "40.00", "EUR", Some("groceries")),
+            expense("2024-02-03T12:00:00Z", "10.00", "USD", Some("taxi")),
+            Expense {
+                payment: Some(true),
+                ..expense("2024-02-04T12:00:00Z", "20.00", "EUR", None)
+            },
+            expense("2024-03-01T00:00:00Z", "99.00", "EUR", None),
+            Expense {
+                group_id: Some("2".to_string()),
+                ..expense("2024-01-05T12:00:00Z", "50.00", "EUR", None)
+            },
+        ])
+        .await
+        .unwrap();
+
+    let res = request()
+        .path(&format!(
+            "/groups/{}/reports?from=2024-01-01T00:00:00Z&to=2024-03-01T00:00:00Z",
+            group_id
+        ))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    let report: GroupReport = serde_json::from_slice(res.body()).unwrap();
+    let months = report
+        .months
+        .iter()
+        .map(|total| {
+            (
+                total.month.as_str(),
+                total.currency_code.as_str(),
+                total.total.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        months,
+        [
+            ("2024-01", "EUR", "42.50"),
+            ("2024-02", "EUR", "40.00"),
+            ("2024-02", "USD", "10.00"),
+        ]
+    );
+    let categories = report
+        .categories
+        .iter()
+        .map(|total| (total.category.as_deref(), total.total.as_str(), total.count))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        categories,
+        [
+            (None, "12.50", 1),
+            (Some("groceries"), "70.00", 2),
+            (Some("taxi"), "10.00", 1),
+        ]
+    );
+    let members = report
+        .members
+        .iter()
+        .map(|total| {
+            (
+                total.user_id.as_str(),
+                total.currency_code.as_str(),
+                total.paid.as_str(),
+                total.consumed.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        members,
+        [
+            ("1", "EUR", "82.50", "41.25"),
+            ("1", "USD", "10.00", "5.00"),
+            ("2", "EUR", "0.00", "41.25"),
+            ("2", "USD", "0.00", "5.00"),
+        ]
+    );
+    let currencies = report
+        .currencies
+        .iter()
+        .map(|total| {
+            (
+                total.currency_code.as_str(),
+                total.total.as_str(),
+                total.count,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(currencies, [("EUR", "82.50", 3), ("USD", "10.00", 1)]);
+
+    let res = request()
+        .path(&format!("/groups/{}/reports", group_id))
+        .reply(&api)
+        .await;
+    let report: GroupReport = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(report.months.len(), 4);
+
+    let res = request().path("/groups/nope/reports").reply(&api).await;
+    assert_eq!(res.status(), 404);
+}
+
+async fn summarise_user_spending(storage: Storage) {
+    let config = ServerConfig {
+        exchange_rates: ExchangeRates::parse("EUR=1,USD=0.9").unwrap(),
+        ..ServerConfig::default()
+    };
+    let api = routes(storage.clone(), &config);
+    let bob_id = storage
+        .users
+        .create_user(CreateUserSpec {
+            first_name: "Bob".to_string(),
+            email: Some("bob@example.com".to_string()),
+            default_currency: "EUR".to_string(),
+        })
+        .await
+        .unwrap();
+    let expense = |group_id: &str, date: &str, cost: &str, currency: &str| Expense {
+        cost: Some(cost.to_string()),
+        currency_code: Some(currency.to_string()),
+        group_id: Some(group_id.to_string()),
+        date: Some(date.parse().unwrap()),
+        users: Some(ShareCalculator::new().equal_share(
+            cost.to_string(),
+            "1".to_string(),
+            vec!["1".to_string(), bob_id.clone()],
+        )),
+        ..Expense::default()
+    };
+    let _imported = storage
+        .expenses
+        .import_expenses(vec![
+            expense("1", "2024-01-05T12:00:00Z", "30.00", "EUR"),
+            expense("1", "2024-01-20T12:00:00Z", "20.00", "USD"),
+            expense("2", "2024-01-25T12:00:00Z", "1000.00", "JPY"),
+            expense("1", "2024-02-02T12:00:00Z", "5.00", "EUR"),
+            Expense {
+                deleted_at: Some(chrono::Utc::now()),
+                ..expense("1", "2024-02-03T12:00:00Z", "80.00", "EUR")
+            },
+            Expense {
+                users: Some(ShareCalculator::new().equal_share(
+                    "40.00".to_string(),
+                    "1".to_string(),
+                    vec!["1".to_string(), "3".to_string()],
+                )),
+                ..expense("1", "2024-02-04T12:00:00Z", "40.00", "EUR")
+            },
+        ])
+        .await
+        .unwrap();
+
+    let res = request()
+        .path(&format!(
+            "/users/{}/summary?from=2024-01-01T00:00:00Z",
+            bob_id
+        ))
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 200);
+    let summary: UserSummary = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(summary.currency_code.as_deref(), Some("EUR"));
+    let months = summary
+        .months
+        .iter()
+        .map(|total| {
+            (
+                total.month.as_str(),
+                total.group_id.as_str(),
+                total.currency_code.as_str(),
+                total.total.as_str(),
+                total.count,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        months,
+        [
+            ("2024-01", "1", "EUR", "24.00", 2),
+            ("2024-01", "2", "JPY", "500.00", 1),
+            ("2024-02", "1", "EUR", "2.50", 1),
+        ]
+    );
+    let currencies = summary
+        .currencies
+        .iter()
+        .map(|total| (total.currency_code.as_str(), total.total.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(currencies, [("EUR", "26.50"), ("JPY", "500.00")]);
+}
@@ -0,0 +1,87 @@
+use swc::config::ServerConfig;
+use swc::route::routes;
+use swc::service::events::EventBus;
+use swc::service::group::CreateGroupSpec;
+use swc::service::storage::Storage;
+use warp::test::request;
+
+#[tokio::test]
+async fn reject_body_over_limit() {
+    let config = ServerConfig {
+        body_limit: 16,
+        ..ServerConfig::default()
+    };
+    let api = routes(Storage::in_memory(EventBus::default()), &config);
+    let res = request()
+        .method("POST")
+        .path("/groups")
+        .json(&CreateGroupSpec {
+            name: "A group with a rather long name".to_string(),
+            users: None,
+        })
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 413);
+}
+
+#[tokio::test]
+async fn propagate_request_id() {
+    let api = routes(
+        Storage::in_memory(EventBus::default()),
+        &ServerConfig::default(),
+    );
+    let res = request()
+        .path("/health")
+        .header("x-request-id", "support-1234")
+        .reply(&api)
+        .await;
+    assert_eq!(res.headers()["x-request-id"], "support-1234");
+
+    let res = request().path("/health").reply(&api).await;
+    assert!(!res.headers()["x-request-id"].is_empty());
+}
+
+#[tokio::test]
+async fn echo_request_id_in_error() {
+    let api = routes(
+        Storage::in_memory(EventBus::default()),
+        &ServerConfig::default(),
+    );
+    let res = request()
+        .method("POST")
+        .path("/expenses")
+        .header("x-request-id", "support-5678")
+        .body("{")
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 400);
+    let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(error["requestId"], "support-5678");
+    assert!(error["error"].as_str().unwrap().contains("deserialize"));
+
+    let res = request().path("/nothing/here").reply(&api).await;
+    assert_eq!(res.status(), 404);
+    let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(error["error"], "Not Found");
+    assert_eq!(
+        error["requestId"],
+        res.headers()["x-request-id"].to_str().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn echo_request_id_in_not_found() {
+    let api = routes(
+        Storage::in_memory(EventBus::default()),
+        &ServerConfig::default(),
+    );
+    let res = request()
+        .path("/expenses/nope")
+        .header("x-request-id", "support-9012")
+        .reply(&api)
+        .await;
+    assert_eq!(res.status(), 404);
+    let error: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(error["error"], "Expense nope not found");
+    assert_eq!(error["requestId"], "support-9012");
+}
@@ -0,0 +1,109 @@
+use swc::service::activity::{ActivityApi, ActivityApiMongoAdapter, ActivityRequest, ActivityType};
+use swc::service::expense::{CreateExpenseSpec, ExpenseApiMongoAdapter, ExpensesApi, User};
+use swc::service::group::{CreateGroupSpec, GroupApi, GroupApiMongoAdapter, GroupUser};
+use testcontainers::{clients, images};
+
+#[tokio::test]
+async fn group_activity_feed() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
+    let url = format!("mongodb://localhost:{}/", node.get_host_port_ipv6(27017));
+    let database = mongodb::Client::with_uri_str(url)
+        .await
+        .unwrap()
+        .database("bot_test_db");
+
+    let group_service = GroupApiMongoAdapter::new(database.clone());
+    let expense_service = ExpenseApiMongoAdapter::new(database.clone());
+    let activity_service = ActivityApiMongoAdapter::new(database.clone());
+
+    let group = group_service
+        .create_group(CreateGroupSpec {
+            name: "Flat".to_string(),
+            users: Some(vec![GroupUser {
+                user_id: "1".to_string(),
+                first_name: Some("Bob".to_string()),
+            }]),
+        })
+        .await
+        .unwrap();
+    let group_id = group.id.unwrap();
+
+    group_service
+        .add_member(
+            group_id.clone(),
+            GroupUser {
+                user_id: "2".to_string(),
+                first_name: Some("Carol".to_string()),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+    expense_service
+        .create_expense(CreateExpenseSpec {
+            cost: "42.00".to_string(),
+            group_id: group_id.clone(),
+            user: User {
+                id: Some("1".to_string()),
+                first_name: Some("Bob".to_string()),
+                ..User::default()
+            },
+            description: Some("Groceries".to_string()),
+            ..CreateExpenseSpec::default()
+        })
+        .await
+        .unwrap();
+
+    let first_page = activity_service
+        .get_group_activity(
+            group_id.clone(),
+            ActivityRequest {
+                user_id: Some("1".to_string()),
+                limit: Some(2),
+                ..ActivityRequest::default()
+            },
+        )
+        .await
+        .unwrap();
+    let summaries = first_page
+        .activities
+        .iter()
+        .map(|activity| activity.summary.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["Bob added Groceries 42.00", "Carol joined"]);
+    assert!(first_page
+        .activities
+        .iter()
+        .all(|activity| activity.unread == Some(true)));
+
+    let second_page = activity_service
+        .get_group_activity(
+            group_id.clone(),
+            ActivityRequest {
+                cursor: first_page.next_cursor,
+                limit: Some(2),
+                ..ActivityRequest::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(second_page.activities.len(), 1);
+    assert_eq!(
+        second_page.activities[0].activity_type,
+        ActivityType::GroupCreated
+    );
+    assert_eq!(second_page.next_cursor, None);
+
+    activity_service.mark_read("1".to_string()).await.unwrap();
+    let user_feed = activity_service
+        .get_user_activity("1".to_string(), ActivityRequest::default())
+        .await
+        .unwrap();
+    assert_eq!(user_feed.activities.len(), 3);
+    assert!(user_feed
+        .activities
+        .iter()
+        .all(|activity| activity.unread == Some(false)));
+}
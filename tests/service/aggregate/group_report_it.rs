@@ -0,0 +1,111 @@
+use swc::service::expense::{Expense, ExpenseApiMongoAdapter, ExpensesApi, ShareCalculator};
+use swc::service::report::{ReportApiMongoAdapter, ReportRequest, ReportsApi};
+use testcontainers::{clients, images};
+
+#[tokio::test]
+async fn aggregate_group_spending() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
+    let url = format!("mongodb://localhost:{}/", node.get_host_port_ipv6(27017));
+    let database = mongodb::Client::with_uri_str(url)
+        .await
+        .unwrap()
+        .database("bot_test_db");
+
+    let expense_service = ExpenseApiMongoAdapter::new(database.clone());
+    let report_service = ReportApiMongoAdapter::new(database.clone());
+
+    let expense = |date: &str, cost: &str, currency: &str, category: Option<&str>| Expense {
+        cost: Some(cost.to_string()),
+        currency_code: Some(currency.to_string()),
+        category: category.map(String::from),
+        group_id: Some("1".to_string()),
+        date: Some(date.parse().unwrap()),
+        users: Some(ShareCalculator::new().equal_share(
+            cost.to_string(),
+            "1".to_string(),
+            vec!["1".to_string(), "2".to_string()],
+        )),
+        ..Expense::default()
+    };
+    expense_service
+        .import_expenses(vec![
+            expense("2024-01-05T12:00:00Z", "30.00", "EUR", Some("groceries")),
+            expense("2024-01-20T12:00:00.250Z", "12.50", "EUR", None),
+            expense("2024-02-02T12:00:00Z", "40.00", "EUR", Some("groceries")),
+            expense("2024-02-03T12:00:00Z", "10.00", "USD", Some("taxi")),
+            Expense {
+                payment: Some(true),
+                ..expense("2024-02-04T12:00:00Z", "20.00", "EUR", None)
+            },
+            expense("2024-03-01T00:00:00Z", "99.00", "EUR", None),
+        ])
+        .await
+        .unwrap();
+
+    let report = report_service
+        .group_report(
+            "1".to_string(),
+            ReportRequest {
+                from: Some("2024-01-01T00:00:00Z".parse().unwrap()),
+                to: Some("2024-03-01T00:00:00Z".parse().unwrap()),
+            },
+        )
+        .await
+        .unwrap();
+
+    let months = report
+        .months
+        .iter()
+        .map(|total| {
+            (
+                total.month.as_str(),
+                total.currency_code.as_str(),
+                total.total.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        months,
+        [
+            ("2024-01", "EUR", "42.50"),
+            ("2024-02", "EUR", "40.00"),
+            ("2024-02", "USD", "10.00"),
+        ]
+    );
+    let categories = report
+        .categories
+        .iter()
+        .map(|total| (total.category.as_deref(), total.total.as_str(), total.count))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        categories,
+        [
+            (None, "12.50", 1),
+            (Some("groceries"), "70.00", 2),
+            (Some("taxi"), "10.00", 1),
+        ]
+    );
+    let bob = report
+        .members
+        .iter()
+        .find(|total| total.user_id == "2" && total.currency_code == "EUR")
+        .unwrap();
+    assert_eq!(
+        (bob.paid.as_str(), bob.consumed.as_str()),
+        ("0.00", "41.25")
+    );
+    assert_eq!(bob.net, "-41.25");
+    let currencies = report
+        .currencies
+        .iter()
+        .map(|total| {
+            (
+                total.currency_code.as_str(),
+                total.total.as_str(),
+                total.count,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(currencies, [("EUR", "82.50", 3), ("USD", "10.00", 1)]);
+}
@@ -6,7 +6,7 @@ use testcontainers::{clients, images};
 #[tokio::test]
 async fn calculation_split_equally_for_three_users() {
     let docker = clients::Cli::default();
-    let node = docker.run(images::mongo::Mongo::default());
+    let node = docker.run(images::mongo::Mongo);
     let host_port = node.get_host_port_ipv6(27017);
     let url = format!("mongodb://localhost:{}/", host_port);
     let database = mongodb::Client::with_uri_str(url)
@@ -28,6 +28,7 @@ async fn calculation_split_equally_for_three_users() {
                 first_name: Some("test".to_string()),
                 ..swc::service::expense::User::default()
             },
+            ..CreateExpenseSpec::default()
         })
         .await
         .unwrap();
@@ -45,7 +46,11 @@ async fn calculation_split_equally_for_three_users() {
     };
 
     expense_service
-        .update_expense(created_expense.id.unwrap().to_hex(), update_expense_spec)
+        .update_expense(
+            created_expense.id.unwrap().to_hex(),
+            update_expense_spec,
+            None,
+        )
         .await
         .unwrap();
 
@@ -0,0 +1,72 @@
+use swc::service::expense::{Expense, ExpenseApiMongoAdapter, ExpensesApi, ShareCalculator};
+use swc::service::report::{ReportApiMongoAdapter, ReportRequest, ReportsApi, UserSpending};
+use testcontainers::{clients, images};
+
+#[tokio::test]
+async fn aggregate_user_spending() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
+    let url = format!("mongodb://localhost:{}/", node.get_host_port_ipv6(27017));
+    let database = mongodb::Client::with_uri_str(url)
+        .await
+        .unwrap()
+        .database("bot_test_db");
+
+    let expense_service = ExpenseApiMongoAdapter::new(database.clone());
+    let report_service = ReportApiMongoAdapter::new(database.clone());
+
+    let expense = |group_id: &str, date: &str, cost: &str, currency: &str| Expense {
+        cost: Some(cost.to_string()),
+        currency_code: Some(currency.to_string()),
+        group_id: Some(group_id.to_string()),
+        date: Some(date.parse().unwrap()),
+        users: Some(ShareCalculator::new().equal_share(
+            cost.to_string(),
+            "1".to_string(),
+            vec!["1".to_string(), "2".to_string()],
+        )),
+        ..Expense::default()
+    };
+    expense_service
+        .import_expenses(vec![
+            expense("1", "2023-12-31T12:00:00Z", "8.00", "EUR"),
+            expense("1", "2024-01-05T12:00:00Z", "30.00", "EUR"),
+            expense("1", "2024-01-20T12:00:00.250Z", "20.00", "USD"),
+            expense("2", "2024-01-25T12:00:00Z", "1000.00", "JPY"),
+            expense("1", "2024-02-02T12:00:00Z", "5.00", "EUR"),
+            Expense {
+                payment: Some(true),
+                ..expense("1", "2024-02-03T12:00:00Z", "20.00", "EUR")
+            },
+        ])
+        .await
+        .unwrap();
+
+    let totals = report_service
+        .user_spending(
+            "2".to_string(),
+            ReportRequest {
+                from: Some("2024-01-01T00:00:00Z".parse().unwrap()),
+                to: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    let spending = |month: &str, group_id: &str, currency: &str, owed: i64| UserSpending {
+        month: month.to_string(),
+        group_id: group_id.to_string(),
+        currency_code: currency.to_string(),
+        owed,
+        count: 1,
+    };
+    assert_eq!(
+        totals,
+        [
+            spending("2024-01", "1", "EUR", 1500),
+            spending("2024-01", "1", "USD", 1000),
+            spending("2024-01", "2", "JPY", 50000),
+            spending("2024-02", "1", "EUR", 250),
+        ]
+    );
+}
@@ -0,0 +1,82 @@
+use swc::service::comment::{CommentApiMongoAdapter, CommentType, CommentsApi, CreateCommentSpec};
+use swc::service::expense::{
+    CreateExpenseSpec, ExpenseApiMongoAdapter, ExpensesApi, UpdateExpenseSpec, User,
+};
+use testcontainers::{clients, images};
+
+#[tokio::test]
+async fn comment_on_expense() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
+    let url = format!("mongodb://localhost:{}/", node.get_host_port_ipv6(27017));
+    let database = mongodb::Client::with_uri_str(url)
+        .await
+        .unwrap()
+        .database("bot_test_db");
+
+    let expense_service = ExpenseApiMongoAdapter::new(database.clone());
+    let comment_service = CommentApiMongoAdapter::new(database.clone());
+
+    let user = User {
+        id: Some("1".to_string()),
+        ..User::default()
+    };
+    let expense_id = expense_service
+        .create_expense(CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: user.clone(),
+            ..CreateExpenseSpec::default()
+        })
+        .await
+        .unwrap()
+        .id
+        .unwrap()
+        .to_hex();
+
+    let comment = comment_service
+        .create_comment(
+            expense_id.clone(),
+            CreateCommentSpec {
+                content: "was this the dinner on Friday?".to_string(),
+                user,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(comment.comment_type, CommentType::User);
+
+    expense_service
+        .update_expense(
+            expense_id.clone(),
+            UpdateExpenseSpec {
+                description: Some("Friday dinner".to_string()),
+                ..UpdateExpenseSpec::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+    let comments = comment_service
+        .get_comments(expense_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(comments.len(), 2);
+    assert_eq!(comments[1].comment_type, CommentType::System);
+    assert_eq!(comments[1].content, "Updated description to Friday dinner");
+
+    let expense = expense_service
+        .get_expense(expense_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(expense.comments_count, Some(2));
+
+    let deleted = comment_service
+        .delete_comment(comment.id.unwrap())
+        .await
+        .unwrap();
+    assert!(deleted.is_some());
+    let expense = expense_service.get_expense(expense_id).await.unwrap();
+    assert_eq!(expense.comments_count, Some(1));
+}
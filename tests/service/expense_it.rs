@@ -1,14 +1,24 @@
+use crate::support::alice;
 use futures::StreamExt;
+use mongodb::bson::oid::ObjectId;
 use mongodb::bson::{doc, Document};
 use swc::service::expense::{
     CreateExpenseSpec, ExpenseApiMongoAdapter, ExpensesApi, UpdateExpenseSpec, User,
 };
+use swc::service::storage::Storage;
+use swc::service::version::Conditional;
+use swc::service::NotFound;
 use testcontainers::{clients, images};
 
+storage_tests!(
+    fail_on_unknown_expenses,
+    leave_expenses_changed_since_expected_version
+);
+
 #[tokio::test]
 async fn create_new_expense() {
     let docker = clients::Cli::default();
-    let node = docker.run(images::mongo::Mongo::default());
+    let node = docker.run(images::mongo::Mongo);
     let host_port = node.get_host_port_ipv6(27017);
     let url = format!("mongodb://localhost:{}/", host_port);
     let database = mongodb::Client::with_uri_str(url)
@@ -27,6 +37,7 @@ async fn create_new_expense() {
                 first_name: Some("test".to_string()),
                 ..User::default()
             },
+            ..CreateExpenseSpec::default()
         })
         .await
         .unwrap();
@@ -59,7 +70,7 @@ async fn create_new_expense() {
 #[tokio::test]
 async fn update_only_non_none_fields_of_expense() {
     let docker = clients::Cli::default();
-    let node = docker.run(images::mongo::Mongo::default());
+    let node = docker.run(images::mongo::Mongo);
     let url = format!("mongodb://localhost:{}/", node.get_host_port_ipv6(27017));
     let database = mongodb::Client::with_uri_str(url)
         .await
@@ -77,6 +88,7 @@ async fn update_only_non_none_fields_of_expense() {
                 first_name: Some("test".to_string()),
                 ..User::default()
             },
+            ..CreateExpenseSpec::default()
         })
         .await
         .unwrap();
@@ -90,6 +102,7 @@ async fn update_only_non_none_fields_of_expense() {
                 description: Some("test".to_string()),
                 ..UpdateExpenseSpec::default()
             },
+            None,
         )
         .await
         .unwrap();
@@ -109,3 +122,75 @@ async fn update_only_non_none_fields_of_expense() {
     let el = cursor.next().await.unwrap().unwrap();
     assert_eq!(el.get_str("description").unwrap(), "test");
 }
+
+async fn fail_on_unknown_expenses(storage: Storage) {
+    for id in ["nope".to_string(), ObjectId::new().to_hex()] {
+        let error = storage.expenses.get_expense(id.clone()).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<NotFound>(),
+            Some(&NotFound::new("Expense", &id))
+        );
+        let update = UpdateExpenseSpec {
+            cost: Some("30".to_string()),
+            updated_by: Some(alice()),
+            ..UpdateExpenseSpec::default()
+        };
+        let error = storage
+            .expenses
+            .update_expense(id.clone(), update, None)
+            .await
+            .unwrap_err();
+        assert!(error.is::<NotFound>(), "{:#}", error);
+        let error = storage
+            .expenses
+            .delete_expense(id.clone(), alice(), None)
+            .await
+            .unwrap_err();
+        assert!(error.is::<NotFound>(), "{:#}", error);
+    }
+}
+
+async fn leave_expenses_changed_since_expected_version(storage: Storage) {
+    let created = storage
+        .expenses
+        .create_expense(CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: alice(),
+            ..CreateExpenseSpec::default()
+        })
+        .await
+        .unwrap();
+    let id = created.id.expect("Expense has no id").to_hex();
+    let update = |cost: &str| UpdateExpenseSpec {
+        cost: Some(cost.to_string()),
+        updated_by: Some(alice()),
+        ..UpdateExpenseSpec::default()
+    };
+
+    let updated = storage
+        .expenses
+        .update_expense(id.clone(), update("30"), Some(1))
+        .await
+        .unwrap();
+    assert!(matches!(updated, Conditional::Applied(_)));
+    let stale = storage
+        .expenses
+        .update_expense(id.clone(), update("50"), Some(1))
+        .await
+        .unwrap();
+    let Conditional::Stale(current) = stale else {
+        panic!("Stale update was applied");
+    };
+    assert_eq!(current.cost, Some("30".to_string()));
+    assert_eq!(current.version, Some(2));
+
+    let stale = storage
+        .expenses
+        .delete_expense(id.clone(), alice(), Some(1))
+        .await
+        .unwrap();
+    assert!(matches!(stale, Conditional::Stale(_)));
+    let expense = storage.expenses.get_expense(id).await.unwrap();
+    assert_eq!(expense.deleted_at, None);
+}
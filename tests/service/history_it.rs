@@ -0,0 +1,100 @@
+use mongodb::bson::Bson;
+use swc::service::expense::{
+    CreateExpenseSpec, ExpenseApiMongoAdapter, ExpensesApi, UpdateExpenseSpec, User,
+};
+use swc::service::history::{ExpenseAction, ExpenseHistoryApi, ExpenseHistoryMongoAdapter};
+use testcontainers::{clients, images};
+
+#[tokio::test]
+async fn record_every_change_of_expense() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
+    let url = format!("mongodb://localhost:{}/", node.get_host_port_ipv6(27017));
+    let database = mongodb::Client::with_uri_str(url)
+        .await
+        .unwrap()
+        .database("bot_test_db");
+
+    let expense_service = ExpenseApiMongoAdapter::new(database.clone());
+    let history_service = ExpenseHistoryMongoAdapter::new(database.clone());
+
+    let user = User {
+        id: Some("1".to_string()),
+        ..User::default()
+    };
+    let id = expense_service
+        .create_expense(CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: user.clone(),
+            ..CreateExpenseSpec::default()
+        })
+        .await
+        .unwrap()
+        .id
+        .unwrap()
+        .to_hex();
+
+    let updated = expense_service
+        .update_expense(
+            id.clone(),
+            UpdateExpenseSpec {
+                cost: Some("30".to_string()),
+                updated_by: Some(user.clone()),
+                ..UpdateExpenseSpec::default()
+            },
+            Some(1),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(updated.cost, Some("30".to_string()));
+    assert_eq!(updated.group_id, Some("1".to_string()));
+
+    expense_service
+        .delete_expense(id.clone(), user.clone(), None)
+        .await
+        .unwrap();
+    expense_service
+        .restore_expense(id.clone(), user)
+        .await
+        .unwrap();
+
+    let history = history_service.get_history(id).await.unwrap();
+    let actions = history
+        .iter()
+        .map(|revision| revision.action)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        actions,
+        vec![
+            ExpenseAction::Created,
+            ExpenseAction::Updated,
+            ExpenseAction::Deleted,
+            ExpenseAction::Restored
+        ]
+    );
+    assert_eq!(
+        history
+            .iter()
+            .map(|revision| revision.version)
+            .collect::<Vec<_>>(),
+        vec![1, 2, 3, 4]
+    );
+
+    let update = &history[1];
+    assert_eq!(
+        update.changed_by.as_ref().unwrap().id,
+        Some("1".to_string())
+    );
+    assert_eq!(update.changes.len(), 1);
+    assert_eq!(update.changes[0].field, "cost");
+    assert_eq!(
+        update.changes[0].from,
+        Some(Bson::String("100".to_string()))
+    );
+    assert_eq!(update.changes[0].to, Some(Bson::String("30".to_string())));
+
+    assert_eq!(history[2].changes[0].field, "deletedAt");
+    assert_eq!(history[3].changes[0].to, None);
+}
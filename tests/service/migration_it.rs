@@ -0,0 +1,79 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use std::time::Duration;
+use swc::service::group::{GroupApi, GroupApiMongoAdapter};
+use swc::service::migration::MongoMigrator;
+use testcontainers::{clients, images};
+
+#[tokio::test]
+async fn apply_migrations_once() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
+    let url = format!("mongodb://localhost:{}/", node.get_host_port_ipv6(27017));
+    let database = mongodb::Client::with_uri_str(url)
+        .await
+        .unwrap()
+        .database("bot_test_db");
+    let _inserted = database
+        .collection::<Document>("groups")
+        .insert_one(
+            doc! {
+                "name": "Flat",
+                "members": [{"id": "1"}, {"id": "2"}],
+                "originalDebts": [{"from": 1_i64, "to": 2_i64, "amount": "10.00"}],
+                "simplifiedDebts": [],
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+    let migrator = MongoMigrator::new(database.clone());
+    let applied = migrator.run().await.unwrap();
+    assert_eq!(applied, vec![1, 2, 3, 4]);
+    assert!(migrator.run().await.unwrap().is_empty());
+
+    let indexes = database
+        .collection::<Document>("groups")
+        .list_index_names()
+        .await
+        .unwrap();
+    assert!(indexes.contains(&"members.id_1".to_string()));
+
+    let expiring = database
+        .collection::<Document>("idempotency_keys")
+        .list_indexes(None)
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|index| index.keys == doc! {"expiresAt": 1})
+        .expect("No index on expiresAt");
+    let expire_after = expiring.options.and_then(|options| options.expire_after);
+    assert_eq!(expire_after, Some(Duration::from_secs(0)));
+
+    let unique_name = database
+        .collection::<Document>("categories")
+        .list_indexes(None)
+        .await
+        .unwrap()
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|index| index.keys == doc! {"groupId": 1, "parentId": 1, "name": 1})
+        .expect("No index on category names");
+    let unique = unique_name.options.and_then(|options| options.unique);
+    assert_eq!(unique, Some(true));
+
+    let groups = GroupApiMongoAdapter::new(database)
+        .get_user_group("2".to_string())
+        .await
+        .unwrap();
+    assert_eq!(groups.len(), 1);
+    let debt = &groups[0].original_debts.as_ref().unwrap()[0];
+    assert_eq!(debt.from, Some("1".to_string()));
+    assert_eq!(debt.to, Some("2".to_string()));
+}
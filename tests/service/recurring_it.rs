@@ -0,0 +1,158 @@
+use crate::support::alice;
+use chrono::Duration;
+use std::sync::Arc;
+use swc::service::activity::{ActivityRequest, ActivityType};
+use swc::service::events::EventBus;
+use swc::service::expense::{
+    CreateExpenseSpec, ExpenseApiMongoAdapter, ExpensesApi, RepeatInterval, UpdateExpenseSpec, User,
+};
+use swc::service::recurring::{run_scheduler, RecurringExpensesApi, RecurringExpensesMongoAdapter};
+use swc::service::storage::Storage;
+use testcontainers::{clients, images};
+use tokio::sync::watch;
+
+storage_tests!(generate_occurrences_until_stopped);
+
+#[tokio::test]
+async fn generate_due_occurrences_once() {
+    let docker = clients::Cli::default();
+    let node = docker.run(images::mongo::Mongo);
+    let url = format!("mongodb://localhost:{}/", node.get_host_port_ipv6(27017));
+    let database = mongodb::Client::with_uri_str(url)
+        .await
+        .unwrap()
+        .database("bot_test_db");
+
+    let expense_service = ExpenseApiMongoAdapter::new(database.clone());
+    let recurring_service = RecurringExpensesMongoAdapter::new(database.clone());
+
+    let created = expense_service
+        .create_expense(CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: User {
+                id: Some("1".to_string()),
+                ..User::default()
+            },
+            repeat_interval: Some(RepeatInterval::Weekly),
+            ..CreateExpenseSpec::default()
+        })
+        .await
+        .unwrap();
+    let series_id = created.id.unwrap().to_hex();
+    let anchor = created.expense.date.unwrap();
+
+    let now = anchor + Duration::days(15);
+    let generated = recurring_service.generate_due_expenses(now).await.unwrap();
+    assert_eq!(generated.len(), 2);
+    assert!(generated
+        .iter()
+        .all(|expense| expense.series_id == Some(series_id.clone())));
+    assert_eq!(generated[0].date, Some(anchor + Duration::weeks(1)));
+    assert_eq!(generated[1].date, Some(anchor + Duration::weeks(2)));
+
+    let generated_again = recurring_service.generate_due_expenses(now).await.unwrap();
+    assert!(generated_again.is_empty());
+
+    let template = expense_service
+        .get_expense(series_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(template.next_repeat, Some(anchor + Duration::weeks(3)));
+
+    let _stopped = expense_service
+        .update_expense(
+            series_id,
+            UpdateExpenseSpec {
+                repeat_interval: Some(RepeatInterval::Never),
+                updated_by: Some(alice()),
+                ..UpdateExpenseSpec::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    let generated_after_stop = recurring_service
+        .generate_due_expenses(now + Duration::weeks(4))
+        .await
+        .unwrap();
+    assert!(generated_after_stop.is_empty());
+}
+
+async fn generate_occurrences_until_stopped(storage: Storage) {
+    let created = storage
+        .expenses
+        .create_expense(CreateExpenseSpec {
+            cost: "100".to_string(),
+            group_id: "1".to_string(),
+            user: alice(),
+            repeat_interval: Some(RepeatInterval::Weekly),
+            ..CreateExpenseSpec::default()
+        })
+        .await
+        .unwrap();
+    let series_id = created.id.unwrap().to_hex();
+    let anchor = created.expense.date.unwrap();
+
+    let now = anchor + Duration::days(15);
+    let generated = storage.recurring.generate_due_expenses(now).await.unwrap();
+    assert_eq!(generated.len(), 2);
+    assert_eq!(generated[0].date, Some(anchor + Duration::weeks(1)));
+    assert_eq!(generated[1].date, Some(anchor + Duration::weeks(2)));
+    let generated_again = storage.recurring.generate_due_expenses(now).await.unwrap();
+    assert!(generated_again.is_empty());
+
+    let template = storage
+        .expenses
+        .get_expense(series_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(template.next_repeat, Some(anchor + Duration::weeks(3)));
+
+    let added = storage
+        .activity
+        .get_group_activity("1".to_string(), ActivityRequest::default())
+        .await
+        .unwrap()
+        .activities
+        .into_iter()
+        .filter(|activity| activity.activity_type == ActivityType::ExpenseAdded)
+        .count();
+    assert_eq!(added, 3);
+
+    let _stopped = storage
+        .expenses
+        .update_expense(
+            series_id,
+            UpdateExpenseSpec {
+                repeat_interval: Some(RepeatInterval::Never),
+                updated_by: Some(alice()),
+                ..UpdateExpenseSpec::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    let generated_after_stop = storage
+        .recurring
+        .generate_due_expenses(now + Duration::weeks(4))
+        .await
+        .unwrap();
+    assert!(generated_after_stop.is_empty());
+}
+
+#[tokio::test]
+async fn stop_scheduler_on_shutdown() {
+    let storage = Storage::in_memory(EventBus::default());
+    let (shutdown, receiver) = watch::channel(false);
+    let scheduler = tokio::spawn(run_scheduler(
+        Arc::clone(&storage.recurring),
+        std::time::Duration::from_secs(3600),
+        receiver,
+    ));
+    shutdown.send(true).unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(5), scheduler)
+        .await
+        .expect("Scheduler did not stop")
+        .unwrap();
+}
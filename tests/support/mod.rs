@@ -0,0 +1,50 @@
+use swc::service::events::EventBus;
+use swc::service::expense::User;
+use swc::service::storage::Storage;
+
+/// Runs every scenario, an async fn taking the storage, against each backend which needs no
+/// Docker: in memory, and SQLite with the `sql` feature.
+macro_rules! storage_tests {
+    ($($scenario:ident),+ $(,)?) => {
+        mod memory {
+            $(
+                #[tokio::test]
+                async fn $scenario() {
+                    super::$scenario($crate::support::memory()).await;
+                }
+            )+
+        }
+
+        #[cfg(feature = "sql")]
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $scenario() {
+                    super::$scenario($crate::support::sqlite().await).await;
+                }
+            )+
+        }
+    };
+}
+
+pub fn memory() -> Storage {
+    Storage::in_memory(EventBus::default())
+}
+
+#[cfg(feature = "sql")]
+pub async fn sqlite() -> Storage {
+    Storage::sql(
+        swc::service::sql::connect("sqlite::memory:")
+            .await
+            .expect("Failed to create SQLite database"),
+        EventBus::default(),
+    )
+}
+
+pub fn alice() -> User {
+    User {
+        id: Some("1".to_string()),
+        first_name: Some("Alice".to_string()),
+        ..User::default()
+    }
+}